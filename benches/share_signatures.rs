@@ -0,0 +1,57 @@
+// How long checking the signatures of a lot of shares takes, one at a time and in parallel (as decrypt does it)
+// -- `cargo bench --bench share_signatures`, ideally on a machine with a few cores
+
+extern crate chachamir;
+extern crate rayon;
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use chachamir::{ShareFromFile, ALGO_VERSION, KEY_LENGTH_BYTES, NONCE_LENGTH_BYTES};
+use chachamir::{construct_header_share, construct_share, fill_random, generate_keypair, parse_share, split_key_into_shares, verify_share_signature};
+
+use rayon::prelude::*;
+
+const SHARES: u8 = 128;
+const ROUNDS: u32 = 10;
+
+fn signed_shares() -> Vec<ShareFromFile> { // SHARES shares of a random key, signed the same way encrypt --sign signs them
+    let mut key = vec![0u8; KEY_LENGTH_BYTES];
+    fill_random(&mut key);
+
+    let mut nonce = vec![0u8; NONCE_LENGTH_BYTES];
+    fill_random(&mut nonce);
+
+    let keypair = generate_keypair();
+    let share_header = construct_header_share(ALGO_VERSION, 2, true, false, &nonce, None, None, false, None);
+
+    split_key_into_shares(&key, SHARES, 2).iter()
+        .map(|share| construct_share(&share_header, Some(&keypair), share) )
+        .map(|share_file| parse_share(share_file, Some(&nonce), &mut |_| Err( io::Error::other("not passphrase protected") ) ).unwrap() )
+        .collect()
+}
+
+fn verify(shf: &ShareFromFile) -> bool {
+    verify_share_signature(shf, shf.pub_key.as_ref().unwrap(), shf.signature.as_ref().unwrap()).is_ok()
+}
+
+fn time(rounds: u32, mut run: impl FnMut() -> bool) -> Duration { // the mean over `rounds` runs
+    let started = Instant::now();
+
+    for _ in 0..rounds {
+        assert!(run());
+    }
+
+    started.elapsed() / rounds
+}
+
+fn main() {
+    let shares = signed_shares();
+
+    let sequential = time(ROUNDS, || shares.iter().all(verify) );
+    let parallel = time(ROUNDS, || shares.par_iter().all(verify) );
+
+    println!("{} signed shares, {} thread(s)", SHARES, rayon::current_num_threads() );
+    println!("one at a time: {:>8.2} ms", sequential.as_secs_f64() * 1000.0 );
+    println!("in parallel:   {:>8.2} ms ({:.1}x)", parallel.as_secs_f64() * 1000.0, sequential.as_secs_f64() / parallel.as_secs_f64() );
+}
@@ -0,0 +1,30 @@
+// Throw arbitrary bytes at everything that parses untrusted input (file and share headers, shares, bundles,
+// metadata blocks, detached signatures) -- every one of them should hand back an Err, never panic
+//
+// cargo +nightly fuzz run parse_headers
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use zeroize::Zeroizing;
+
+use chachamir::{is_encrypted, parse_header_file, parse_header_share, parse_share, parse_share_bundle, parse_metadata, parse_detached_signature, KEY_LENGTH_BYTES};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = is_encrypted(data);
+    let _ = parse_header_file(data);
+    let _ = parse_header_share(data);
+    let _ = parse_metadata(data);
+    let _ = parse_detached_signature(data);
+
+    // what share_from_file hands over once it's read a share file (a fixed key stands in for the passphrase)
+    let mut wrapping_key = |_: &[u8]| Ok( Zeroizing::new( vec![0u8; KEY_LENGTH_BYTES] ) );
+    let _ = parse_share(data.to_vec(), None, &mut wrapping_key);
+
+    if let Ok(shares) = parse_share_bundle(data) {
+        for share in shares {
+            let _ = parse_share(share.to_vec(), None, &mut wrapping_key);
+        }
+    }
+});
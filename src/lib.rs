@@ -0,0 +1,2421 @@
+//! ChaCha20-based file encryption, utilising Shamir's Secret Sharing to distribute its key.
+//!
+//! This is the core of the `chachamir` binary -- header construction/parsing, share splitting and
+//! recovery, and the (streamed) ChaCha20-Poly1305 encryption itself. Nothing in here prints or exits;
+//! everything hands back a `Result` for the caller to deal with.
+
+// ---------
+// deps & crates
+// ---------
+
+extern crate chacha20poly1305; // chacha20 implementation
+extern crate argon2; // Argon2id (deriving keys from passphrases)
+extern crate blake3; // BLAKE3 (optional plaintext checksums)
+extern crate ed25519_dalek; // ed25519 (share integrity)
+extern crate hex; // Hex stuff (for saved keypairs)
+extern crate rand; // RNG (for passphrase salts and nonces)
+extern crate sha2; // SHA-512 (prehashing streamed files for signing)
+extern crate sharks; // Shamir's Secret Sharing
+extern crate subtle; // Constant-time comparisons (of plaintext and key material)
+extern crate zeroize; // Wiping key material from memory
+extern crate zstd; // zstd (optional compression before encryption)
+#[cfg(feature = "test-rng")] extern crate rand_chacha; // Seeded RNG (test-rng only)
+#[cfg(feature = "test-rng")] extern crate rand_chacha_sharks; // and the same again for sharks' rand
+
+// a predictable RNG has no business anywhere near real keys
+#[cfg(all(feature = "test-rng", not(debug_assertions)))]
+compile_error!("the test-rng feature is for tests only, and can't be enabled in release builds");
+
+// things from the stdlib
+use std::cell::Cell;
+use std::fs;
+use std::io::{Result, Error, ErrorKind};
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::io::{BufReader, BufWriter};
+use std::ops::Sub;
+use std::path::{Path, PathBuf};
+use std::str;
+#[cfg(feature = "test-rng")] use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// pulling from our crates
+use chacha20poly1305::aead::{Aead, AeadInPlace, NewAead, Payload};
+use chacha20poly1305::aead::consts::U5;
+use chacha20poly1305::aead::generic_array::{ArrayLength, GenericArray};
+use chacha20poly1305::aead::stream::{EncryptorBE32, DecryptorBE32};
+use chacha20poly1305::{ChaCha20Poly1305, XChaCha20Poly1305, Key, Nonce};
+
+use argon2::Argon2;
+
+use ed25519_dalek::{Keypair, Signature, SignatureError, Signer, Verifier, PublicKey, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+#[cfg(feature = "test-rng")] use rand::SeedableRng;
+
+#[cfg(feature = "test-rng")] use rand_chacha::ChaCha20Rng;
+
+use sha2::{Digest, Sha512};
+
+use sharks::{ Sharks, Share };
+
+use subtle::ConstantTimeEq;
+
+use zeroize::{Zeroize, Zeroizing};
+
+/*----------+
+| constants |
+-----------*/
+
+// algorithm version (used for major changes to enc/dec algo -- added to file headers)
+// v1 = whole file in memory, no flags byte; v2 = flags byte added (chunked streaming); v3 = XChaCha20 (24 byte nonce);
+// v4 = header authenticated as associated data; v5 = chunk size stored in the header;
+// v6 = optional plaintext checksum in the header, and keys split between more than one share scheme;
+// v7 = cipher named in its own header byte (rather than implied by the version); v8 = optional label in the header, and MACs in shares;
+// v9 = is-signed byte used as bits, one of which says the contents are bound to associated data of the user's own
+pub const ALGO_VERSION: u8 = 9;
+// first version whose file header is bound into the AEAD
+pub const ALGO_VERSION_HEADER_AAD: u8 = 4;
+// first version whose file header says how big its chunks are (always STREAM_CHUNK_BYTES before)
+pub const ALGO_VERSION_CHUNK_SIZE: u8 = 5;
+// first version whose file header can carry a checksum of the plaintext
+pub const ALGO_VERSION_CHECKSUM: u8 = 6;
+// first version whose key can be split between more than one share scheme
+pub const ALGO_VERSION_SCHEMES: u8 = 6;
+// first version whose file header says which cipher it was encrypted with
+pub const ALGO_VERSION_CIPHER: u8 = 7;
+// first version whose file header can carry a label
+pub const ALGO_VERSION_LABEL: u8 = 8;
+// first version whose shares can carry a MAC under the key they recover
+pub const ALGO_VERSION_SHARE_MAC: u8 = 8;
+// first version whose contents can be bound to associated data the user gave (--aad)
+pub const ALGO_VERSION_AAD: u8 = 9;
+// key length in bytes (can only be a 256-bit key for chacha20)
+pub const KEY_LENGTH_BYTES: usize = 32;
+// nonce length in bytes (XChaCha20, v3 onwards)
+pub const NONCE_LENGTH_BYTES: usize = 24;
+// nonce length in bytes for v1 and v2 (ChaCha20)
+pub const NONCE_LENGTH_BYTES_V2: usize = 12;
+// nonce length in bytes for wrapping passphrase protected shares (ChaCha20; fresh key every encryption)
+pub const WRAP_NONCE_LENGTH_BYTES: usize = 12;
+// Poly1305 tag length in bytes (appended to every encrypted chunk)
+pub const TAG_LENGTH_BYTES: usize = 16;
+// the STREAM construction eats 5 bytes of the nonce for its counter + last block flag
+pub const STREAM_NONCE_OVERHEAD_BYTES: usize = 5;
+// plaintext bytes per chunk when streaming (64 KiB; the default from v5, and the only option before)
+pub const STREAM_CHUNK_BYTES: usize = 64 * 1024;
+// smallest and largest chunk sizes we'll use or accept (4 KiB - 16 MiB, powers of two only)
+pub const STREAM_CHUNK_BYTES_MIN: usize = 4 * 1024;
+pub const STREAM_CHUNK_BYTES_MAX: usize = 16 * 1024 * 1024;
+// the STREAM construction's 32-bit counter runs out after this many chunks
+pub const STREAM_MAX_CHUNKS: u64 = u32::MAX as u64;
+// salt length in bytes for passphrase-derived keys
+pub const SALT_LENGTH_BYTES: usize = 16;
+// plaintext checksum length in bytes (BLAKE3)
+pub const CHECKSUM_LENGTH_BYTES: usize = 32;
+// share MAC length in bytes (keyed BLAKE3)
+pub const SHARE_MAC_LENGTH_BYTES: usize = 32;
+// public key fingerprint length in bytes (of the key's BLAKE3 hash)
+pub const FINGERPRINT_LENGTH_BYTES: usize = 8;
+// longest label a file header can carry (its length is stored in one byte)
+pub const LABEL_MAX_LENGTH_BYTES: usize = 255;
+// longest secret that can be split on its own, rather than encrypted (its length is stored in one byte)
+pub const SECRET_MAX_LENGTH_BYTES: usize = 255;
+
+/*-----------------+
+| file header crap |
+-------------------*/
+pub const HEADER_FILE: [u8; 3] = [67, 67, 77]; // "CCM"
+pub const HEADER_SHARE: [u8; 4] = [67, 67, 77, 83]; // "CCMS"
+pub const HEADER_SIG: [u8; 4] = [67, 67, 77, 71]; // "CCMG" (detached signatures)
+pub const HEADER_BUNDLE: [u8; 4] = [67, 67, 77, 66]; // "CCMB" (share bundles)
+
+// number of bytes before nonce in header(s)
+pub const HEADER_PRE_NONCE_BYTES_FILE: usize = 9;
+pub const HEADER_PRE_NONCE_BYTES_FILE_V6: usize = 8;
+pub const HEADER_PRE_NONCE_BYTES_FILE_V4: usize = 7;
+pub const HEADER_PRE_NONCE_BYTES_FILE_V1: usize = 6;
+pub const HEADER_PRE_NONCE_BYTES_SHARE: usize = 7;
+
+// location of the is_signed bool
+pub const HEADER_IS_SIGNED_BYTE_FILE: usize = 6;
+pub const HEADER_IS_SIGNED_BYTE_SHARE: usize = 7;
+
+// bits of a file's is_signed byte (v9 onwards; before that it's only ever 0 or 1)
+pub const IS_SIGNED_BIT: u8 = 0b0000_0001; // file is signed
+pub const HAS_AAD_BIT: u8 = 0b0000_0010; // contents are also authenticated with associated data the user gave, which isn't stored anywhere
+
+// location of the flags byte (files v2 onwards; shares have theirs at the end of the header, and used it as padding before they had flags)
+pub const HEADER_FLAGS_BYTE_FILE: usize = 7;
+
+// location of the chunk size byte (files v5 onwards)
+pub const HEADER_CHUNK_SIZE_BYTE_FILE: usize = 8;
+
+// location of the cipher byte (files v7 onwards)
+pub const HEADER_CIPHER_BYTE_FILE: usize = 9;
+
+// flag bits
+pub const FLAG_STREAMED: u8 = 0b0000_0001; // content is split into chunks, each with its own tag
+pub const FLAG_COMPRESSED: u8 = 0b0000_0010; // plaintext was compressed with zstd before encryption
+pub const FLAG_METADATA: u8 = 0b0000_0100; // plaintext starts with a metadata block (original filename, size, mtime)
+pub const FLAG_CHECKSUM: u8 = 0b0000_1000; // header carries a BLAKE3 checksum of the plaintext after the nonce (v6 onwards)
+pub const FLAG_SCHEMES: u8 = 0b0001_0000; // key was split between more than one share scheme; their count follows the nonce (and checksum) (v6 onwards)
+pub const FLAG_LABEL: u8 = 0b0010_0000; // header carries a label after everything else, before any public key (v8 onwards)
+pub const FLAG_SHARES_UNSIGNED: u8 = 0b0100_0000; // file is signed, but its shares were left unsigned on purpose
+pub const FLAG_ARCHIVE: u8 = 0b1000_0000; // plaintext is an archive of several files (see `ArchiveReader`), rather than the one file
+
+// share flag bits
+pub const SHARE_FLAG_PASSPHRASE: u8 = 0b0000_0001; // share content is wrapped under a passphrase-derived key; salt follows the header
+pub const SHARE_FLAG_SCHEME: u8 = 0b0000_0010; // share belongs to one of several schemes; its number follows the header, before any salt (v6 onwards)
+pub const SHARE_FLAG_MAC: u8 = 0b0000_0100; // share content is followed by a MAC of the share under the key it recovers (v8 onwards)
+pub const SHARE_FLAG_FILE_UNSIGNED: u8 = 0b0000_1000; // share is signed, but its file was left unsigned on purpose
+pub const SHARE_FLAG_SECRET: u8 = 0b0001_0000; // share is of a secret split on its own (see `split`), not a file's key; its length follows the header, before any scheme number
+
+/* FILE HEADER STRUCTURE
+
+Files (33 bytes w/o checksum, public key and sig)
+43 43 4D VV TT SS FF CS AL NN*24
+(32 byte BLAKE3 checksum of the plaintext, if FF has FLAG_CHECKSUM; v6 onwards)
+(1 byte number of share schemes, if FF has FLAG_SCHEMES; v6 onwards)
+(LL label, if FF has FLAG_LABEL; v8 onwards)
+(32 byte public key)
+(64 byte signature)
+content
+
+Version 5 and 6 files (32 bytes w/o checksum, public key and sig) lack the AL byte, and version 5 files never have a checksum or scheme count
+43 43 4D VV TT SS FF CS NN*24
+
+Version 3 and 4 files (31 bytes w/o public key and sig) lack the CS byte
+43 43 4D VV TT SS FF NN*24
+
+Version 2 files (19 bytes w/o public key and sig) have a 12 byte nonce
+43 43 4D VV TT SS FF NN*12
+
+Version 1 files (18 bytes w/o public key and sig) also lack the FF byte
+43 43 4D VV TT SS NN*12
+
+Shares (32 bytes w/o salt, public key and sig; 20 bytes before v3, with a 12 byte nonce)
+43 43 4D 53 VV TT SS NN*24 FF
+(1 byte length of the secret, if FF has SHARE_FLAG_SECRET)
+(1 byte scheme number, counting from 1, if FF has SHARE_FLAG_SCHEME; v6 onwards)
+(16 byte salt, if passphrase protected)
+(32 byte public key)
+(64 byte signature)
+content
+(32 byte MAC, if FF has SHARE_FLAG_MAC; v8 onwards)
+
+VV = version
+TT = threshold (of the first scheme, for files with FLAG_SCHEMES)
+SS = is signed? (files from v9 onwards: IS_SIGNED_BIT, and HAS_AAD_BIT if there's associated data of the user's own)
+FF = flags
+CS = chunk size, as a power of two (i.e. chunks are 2^CS bytes of plaintext; 12-24)
+AL = cipher (1 = XChaCha20-Poly1305; see Cipher)
+NN = nonce bytes
+LL = length of the label that follows (0-255 bytes of UTF-8, no control characters)
+
+From version 7 the AL byte says which cipher the contents are encrypted with; before that, version 3 onwards
+uses XChaCha20-Poly1305 and earlier versions use ChaCha20-Poly1305.
+
+From version 4, the file header (w/o public key and sig) is the associated data for every chunk, so changing
+the version, threshold, flags, chunk size, cipher, nonce, checksum or label makes decryption fail, signed or not. The header can't be changed
+without re-encrypting the contents under a fresh nonce.
+
+From version 9, a file can also be bound to associated data the user gave when encrypting it (e.g. "hostname=vault01"),
+which is appended to the header in the associated data. It isn't stored, so it has to be given again to decrypt the file;
+HAS_AAD_BIT only says that it's needed.
+
+A key split between several share schemes is dealt out separately for each of them, with its own players and threshold,
+so any one complete scheme recovers it. Each share records which scheme it came from; shares without one are from the first.
+
+A share's MAC is a keyed BLAKE3 hash of the share (unwrapped, if passphrase protected) under a key derived from the file's key
+with SHARE_MAC_CONTEXT. It can only be checked once the key has been recovered, but then it shows which shares really
+belong to the file, signed or not. A share's signature covers its MAC.
+
+Passphrase protected share content is a 12 byte nonce followed by the share, encrypted with
+ChaCha20-Poly1305 under an Argon2id key derived from the passphrase and salt.
+
+Files with metadata have a block prepended to their plaintext before anything else happens to it
+(so it's encrypted and authenticated along with everything else):
+LL*4 NL*2 name SZ*8 MT*8
+
+LL = length of the rest of the block (big endian)
+NL = length of the original filename (big endian, UTF-8)
+SZ = original size in bytes (big endian)
+MT = original modification time, in seconds since the Unix epoch (big endian)
+
+The checksum covers the original plaintext only (not the metadata block, and before compression), so it can be
+checked against the decrypted file as it's written.
+
+Compressed files have their plaintext run through zstd first; everything else (chunking, signatures) applies to the compressed data.
+
+Streamed content is a series of (chunk size + 16 byte tag) chunks, with a shorter final chunk. The chunk size is
+STREAM_CHUNK_BYTES before v5. The STREAM construction counts chunks in 32 bits, so a file can't have more than
+STREAM_MAX_CHUNKS of them.
+Signatures over streamed files are ed25519ph (SHA-512 prehashed) so the file never has to be held in memory.
+
+Detached signatures (100 bytes, in {name}.ccm.sig) leave the file itself byte-for-byte as it would be unsigned
+43 43 4D 47
+(32 byte public key)
+(64 byte signature)
+
+The signature is ed25519ph over the public key followed by the whole encrypted file, with DETACHED_SIG_CONTEXT as its context.
+
+Share bundles (.ccmbundle) are complete share files end to end, each after its length (big endian), for moving a whole set around as one file
+43 43 4D 42
+(LL*2 share)*
+*/
+
+// number of bytes total in header(s) before the signature or public key
+pub const HEADER_LENGTH_FILE: usize = HEADER_FILE.len() + 1 + 1 + 1 + 1 + 1 + 1 + NONCE_LENGTH_BYTES; // 33 bytes
+pub const HEADER_LENGTH_FILE_V6: usize = HEADER_LENGTH_FILE - 1; // 32 bytes (v5 too)
+pub const HEADER_LENGTH_FILE_V4: usize = HEADER_LENGTH_FILE_V6 - 1; // 31 bytes (v3 too)
+pub const HEADER_LENGTH_FILE_V2: usize = HEADER_FILE.len() + 1 + 1 + 1 + 1 + NONCE_LENGTH_BYTES_V2; // 19 bytes
+pub const HEADER_LENGTH_FILE_V1: usize = HEADER_LENGTH_FILE_V2 - 1; // 18 bytes
+pub const HEADER_LENGTH_SHARE: usize = HEADER_SHARE.len() + 1 + 1 + 1 + NONCE_LENGTH_BYTES + 1; // 32 bytes
+pub const HEADER_LENGTH_SHARE_V2: usize = HEADER_SHARE.len() + 1 + 1 + 1 + NONCE_LENGTH_BYTES_V2 + 1; // 20 bytes (v1 too)
+
+// largest possible header(s), incl. everything optional
+pub const HEADER_MAX_LENGTH_FILE: usize = HEADER_LENGTH_FILE + CHECKSUM_LENGTH_BYTES + 1 + 1 + LABEL_MAX_LENGTH_BYTES + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH;
+pub const HEADER_MAX_LENGTH_SHARE: usize = HEADER_LENGTH_SHARE + 1 + 1 + SALT_LENGTH_BYTES + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH;
+
+// a share's payload, straight after its header: the x byte and y (the length of the key), or those wrapped under a passphrase
+pub const SHARE_DATA_LENGTH_BYTES: usize = 1 + KEY_LENGTH_BYTES;
+pub const SHARE_WRAPPED_LENGTH_BYTES: usize = WRAP_NONCE_LENGTH_BYTES + SHARE_DATA_LENGTH_BYTES + TAG_LENGTH_BYTES;
+
+// largest possible share file: the largest header, then a share of the longest secret (longer than a passphrase-wrapped key share and its MAC)
+pub const SHARE_MAX_LENGTH: usize = HEADER_MAX_LENGTH_SHARE + 1 + SECRET_MAX_LENGTH_BYTES;
+
+// whitespace a share file may pick up after its payload (a newline from a text editor, CRLFs from text-mode FTP) and still be read
+pub const SHARE_TRAILING_MAX_BYTES: usize = 8;
+
+// largest possible share bundle: a full set of the largest possible shares
+pub const SHARE_BUNDLE_MAX_LENGTH: usize = HEADER_BUNDLE.len() + 255 * (2 + SHARE_MAX_LENGTH);
+
+// detached signatures are always the same length
+pub const DETACHED_SIG_LENGTH: usize = HEADER_SIG.len() + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH; // 100 bytes
+
+// ed25519ph context for detached signatures, so one can never pass for an embedded signature (or vice versa)
+pub const DETACHED_SIG_CONTEXT: &[u8] = b"chachamir detached signature";
+
+// BLAKE3 key derivation context for share MAC keys
+pub const SHARE_MAC_CONTEXT: &str = "chachamir 2026-10 share MAC";
+
+// largest possible metadata block (after its length), i.e. with a filename of u16::MAX bytes
+const METADATA_MAX_LENGTH: usize = 2 + u16::MAX as usize + 8 + 8;
+
+/*---------+
+| structs  |
+----------*/
+
+/// Info we retrieve from a file header
+pub struct FileHeader {
+    pub algo_version: u8,
+    pub threshold: u8,
+    pub is_signed: bool,
+    pub flags: u8,
+    pub chunk_size: usize, // plaintext bytes per chunk (STREAM_CHUNK_BYTES before v5)
+    pub cipher: Cipher, // implied by the version before v7
+    pub nonce: Vec<u8>,
+    pub checksum: Option<Vec<u8>>, // BLAKE3 of the plaintext, if FLAG_CHECKSUM (v6 onwards)
+    pub schemes: Option<u8>, // number of share schemes, if FLAG_SCHEMES (v6 onwards)
+    pub label: Option<String>, // if FLAG_LABEL (v8 onwards)
+    pub has_aad: bool, // HAS_AAD_BIT (v9 onwards): the contents are bound to associated data of the user's own
+    pub aad: Option<Vec<u8>>, // that associated data, which is never stored -- fill it in before decrypting, if has_aad
+    pub pub_key_bytes: Vec<u8>, // empty if unsigned
+    pub signature_bytes: Vec<u8>, // empty if unsigned
+    pub length: usize, // total header length, i.e. where the contents start
+}
+
+/// Info we retrieve from a share header
+pub struct ShareHeader {
+    pub algo_version: u8,
+    pub threshold: u8,
+    pub is_signed: bool,
+    pub flags: u8,
+    pub nonce: Vec<u8>,
+    pub scheme: Option<u8>, // only if SHARE_FLAG_SCHEME
+    pub salt: Option<Vec<u8>>, // only if passphrase protected
+    pub has_mac: bool, // SHARE_FLAG_MAC (v8 onwards): the share is followed by its MAC
+    pub file_unsigned: bool, // SHARE_FLAG_FILE_UNSIGNED: only the share is signed, not its file
+    pub secret_length: Option<usize>, // only if SHARE_FLAG_SECRET
+    pub pub_key_bytes: Vec<u8>, // empty if unsigned
+    pub signature_bytes: Vec<u8>, // empty if unsigned
+    pub length: usize, // total header length, i.e. where the share starts
+}
+
+/// Info we retrieve from a share file
+pub struct ShareFromFile {
+    pub algo_version: u8,
+    pub threshold: u8,
+    pub is_signed: bool,
+    pub nonce: Vec<u8>,
+    pub scheme: Option<u8>, // only if SHARE_FLAG_SCHEME (i.e. None is the first scheme)
+    pub salt: Option<Vec<u8>>, // only if passphrase protected
+    pub pub_key: Option<PublicKey>,
+    pub signature: Option<Signature>,
+    pub payload: Zeroizing<Vec<u8>>, // share content as it was stored (i.e. still wrapped, if passphrase protected)
+    pub mac: Option<Vec<u8>>, // only if SHARE_FLAG_MAC
+    pub file_unsigned: bool, // SHARE_FLAG_FILE_UNSIGNED
+    pub secret_length: Option<usize>, // only if SHARE_FLAG_SECRET (i.e. None is a file's key)
+    pub share_data: Share,
+}
+
+/// AEAD the contents of a file are encrypted with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cipher {
+    ChaCha20Poly1305, // 12 byte nonce (v1 and v2)
+    XChaCha20Poly1305, // 24 byte nonce (v3 onwards)
+}
+
+impl Cipher {
+    /// Ciphers new files can be encrypted with
+    pub const SUPPORTED: [Cipher; 1] = [Cipher::XChaCha20Poly1305];
+
+    /// Name on the command line (and in `info`)
+    pub fn name(self) -> &'static str {
+        match self {
+            Cipher::ChaCha20Poly1305 => "chacha20poly1305",
+            Cipher::XChaCha20Poly1305 => "xchacha20poly1305",
+        }
+    }
+
+    /// Look up one of the SUPPORTED ciphers by name (case insensitive)
+    pub fn from_name(name: &str) -> Option<Cipher> {
+        Cipher::SUPPORTED.into_iter().find(|cipher| cipher.name().eq_ignore_ascii_case(name) )
+    }
+
+    /// Cipher implied by an algorithm version from before the header had an AL byte
+    pub fn for_version(algo_version: u8) -> Cipher {
+        match algo_version {
+            1 | 2 => Cipher::ChaCha20Poly1305,
+            _ => Cipher::XChaCha20Poly1305,
+        }
+    }
+
+    pub fn nonce_length(self) -> usize {
+        match self {
+            Cipher::ChaCha20Poly1305 => NONCE_LENGTH_BYTES_V2,
+            Cipher::XChaCha20Poly1305 => NONCE_LENGTH_BYTES,
+        }
+    }
+
+    fn header_byte(self) -> u8 { // AL byte (v7 onwards)
+        match self {
+            Cipher::ChaCha20Poly1305 => 0,
+            Cipher::XChaCha20Poly1305 => 1,
+        }
+    }
+
+    fn from_header_byte(byte: u8) -> Option<Cipher> {
+        match byte {
+            0 => Some(Cipher::ChaCha20Poly1305),
+            1 => Some(Cipher::XChaCha20Poly1305),
+            _ => None,
+        }
+    }
+}
+
+/// Original file details, stored in the metadata block at the start of the plaintext
+pub struct FileMetadata {
+    pub name: String, // filename only, no directories
+    pub size: u64,
+    pub mtime: u64, // seconds since the Unix epoch
+}
+
+/// How `encrypt_to_file` should treat the contents
+pub struct EncryptSettings {
+    pub compression_level: Option<i32>, // compress with zstd first (the header should have FLAG_COMPRESSED set)
+    pub aad: Option<Vec<u8>>, // associated data of the user's own to bind the contents to (the header should have HAS_AAD_BIT set)
+    pub verify: bool, // decrypt (and decompress) everything again as we go, to make sure it round-trips
+    pub verify_time: Cell<Duration>, // time spent decrypting chunks again to check them (added to as it goes)
+}
+
+/// Passes writes through to `inner` while hashing them (for ed25519ph signatures)
+pub struct DigestWriter<W: Write> {
+    pub inner: W,
+    pub digest: Sha512,
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.digest.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Passes reads through from `inner` while hashing them
+pub struct DigestReader<R: Read> {
+    pub inner: R,
+    pub digest: Sha512,
+}
+
+impl<R: Read> Read for DigestReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.digest.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+struct TeeReader<R: Read, W: Write> { // passes reads through from `inner`, copying them into `tee`
+    inner: R,
+    tee: W,
+}
+
+impl<R: Read, W: Write> Read for TeeReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.tee.write_all(&buf[..read])?;
+        Ok(read)
+    }
+}
+
+struct ChecksumWriter<W: Write> { // passes writes through to `inner`, hashing them with BLAKE3 if there's a checksum to check
+    inner: W,
+    hasher: Option<blake3::Hasher>,
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = self.inner.write(buf)?;
+
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(&buf[..written]);
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct MetadataSplitter<W: Write> { // takes the metadata block off the front of the plaintext, passing the rest through to `inner`
+    inner: W,
+    block: Vec<u8>,
+    metadata: Option<FileMetadata>,
+}
+
+impl<W: Write> Write for MetadataSplitter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.metadata.is_some() { // done with the block, the rest is the actual file
+            return self.inner.write(buf)
+        }
+
+        let wanted = match self.block.len() < 4 { // length first, then the block itself
+            true => 4 - self.block.len(),
+            false => {
+                let length = u32::from_be_bytes(self.block[..4].try_into().unwrap()) as usize;
+
+                if length > METADATA_MAX_LENGTH {
+                    return Err( Error::new(ErrorKind::InvalidData, "Metadata block is too long") )
+                }
+
+                4 + length - self.block.len()
+            }
+        };
+
+        let taken = wanted.min(buf.len());
+        self.block.extend_from_slice(&buf[..taken]);
+
+        if self.block.len() > 4 && self.block.len() == 4 + u32::from_be_bytes(self.block[..4].try_into().unwrap()) as usize {
+            self.metadata = Some( parse_metadata(&self.block[4..])? );
+        }
+
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A file to go in an archive, and the name it's stored (and unpacked) under -- relative, with `/` between directories
+#[derive(Clone)]
+pub struct ArchiveEntry {
+    pub path: PathBuf,
+    pub name: String,
+}
+
+/// Reads a set of files as one archive, to encrypt in place of a single file (see FLAG_ARCHIVE)
+///
+/// Each file becomes its name's length (u16) and name, its length (u64), then its contents. Files are opened one at a
+/// time as they're reached, and each is taken at the length it had when it was opened -- one that shrinks while it's
+/// being read is an error, rather than an entry shorter than its length says
+pub struct ArchiveReader {
+    entries: std::vec::IntoIter<ArchiveEntry>,
+    current: Option<(std::io::Cursor<Vec<u8>>, std::io::Take<fs::File>, PathBuf)>, // the entry's name and length, then its contents
+}
+
+impl ArchiveReader {
+    pub fn new(entries: Vec<ArchiveEntry>) -> ArchiveReader {
+        ArchiveReader { entries: entries.into_iter(), current: None }
+    }
+}
+
+impl Read for ArchiveReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0)
+        }
+
+        loop {
+            let (entry_header, contents, path) = match &mut self.current {
+                Some(current) => current,
+                None => {
+                    let entry = match self.entries.next() {
+                        Some(entry) => entry,
+                        None => return Ok(0), // that's everything
+                    };
+
+                    let opened = fs::File::open(&entry.path).and_then(|file| file.metadata().map(|metadata| (file, metadata.len()) ) );
+
+                    let (file, length) = match opened {
+                        Ok(opened) => opened,
+                        Err(error) => return Err( Error::new(error.kind(), format!("Could not read {} ({})", entry.path.display(), error)) ),
+                    };
+
+                    let entry_header = construct_archive_entry_header(&entry.name, length)?;
+                    self.current.insert( (std::io::Cursor::new(entry_header), file.take(length), entry.path) )
+                },
+            };
+
+            let read = entry_header.read(buf)?;
+
+            if read > 0 {
+                return Ok(read)
+            }
+
+            let read = contents.read(buf)?;
+
+            if read > 0 {
+                return Ok(read)
+            }
+
+            if contents.limit() > 0 {
+                return Err( Error::new(ErrorKind::UnexpectedEof, format!("{} got shorter while it was being read", path.display())) )
+            }
+
+            self.current = None;
+        }
+    }
+}
+
+/// Unpacks an archive into a directory as it's written (the other end of `ArchiveReader`)
+///
+/// Every file is created afresh: one that's already there (e.g. an entry named twice) is an error, never overwritten.
+/// Call `finish` once everything's been written, to catch an archive that ends partway through an entry
+pub struct ArchiveUnpacker {
+    dir: PathBuf,
+    entry_header: Vec<u8>, // the next entry's name and length, as it comes in
+    current: Option<(BufWriter<fs::File>, u64)>, // and the file it's being unpacked to, with how much is left to go in it
+    pub unpacked: Vec<PathBuf>, // every file created so far (each one new, so none of them were anybody else's)
+}
+
+impl ArchiveUnpacker {
+    pub fn new(dir: &Path) -> ArchiveUnpacker {
+        ArchiveUnpacker { dir: dir.to_path_buf(), entry_header: vec![], current: None, unpacked: vec![] }
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        match self.current.take() {
+            Some((_, remaining)) if remaining > 0 => Err( Error::new(ErrorKind::InvalidData, "Archive ended partway through a file") ),
+            Some((mut file, _)) => file.flush(),
+            None if !self.entry_header.is_empty() => Err( Error::new(ErrorKind::InvalidData, "Archive ended partway through a file's name") ),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Write for ArchiveUnpacker {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if let Some((file, remaining)) = &mut self.current {
+            if *remaining > 0 {
+                let written = file.write( &buf[..buf.len().min(*remaining as usize)] )?;
+                *remaining -= written as u64;
+                return Ok(written)
+            }
+
+            file.flush()?; // done with that one
+            self.current = None;
+        }
+
+        let wanted = match self.entry_header.len() < 2 { // name length, then the name and the file's length
+            true => 2 - self.entry_header.len(),
+            false => 2 + u16::from_be_bytes(self.entry_header[..2].try_into().unwrap()) as usize + 8 - self.entry_header.len(),
+        };
+
+        let taken = wanted.min(buf.len());
+        self.entry_header.extend_from_slice(&buf[..taken]);
+
+        if self.entry_header.len() >= 2 + 8 && self.entry_header.len() == 2 + u16::from_be_bytes(self.entry_header[..2].try_into().unwrap()) as usize + 8 {
+            let (name, length) = parse_archive_entry_header(&self.entry_header)?;
+            let path = archive_entry_path(&self.dir, &name)?;
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let file = fs::OpenOptions::new().write(true).create_new(true).open(&path)?;
+            self.unpacked.push(path);
+
+            self.current = Some( (BufWriter::new(file), length) );
+            self.entry_header.clear();
+        }
+
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match &mut self.current {
+            Some((file, _)) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/*---------+
+| headers  |
+----------*/
+
+/// Nonce length for a given algorithm version
+pub fn nonce_length(algo_version: u8) -> usize {
+    match algo_version {
+        1 | 2 => NONCE_LENGTH_BYTES_V2,
+        _ => NONCE_LENGTH_BYTES,
+    }
+}
+
+/// Length of a file header (w/o public key and sig) for a given algorithm version and flags (incl. the checksum and scheme count, if there are any)
+///
+/// A label's length byte is counted, but not the label itself; see `file_header_length` for that
+pub fn header_length_file(algo_version: u8, flags: u8) -> usize {
+    let checksum_length = match has_checksum(algo_version, flags) {
+        true => CHECKSUM_LENGTH_BYTES,
+        false => 0,
+    };
+
+    let schemes_length = match has_schemes(algo_version, flags) {
+        true => 1,
+        false => 0,
+    };
+
+    let label_length = match has_label(algo_version, flags) {
+        true => 1,
+        false => 0,
+    };
+
+    checksum_length + schemes_length + label_length + match algo_version {
+        1 => HEADER_LENGTH_FILE_V1,
+        2 => HEADER_LENGTH_FILE_V2,
+        3 | 4 => HEADER_LENGTH_FILE_V4,
+        5 | 6 => HEADER_LENGTH_FILE_V6,
+        _ => HEADER_LENGTH_FILE,
+    }
+}
+
+fn has_checksum(algo_version: u8, flags: u8) -> bool { // only v6 onwards know about FLAG_CHECKSUM
+    algo_version >= ALGO_VERSION_CHECKSUM && flags & FLAG_CHECKSUM != 0
+}
+
+fn has_schemes(algo_version: u8, flags: u8) -> bool { // likewise FLAG_SCHEMES
+    algo_version >= ALGO_VERSION_SCHEMES && flags & FLAG_SCHEMES != 0
+}
+
+fn has_label(algo_version: u8, flags: u8) -> bool { // and v8 onwards about FLAG_LABEL
+    algo_version >= ALGO_VERSION_LABEL && flags & FLAG_LABEL != 0
+}
+
+fn file_header_length(header: &[u8], algo_version: u8, flags: u8) -> Option<usize> { // incl. the label, if there's enough of the header to say how long it is
+    let header_length = header_length_file(algo_version, flags);
+
+    if header.len() < header_length {
+        return None
+    }
+
+    match has_label(algo_version, flags) {
+        true => Some( header_length + header[header_length - 1] as usize ), // its length byte is last
+        false => Some(header_length),
+    }
+}
+
+/// Checks a label can be stored in a (v8 onwards) file header: at most LABEL_MAX_LENGTH_BYTES of UTF-8, with no control characters
+pub fn check_label(label: &str) -> Result<()> {
+    if label.len() > LABEL_MAX_LENGTH_BYTES {
+        return Err( Error::other( format!("Label is {} bytes long (at most {})", label.len(), LABEL_MAX_LENGTH_BYTES) ) )
+    }
+
+    if label.chars().any(char::is_control) {
+        return Err( Error::other("Label contains control characters") )
+    }
+
+    Ok(())
+}
+
+/// Length of a share header (w/o salt, public key and sig) for a given algorithm version
+pub fn header_length_share(algo_version: u8) -> usize {
+    match algo_version {
+        1 | 2 => HEADER_LENGTH_SHARE_V2,
+        _ => HEADER_LENGTH_SHARE,
+    }
+}
+
+/// Number of bytes before the nonce in a file header for a given algorithm version
+pub fn header_pre_nonce_bytes_file(algo_version: u8) -> usize {
+    match algo_version {
+        1 => HEADER_PRE_NONCE_BYTES_FILE_V1,
+        2..=4 => HEADER_PRE_NONCE_BYTES_FILE_V4,
+        5 | 6 => HEADER_PRE_NONCE_BYTES_FILE_V6,
+        _ => HEADER_PRE_NONCE_BYTES_FILE,
+    }
+}
+
+/// Associated data the contents of a file are authenticated with (the header w/o public key and sig; nothing before v4)
+///
+/// From v9 the user's own associated data follows, if the header says there is some; it's an `ErrorKind::InvalidInput` error
+/// if it hasn't been filled in (see `FileHeader::aad`)
+pub fn header_aad(header: &FileHeader) -> Result<Vec<u8>> {
+    let mut aad = match header.algo_version >= ALGO_VERSION_HEADER_AAD {
+        true => construct_header_file(header.algo_version, header.threshold, header.is_signed, header.has_aad, header.flags, header.chunk_size, header.cipher, &header.nonce, header.checksum.as_deref(), header.schemes, header.label.as_deref()),
+        false => vec![],
+    };
+
+    if header.has_aad {
+        match &header.aad {
+            Some(user_aad) => aad.extend(user_aad),
+            None => return Err( Error::new(ErrorKind::InvalidInput, "File is bound to associated data of its own, which has to be given again to decrypt it") ),
+        }
+    }
+
+    Ok(aad)
+}
+
+fn signed_byte_bits(algo_version: u8, signed_byte: u8) -> (bool, bool) { // is_signed byte -> (is signed?, has AAD?)
+    match algo_version >= ALGO_VERSION_AAD {
+        true => (signed_byte & IS_SIGNED_BIT != 0, signed_byte & HAS_AAD_BIT != 0),
+        false => (signed_byte != 0, false),
+    }
+}
+
+/// Checks a chunk size can be stored in a (v5 onwards) header: a power of two, STREAM_CHUNK_BYTES_MIN - STREAM_CHUNK_BYTES_MAX
+pub fn check_chunk_size(chunk_size: usize) -> Result<()> {
+    if !chunk_size.is_power_of_two() {
+        return Err( Error::other( format!("Chunk size {} is not a power of two", chunk_size) ) )
+    }
+
+    if !(STREAM_CHUNK_BYTES_MIN..=STREAM_CHUNK_BYTES_MAX).contains(&chunk_size) {
+        return Err( Error::other( format!("Chunk size {} is outside {} - {} bytes", chunk_size, STREAM_CHUNK_BYTES_MIN, STREAM_CHUNK_BYTES_MAX) ) )
+    }
+
+    Ok(())
+}
+
+fn chunk_size_from_header_byte(exponent: u8) -> Result<usize> { // CS byte -> chunk size, refusing anything we wouldn't have written
+    let chunk_size = 1usize.checked_shl(exponent as u32).unwrap_or(0);
+
+    check_chunk_size(chunk_size).map_err(|_| Error::new(ErrorKind::InvalidData, "File header has an invalid chunk size") )?;
+
+    Ok(chunk_size)
+}
+
+fn cipher_from_header(algo_version: u8, header: &[u8]) -> Result<Cipher> { // AL byte -> cipher (or the one the version implies)
+    if algo_version < ALGO_VERSION_CIPHER {
+        return Ok( Cipher::for_version(algo_version) )
+    }
+
+    match Cipher::from_header_byte(header[HEADER_CIPHER_BYTE_FILE - 1]) {
+        Some(cipher) if cipher.nonce_length() == nonce_length(algo_version) => Ok(cipher),
+        Some(cipher) => Err( Error::new(ErrorKind::InvalidData, format!("File header names {}, which version {} files can't use", cipher.name(), algo_version) ) ),
+        None => Err( Error::new(ErrorKind::Unsupported, "File header names an unknown cipher; it may need a newer version of chachamir") ),
+    }
+}
+
+/// Most plaintext that can be streamed in chunks of `chunk_size` before the STREAM counter runs out
+pub fn max_stream_length(chunk_size: usize) -> u64 {
+    STREAM_MAX_CHUNKS.saturating_mul(chunk_size as u64)
+}
+
+fn check_algo_version(algo_version: u8, what: &str) -> Result<()> { // refuse headers from a newer version, rather than guess at their layout
+    if algo_version == 0 { // there's never been a version 0, and it'd be laid out like the newest one
+        return Err( Error::new(ErrorKind::InvalidData, format!("This {} has an invalid algorithm version (0)", what) ) )
+    }
+
+    if algo_version > ALGO_VERSION {
+        return Err( Error::new(ErrorKind::Unsupported, format!("This {} was written by a newer version of chachamir (v{}); please upgrade", what, algo_version) ) )
+    }
+
+    Ok(())
+}
+
+/// Checks if the target file is encrypted; returns header (incl. public key and sig) if it is
+///
+/// Files from a newer algorithm version than this one are refused with an `ErrorKind::Unsupported` error
+pub fn is_encrypted(file: &[u8]) -> Result<Vec<u8>> {
+    if file.len() < HEADER_LENGTH_FILE_V1 { // this is clearly not a CCM file and we will panic if we try to slice < header bytes
+        return Err( Error::other("File not encrypted (smaller than CCM header)") )
+    }
+
+    if file[0..HEADER_FILE.len()] != HEADER_FILE { // file is missing header
+        return Err( Error::other("File not encrypted (CCM header missing)") )
+    }
+
+    check_algo_version(file[HEADER_FILE.len()], "file")?;
+
+    let algo_version = file[HEADER_FILE.len()];
+
+    let flags = match algo_version { // version 1 files have no flags byte
+        1 => 0,
+        _ => file[HEADER_FLAGS_BYTE_FILE - 1],
+    };
+
+    // header length depends on algorithm version (and whether there's a checksum, label etc.)
+    let header_length = match file_header_length(file, algo_version, flags) {
+        Some(header_length) if file.len() >= header_length => header_length,
+        _ => return Err( Error::other("File not encrypted (smaller than CCM header)") ),
+    };
+
+    let (file_is_signed, _) = signed_byte_bits(algo_version, file[HEADER_IS_SIGNED_BYTE_FILE - 1]); // is the file signed?
+
+    // Returns full header if successful
+    if file_is_signed { // signed header
+        if file.len() < (header_length + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH) { // File claims to be signed but is too small
+            return Err( Error::other("File not encrypted (smaller than signed CCM header)") )
+        }
+
+        Ok( file[0..header_length + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH].to_vec() )
+    }
+    else { // unsigned header
+        Ok( file[0..header_length].to_vec() )
+    }
+}
+
+/// Parse the header at the start of an encrypted file
+pub fn parse_header_file(file: &[u8]) -> Result<FileHeader> {
+    let header = is_encrypted(file)?;
+
+    let algo_version: u8 = header[HEADER_FILE.len()]; // Algorithm version
+    let threshold: u8 = header[HEADER_FILE.len() + 1]; // Threshold
+
+    let flags: u8 = match algo_version { // Flags (version 1 files have none)
+        1 => 0,
+        _ => header[HEADER_FLAGS_BYTE_FILE - 1],
+    };
+
+    let header_length = file_header_length(&header, algo_version, flags).unwrap_or(header.len()); // is_encrypted has already checked it's all there
+    let pre_nonce_bytes = header_pre_nonce_bytes_file(algo_version);
+    let nonce_end = pre_nonce_bytes + nonce_length(algo_version);
+    let nonce: Vec<u8> = header[pre_nonce_bytes..nonce_end].to_vec(); // Nonce
+
+    let checksum: Option<Vec<u8>> = match has_checksum(algo_version, flags) { // Checksum (straight after the nonce, v6 onwards)
+        true => Some( header[nonce_end..(nonce_end + CHECKSUM_LENGTH_BYTES)].to_vec() ),
+        false => None,
+    };
+
+    let checksum_end = nonce_end + checksum.as_ref().map_or(0, |checksum| checksum.len() );
+
+    let schemes: Option<u8> = match has_schemes(algo_version, flags) { // Scheme count (after the nonce and checksum, v6 onwards)
+        true => Some( header[checksum_end] ),
+        false => None,
+    };
+
+    let schemes_end = checksum_end + schemes.map_or(0, |_| 1 );
+
+    let label: Option<String> = match has_label(algo_version, flags) { // Label (after everything else, v8 onwards)
+        true => {
+            let label = String::from_utf8( header[(schemes_end + 1)..header_length].to_vec() )
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "File header has a label that isn't UTF-8") )?;
+
+            check_label(&label).map_err(|_| Error::new(ErrorKind::InvalidData, "File header has an invalid label") )?;
+
+            Some(label)
+        },
+        false => None,
+    };
+
+    let chunk_size: usize = match algo_version >= ALGO_VERSION_CHUNK_SIZE { // Chunk size (fixed before v5)
+        true => chunk_size_from_header_byte(header[HEADER_CHUNK_SIZE_BYTE_FILE - 1])?,
+        false => STREAM_CHUNK_BYTES,
+    };
+
+    let cipher = cipher_from_header(algo_version, &header)?; // Cipher (implied by the version before v7)
+
+    let (is_signed, has_aad) = signed_byte_bits(algo_version, header[HEADER_IS_SIGNED_BYTE_FILE - 1]); // is the file signed? (and bound to the user's own AAD?)
+
+    let (pub_key_bytes, signature_bytes) = match is_signed { // is_encrypted has already checked these are all there
+        true => (
+            header[header_length..(header_length + PUBLIC_KEY_LENGTH)].to_vec(),
+            header[(header_length + PUBLIC_KEY_LENGTH)..(header_length + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH)].to_vec(),
+        ),
+        false => (vec![], vec![]),
+    };
+
+    Ok(FileHeader {
+        algo_version,
+        threshold,
+        is_signed,
+        flags,
+        chunk_size,
+        cipher,
+        nonce,
+        checksum,
+        schemes,
+        label,
+        has_aad,
+        aad: None,
+        pub_key_bytes,
+        signature_bytes,
+        length: header.len(),
+    })
+}
+
+/// Construct a file header (w/o public key and sig)
+///
+/// `chunk_size` must pass `check_chunk_size`; it's only stored from v5 (earlier versions always use STREAM_CHUNK_BYTES).
+/// `cipher` is only stored from v7 (earlier versions imply theirs, see `Cipher::for_version`).
+/// A `checksum` (from `plaintext_checksum`; v6 onwards) sets FLAG_CHECKSUM and goes after the nonce.
+/// A number of `schemes` (v6 onwards) sets FLAG_SCHEMES and goes after that; `threshold` is the first scheme's.
+/// A `label` (which must pass `check_label`; v8 onwards) sets FLAG_LABEL and goes last, after its length.
+/// `has_aad` (v9 onwards) sets HAS_AAD_BIT, saying the contents are bound to associated data of the user's own
+#[allow(clippy::too_many_arguments)]
+pub fn construct_header_file(algo_version: u8, threshold: u8, is_signed: bool, has_aad: bool, flags: u8, chunk_size: usize, cipher: Cipher, nonce: &[u8], checksum: Option<&[u8]>, schemes: Option<u8>, label: Option<&str> ) -> Vec<u8> {
+    let flags = match checksum {
+        Some(_) => flags | FLAG_CHECKSUM,
+        None => flags,
+    };
+
+    let flags = match schemes {
+        Some(_) => flags | FLAG_SCHEMES,
+        None => flags,
+    };
+
+    let flags = match label {
+        Some(_) => flags | FLAG_LABEL,
+        None => flags,
+    };
+
+    // header "CCM"
+    let mut file_header: Vec<u8> = HEADER_FILE.to_vec();
+    // algorithm version
+    file_header.push(algo_version);
+    // threshold
+    file_header.push(threshold);
+    // is signed? (and, version 9 onwards, bound to the user's own associated data?)
+    let mut signed_byte = match is_signed {
+        true => IS_SIGNED_BIT,
+        false => 0,
+    };
+
+    if has_aad && algo_version >= ALGO_VERSION_AAD {
+        signed_byte |= HAS_AAD_BIT;
+    }
+
+    file_header.push(signed_byte);
+
+    // flags (version 1 files have none)
+    if algo_version > 1 {
+        file_header.push(flags);
+    }
+
+    // chunk size (version 5 onwards)
+    if algo_version >= ALGO_VERSION_CHUNK_SIZE {
+        file_header.push(chunk_size.trailing_zeros() as u8);
+    }
+
+    // cipher (version 7 onwards)
+    if algo_version >= ALGO_VERSION_CIPHER {
+        file_header.push(cipher.header_byte());
+    }
+
+    // nonce
+    file_header.extend(nonce);
+
+    // checksum (version 6 onwards, if there is one)
+    if let Some(checksum) = checksum {
+        file_header.extend(checksum);
+    }
+
+    // number of share schemes (version 6 onwards, if there's more than one)
+    if let Some(schemes) = schemes {
+        file_header.push(schemes);
+    }
+
+    // label (version 8 onwards, if there is one)
+    if let Some(label) = label {
+        file_header.push(label.len() as u8);
+        file_header.extend(label.as_bytes());
+    }
+
+    file_header
+}
+
+/// Construct a share header (w/o public key and sig), incl. the scheme number if there's more than one scheme (v6 onwards)
+/// and the salt if shares are passphrase protected
+///
+/// `mac` (v8 onwards) sets SHARE_FLAG_MAC, saying the share content is followed by a MAC from `share_mac`
+///
+/// `file_unsigned` sets SHARE_FLAG_FILE_UNSIGNED, saying a signed share's file was left unsigned on purpose, and a
+/// `secret_length` (at most SECRET_MAX_LENGTH_BYTES) sets SHARE_FLAG_SECRET, for shares of a secret split on its own
+#[allow(clippy::too_many_arguments)]
+pub fn construct_header_share(algo_version: u8, threshold: u8, is_signed: bool, file_unsigned: bool, nonce: &[u8], scheme: Option<u8>, salt: Option<&[u8]>, mac: bool, secret_length: Option<usize> ) -> Vec<u8> {
+    let mut share_header: Vec<u8> = HEADER_SHARE.to_vec();
+    // algorithm version
+    share_header.push(algo_version);
+    // threshold
+    share_header.push(threshold);
+    // is signed?
+    if is_signed {
+        share_header.push(1);
+    }
+    else {
+        share_header.push(0);
+    }
+
+    // nonce
+    share_header.extend(nonce);
+
+    // flags
+    let mut flags: u8 = 0;
+
+    if salt.is_some() {
+        flags |= SHARE_FLAG_PASSPHRASE;
+    }
+
+    if scheme.is_some() {
+        flags |= SHARE_FLAG_SCHEME;
+    }
+
+    if mac {
+        flags |= SHARE_FLAG_MAC;
+    }
+
+    if file_unsigned {
+        flags |= SHARE_FLAG_FILE_UNSIGNED;
+    }
+
+    if secret_length.is_some() {
+        flags |= SHARE_FLAG_SECRET;
+    }
+
+    share_header.push(flags);
+
+    // secret length
+    if let Some(secret_length) = secret_length {
+        share_header.push(secret_length as u8);
+    }
+
+    // scheme number
+    if let Some(scheme) = scheme {
+        share_header.push(scheme);
+    }
+
+    // salt
+    if let Some(salt) = salt {
+        share_header.extend(salt);
+    }
+
+    share_header
+}
+
+/*------------+
+| randomness  |
+-------------*/
+
+// seeded stand-in for OsRng, once seed_test_rng has been called (test-rng only)
+#[cfg(feature = "test-rng")]
+static TEST_RNG: Mutex<Option<ChaCha20Rng>> = Mutex::new(None);
+
+/// Take keys, nonces, salts, keypairs and shares from a ChaCha20 RNG seeded with `seed` from now on, instead of the OS,
+/// so the same inputs give byte-for-byte the same outputs (test-rng builds only -- never for real files)
+#[cfg(feature = "test-rng")]
+pub fn seed_test_rng(seed: [u8; 32]) {
+    *TEST_RNG.lock().unwrap_or_else(|poisoned| poisoned.into_inner() ) = Some( ChaCha20Rng::from_seed(seed) );
+}
+
+/// Fill `buf` with random bytes from the OS (or the seeded test RNG, if there is one)
+pub fn fill_random(buf: &mut [u8]) {
+    #[cfg(feature = "test-rng")]
+    if let Some(rng) = TEST_RNG.lock().unwrap_or_else(|poisoned| poisoned.into_inner() ).as_mut() {
+        return rng.fill_bytes(buf)
+    }
+
+    OsRng.fill_bytes(buf);
+}
+
+/// Generate a new ed25519 signing keypair from the OS RNG (or the seeded test RNG, if there is one)
+pub fn generate_keypair() -> Keypair {
+    #[cfg(feature = "test-rng")]
+    if let Some(rng) = TEST_RNG.lock().unwrap_or_else(|poisoned| poisoned.into_inner() ).as_mut() {
+        return Keypair::generate(rng)
+    }
+
+    Keypair::generate(&mut OsRng)
+}
+
+/*--------+
+| shares  |
+---------*/
+
+/// Wipe a share's data from memory (sharks doesn't do this itself)
+pub fn zeroize_share(share: &mut Share) {
+    share.x.0.zeroize();
+    share.y.iter_mut().for_each(|y| y.0.zeroize() );
+}
+
+/// Check two shares are the same share, in constant time (unlike `==`, which stops at the first difference)
+pub fn shares_match(a: &Share, b: &Share) -> bool {
+    let a: Zeroizing<Vec<u8>> = Zeroizing::new( Vec::from(a) );
+    let b: Zeroizing<Vec<u8>> = Zeroizing::new( Vec::from(b) );
+
+    a.as_slice().ct_eq(b.as_slice()).into()
+}
+
+/// Split a key (or a secret of its own) into `players` shares, any `threshold` of which can recover it
+pub fn split_key_into_shares(key: &[u8], players: u8, threshold: u8) -> Zeroizing<Vec<Vec<u8>>> {
+    let sss = Sharks(threshold); // init sharks and set threshold
+
+    // sharks is on a newer rand than us, so its RNG is seeded from ours rather than shared
+    #[cfg(feature = "test-rng")]
+    if TEST_RNG.lock().unwrap_or_else(|poisoned| poisoned.into_inner() ).is_some() {
+        let mut sharks_seed = Zeroizing::new( [0u8; 32] );
+        fill_random(sharks_seed.as_mut_slice());
+
+        let mut rng = <rand_chacha_sharks::ChaCha20Rng as rand_chacha_sharks::rand_core::SeedableRng>::from_seed(*sharks_seed);
+        return collect_shares(sss.dealer_rng(key, &mut rng), players)
+    }
+
+    collect_shares(sss.dealer(key), players)
+}
+
+fn collect_shares(dealer: impl Iterator<Item = Share>, players: u8) -> Zeroizing<Vec<Vec<u8>>> { // take `players` shares from a dealer as bytes
+    // push all the generated shares into a 2d vector, wiping the originals as we go
+    let shares = dealer.take(<usize as From<u8>>::from(players) ).map(|mut s| {
+        let share_bytes = Vec::from(&s);
+        zeroize_share(&mut s);
+
+        share_bytes
+    }).collect();
+
+    Zeroizing::new(shares)
+}
+
+/// Recover a secret split on its own from shares, given the threshold they were split with
+///
+/// Nothing checks the secret -- shares from different splits of the same length combine into garbage, not an error
+pub fn recover_secret_from_shares(shares: &[Share], threshold: u8) -> Result<Zeroizing<Vec<u8>>> {
+    let sss = Sharks(threshold);
+    sss.recover(shares).map(Zeroizing::new).map_err(Error::other)
+}
+
+/// Recover a key from shares, given the threshold they were split with
+pub fn recover_key_from_shares(shares: &[Share], threshold: u8) -> Result<Zeroizing<Vec<u8>>> {
+    let key = recover_secret_from_shares(shares, threshold)?;
+
+    // shares from different splits can still be combined into *something*, just not a key we can use
+    check_key_length(&key).map_err(|_| Error::new(ErrorKind::InvalidData, format!("Recovered key is not {} bytes -- shares are incompatible", KEY_LENGTH_BYTES)) )?;
+
+    Ok(key)
+}
+
+/// Deal more shares from the same split as `shares` (at least `threshold` of them), one at each of `indices`
+///
+/// Any `threshold` shares pin down the polynomial the key was split with, so the new shares are points on it too, and
+/// combine with the old ones just as if they'd been dealt at the same time.
+pub fn extend_shares(shares: &[Share], threshold: u8, indices: &[u8]) -> Result<Zeroizing<Vec<Vec<u8>>>> {
+    let mut known: Vec<&Share> = vec![];
+
+    for share in shares { // the first `threshold` distinct shares are as good as any others
+        if known.len() < threshold as usize && known.iter().all(|known| known.x != share.x) {
+            known.push(share);
+        }
+    }
+
+    if threshold == 0 || known.len() < threshold as usize {
+        return Err( Error::new(ErrorKind::InvalidInput, format!("Need {} distinct shares to deal more (have {})", threshold, known.len())) )
+    }
+
+    if known.iter().any(|share| share.y.len() != known[0].y.len()) {
+        return Err( Error::new(ErrorKind::InvalidData, "Shares are different lengths -- they're not from the same split") )
+    }
+
+    // index 0 is where the key itself sits, and an index we already have would just be a copy
+    if let Some(index) = indices.iter().find(|&&index| index == 0 || known.iter().any(|share| share.x.0 == index)) {
+        return Err( Error::new(ErrorKind::InvalidInput, format!("Can't deal a new share at index {}", index)) )
+    }
+
+    let new_shares = indices.iter().map(|&x| {
+        // Lagrange basis for each known share at x (only the indices go into these, never any secret bytes)
+        let basis: Vec<u8> = known.iter().map(|share_i| {
+            known.iter()
+                .filter(|share_j| share_j.x != share_i.x)
+                .fold(1, |basis, share_j| gf256_mul(basis, gf256_mul(x ^ share_j.x.0, gf256_inv(share_i.x.0 ^ share_j.x.0))) )
+        }).collect();
+
+        let mut share_bytes = vec![x];
+
+        share_bytes.extend( (0..known[0].y.len()).map(|byte_i| {
+            known.iter().zip(&basis).fold(0, |y, (share_i, &basis)| y ^ gf256_mul(basis, share_i.y[byte_i].0) )
+        }) );
+
+        share_bytes
+    }).collect();
+
+    Ok( Zeroizing::new(new_shares) )
+}
+
+fn gf256_mul(a: u8, b: u8) -> u8 { // multiply in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1 (0x11d, the same field as sharks), without branching on either
+    let (mut a, mut b, mut product) = (a, b, 0u8);
+
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        a = (a << 1) ^ (0x1d & 0u8.wrapping_sub(a >> 7));
+        b >>= 1;
+    }
+
+    product
+}
+
+fn gf256_inv(a: u8) -> u8 { // a^254 is a^-1 in GF(2^8) (and 0 for 0)
+    let (mut result, mut square) = (1, a);
+
+    for bit in 0..8 {
+        if 254 & (1 << bit) != 0 {
+            result = gf256_mul(result, square);
+        }
+
+        square = gf256_mul(square, square);
+    }
+
+    result
+}
+
+/// Build a complete share file -- header, public key and signature (if signing), then the share itself
+pub fn construct_share(share_header: &[u8], keypair: Option<&Keypair>, share_data: &[u8]) -> Vec<u8> {
+    // we do not include the share number or totals as that is encoded within the share data itself,
+    // so just push the universal header and the share data
+    let mut share_full: Vec<u8> = share_header.to_vec();
+
+    if let Some(keypair) = keypair { // are we signing shares?
+        share_full.extend(keypair.public.to_bytes() );
+
+        // sign the contents of the header (incl public key) + share content
+        let mut share_signable = share_full.clone();
+        share_signable.extend(share_data);
+
+        let share_ed25519_signature: Signature = keypair.sign( &share_signable[..] );
+
+        // then add it to the file in between the header and contents
+        share_full.extend(share_ed25519_signature.to_bytes() );
+    }
+
+    // write share content in
+    share_full.extend(share_data);
+
+    share_full
+}
+
+/// Parse a share header (incl. salt, public key and sig if present) without touching the share itself
+pub fn parse_header_share(share: &[u8]) -> Result<ShareHeader> {
+    if share.len() < HEADER_LENGTH_SHARE_V2 { // this is clearly not a share and we will panic if we try to slice < header bytes
+        return Err( Error::other("Invalid share (file smaller than CCMS header)") )
+    }
+
+    if share[0..(HEADER_SHARE.len())] != HEADER_SHARE { // share is missing header
+        return Err( Error::other("Invalid share (CCMS header missing)") )
+    }
+
+    let algo_version = share[HEADER_SHARE.len()]; // algorithm version, according to this share
+    check_algo_version(algo_version, "share")?;
+
+    let mut length = header_length_share(algo_version); // header length depends on algorithm version
+
+    if share.len() < length {
+        return Err( Error::other("Invalid share (file smaller than CCMS header)") )
+    }
+
+    let threshold = share[HEADER_SHARE.len() + 1]; // threshold, according to this share
+    let is_signed = share[HEADER_IS_SIGNED_BYTE_SHARE - 1] != 0; // is this share signed?
+    let flags = share[length - 1]; // flags, at the end of the header (always 0 on older shares)
+    let nonce = share[HEADER_PRE_NONCE_BYTES_SHARE..(HEADER_PRE_NONCE_BYTES_SHARE + nonce_length(algo_version))].to_vec(); // share's nonce
+
+    let mut secret_length: Option<usize> = None;
+
+    // is this share of a secret split on its own?
+    if flags & SHARE_FLAG_SECRET != 0 {
+        if share.len() < (length + 1) {
+            return Err( Error::other("Invalid share (file smaller than CCMS header)") )
+        }
+
+        if share[length] == 0 { // nothing was split
+            return Err( Error::new(ErrorKind::InvalidData, "Invalid share (secret of 0 bytes)") )
+        }
+
+        secret_length = Some( share[length] as usize );
+        length += 1;
+    }
+
+    let mut scheme: Option<u8> = None;
+
+    // is this share from one of several schemes?
+    if algo_version >= ALGO_VERSION_SCHEMES && flags & SHARE_FLAG_SCHEME != 0 {
+        if share.len() < (length + 1) {
+            return Err( Error::other("Invalid share (file smaller than CCMS header)") )
+        }
+
+        scheme = Some( share[length] );
+        length += 1;
+    }
+
+    let mut salt: Option<Vec<u8>> = None;
+
+    // is this share passphrase protected?
+    if flags & SHARE_FLAG_PASSPHRASE != 0 {
+        if share.len() < (length + SALT_LENGTH_BYTES) {
+            return Err( Error::other("Invalid share (file smaller than passphrase protected CCMS header)") )
+        }
+
+        salt = Some( share[length..(length + SALT_LENGTH_BYTES)].to_vec() );
+        length += SALT_LENGTH_BYTES;
+    }
+
+    let (pub_key_bytes, signature_bytes) = match is_signed {
+        true => {
+            if share.len() < (length + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH) {
+                // this is clearly not a share and we will panic if we try to slice < header bytes
+                return Err( Error::other("Invalid share (file smaller than signed CCMS header)") )
+            }
+
+            let keys = (
+                share[length..(length + PUBLIC_KEY_LENGTH)].to_vec(),
+                share[(length + PUBLIC_KEY_LENGTH)..(length + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH)].to_vec(),
+            );
+            length += PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH;
+
+            keys
+        },
+        false => (vec![], vec![]),
+    };
+
+    Ok(ShareHeader {
+        algo_version,
+        threshold,
+        is_signed,
+        flags,
+        nonce,
+        scheme,
+        salt,
+        has_mac: algo_version >= ALGO_VERSION_SHARE_MAC && flags & SHARE_FLAG_MAC != 0,
+        file_unsigned: flags & SHARE_FLAG_FILE_UNSIGNED != 0,
+        secret_length,
+        pub_key_bytes,
+        signature_bytes,
+        length,
+    })
+}
+
+/// Pull a share back out of the contents of a share file, making sure it belongs to the file with `nonce`
+/// (or any file at all, with `None` -- e.g. to check a share on its own)
+///
+/// `wrapping_key` is only called for passphrase protected shares; it's given the share's salt and should
+/// hand back the key from `derive_wrapping_key`
+///
+/// The payload is the `SHARE_DATA_LENGTH_BYTES` (or `SHARE_WRAPPED_LENGTH_BYTES`) straight after the header, and
+/// nothing more (bar its MAC, if it has one); whitespace or zero padding after it is dropped, anything else is left
+/// for `Share::try_from` to refuse
+pub fn parse_share(
+    mut share_file: Vec<u8>,
+    nonce: Option<&[u8]>,
+    wrapping_key: &mut impl FnMut(&[u8]) -> Result<Zeroizing<Vec<u8>>> ) -> Result<ShareFromFile> {
+
+    let header = parse_header_share(&share_file)?;
+
+    if nonce.is_some_and(|nonce| header.nonce != nonce) { // compare share nonce to file
+        return Err( Error::other("Share does not match target file nonce") )
+    }
+
+    if header.threshold == 0 { // no scheme needs zero shares, so the header's been mangled
+        return Err( Error::new(ErrorKind::InvalidData, "Invalid share (threshold of 0)") )
+    }
+
+    let mut share_pubkey: Option<PublicKey> = None; // public key
+    let mut share_signature: Option<Signature> = None; // signature
+
+    if header.is_signed {
+        share_pubkey = match PublicKey::from_bytes(&header.pub_key_bytes) { // check for public key validity (ed25519 will throw if it's garbage)
+            Ok(pk) => Some(pk),
+            Err(error) => return Err( Error::other( format!("Invalid share (bad public key: {})", error) ) )
+        };
+
+        share_signature = match Signature::from_bytes(&header.signature_bytes) { // likewise for signatures
+            Ok(sig) => Some(sig),
+            Err(error) => return Err( Error::other( format!("Invalid share (bad signature: {})", error) ) )
+        };
+    }
+
+    let mut share_payload: Vec<u8> = share_file.split_off(header.length); // Grab the contents from the share
+
+    // only ever whitespace after the payload -- y is random, so it can't be stripped from the payload itself
+    let payload_length = match (&header.salt, header.secret_length) {
+        (Some(_), Some(_)) => return Err( Error::other("Invalid share (secret shares can't be passphrase protected)") ),
+        (Some(_), None) => SHARE_WRAPPED_LENGTH_BYTES,
+        (None, Some(secret_length)) => 1 + secret_length,
+        (None, None) => SHARE_DATA_LENGTH_BYTES,
+    };
+
+    let stored_length = match header.has_mac {
+        true => payload_length + SHARE_MAC_LENGTH_BYTES,
+        false => payload_length,
+    };
+
+    if share_payload.len() > stored_length && share_payload[stored_length..].iter().all(|byte| matches!(byte, b'\n' | b'\r' | b' ' | b'\t' | 0) ) {
+        share_payload.truncate(stored_length);
+    }
+
+    let mac: Option<Vec<u8>> = match header.has_mac { // MAC comes last
+        true if share_payload.len() == stored_length => Some( share_payload.split_off(payload_length) ),
+        true => return Err( Error::other("Invalid share (wrong length for a share with a MAC)") ),
+        false => None,
+    };
+
+    let share_contents: Zeroizing<Vec<u8>> = match &header.salt { // unwrap the contents if they're passphrase protected
+        Some(salt) => unwrap_share(&wrapping_key(salt)?, &share_payload)?,
+        None => Zeroizing::new( share_payload.clone() ),
+    };
+
+    let found_share = Share::try_from(share_contents.as_slice());
+
+    match found_share { // Share::try_from returns a borrowed string when it errors for some reason so we have to handle that
+        Ok(sh) => {
+            let share_tuple = ShareFromFile {
+                algo_version: header.algo_version,
+                threshold: header.threshold,
+                nonce: header.nonce,
+                scheme: header.scheme,
+                salt: header.salt,
+                payload: Zeroizing::new(share_payload),
+                mac,
+                file_unsigned: header.file_unsigned,
+                secret_length: header.secret_length,
+                share_data: sh,
+                is_signed: header.is_signed,
+                pub_key: share_pubkey,
+                signature: share_signature,
+            };
+
+            Ok(share_tuple)
+        },
+        Err(err_string) => Err( Error::other(err_string) )
+    }
+}
+
+/// Verify a share's signature, reconstructing the conditions it was originally signed under
+pub fn verify_share_signature(shf: &ShareFromFile, pub_key: &PublicKey, signature: &Signature) -> std::result::Result<(), SignatureError> {
+    let mut reconstructed_share = construct_header_share(shf.algo_version, shf.threshold, shf.is_signed, shf.file_unsigned, &shf.nonce, shf.scheme, shf.salt.as_deref(), shf.mac.is_some(), shf.secret_length);
+
+    reconstructed_share.extend( pub_key.to_bytes() );
+    reconstructed_share.extend(shf.payload.iter() );
+
+    if let Some(mac) = &shf.mac {
+        reconstructed_share.extend(mac);
+    }
+
+    pub_key.verify(&reconstructed_share, signature)
+}
+
+/// MAC of a share (x and y, as from `Vec::from(&share)`) under the key it was split from, binding it to that key's file
+pub fn share_mac(key: &[u8], share_data: &[u8]) -> Vec<u8> {
+    let mac_key = Zeroizing::new( blake3::derive_key(SHARE_MAC_CONTEXT, key) );
+
+    blake3::keyed_hash(&mac_key, share_data).as_bytes().to_vec()
+}
+
+/// Check a share's MAC (from `share_mac`) against the key that's been recovered (in constant time)
+pub fn share_mac_matches(key: &[u8], share_data: &[u8], mac: &[u8]) -> bool {
+    share_mac(key, share_data).ct_eq(mac).into()
+}
+
+/// Bundle complete share files (from `construct_share`) into one, to be split up again with `parse_share_bundle`
+pub fn construct_share_bundle<T: AsRef<[u8]>>(shares: &[T]) -> Zeroizing<Vec<u8>> {
+    let mut bundle: Zeroizing<Vec<u8>> = Zeroizing::new( HEADER_BUNDLE.to_vec() );
+
+    for share in shares {
+        let share = share.as_ref();
+
+        bundle.extend( (share.len() as u16).to_be_bytes() ); // shares are never anywhere near 64k
+        bundle.extend(share);
+    }
+
+    bundle
+}
+
+/// Split a share bundle back into the share files it was made from (not checked any further -- see `parse_share`)
+pub fn parse_share_bundle(bundle: &[u8]) -> Result<Vec<Zeroizing<Vec<u8>>>> {
+    if bundle.len() < HEADER_BUNDLE.len() || bundle[0..HEADER_BUNDLE.len()] != HEADER_BUNDLE {
+        return Err( Error::new(ErrorKind::InvalidData, "Not a share bundle (CCMB header missing)") )
+    }
+
+    let mut shares: Vec<Zeroizing<Vec<u8>>> = vec![];
+    let mut rest = &bundle[HEADER_BUNDLE.len()..];
+
+    while !rest.is_empty() {
+        if rest.len() < 2 {
+            return Err( Error::new(ErrorKind::InvalidData, "Invalid share bundle (truncated share length)") )
+        }
+
+        let length = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+
+        if length > SHARE_MAX_LENGTH || rest.len() < 2 + length {
+            return Err( Error::new(ErrorKind::InvalidData, format!("Invalid share bundle (share {} is truncated or too big)", shares.len() + 1)) )
+        }
+
+        shares.push( Zeroizing::new( rest[2..(2 + length)].to_vec() ) );
+        rest = &rest[(2 + length)..];
+    }
+
+    Ok(shares)
+}
+
+/// Encode a keypair as hex (secret key, then public key) for saving to a key file
+pub fn keypair_to_hex(keypair: &Keypair) -> Zeroizing<String> {
+    let keypair_bytes = Zeroizing::new( keypair.to_bytes() );
+
+    Zeroizing::new( hex::encode(keypair_bytes.as_slice()) )
+}
+
+/// A short fingerprint of a public key, for humans to compare (e.g. read out over the phone): the start of its BLAKE3 hash, as groups of hex like `1a2b-3c4d-5e6f-7a8b`
+pub fn pubkey_fingerprint(pub_key: &[u8]) -> String {
+    let hash = blake3::hash(pub_key);
+
+    hash.as_bytes()[..FINGERPRINT_LENGTH_BYTES].chunks(2).map(hex::encode).collect::<Vec<String>>().join("-")
+}
+
+/// Load a keypair from the contents of a key file written with `keypair_to_hex`
+pub fn keypair_from_hex(hex_keypair: &str) -> Result<Keypair> {
+    let keypair_bytes = hex::decode(hex_keypair.trim())
+        .map(Zeroizing::new)
+        .map_err(|error| Error::other( format!("Invalid key file ({})", error) ) )?;
+
+    Keypair::from_bytes(&keypair_bytes).map_err(|error| Error::other( format!("Invalid key file ({})", error) ) )
+}
+
+/// Generate a random salt for `derive_wrapping_key`
+pub fn generate_salt() -> Vec<u8> {
+    let mut salt = vec![0u8; SALT_LENGTH_BYTES];
+    fill_random(&mut salt);
+
+    salt
+}
+
+/// Derive a key for wrapping shares from a passphrase (Argon2id, default parameters)
+pub fn derive_wrapping_key(passphrase: &[u8], salt: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    let mut wrapping_key = Zeroizing::new( vec![0u8; KEY_LENGTH_BYTES] );
+
+    Argon2::default().hash_password_into(passphrase, salt, &mut wrapping_key)
+        .map_err(|error| Error::other( format!("Could not derive key from passphrase ({})", error) ) )?;
+
+    Ok(wrapping_key)
+}
+
+/// Encrypt share data under a passphrase-derived key; returns the nonce followed by the ciphertext
+pub fn wrap_share(wrapping_key: &[u8], share_data: &[u8]) -> Result<Vec<u8>> {
+    let cc20 = ChaCha20Poly1305::new( Key::from_slice(wrapping_key) );
+
+    // every share gets its own nonce, as they're all wrapped under the same key
+    let mut wrap_nonce = [0u8; WRAP_NONCE_LENGTH_BYTES];
+    fill_random(&mut wrap_nonce);
+
+    let wrapped = cc20.encrypt(Nonce::from_slice(&wrap_nonce), share_data)
+        .map_err(|_| Error::other("Failure when wrapping share") )?;
+
+    Ok( wrap_nonce.iter().cloned().chain(wrapped).collect() )
+}
+
+/// Decrypt share data wrapped by `wrap_share`
+pub fn unwrap_share(wrapping_key: &[u8], payload: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    if payload.len() < WRAP_NONCE_LENGTH_BYTES + TAG_LENGTH_BYTES {
+        return Err( Error::other("Invalid share (wrapped share too small)") )
+    }
+
+    let cc20 = ChaCha20Poly1305::new( Key::from_slice(wrapping_key) );
+    let (wrap_nonce, wrapped) = payload.split_at(WRAP_NONCE_LENGTH_BYTES);
+
+    cc20.decrypt(Nonce::from_slice(wrap_nonce), wrapped)
+        .map(Zeroizing::new)
+        .map_err(|_| Error::other("Could not unwrap share (wrong passphrase?)") )
+}
+
+/*----------+
+| metadata  |
+-----------*/
+
+/// Build the metadata block that goes in front of the plaintext (see FLAG_METADATA)
+pub fn construct_metadata(metadata: &FileMetadata) -> Result<Vec<u8>> {
+    let name = metadata.name.as_bytes();
+
+    if name.len() > u16::MAX as usize {
+        return Err( Error::other("Filename too long to store") )
+    }
+
+    let mut block: Vec<u8> = vec![];
+
+    block.extend_from_slice( &((2 + name.len() + 8 + 8) as u32).to_be_bytes() );
+    block.extend_from_slice( &(name.len() as u16).to_be_bytes() );
+    block.extend_from_slice(name);
+    block.extend_from_slice( &metadata.size.to_be_bytes() );
+    block.extend_from_slice( &metadata.mtime.to_be_bytes() );
+
+    Ok(block)
+}
+
+/// Parse a metadata block (after its length)
+pub fn parse_metadata(block: &[u8]) -> Result<FileMetadata> {
+    let bad_block = || Error::new(ErrorKind::InvalidData, "Malformed metadata block");
+
+    let name_length = u16::from_be_bytes( block.get(..2).ok_or_else(bad_block)?.try_into().unwrap() ) as usize;
+
+    if block.len() != 2 + name_length + 8 + 8 {
+        return Err( bad_block() )
+    }
+
+    let name = str::from_utf8(&block[2..2 + name_length]).map_err(|_| bad_block() )?;
+    let size = u64::from_be_bytes( block[2 + name_length..2 + name_length + 8].try_into().unwrap() );
+    let mtime = u64::from_be_bytes( block[2 + name_length + 8..].try_into().unwrap() );
+
+    Ok( FileMetadata { name: name.to_string(), size, mtime } )
+}
+
+/// Build the name and length that go in front of a file in an archive (see `ArchiveReader`)
+pub fn construct_archive_entry_header(name: &str, length: u64) -> Result<Vec<u8>> {
+    if name.len() > u16::MAX as usize {
+        return Err( Error::other(format!("Name too long to store in an archive: {}", name)) )
+    }
+
+    let mut entry_header: Vec<u8> = vec![];
+
+    entry_header.extend_from_slice( &(name.len() as u16).to_be_bytes() );
+    entry_header.extend_from_slice( name.as_bytes() );
+    entry_header.extend_from_slice( &length.to_be_bytes() );
+
+    Ok(entry_header)
+}
+
+/// Parse the name and length in front of a file in an archive
+pub fn parse_archive_entry_header(entry_header: &[u8]) -> Result<(String, u64)> {
+    let bad_entry = || Error::new(ErrorKind::InvalidData, "Malformed archive entry");
+
+    let name_length = u16::from_be_bytes( entry_header.get(..2).ok_or_else(bad_entry)?.try_into().unwrap() ) as usize;
+
+    if entry_header.len() != 2 + name_length + 8 {
+        return Err( bad_entry() )
+    }
+
+    let name = str::from_utf8(&entry_header[2..2 + name_length]).map_err(|_| bad_entry() )?;
+    let length = u64::from_be_bytes( entry_header[2 + name_length..].try_into().unwrap() );
+
+    Ok( (name.to_string(), length) )
+}
+
+/// Where a file named `name` in an archive is unpacked to under `dir`
+///
+/// Names come out of the (decrypted, so authenticated) archive, but are still checked: only plain relative paths are
+/// allowed, so an archive can't write anywhere outside the directory it's unpacked into
+pub fn archive_entry_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    let mut path = dir.to_path_buf();
+
+    for component in name.split('/') {
+        if component.is_empty() || component == "." || component == ".." || component.contains(['\\', ':', '\0']) {
+            return Err( Error::new(ErrorKind::InvalidData, format!("Archive has a file with an unsafe name: {:?}", name)) )
+        }
+
+        path.push(component);
+    }
+
+    Ok(path)
+}
+
+/// How long the archive of these files will be (as they are now)
+pub fn archive_length(entries: &[ArchiveEntry]) -> Result<u64> {
+    entries.iter().try_fold(0u64, |total, entry| {
+        Ok( total + 2 + entry.name.len() as u64 + 8 + fs::metadata(&entry.path)?.len() )
+    })
+}
+
+/*----------+
+| chacha20  |
+-----------*/
+
+fn read_chunk(reader: &mut impl Read, buffer: &mut [u8]) -> Result<usize> { // fill as much of the buffer as possible; short read means EOF
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(filled)
+}
+
+fn for_each_chunk( // feed a reader through a callback in fixed-size chunks, flagging the final one
+    reader: &mut impl Read,
+    chunk_size: usize,
+    mut callback: impl FnMut(&[u8], bool) -> Result<()> ) -> Result<()> {
+
+    let mut chunk = vec![0u8; chunk_size];
+    let mut next = vec![0u8; chunk_size];
+    let mut chunk_len = read_chunk(reader, &mut chunk)?;
+
+    loop {
+        // we need to look one chunk ahead, since the STREAM construction has to know which chunk is the last
+        let next_len = match chunk_len < chunk_size {
+            true => 0,
+            false => read_chunk(reader, &mut next)?,
+        };
+
+        if next_len == 0 { // current chunk is the last one
+            return callback(&chunk[..chunk_len], true)
+        }
+
+        callback(&chunk[..chunk_len], false)?;
+
+        std::mem::swap(&mut chunk, &mut next);
+        chunk_len = next_len;
+    }
+}
+
+fn check_key_length(u8_key: &[u8]) -> Result<()> { // make sure the key fits the cipher before we hand it over (it'll panic otherwise)
+    if u8_key.len() != KEY_LENGTH_BYTES {
+        return Err( Error::other( format!("Key length {} is not {} bytes", u8_key.len(), KEY_LENGTH_BYTES) ) )
+    }
+
+    Ok(())
+}
+
+fn check_nonce_length(cipher: Cipher, u8_nonce: &[u8]) -> Result<()> { // make sure the nonce fits the cipher before we hand it over (it'll panic otherwise)
+    if u8_nonce.len() != cipher.nonce_length() {
+        return Err( Error::other( format!("Nonce length {} does not match {}", u8_nonce.len(), cipher.name()) ) )
+    }
+
+    Ok(())
+}
+
+fn encrypt_stream_with<A>( // chacha_encrypt_stream for a particular cipher
+    chunk_size: usize,
+    u8_key: &[u8],
+    u8_nonce: &[u8],
+    aad: &[u8],
+    plaintext: &mut impl Read,
+    ciphertext: &mut impl Write,
+    verify: Option<&Cell<Duration>> ) -> Result<u64> // where to add the time spent checking chunks (None not to check them)
+where
+    A: AeadInPlace + NewAead,
+    A::NonceSize: Sub<U5>,
+    <A::NonceSize as Sub<U5>>::Output: ArrayLength<u8>,
+{
+    let key = GenericArray::from_slice(u8_key);
+    let stream_nonce = GenericArray::from_slice(&u8_nonce[..(u8_nonce.len() - STREAM_NONCE_OVERHEAD_BYTES)]);
+
+    let mut encryptor = Some( EncryptorBE32::<A>::new(key, stream_nonce) );
+    // Decrypt each chunk as we go to ensure that it works (unless told not to)
+    let mut decryptor = Some( DecryptorBE32::<A>::new(key, stream_nonce) );
+
+    let mut written: u64 = 0;
+
+    for_each_chunk(plaintext, chunk_size, |chunk, last| {
+        let encrypted = match last {
+            false => encryptor.as_mut().unwrap().encrypt_next(Payload { msg: chunk, aad }),
+            true => encryptor.take().unwrap().encrypt_last(Payload { msg: chunk, aad }), // last chunk consumes the encryptor/decryptor
+        };
+
+        let encrypted = encrypted.map_err(|_| Error::other("Failure when encrypting file") )?;
+
+        if let Some(verify_time) = verify {
+            let started = Instant::now();
+
+            let chk_plaintext = match last {
+                false => decryptor.as_mut().unwrap().decrypt_next(Payload { msg: &encrypted, aad }),
+                true => decryptor.take().unwrap().decrypt_last(Payload { msg: &encrypted, aad }),
+            };
+
+            // compared in constant time, like anything else derived from the plaintext or key
+            let round_tripped = chk_plaintext.map(Zeroizing::new).is_ok_and(|chk_plaintext| bool::from( chk_plaintext.as_slice().ct_eq(chunk) ));
+
+            if !round_tripped { // oh noes
+                return Err( Error::other("Critical error in encryption process - decrypted ciphertext does not match plaintext!") )
+            }
+
+            verify_time.set(verify_time.get() + started.elapsed() );
+        }
+
+        ciphertext.write_all(&encrypted)?;
+        written += encrypted.len() as u64;
+
+        Ok(())
+    })?;
+
+    Ok(written)
+}
+
+fn decrypt_stream_with<A>( // chacha_decrypt_stream for a particular cipher
+    chunk_size: usize,
+    u8_key: &[u8],
+    u8_nonce: &[u8],
+    aad: &[u8],
+    ciphertext: &mut impl Read,
+    plaintext: &mut impl Write ) -> Result<u64>
+where
+    A: AeadInPlace + NewAead,
+    A::NonceSize: Sub<U5>,
+    <A::NonceSize as Sub<U5>>::Output: ArrayLength<u8>,
+{
+    let key = GenericArray::from_slice(u8_key);
+    let stream_nonce = GenericArray::from_slice(&u8_nonce[..(u8_nonce.len() - STREAM_NONCE_OVERHEAD_BYTES)]);
+
+    let mut decryptor = Some( DecryptorBE32::<A>::new(key, stream_nonce) );
+    let mut written: u64 = 0;
+
+    for_each_chunk(ciphertext, chunk_size + TAG_LENGTH_BYTES, |chunk, last| {
+        let decrypted = match last {
+            false => decryptor.as_mut().unwrap().decrypt_next(Payload { msg: chunk, aad }),
+            true => decryptor.take().unwrap().decrypt_last(Payload { msg: chunk, aad }),
+        };
+
+        // aead doesn't use a normal Error to avoid side-channel leaks
+        let decrypted = decrypted.map_err(|_| Error::new(ErrorKind::InvalidData, "[reason obfuscated]") )?;
+
+        plaintext.write_all(&decrypted)?;
+        written += decrypted.len() as u64;
+
+        Ok(())
+    })?;
+
+    Ok(written)
+}
+
+/// Length of the ciphertext `chacha_encrypt_stream` makes from `plaintext_length` bytes (a tag per chunk, and always at least one chunk)
+pub fn ciphertext_length(plaintext_length: u64, chunk_size: usize) -> u64 {
+    let chunks = plaintext_length.div_ceil(chunk_size as u64).max(1);
+    plaintext_length + chunks * TAG_LENGTH_BYTES as u64
+}
+
+/// Encrypt a stream in `chunk_size` chunks, checking each chunk decrypts again; returns ciphertext length
+///
+/// `cipher` is the file header's (see `FileHeader::cipher`); `u8_nonce` has to be the length it expects.
+/// Every chunk is authenticated along with `aad` (see `header_aad`; empty before v4)
+pub fn chacha_encrypt_stream(
+    cipher: Cipher,
+    chunk_size: usize,
+    u8_key: &[u8],
+    u8_nonce: &[u8],
+    aad: &[u8],
+    plaintext: &mut impl Read,
+    ciphertext: &mut impl Write ) -> Result<u64> {
+
+    encrypt_stream(cipher, chunk_size, u8_key, u8_nonce, aad, plaintext, ciphertext, Some(&Cell::new(Duration::ZERO)))
+}
+
+#[allow(clippy::too_many_arguments)] // it's chacha_encrypt_stream's arguments plus one
+fn encrypt_stream( // chacha_encrypt_stream, with the per-chunk check optional
+    cipher: Cipher,
+    chunk_size: usize,
+    u8_key: &[u8],
+    u8_nonce: &[u8],
+    aad: &[u8],
+    plaintext: &mut impl Read,
+    ciphertext: &mut impl Write,
+    verify: Option<&Cell<Duration>> ) -> Result<u64> {
+
+    check_key_length(u8_key)?;
+    check_nonce_length(cipher, u8_nonce)?;
+    check_chunk_size(chunk_size)?;
+
+    match cipher {
+        Cipher::ChaCha20Poly1305 => encrypt_stream_with::<ChaCha20Poly1305>(chunk_size, u8_key, u8_nonce, aad, plaintext, ciphertext, verify),
+        Cipher::XChaCha20Poly1305 => encrypt_stream_with::<XChaCha20Poly1305>(chunk_size, u8_key, u8_nonce, aad, plaintext, ciphertext, verify),
+    }
+}
+
+/// Decrypt a stream produced by `chacha_encrypt_stream` (with the same `chunk_size` and `aad`); returns plaintext length
+pub fn chacha_decrypt_stream(
+    cipher: Cipher,
+    chunk_size: usize,
+    u8_key: &[u8],
+    u8_nonce: &[u8],
+    aad: &[u8],
+    ciphertext: &mut impl Read,
+    plaintext: &mut impl Write ) -> Result<u64> {
+
+    check_key_length(u8_key)?;
+    check_nonce_length(cipher, u8_nonce)?;
+    check_chunk_size(chunk_size)?;
+
+    match cipher {
+        Cipher::ChaCha20Poly1305 => decrypt_stream_with::<ChaCha20Poly1305>(chunk_size, u8_key, u8_nonce, aad, ciphertext, plaintext),
+        Cipher::XChaCha20Poly1305 => decrypt_stream_with::<XChaCha20Poly1305>(chunk_size, u8_key, u8_nonce, aad, ciphertext, plaintext),
+    }
+}
+
+/// Encrypt plaintext in memory (same chunked framing as `chacha_encrypt_stream`)
+pub fn chacha_encrypt(cipher: Cipher, chunk_size: usize, u8_key: &[u8], u8_nonce: &[u8], aad: &[u8], plaintext: &[u8] ) -> Result<Vec<u8>> {
+    let mut ciphertext: Vec<u8> = Vec::with_capacity(plaintext.len() + TAG_LENGTH_BYTES);
+
+    chacha_encrypt_stream(cipher, chunk_size, u8_key, u8_nonce, aad, &mut &plaintext[..], &mut ciphertext)?;
+
+    Ok(ciphertext)
+}
+
+/// Decrypt ciphertext produced by `chacha_encrypt` (with the same `aad`) in memory
+pub fn chacha_decrypt(cipher: Cipher, chunk_size: usize, u8_key: &[u8], u8_nonce: &[u8], aad: &[u8], ciphertext: &[u8] ) -> Result<Vec<u8>> {
+    let mut plaintext: Vec<u8> = Vec::with_capacity(ciphertext.len());
+
+    chacha_decrypt_stream(cipher, chunk_size, u8_key, u8_nonce, aad, &mut &ciphertext[..], &mut plaintext)?;
+
+    Ok(plaintext)
+}
+
+fn reencrypt_stream( // decrypt a (v4 onwards) stream and encrypt it again under a new nonce and associated data (and maybe key), chunk by chunk
+    u8_key: &[u8],
+    new_u8_key: &[u8],
+    old_header: &FileHeader,
+    new_nonce: &[u8],
+    new_aad: &[u8],
+    ciphertext: &mut impl Read,
+    reencrypted: &mut impl Write ) -> Result<u64> {
+
+    check_key_length(u8_key)?;
+    check_key_length(new_u8_key)?;
+    check_nonce_length(old_header.cipher, &old_header.nonce)?;
+    check_nonce_length(old_header.cipher, new_nonce)?;
+
+    match old_header.cipher {
+        Cipher::ChaCha20Poly1305 => reencrypt_stream_with::<ChaCha20Poly1305>(u8_key, new_u8_key, old_header, new_nonce, new_aad, ciphertext, reencrypted),
+        Cipher::XChaCha20Poly1305 => reencrypt_stream_with::<XChaCha20Poly1305>(u8_key, new_u8_key, old_header, new_nonce, new_aad, ciphertext, reencrypted),
+    }
+}
+
+fn reencrypt_stream_with<A>( // reencrypt_stream for a particular cipher
+    u8_key: &[u8],
+    new_u8_key: &[u8],
+    old_header: &FileHeader,
+    new_nonce: &[u8],
+    new_aad: &[u8],
+    ciphertext: &mut impl Read,
+    reencrypted: &mut impl Write ) -> Result<u64>
+where
+    A: AeadInPlace + NewAead,
+    A::NonceSize: Sub<U5>,
+    <A::NonceSize as Sub<U5>>::Output: ArrayLength<u8>,
+{
+    let old_aad = header_aad(old_header)?;
+
+    let key = GenericArray::from_slice(u8_key);
+    let new_key = GenericArray::from_slice(new_u8_key);
+    let old_stream_nonce = GenericArray::from_slice(&old_header.nonce[..(old_header.nonce.len() - STREAM_NONCE_OVERHEAD_BYTES)]);
+    let new_stream_nonce = GenericArray::from_slice(&new_nonce[..(new_nonce.len() - STREAM_NONCE_OVERHEAD_BYTES)]);
+
+    let mut decryptor = Some( DecryptorBE32::<A>::new(key, old_stream_nonce) );
+    let mut encryptor = Some( EncryptorBE32::<A>::new(new_key, new_stream_nonce) );
+
+    let mut written: u64 = 0;
+
+    // plaintext chunks are the same size either way, so each ciphertext chunk maps straight onto a new one
+    // (and no plaintext ever touches the disk)
+    for_each_chunk(ciphertext, old_header.chunk_size + TAG_LENGTH_BYTES, |chunk, last| {
+        let decrypted = match last {
+            false => decryptor.as_mut().unwrap().decrypt_next(Payload { msg: chunk, aad: &old_aad }),
+            true => decryptor.take().unwrap().decrypt_last(Payload { msg: chunk, aad: &old_aad }),
+        };
+
+        // aead doesn't use a normal Error to avoid side-channel leaks
+        let decrypted = Zeroizing::new( decrypted.map_err(|_| Error::new(ErrorKind::InvalidData, "[reason obfuscated]") )? );
+
+        let encrypted = match last {
+            false => encryptor.as_mut().unwrap().encrypt_next(Payload { msg: &decrypted, aad: new_aad }),
+            true => encryptor.take().unwrap().encrypt_last(Payload { msg: &decrypted, aad: new_aad }),
+        };
+
+        let encrypted = encrypted.map_err(|_| Error::other("Failure when encrypting file") )?;
+
+        reencrypted.write_all(&encrypted)?;
+        written += encrypted.len() as u64;
+
+        Ok(())
+    })?;
+
+    Ok(written)
+}
+
+/// Decrypt the contents of a version 1 (non-streamed) file, whole file in memory
+pub fn chacha_decrypt_v1(u8_key: &[u8], u8_nonce: &[u8], ciphertext: &[u8] ) -> Result<Vec<u8>> {
+    check_key_length(u8_key)?;
+    check_nonce_length(Cipher::ChaCha20Poly1305, u8_nonce)?;
+
+    let key = Key::from_slice(u8_key);
+    let cc20 = ChaCha20Poly1305::new(key);
+
+    let nonce = Nonce::from_slice(u8_nonce);
+
+    // Decrypt the ciphertext
+    match cc20.decrypt(nonce, ciphertext) {
+        Ok(plain) => Ok(plain),
+        Err(_error) => { // aead doesn't use a normal Error to avoid side-channel leaks
+            Err( Error::new(ErrorKind::InvalidData, "[reason obfuscated]") )
+        }
+    }
+}
+
+/// Hash a (reconstructed) header followed by the file contents, for checking ed25519ph signatures on streamed files
+pub fn file_signature_digest(header: &[u8], contents: &mut impl Read) -> Result<Sha512> {
+    let mut digest_sink = DigestWriter { inner: std::io::sink(), digest: Sha512::new() };
+
+    digest_sink.digest.update(header);
+    std::io::copy(contents, &mut digest_sink)?;
+
+    Ok(digest_sink.digest)
+}
+
+/// Where a file's detached signature lives (`{name}.sig`, next to it)
+pub fn detached_sig_path(filepath: &Path) -> PathBuf {
+    let mut sig_path = filepath.as_os_str().to_os_string();
+    sig_path.push(".sig");
+
+    PathBuf::from(sig_path)
+}
+
+/// Hash a public key followed by a whole encrypted file, for detached ed25519ph signatures (see `DETACHED_SIG_CONTEXT`)
+pub fn detached_signature_digest(pub_key: &PublicKey, contents: &mut impl Read) -> Result<Sha512> {
+    file_signature_digest(pub_key.as_bytes(), contents)
+}
+
+/// Sign a `detached_signature_digest`, giving the contents of a detached signature file
+pub fn construct_detached_signature(keypair: &Keypair, digest: Sha512) -> Result<Vec<u8>> {
+    let signature: Signature = keypair.sign_prehashed(digest, Some(DETACHED_SIG_CONTEXT)).map_err(Error::other)?;
+
+    let mut detached_sig: Vec<u8> = HEADER_SIG.to_vec();
+    detached_sig.extend( keypair.public.to_bytes() );
+    detached_sig.extend( signature.to_bytes() );
+
+    Ok(detached_sig)
+}
+
+/// Parse a detached signature file into its public key and signature
+pub fn parse_detached_signature(detached_sig: &[u8]) -> Result<(PublicKey, Signature)> {
+    if detached_sig.len() != DETACHED_SIG_LENGTH || detached_sig[0..HEADER_SIG.len()] != HEADER_SIG {
+        return Err( Error::new(ErrorKind::InvalidData, "Not a detached signature (CCMG header missing, or wrong length)") )
+    }
+
+    let pub_key_bytes = &detached_sig[HEADER_SIG.len()..(HEADER_SIG.len() + PUBLIC_KEY_LENGTH)];
+    let signature_bytes = &detached_sig[(HEADER_SIG.len() + PUBLIC_KEY_LENGTH)..];
+
+    let pub_key = PublicKey::from_bytes(pub_key_bytes).map_err(|error| Error::new(ErrorKind::InvalidData, format!("Invalid detached signature (bad public key: {})", error)) )?;
+    let signature = Signature::from_bytes(signature_bytes).map_err(|error| Error::new(ErrorKind::InvalidData, format!("Invalid detached signature (bad signature: {})", error)) )?;
+
+    Ok( (pub_key, signature) )
+}
+
+/// Sibling path to write a file to before renaming it into place (`.tmp-{name}` in the same directory)
+pub fn temp_path(filepath: &Path) -> PathBuf {
+    let mut temp_name = std::ffi::OsString::from(".tmp-");
+    temp_name.push( filepath.file_name().unwrap_or_default() );
+
+    filepath.with_file_name(temp_name)
+}
+
+fn write_signed_file( // write_signed_file_to a temp file, then rename it into place (so a crash never leaves half a file behind)
+    filepath: &Path,
+    header: &[u8],
+    keypair: Option<&Keypair>,
+    write_contents: impl FnOnce(&mut DigestWriter<BufWriter<fs::File>>) -> Result<u64> ) -> Result<u64> {
+
+    let temp_filepath = temp_path(filepath);
+
+    let written = write_signed_file_to(&temp_filepath, header, keypair, write_contents)
+        .and_then(|written| fs::rename(&temp_filepath, filepath).map(|_| written) );
+
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_filepath);
+    }
+
+    written
+}
+
+fn write_signed_file_to( // write header, (optional) signature placeholder and contents, then come back and fill in the signature
+    filepath: &Path,
+    header: &[u8],
+    keypair: Option<&Keypair>,
+    write_contents: impl FnOnce(&mut DigestWriter<BufWriter<fs::File>>) -> Result<u64> ) -> Result<u64> {
+
+    let file = fs::File::create(filepath)?;
+
+    // the signature covers everything either side of it, so hash the header + ciphertext as it gets written,
+    // then come back and fill in the signature afterwards
+    let mut digest = Sha512::new();
+    digest.update(header);
+
+    let mut enc_writer = DigestWriter { inner: BufWriter::new(file), digest };
+
+    enc_writer.inner.write_all(header)?;
+
+    if keypair.is_some() { // placeholder for the signature
+        enc_writer.inner.write_all(&[0u8; SIGNATURE_LENGTH])?;
+    }
+
+    let written = write_contents(&mut enc_writer)?;
+
+    let DigestWriter { inner: mut file, digest } = enc_writer;
+
+    if let Some(keypair) = keypair {
+        let file_ed25519_signature: Signature = keypair.sign_prehashed(digest, None).map_err(Error::other)?;
+
+        // add signature
+        file.seek(SeekFrom::Start(header.len() as u64))?;
+        file.write_all(&file_ed25519_signature.to_bytes() )?;
+    }
+
+    file.flush()?;
+    file.get_ref().sync_all()?; // make sure it's all on disk before it gets renamed into place
+
+    Ok(written)
+}
+
+/// Write header, (optional) signature and streamed ciphertext out to a file; returns ciphertext length
+///
+/// With a `compression_level`, the plaintext is compressed with zstd first (the header should have FLAG_COMPRESSED set).
+/// Without `verify`, nothing is decrypted or decompressed again to check it -- roughly halving the work
+pub fn encrypt_to_file(
+    filepath: &Path,
+    header: &[u8], // full header, incl. public key if signing
+    keypair: Option<&Keypair>,
+    u8_key: &[u8],
+    u8_nonce: &[u8],
+    settings: &EncryptSettings,
+    plaintext: &mut impl Read ) -> Result<u64> {
+
+    let algo_version = header[HEADER_FILE.len()]; // cipher depends on the version we're writing
+
+    let cipher = cipher_from_header(algo_version, header)?;
+
+    let chunk_size = match algo_version >= ALGO_VERSION_CHUNK_SIZE { // so does the chunk size
+        true => chunk_size_from_header_byte(header[HEADER_CHUNK_SIZE_BYTE_FILE - 1])?,
+        false => STREAM_CHUNK_BYTES,
+    };
+
+    // from v4 the header (w/o public key and sig) is authenticated along with the contents
+    let mut aad: Vec<u8> = match algo_version >= ALGO_VERSION_HEADER_AAD {
+        true => header[..file_header_length(header, algo_version, header[HEADER_FLAGS_BYTE_FILE - 1]).unwrap_or(header.len())].to_vec(),
+        false => vec![],
+    };
+
+    // and from v9, any associated data of the user's own after it
+    let (_, has_aad) = signed_byte_bits(algo_version, header[HEADER_IS_SIGNED_BYTE_FILE - 1]);
+
+    match (has_aad, &settings.aad) {
+        (true, Some(user_aad)) => aad.extend(user_aad),
+        (false, None) => (),
+        _ => return Err( Error::new(ErrorKind::InvalidInput, "Associated data has to be given if (and only if) the header says there is some") ),
+    }
+
+    let aad = aad.as_slice();
+
+    write_signed_file(filepath, header, keypair, |enc_writer| {
+        match settings.compression_level {
+            Some(level) if !settings.verify => {
+                let mut compressed = zstd::stream::read::Encoder::new(plaintext, level)?;
+                encrypt_stream(cipher, chunk_size, u8_key, u8_nonce, aad, &mut compressed, enc_writer, None)
+            },
+            Some(level) => {
+                // checking chunks only shows the compressed data round-trips, so decompress it again on the side
+                // and make sure we end up with what we started with
+                let mut original = DigestReader { inner: plaintext, digest: Sha512::new() };
+                let mut check = zstd::stream::write::Decoder::new( DigestWriter { inner: std::io::sink(), digest: Sha512::new() } )?;
+
+                let compressor = zstd::stream::read::Encoder::new(&mut original, level)?;
+                let mut compressed = TeeReader { inner: compressor, tee: &mut check };
+
+                let written = encrypt_stream(cipher, chunk_size, u8_key, u8_nonce, aad, &mut compressed, enc_writer, Some(&settings.verify_time))?;
+                drop(compressed);
+
+                check.flush()?;
+
+                if !bool::from( check.into_inner().digest.finalize().as_slice().ct_eq( original.digest.finalize().as_slice() ) ) { // oh noes
+                    return Err( Error::other("Critical error in compression process - decompressed data does not match plaintext!") )
+                }
+
+                Ok(written)
+            },
+            None => encrypt_stream(cipher, chunk_size, u8_key, u8_nonce, aad, plaintext, enc_writer, settings.verify.then_some(&settings.verify_time)),
+        }
+    })
+}
+
+/// Re-encrypt a (v4 onwards) file's contents under a new header, writing the result out like `encrypt_to_file`
+///
+/// From v4, the header is authenticated along with the contents, so changing it (e.g. the threshold) means
+/// re-encrypting -- under a new nonce, as reusing one with different associated data would give away two
+/// tags under the same Poly1305 key. Nothing is decompressed or recompressed, and no plaintext touches the disk.
+/// `new_key` is `u8_key` again to keep the key, or a fresh one to rotate it
+pub fn reencrypt_to_file(
+    filepath: &Path,
+    header: &[u8], // new full header, incl. public key if signing (and a different nonce to the old one)
+    keypair: Option<&Keypair>,
+    u8_key: &[u8],
+    new_key: &[u8],
+    old_header: &FileHeader,
+    ciphertext: &mut impl Read ) -> Result<u64> {
+
+    let mut new_header = parse_header_file(header).or_else(|_| { // signed headers don't have a signature yet
+        let mut placeholder = header.to_vec();
+        placeholder.extend([0u8; SIGNATURE_LENGTH]);
+        parse_header_file(&placeholder)
+    })?;
+
+    new_header.aad = old_header.aad.clone(); // never stored, so it comes along from the old header
+
+    if old_header.algo_version < ALGO_VERSION_HEADER_AAD || new_header.algo_version != old_header.algo_version {
+        return Err( Error::other("Only version 4 files onwards are re-encrypted, and must keep their version") )
+    }
+
+    if new_header.nonce == old_header.nonce {
+        return Err( Error::other("Re-encrypting needs a new nonce") )
+    }
+
+    if new_header.chunk_size != old_header.chunk_size { // chunks are re-encrypted one for one
+        return Err( Error::other("Re-encrypting has to keep the same chunk size") )
+    }
+
+    if new_header.cipher != old_header.cipher {
+        return Err( Error::other("Re-encrypting has to keep the same cipher") )
+    }
+
+    if new_header.has_aad != old_header.has_aad {
+        return Err( Error::other("Re-encrypting has to keep the same associated data") )
+    }
+
+    let new_aad = header_aad(&new_header)?;
+
+    write_signed_file(filepath, header, keypair, |enc_writer| {
+        reencrypt_stream(u8_key, new_key, old_header, &new_header.nonce, &new_aad, ciphertext, enc_writer)
+    })
+}
+
+/// Overwrite an encrypted file's header in place (e.g. with a new threshold), re-signing the file if a keypair is given
+///
+/// The new header (incl. public key if signing) must be the same length as the old one; the contents are left untouched
+pub fn rewrite_file_header(filepath: &Path, header: &[u8], keypair: Option<&Keypair>) -> Result<()> {
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(filepath)?;
+
+    let mut old_header = vec![];
+    (&mut file).take(HEADER_MAX_LENGTH_FILE as u64).read_to_end(&mut old_header)?;
+    let old_header = parse_header_file(&old_header)?;
+
+    let signature_length = match keypair {
+        Some(_) => SIGNATURE_LENGTH,
+        None => 0,
+    };
+
+    if old_header.length != header.len() + signature_length { // we'd be writing over the contents (or leaving junk behind)
+        return Err( Error::other("New header does not match the length of the existing one") )
+    }
+
+    let signature = match keypair {
+        Some(keypair) => {
+            file.seek(SeekFrom::Start(old_header.length as u64))?;
+            let mut contents = BufReader::new(&mut file);
+
+            let signature = match old_header.flags & FLAG_STREAMED != 0 {
+                true => keypair.sign_prehashed(file_signature_digest(header, &mut contents)?, None).map_err(Error::other)?,
+                false => { // version 1 files were signed over the whole thing
+                    let mut message = header.to_vec();
+                    contents.read_to_end(&mut message)?;
+
+                    keypair.sign(&message)
+                }
+            };
+
+            Some(signature)
+        },
+        None => None,
+    };
+
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(header)?;
+
+    if let Some(signature) = signature {
+        file.write_all(&signature.to_bytes() )?;
+    }
+
+    file.flush()
+}
+
+/// BLAKE3 checksum of a file's plaintext, for storing in its header with `construct_header_file`
+pub fn plaintext_checksum(plaintext: &mut impl Read) -> Result<Vec<u8>> {
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(plaintext, &mut hasher)?;
+
+    Ok( hasher.finalize().as_bytes().to_vec() )
+}
+
+/// Decrypt a streamed file's contents (i.e. after the header), decompressing them if the header says to
+///
+/// If the file has a metadata block, it's kept out of `plaintext` and handed back instead. If the header has a
+/// checksum, the plaintext is checked against it once it's all been written (an `ErrorKind::InvalidData` error if not)
+pub fn decrypt_contents(
+    header: &FileHeader,
+    u8_key: &[u8],
+    ciphertext: &mut impl Read,
+    plaintext: &mut impl Write ) -> Result<Option<FileMetadata>> {
+
+    let mut plaintext = ChecksumWriter { inner: plaintext, hasher: header.checksum.as_ref().map(|_| blake3::Hasher::new() ) };
+
+    let metadata = match header.flags & FLAG_METADATA != 0 {
+        true => {
+            let mut splitter = MetadataSplitter { inner: &mut plaintext, block: vec![], metadata: None };
+            decrypt_and_decompress(header, u8_key, ciphertext, &mut splitter)?;
+
+            match splitter.metadata {
+                Some(metadata) => Some(metadata),
+                None => return Err( Error::new(ErrorKind::InvalidData, "File ended before its metadata block did") ),
+            }
+        },
+        false => {
+            decrypt_and_decompress(header, u8_key, ciphertext, &mut plaintext)?;
+            None
+        },
+    };
+
+    if let (Some(checksum), Some(hasher)) = (&header.checksum, plaintext.hasher) {
+        if !bool::from( hasher.finalize().as_bytes().as_slice().ct_eq(checksum) ) {
+            return Err( Error::new(ErrorKind::InvalidData, "Decrypted file does not match its checksum (blake3)") )
+        }
+    }
+
+    Ok(metadata)
+}
+
+fn decrypt_and_decompress( // decrypt_contents, minus the metadata
+    header: &FileHeader,
+    u8_key: &[u8],
+    ciphertext: &mut impl Read,
+    plaintext: &mut impl Write ) -> Result<()> {
+
+    let aad = header_aad(header)?;
+
+    if header.flags & FLAG_COMPRESSED != 0 {
+        let mut decompressor = zstd::stream::write::Decoder::new(plaintext)?;
+
+        chacha_decrypt_stream(header.cipher, header.chunk_size, u8_key, &header.nonce, &aad, ciphertext, &mut decompressor)?;
+        decompressor.flush()
+    }
+    else {
+        chacha_decrypt_stream(header.cipher, header.chunk_size, u8_key, &header.nonce, &aad, ciphertext, plaintext)?;
+        Ok(())
+    }
+}
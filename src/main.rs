@@ -2,42 +2,135 @@
 // deps & crates
 // ---------
 
-extern crate chacha20poly1305; // chacha20 implementation
+extern crate base64; // base64 (shares on stdin/stdout)
+extern crate chachamir; // our own library (all the actual crypto lives there)
 extern crate clap; // clap (CLI parser)
+extern crate ctrlc; // Ctrl+C handling (cleaning up after an interrupted encryption)
 extern crate ed25519_dalek; // ed25519 (share integrity)
-extern crate glob; // glob (for handling file directories)
+extern crate glob; // glob patterns (for --exclude)
 extern crate hex; // Hex stuff (for using nonces as IDs)
+extern crate image; // PNG encoding (for share QR codes)
 extern crate infer; // MIME type recognition (not really necessary, just for post-decryption fun)
-extern crate path_clean; // Path clean (for absolute paths)
-extern crate rand; // RNG (for key generation)
+extern crate qrcode; // QR codes (paper backups of shares)
+extern crate rayon; // checking share signatures in parallel
+extern crate rpassword; // Hidden passphrase prompts
+extern crate serde; // config file parsing
+extern crate serde_json; // JSON output (for `--json`)
 extern crate sharks; // Shamir's Secret Sharing
+extern crate subtle; // Constant-time comparisons (of key material)
+extern crate toml; // config files (chachamir.toml)
+extern crate zeroize; // Wiping key material from memory
+extern crate zstd; // zstd (compression level defaults/limits)
 
 // things from the stdlib
+use std::cell::Cell;
+use std::collections::HashSet;
 use std::env;
 use std::fs;
 use std::io;
-use std::io::{Result, Error, ErrorKind};
-use std::io::{Read, Write};
-use std::path::{PathBuf, Path};
+use std::io::Result;
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::io::{BufRead, BufReader, BufWriter, Cursor, IsTerminal};
+use std::path::{Component, PathBuf, Path};
 use std::process;
+use std::slice;
 use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 // pulling from our crates
-use chacha20poly1305::aead::{Aead, NewAead};
-use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chachamir::{Cipher, EncryptSettings, FileHeader, FileMetadata, ShareFromFile, ALGO_VERSION, ALGO_VERSION_HEADER_AAD, KEY_LENGTH_BYTES, NONCE_LENGTH_BYTES, NONCE_LENGTH_BYTES_V2, CHECKSUM_LENGTH_BYTES, FLAG_STREAMED, FLAG_COMPRESSED, FLAG_METADATA, FLAG_SCHEMES, FLAG_SHARES_UNSIGNED, FLAG_ARCHIVE, SHARE_FLAG_PASSPHRASE, SECRET_MAX_LENGTH_BYTES};
+use chachamir::{HEADER_FILE, HEADER_SHARE, HEADER_MAX_LENGTH_FILE, HEADER_MAX_LENGTH_SHARE, SHARE_MAX_LENGTH, SHARE_TRAILING_MAX_BYTES, SHARE_BUNDLE_MAX_LENGTH};
+use chachamir::{is_encrypted, parse_header_file, parse_header_share, construct_header_file, construct_header_share};
+use chachamir::{fill_random, generate_keypair, generate_salt, derive_wrapping_key, wrap_share, keypair_to_hex, keypair_from_hex, pubkey_fingerprint};
+use chachamir::{split_key_into_shares, recover_key_from_shares, recover_secret_from_shares, extend_shares, zeroize_share, shares_match, construct_share, parse_share, verify_share_signature, construct_share_bundle, parse_share_bundle, share_mac, share_mac_matches};
+use chachamir::{chacha_encrypt, chacha_decrypt, unwrap_share, STREAM_CHUNK_BYTES_MIN};
+use chachamir::{ArchiveEntry, ArchiveReader, ArchiveUnpacker, archive_length};
+use chachamir::{check_chunk_size, check_label, max_stream_length, temp_path, ciphertext_length, construct_metadata, plaintext_checksum, decrypt_contents, chacha_decrypt_v1, encrypt_to_file, reencrypt_to_file, rewrite_file_header, file_signature_digest};
+use chachamir::{DETACHED_SIG_LENGTH, DETACHED_SIG_CONTEXT, detached_sig_path, detached_signature_digest, construct_detached_signature, parse_detached_signature};
+#[cfg(feature = "test-rng")] use chachamir::seed_test_rng;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 
 use clap::{Parser, Subcommand};
 
-use ed25519_dalek::{Keypair, Signature, Signer, Verifier, PublicKey, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+use ed25519_dalek::{Keypair, Signature, SignatureError, Verifier, PublicKey, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
 
-use glob::glob;
+use glob::Pattern;
 
-use path_clean::PathClean;
+use qrcode::{QrCode, EcLevel, Version};
 
-use rand::rngs::OsRng;
-use rand::RngCore;
+use rayon::prelude::*;
 
-use sharks::{ Sharks, Share };
+use serde::Deserialize;
+
+use sharks::Share;
+
+use subtle::ConstantTimeEq;
+
+use zeroize::{Zeroize, Zeroizing};
+
+// -------
+// Reporting
+// -------
+
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false); // set from --json before anything gets printed
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false); // stdout is kept for data (shares or plaintext), so everything else goes to stderr
+static QUIET: AtomicBool = AtomicBool::new(false); // only errors, warnings and results (set from --quiet)
+static VERBOSE: AtomicBool = AtomicBool::new(false); // timings too (set from --verbose)
+static PROGRESS_JSON: AtomicBool = AtomicBool::new(false); // progress events on stderr (set from --progress-json)
+
+macro_rules! report { // eprintln!, routed through the reporter (status goes to stderr, so stdout only ever has data on it)
+    ($($arg:tt)*) => { report_line(&format!($($arg)*)) };
+}
+
+macro_rules! output { // println!, for what the command was run to get (header info, a key), routed through the reporter
+    ($($arg:tt)*) => { output_line(&format!($($arg)*)) };
+}
+
+// -------
+// Prompting
+// -------
+
+static NON_INTERACTIVE: AtomicBool = AtomicBool::new(false); // never wait on stdin (set from --non-interactive)
+
+// -------
+// Audit log
+// -------
+
+static AUDIT_LOG: Mutex<Option<fs::File>> = Mutex::new(None); // opened (for appending) from --audit-log
+
+// -------
+// Interrupts
+// -------
+
+static PARTIAL_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new()); // shares (and the like) written for an encryption that isn't finished yet, removed on Ctrl+C
+
+// -------
+// Exit codes
+// -------
+
+const EXIT_FAILURE: i32 = 1; // anything without a more specific code (mostly I/O)
+const EXIT_NEEDS_CONFIRMATION: i32 = 2; // we'd have to ask a human, but can't
+const EXIT_NOT_ENCRYPTED: i32 = 3; // target isn't a (valid) encrypted file
+const EXIT_NO_SHARES: i32 = 4; // no usable shares were found at all
+const EXIT_RECOVERY_FAILED: i32 = 5; // shares were found, but the key couldn't be recovered from them
+const EXIT_DECRYPTION_FAILED: i32 = 6; // the contents failed to authenticate under the recovered key
+const EXIT_TAMPERED: i32 = 7; // signature (or other integrity) check failed in strict mode
+const EXIT_INTERRUPTED: i32 = 130; // Ctrl+C (the usual 128 + SIGINT)
+
+const EXIT_CODES_HELP: &str = "EXIT CODES:
+    0    Success
+    1    Generic error (mostly I/O)
+    2    Confirmation needed, but running non-interactively
+    3    Target is not an encrypted file
+    4    No shares located
+    5    Could not recover the key from the shares (too few, or incompatible)
+    6    Decryption failed (wrong key, or corrupted/tampered contents)
+    7    Signature verification failed under --strict (or --expect-pubkey), or the signer's fingerprint wasn't confirmed";
 
 // -------
 // CLI parsing
@@ -50,31 +143,223 @@ struct Arguments {
     /// Choose to encrypt or decrypt file
     #[clap(subcommand)]
     command: Commands,
+
+    /// Report progress and results as newline-delimited JSON objects on stdout
+    #[clap(long, global = true)]
+    json: bool,
+
+    /// Never wait for input: take the default answer where there is one, otherwise exit with code 2
+    #[clap(short = 'y', long, alias = "yes", global = true)]
+    non_interactive: bool,
+
+    /// Only print errors, warnings and where things were written (no logo, progress or status lines)
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Also print how long each stretch of a file took to encrypt or decrypt
+    #[clap(short, long, global = true)]
+    verbose: bool,
+
+    /// Report progress through a file (and through writing its shares) as JSON lines on stderr, a few times a second, for a frontend's progress bar
+    #[clap(long, global = true)]
+    progress_json: bool,
+
+    /// Take default players, threshold, share directory and signing from this config file, rather than a chachamir.toml found in the working directory or $XDG_CONFIG_HOME
+    #[clap(parse(from_os_str), long, value_name = "PATH", global = true, conflicts_with = "no-config")]
+    config: Option<PathBuf>,
+
+    /// Don't read a config file, even if there's one about
+    #[clap(long, global = true)]
+    no_config: bool,
+
+    /// Append a timestamped JSON line to this file for every signature, public key and threshold check (and what came of it)
+    #[clap(parse(from_os_str), long, value_name = "PATH", global = true)]
+    audit_log: Option<PathBuf>,
+
+    /// Take keys, nonces and shares from an RNG seeded with this (32 bytes of hex), so output is reproducible (test-rng builds only)
+    #[cfg(feature = "test-rng")]
+    #[clap(long, value_name = "HEX", global = true, hide = true, parse(try_from_str = parse_seed))]
+    seed: Option<[u8; 32]>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Encrypt file
     Encrypt {
-        /// Path to the file needing encryption
-        #[clap(parse(from_os_str), forbid_empty_values = true)]
-        file: PathBuf,
+        /// Path to the file needing encryption (not with --input-list)
+        #[clap(parse(from_os_str), forbid_empty_values = true, required_unless_present = "input-list")]
+        file: Option<PathBuf>,
 
-        /// Total number of shares to generate (max 255)
-        players: u8,
+        /// Total number of shares to generate (max 255; can come from the config file instead)
+        #[clap(parse(try_from_str = parse_share_count))]
+        players: Option<u8>,
 
-        /// Number of shares needed to reconstruct the secret (max 255; cannot be more than total)
-        threshold: u8,
+        /// Number of shares needed to reconstruct the secret (max 255; cannot be more than total; can come from the config file instead)
+        #[clap(parse(try_from_str = parse_share_count))]
+        threshold: Option<u8>,
+
+        /// Total number of shares to generate, instead of PLAYERS (so it can't be mixed up with the threshold)
+        #[clap(long = "players", short = 'n', value_name = "N", parse(try_from_str = parse_share_count))]
+        named_players: Option<u8>,
+
+        /// Number of shares needed to reconstruct the secret, instead of THRESHOLD
+        #[clap(long = "threshold", short = 't', value_name = "N", parse(try_from_str = parse_share_count))]
+        named_threshold: Option<u8>,
 
-        /// Path to the directory containing shares, or to write shares to (defaults to current working dir)
+        /// Path to the directory containing shares, or to write shares to (defaults to the config file's, then current working dir)
         #[clap(parse(from_os_str), short, long)]
         share_dir: Option<PathBuf>,
 
-        /// Choose to sign files and shares for extra integrity (will cause additional overhead)
+        /// Choose to sign files and shares for extra integrity (will cause additional overhead; same as --sign-file --sign-shares)
         #[clap(long)]
         sign: bool,
+
+        /// Sign the encrypted file, but not its shares (unless --sign-shares is given too)
+        #[clap(long)]
+        sign_file: bool,
+
+        /// Sign the shares, but not the encrypted file (unless --sign-file is given too)
+        #[clap(long)]
+        sign_shares: bool,
+
+        /// Don't sign, even if the config file says to
+        #[clap(long, conflicts_with_all = &["sign", "sign-file", "sign-shares"])]
+        no_sign: bool,
+
+        /// Protect each share with a passphrase (you will be prompted for it; needed again to decrypt)
+        #[clap(long)]
+        passphrase: bool,
+
+        /// Sign with the keypair saved in this key file, rather than a new one (see `keygen`; needs --sign, --sign-file or --sign-shares)
+        #[clap(parse(from_os_str), long)]
+        key_file: Option<PathBuf>,
+
+        /// Put the file's signature and public key in a separate <FILE>.ccm.sig, leaving the encrypted file identical to an unsigned one (needs --sign or --sign-file)
+        #[clap(long)]
+        detached_sig: bool,
+
+        /// Write the encrypted file as "ccm" (header and ciphertext together) or "raw" (only the ciphertext, with the header -- nonce, threshold and all -- in a separate <FILE>.ccm.hdr, for embedding in other containers)
+        #[clap(long, value_name = "FORMAT", default_value = "ccm", parse(try_from_str = parse_output_format))]
+        output_format: OutputFormat,
+
+        /// Overwrite an existing encrypted file, and shares for the same nonce in the share directory, without stopping (nor asking before encrypting an already encrypted file)
+        #[clap(long)]
+        force: bool,
+
+        /// Store the original filename, size and modification time inside the encrypted file, so they can be restored even if it gets renamed
+        #[clap(long)]
+        metadata: bool,
+
+        /// Store a BLAKE3 checksum of the file in its (authenticated) header, checked again on decryption (reads the file twice)
+        #[clap(long)]
+        checksum: bool,
+
+        /// Stamp a short label, e.g. "Q3 financials", into the file's (authenticated) header, shown by `info` and decrypt (at most 255 bytes, no control characters)
+        #[clap(long, value_name = "TEXT", parse(try_from_str = parse_label))]
+        label: Option<String>,
+
+        /// Bind the file to some context of your own, e.g. "hostname=vault01", which has to be given again with --aad to decrypt it (it isn't stored; the header only says it's needed)
+        #[clap(long, value_name = "TEXT", forbid_empty_values = true)]
+        aad: Option<String>,
+
+        /// Add a MAC under the file's key to each share, so once the key is recovered every share found can be checked against it, signed or not
+        #[clap(long)]
+        share_mac: bool,
+
+        /// Encrypt every file in the directory <FILE> (recursively), each with its own key and shares
+        #[clap(short, long, conflicts_with = "output")]
+        recursive: bool,
+
+        /// Pack <FILE> (every file in it, recursively, if it's a directory) into one encrypted archive, under one key and one set of shares, along with anything given with --with; decrypting unpacks it into a directory again
+        #[clap(long, conflicts_with_all = &["recursive", "input-list", "metadata"])]
+        archive: bool,
+
+        /// With --archive, pack this file or directory in too, under its own name (give more than once for more)
+        #[clap(parse(from_os_str), long, value_name = "PATH", multiple_occurrences = true, requires = "archive")]
+        with: Vec<PathBuf>,
+
+        /// Encrypt every file listed in this manifest (one path per line), each with its own key and its shares in a directory of its own under the share dir
+        #[clap(parse(from_os_str), long, value_name = "PATH", conflicts_with_all = &["recursive", "output", "shares-stdout", "share-bundle"])]
+        input_list: Option<PathBuf>,
+
+        /// With --recursive or --archive, skip files and directories matching this glob, e.g. "*.tmp" or ".git" (give more than once for more)
+        #[clap(long, value_name = "PATTERN", multiple_occurrences = true, parse(try_from_str = parse_exclude))]
+        exclude: Vec<Pattern>,
+
+        /// With --recursive, only encrypt files modified since this time (RFC 3339, e.g. 2026-10-01T00:00:00Z, or just a date), for incremental backups
+        #[clap(long, value_name = "TIME", requires = "recursive", parse(try_from_str = parse_since))]
+        since: Option<SystemTime>,
+
+        /// Print shares to stdout as base64, one per line, instead of writing share files
+        #[clap(long, conflicts_with_all = &["share-dir", "recursive"])]
+        shares_stdout: bool,
+
+        /// Also save each share as a QR code PNG next to its share file, for paper backups
+        #[clap(long, conflicts_with = "shares-stdout")]
+        qr: bool,
+
+        /// Write each share as ASCII armor (a base64 block between BEGIN/END lines, with a checksum) in a .ccms.asc file, for pasting into emails or tickets
+        #[clap(long, conflicts_with_all = &["shares-stdout", "share-bundle"])]
+        armor: bool,
+
+        /// Skip the checks that the new shares recover the key and the encrypted file decrypts (roughly halves the work)
+        #[clap(long, alias = "no-verify")]
+        fast: bool,
+
+        /// Before writing anything, also check that threshold-sized subsets of the new shares each recover the key (every subset for small schemes, a random selection for big ones)
+        #[clap(long, alias = "threshold-check", conflicts_with = "fast")]
+        verify_subsets: bool,
+
+        /// Print how long each phase took at the end (key generation, share splitting and checking, reading, encrypting, verifying, writing shares)
+        #[clap(long, conflicts_with = "dry-run")]
+        time: bool,
+
+        /// Show which files would be written (and how big they'd be) without writing anything
+        #[clap(long, conflicts_with = "shares-stdout")]
+        dry_run: bool,
+
+        /// Plaintext bytes per encrypted chunk, e.g. 16k or 1M (a power of two, 4k-16M); bigger chunks are faster on big files
+        #[clap(long, value_name = "SIZE", default_value = "64k", parse(try_from_str = parse_chunk_size))]
+        chunk_size: usize,
+
+        /// Cipher to encrypt the file with (only xchacha20poly1305 for now); it's recorded in the header, so decrypting works it out for itself
+        #[clap(long, value_name = "NAME", default_value = "xchacha20poly1305", parse(try_from_str = parse_cipher))]
+        algo: Cipher,
+
+        /// Name share files after this template, e.g. "share_{index}_of_{total}_{nonce}.ccms" ({index}, {total}, {threshold}, {nonce} and {checksum} are filled in; must contain {index} and end in .ccms)
+        #[clap(long, value_name = "TEMPLATE", default_value = DEFAULT_SHARE_NAME_TEMPLATE, conflicts_with = "shares-stdout", parse(try_from_str = parse_share_name_template))]
+        share_name_template: String,
+
+        /// Also split the key into another, independent set of shares, e.g. 5:3 (give more than once for more); any one complete set can decrypt, and each goes in its own scheme-N directory in the share directory
+        #[clap(long, value_name = "PLAYERS:THRESHOLD", multiple_occurrences = true, conflicts_with = "shares-stdout", parse(try_from_str = parse_scheme))]
+        scheme: Vec<(u8, u8)>,
+
+        /// Write every share into this one .ccmbundle file instead of separate share files, for moving them around before they're handed out
+        #[clap(parse(from_os_str), long, value_name = "PATH", conflicts_with_all = &["shares-stdout", "recursive", "scheme", "qr", "share-name-template"])]
+        share_bundle: Option<PathBuf>,
+
+        /// Permissions for share files, bundles and QR codes, in octal (Unix only; by default only you can read them)
+        #[clap(long, value_name = "OCTAL", default_value = DEFAULT_SHARE_MODE, conflicts_with = "shares-stdout", parse(try_from_str = parse_mode))]
+        mode: u32,
+
+        /// How many share files to write at once (defaults to one per CPU)
+        #[clap(long, value_name = "N", conflicts_with = "shares-stdout", parse(try_from_str = parse_thread_count))]
+        threads: Option<usize>,
+
+        /// Compress the file with zstd before encrypting it
+        #[clap(long)]
+        compress: bool,
+
+        /// zstd compression level to use with --compress (1-22)
+        #[clap(long, value_name = "LEVEL", default_value_t = zstd::DEFAULT_COMPRESSION_LEVEL, requires = "compress")]
+        compress_level: i32,
+
+        /// Path to write the encrypted file to, or a directory to write it into (defaults to <FILE>.ccm)
+        #[clap(parse(from_os_str), short, long)]
+        output: Option<PathBuf>,
     },
     /// Decrypt file
+    #[clap(after_help = EXIT_CODES_HELP)]
     Decrypt {
         /// Path to the file needing decryption
         #[clap(parse(from_os_str), forbid_empty_values = true)]
@@ -84,523 +369,5655 @@ enum Commands {
         #[clap(short, long)]
         all: bool,
 
-        /// Path to the directory containing shares, or to write shares to (defaults to current working dir)
-        #[clap(parse(from_os_str), short, long)]
-        share_dir: Option<PathBuf>,
+        /// Path to a directory containing shares; give more than once to gather shares from several (defaults to current working dir)
+        #[clap(parse(from_os_str), short, long, multiple_occurrences = true)]
+        share_dir: Vec<PathBuf>,
 
         /// Force shares to have valid signatures before use (only works with signed files)
         #[clap(long)]
-        strict: bool
+        strict: bool,
+
+        /// Abort unless the file is signed with this public key (hex); implies --strict
+        #[clap(long, value_name = "HEX")]
+        expect_pubkey: Option<String>,
+
+        /// Show the signer's public key fingerprint and ask whether it's the one you expect before going any further (signed files only)
+        #[clap(long)]
+        verify_pubkey_fingerprint: bool,
+
+        /// List the shares found for the file (index, and whether they're signed and check out), and ask before recovering the key with them
+        #[clap(long)]
+        list_shares: bool,
+
+        /// Check the signature of every share found, not just enough to decrypt, and report any that fail together at the end (signed files only)
+        #[clap(long)]
+        verify_all_signatures: bool,
+
+        /// Check signatures and shares, and that the file decrypts, without writing the plaintext anywhere
+        #[clap(long, conflicts_with = "output")]
+        verify_only: bool,
+
+        /// Refuse to recover the key from fewer than this many distinct shares, even if the file's threshold is lower (e.g. a policy of three custodians present)
+        #[clap(long, value_name = "N", parse(try_from_str = parse_share_count))]
+        min_shares: Option<u8>,
+
+        /// Threshold to use if shares disagree with the file about it (instead of asking)
+        #[clap(long, parse(try_from_str = parse_share_count))]
+        threshold: Option<u8>,
+
+        /// Take the threshold from what most of the gathered shares say, rather than trusting the file's header
+        #[clap(long, conflicts_with = "threshold")]
+        threshold_from_shares: bool,
+
+        /// Decrypt every .ccm file in the directory <FILE> (recursively), as encrypted with `encrypt --recursive`
+        #[clap(short, long, conflicts_with = "output")]
+        recursive: bool,
+
+        /// Skip files matching this glob when looking for shares (and, with --recursive, encrypted files), e.g. "*.tmp" (give more than once for more)
+        #[clap(long, value_name = "PATTERN", multiple_occurrences = true, conflicts_with_all = &["shares-stdin", "share-bundle"], parse(try_from_str = parse_exclude))]
+        exclude: Vec<Pattern>,
+
+        /// Stop looking for shares after checking this many files in the share directory (a guard for --all)
+        #[clap(long, value_name = "N")]
+        max_shares_scanned: Option<usize>,
+
+        /// Try reading a share file again up to this many times if it fails with a transient error, e.g. a timeout on a network drive
+        #[clap(long, value_name = "N", default_value_t = DEFAULT_READ_RETRIES)]
+        read_retries: u32,
+
+        /// Milliseconds to wait before retrying a share file, doubling with each retry
+        #[clap(long, value_name = "MS", default_value_t = DEFAULT_RETRY_BACKOFF_MS)]
+        retry_backoff: u64,
+
+        /// Write the plaintext to stdout instead of a file (everything else goes to stderr)
+        #[clap(long, conflicts_with_all = &["output", "verify-only", "recursive"])]
+        stdout: bool,
+
+        /// Overwrite an existing file at the output path without asking
+        #[clap(long, conflicts_with = "verify-only")]
+        force: bool,
+
+        /// If the decrypted file's name has no extension (and none was stored with --metadata), add one from its detected type, e.g. .jpg or .pdf
+        #[clap(long, conflicts_with_all = &["verify-only", "stdout"])]
+        guess_extension: bool,
+
+        /// Read shares from stdin, one base64 share per line (as printed by `encrypt --shares-stdout`)
+        #[clap(long, conflicts_with_all = &["share-dir", "recursive", "all"])]
+        shares_stdin: bool,
+
+        /// Read shares from this .ccmbundle file (as written by `encrypt --share-bundle`) instead of the share directory
+        #[clap(parse(from_os_str), long, value_name = "PATH", conflicts_with_all = &["share-dir", "recursive", "all", "shares-stdin"])]
+        share_bundle: Option<PathBuf>,
+
+        /// Use this nonce (hex) instead of the one in the file's header, e.g. if the header's been damaged but the nonce survives in the share filenames
+        #[clap(long, value_name = "HEX", conflicts_with = "recursive")]
+        nonce: Option<String>,
+
+        /// Associated data the file was encrypted with (encrypt --aad), if any; it's asked for if needed and not given
+        #[clap(long, value_name = "TEXT", forbid_empty_values = true)]
+        aad: Option<String>,
+
+        /// Path to write the decrypted file to, or a directory to write it into (defaults to <FILE> without .ccm)
+        #[clap(parse(from_os_str), short, long)]
+        output: Option<PathBuf>,
     },
-    /// Print license information
-    Licenses {},
-}
+    /// Print header info of an encrypted file or share (no shares needed)
+    Info {
+        /// Path to the encrypted file or share
+        #[clap(parse(from_os_str), forbid_empty_values = true)]
+        file: PathBuf,
+    },
+    /// Check a single share on its own: that it's intact, and (if signed) that it matches its own signature
+    VerifyShare {
+        /// Path to the share
+        #[clap(parse(from_os_str), forbid_empty_values = true)]
+        share: PathBuf,
+    },
+    /// Re-split an encrypted file's key into a new set of shares, without re-encrypting it
+    Rekey {
+        /// Path to the encrypted file
+        #[clap(parse(from_os_str), forbid_empty_values = true)]
+        file: PathBuf,
 
-/*----------+
-| constants |
------------*/
+        /// New total number of shares to generate (max 255)
+        #[clap(parse(try_from_str = parse_share_count))]
+        players: u8,
 
-// package version
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-// algorithm version (used for major changes to enc/dec algo -- added to file headers)
-const ALGO_VERSION: u8 = 1;
-// key length in bytes (can only be a 256-bit key for chacha20)
-const KEY_LENGTH_BYTES: usize = 32;
-// nonce length in bytes
-const NONCE_LENGTH_BYTES: usize = 12;
-
-struct ShareFromFile { // struct for storing info we retrieve from a share file
-    threshold: u8,
-    is_signed: bool,
-    nonce: Vec<u8>,
-    pub_key: Option<PublicKey>,
-    signature: Option<Signature>,
-    share_data: Share,
-}
+        /// New number of shares needed to reconstruct the secret (max 255; cannot be more than total)
+        #[clap(parse(try_from_str = parse_share_count))]
+        threshold: u8,
 
-/*-----------------+
-| file header crap |
--------------------*/
-const HEADER_FILE: [u8; 3] = [67, 67, 77]; // "CCM"
-const HEADER_SHARE: [u8; 4] = [67, 67, 77, 83]; // "CCMS"
+        /// Path to the directory containing the current shares (defaults to current working dir)
+        #[clap(parse(from_os_str), short, long)]
+        share_dir: Option<PathBuf>,
 
-// number of bytes before nonce in header(s)
-const HEADER_PRE_NONCE_BYTES_FILE: usize = 6;
-const HEADER_PRE_NONCE_BYTES_SHARE: usize = 7;
+        /// Path to the directory to write the new shares to (must be different to the current share directory; created if need be)
+        #[clap(parse(from_os_str), short, long, forbid_empty_values = true)]
+        new_share_dir: PathBuf,
 
-// location of the is_signed bool
-const HEADER_IS_SIGNED_BYTE_FILE: usize = 6;
-const HEADER_IS_SIGNED_BYTE_SHARE: usize = 7;
+        /// Treat all files in share directory as potential shares (not recommended)
+        #[clap(short, long)]
+        all: bool,
 
-/* FILE HEADER STRUCTURE
+        /// Force shares to have valid signatures before use (only works with signed files)
+        #[clap(long)]
+        strict: bool,
 
-Files (18 bytes w/o public key and sig)
-43 43 4D VV TT SS NN NN NN NN NN NN NN NN NN NN NN NN
-(32 byte public key)
-(64 byte signature)
-content
+        /// Protect each new share with a passphrase (you will be prompted for it; needed again to decrypt)
+        #[clap(long)]
+        passphrase: bool,
 
-Shares (20 bytes w/o public key and sig)
-43 43 4D 53 VV TT SS NN NN NN NN NN NN NN NN NN NN NN NN 00
-(32 byte public key)
-(64 byte signature)
-content
+        /// Re-sign with the keypair saved in this key file, rather than a new one (signed files only)
+        #[clap(parse(from_os_str), long)]
+        key_file: Option<PathBuf>,
 
-VV = version
-TT = threshold
-SS = is signed?
-NN = nonce bytes
-*/
+        /// Write new shares even if shares for the same nonce already exist in the new share directory (overwriting them)
+        #[clap(long)]
+        force: bool,
 
-// number of bytes total in header(s) before the signature or public key
-const HEADER_LENGTH_FILE: usize = HEADER_FILE.len() + 1 + 1 + 1 + NONCE_LENGTH_BYTES; // 18 bytes
-const HEADER_LENGTH_SHARE: usize = HEADER_SHARE.len() + 1 + 1 + 1 + NONCE_LENGTH_BYTES + 1; // 20 bytes
+        /// Permissions for the new share files, in octal (Unix only; by default only you can read them)
+        #[clap(long, value_name = "OCTAL", default_value = DEFAULT_SHARE_MODE, parse(try_from_str = parse_mode))]
+        mode: u32,
 
-/*----------+
-| functions |
------------*/
+        /// Associated data the file was encrypted with (encrypt --aad), if any; the re-encrypted file is bound to it too
+        #[clap(long, value_name = "TEXT", forbid_empty_values = true)]
+        aad: Option<String>,
+    },
+    /// Re-encrypt a file under a new key and nonce with a new set of shares, so the old shares stop working
+    Rotate {
+        /// Path to the encrypted file
+        #[clap(parse(from_os_str), forbid_empty_values = true)]
+        file: PathBuf,
 
-fn absolute_path(path: impl AsRef<Path>) -> Result<PathBuf> { // absolute path code knicked from SO
-    let path = path.as_ref();
+        /// Path to the directory containing the current shares (defaults to current working dir)
+        #[clap(parse(from_os_str), short, long)]
+        share_dir: Option<PathBuf>,
 
-    let absolute = if path.is_absolute() {
-        path.to_path_buf()
-    } else {
-        env::current_dir()?.join(path)
-    }.clean();
+        /// Path to the directory to write the new shares to (must be different to the current share directory; created if need be)
+        #[clap(parse(from_os_str), short, long, forbid_empty_values = true)]
+        new_share_dir: PathBuf,
 
-    Ok(absolute)
-}
+        /// New total number of shares to generate (max 255; how many there were isn't recorded, so it has to be given)
+        #[clap(long, parse(try_from_str = parse_share_count))]
+        players: u8,
 
-fn strip_newline(input: &str) -> &str { // trailing newline stripper (also knicked from SO)
-    input
-        .strip_suffix("\r\n")
-        .or(input.strip_suffix("\n"))
-        .unwrap_or(input)
-}
+        /// New number of shares needed to reconstruct the secret (max 255; defaults to the current threshold)
+        #[clap(long, parse(try_from_str = parse_share_count))]
+        threshold: Option<u8>,
 
-fn stringify_path(path: &PathBuf) -> String { // turn path into string for printing
-    let str_path = absolute_path(path).unwrap().into_os_string().into_string().unwrap();
-    str_path
-}
+        /// Treat all files in share directory as potential shares (not recommended)
+        #[clap(short, long)]
+        all: bool,
 
-fn get_paths(share_dir: Option<PathBuf>, target_file: PathBuf) -> [PathBuf; 2] { // Resolve the targeted file and share directory
-    // Get target file from path buffer
-    println!("[+] File: {}", stringify_path(&target_file) );
+        /// Force shares to have valid signatures before use (only works with signed files)
+        #[clap(long)]
+        strict: bool,
 
-    let share_dir = match share_dir {
-        Some(val) => PathBuf::from(val), // directory provided
-        None => { // default to working dir
-            println!("[+] Shares directory not provided... using current working directory");
-            let default_dir = env::current_dir().unwrap();
+        /// Protect each new share with a passphrase (you will be prompted for it; needed again to decrypt)
+        #[clap(long)]
+        passphrase: bool,
 
-            nl();
-            println!("[#] Would you like to continue,");
-            println!("[#] using {} as the share directory?", stringify_path(&default_dir) );
-            println!("[#] (Ctrl+C to abort; provide path to use that instead; empty for default)");
+        /// Re-sign with the keypair saved in this key file, rather than a new one (signed files only)
+        #[clap(parse(from_os_str), long)]
+        key_file: Option<PathBuf>,
 
-            // Wait for user confirmation
-            let mut confirm = String::new();
-            io::stdin().read_line(&mut confirm).expect("[!] Critical error with input");
-            
-            let confirm: &str = strip_newline(&confirm[..]);
+        /// Write new shares even if shares for the same nonce already exist in the new share directory (overwriting them)
+        #[clap(long)]
+        force: bool,
 
-            if !confirm.is_empty() {
-                PathBuf::from(confirm)
-            } else {
-                PathBuf::from(default_dir)
-            }
-        }
-    };
+        /// Permissions for the new share files, in octal (Unix only; by default only you can read them)
+        #[clap(long, value_name = "OCTAL", default_value = DEFAULT_SHARE_MODE, parse(try_from_str = parse_mode))]
+        mode: u32,
 
-    let paths: [PathBuf; 2] = [target_file, share_dir];
-    paths
-}
+        /// Associated data the file was encrypted with (encrypt --aad), if any; the re-encrypted file is bound to it too
+        #[clap(long, value_name = "TEXT", forbid_empty_values = true)]
+        aad: Option<String>,
+    },
+    /// Deal more shares for an encrypted file from its current ones, to go alongside them (same key, same threshold)
+    AddShares {
+        /// Path to the encrypted file
+        #[clap(parse(from_os_str), forbid_empty_values = true)]
+        file: PathBuf,
 
-fn nl(){ // Newline
-    println!("");
-}
+        /// Number of new shares to deal
+        #[clap(parse(try_from_str = parse_share_count))]
+        count: u8,
 
-fn enl(){ // Newline to stderr
-    eprintln!("");
-}
+        /// Path to the directory containing the current shares (defaults to current working dir)
+        #[clap(parse(from_os_str), short, long)]
+        share_dir: Option<PathBuf>,
 
-fn fatal_error(error: &io::Error, diagnosis: String) { // Fatal error handling (read: aborting)
-    nl();
-    eprintln!("[!] {}", &diagnosis);
-    eprintln!("[!] {}", &error.to_string() );
-    nl();
-    process::exit(1);
-}
+        /// Path to the directory to write the new shares to (defaults to the share directory; created if need be)
+        #[clap(parse(from_os_str), short, long)]
+        new_share_dir: Option<PathBuf>,
 
-fn share_from_file(file: &Path, nonce: &Vec<u8>) -> Result<ShareFromFile> { // Pull shares back out of share files
-    let mut share_header = read_file(&file);
+        /// Number the new shares from this index, rather than straight after the highest one found (shares handed out earlier, and not in the share directory, can't be seen)
+        #[clap(long, value_name = "N", parse(try_from_str = parse_share_count))]
+        first_index: Option<u8>,
 
-    if share_header.len() < HEADER_LENGTH_SHARE { // this is clearly not a share and we will panic if we try to slice < header bytes
-        return Err( Error::new( ErrorKind::Other, "Invalid share (file smaller than CCMS header)" ) )
-    }
+        /// Treat all files in share directory as potential shares (not recommended)
+        #[clap(short, long)]
+        all: bool,
 
-    let share_nonce = (&share_header[HEADER_PRE_NONCE_BYTES_SHARE..(HEADER_PRE_NONCE_BYTES_SHARE + NONCE_LENGTH_BYTES)]).to_vec(); // get share's nonce
-    let share_threshold = share_header[HEADER_SHARE.len() + 1]; // threshold, according to this share
-    let mut share_is_signed: bool = false; // is this share signed?
-    let mut share_pubkey: Option<PublicKey> = None; // public key
-    let mut share_signature: Option<Signature> = None; // signature
+        /// Force shares to have valid signatures before use (only works with signed files)
+        #[clap(long)]
+        strict: bool,
 
-    if share_header[0..(HEADER_SHARE.len())] != HEADER_SHARE { // share is missing header
-        return Err( Error::new( ErrorKind::Other, "Invalid share (CCMS header missing)" ) )
-    }
+        /// Protect each new share with a passphrase (you will be prompted for it; needed again to decrypt)
+        #[clap(long)]
+        passphrase: bool,
 
-    if &share_nonce == nonce { // compare share nonce to file
+        /// Sign the new shares with the keypair saved in this key file (needed for signed files -- it must be the key the file was signed with)
+        #[clap(parse(from_os_str), long)]
+        key_file: Option<PathBuf>,
 
-        // is this share signed?
-        if share_header[HEADER_IS_SIGNED_BYTE_SHARE - 1] != 0 {
-            share_is_signed = true;
+        /// Overwrite files already at the new shares' paths
+        #[clap(long)]
+        force: bool,
 
-            if share_header.len() < (HEADER_LENGTH_SHARE + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH) { 
-                // this is clearly not a share and we will panic if we try to slice < header bytes
-                return Err( Error::new( ErrorKind::Other, "Invalid share (file smaller than signed CCMS header)" ) )
-            }
+        /// Permissions for the new share files, in octal (Unix only; by default only you can read them)
+        #[clap(long, value_name = "OCTAL", default_value = DEFAULT_SHARE_MODE, parse(try_from_str = parse_mode))]
+        mode: u32,
+    },
+    /// Recover a file's key from its shares and print it, without decrypting anything
+    Recombine {
+        /// Path to the encrypted file
+        #[clap(parse(from_os_str), forbid_empty_values = true)]
+        file: PathBuf,
 
-            let share_pubkey_res = PublicKey::from_bytes(&share_header[HEADER_LENGTH_SHARE..(HEADER_LENGTH_SHARE + PUBLIC_KEY_LENGTH)]);
+        /// Treat all files in share directory as potential shares (not recommended)
+        #[clap(short, long)]
+        all: bool,
 
-            share_pubkey = match share_pubkey_res { // check for public key validity (ed25519 will throw if it's garbage)
-                Ok(pk) => Some(pk),
-                Err(error) => {
-                    eprintln!("[^] Bad public key from {}", &file.display() );
-                    eprintln!("[^] {}", error.to_string() );
+        /// Path to the directory containing shares (defaults to current working dir)
+        #[clap(parse(from_os_str), short, long)]
+        share_dir: Option<PathBuf>,
 
-                    return Err( Error::new( ErrorKind::Other, "Invalid share (bad public key)" ) )
-                }
-            };
-            
-            let share_signature_res = Signature::from_bytes(
-                &share_header[(HEADER_LENGTH_SHARE + PUBLIC_KEY_LENGTH)..(HEADER_LENGTH_SHARE + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH)]
-            );
+        /// Force shares to have valid signatures before use (only works with signed files)
+        #[clap(long)]
+        strict: bool,
+    },
+    /// Split a secret of your own (a password, a wallet seed, ...; up to 255 bytes) straight into shares, with nothing encrypted
+    Split {
+        /// Path to the file holding the secret (split byte for byte, trailing newline and all)
+        #[clap(parse(from_os_str), forbid_empty_values = true)]
+        secret_file: PathBuf,
 
-            share_signature = match share_signature_res { // likewise for signatures
-                Ok(sig) => Some(sig),
-                Err(error) => {
-                    eprintln!("[^] Bad signature from {}", &file.display() );
-                    eprintln!("[^] {}", error.to_string() );
-                    
-                    return Err( Error::new( ErrorKind::Other, "Invalid share (bad signature)" ) )
-                }
-            };
-        }
+        /// Total number of shares to generate (max 255)
+        #[clap(parse(try_from_str = parse_share_count))]
+        players: u8,
 
-        let split_length = match share_is_signed { // change header length depending on if signed or unsigned
-            false => HEADER_LENGTH_SHARE,
-            true => (HEADER_LENGTH_SHARE + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH)
-        };
+        /// Number of shares needed to reconstruct the secret (max 255; cannot be more than total)
+        #[clap(parse(try_from_str = parse_share_count))]
+        threshold: u8,
 
-        let share_contents: Vec<u8> = share_header.split_off(split_length); // Grab the contents from the share
-        let found_share = Share::try_from(share_contents.as_slice());
-        
-        match found_share { // Share::try_from returns a borrowed string when it errors for some reason so we have to handle that
-            Ok(sh) => {
-                let share_tuple = ShareFromFile {
-                    threshold: share_threshold,
-                    nonce: share_nonce,
-                    share_data: sh,
-                    is_signed: share_is_signed,
-                    pub_key: share_pubkey,
-                    signature: share_signature,
-                };
+        /// Path to the directory to write shares to (defaults to the config file's, then current working dir)
+        #[clap(parse(from_os_str), short, long)]
+        share_dir: Option<PathBuf>,
 
-                Ok(share_tuple)
-            },
-            Err(err_string) => Err( Error::new( ErrorKind::Other, err_string ) )
-        }
-    }
-    else {
-        Err( Error::new( ErrorKind::Other, "Share does not match target file nonce" ) )
-    }
-}
+        /// Permissions for the share files, in octal (Unix only; by default only you can read them)
+        #[clap(long, value_name = "OCTAL", default_value = DEFAULT_SHARE_MODE, parse(try_from_str = parse_mode))]
+        mode: u32,
+    },
+    /// Recover a secret split with `split` from its shares, and print it (or write it to a file)
+    Combine {
+        /// Path to the directory containing the shares (of one secret; shares of files and other secrets are left alone)
+        #[clap(parse(from_os_str), forbid_empty_values = true)]
+        share_dir: PathBuf,
 
-fn is_encrypted(file: &Vec<u8>) -> Result<Vec<u8>> { // checks if the target file is encrypted; returns header if it is
-    if file.len() < HEADER_LENGTH_FILE { // this is clearly not a CCM file and we will panic if we try to slice < header bytes
-        return Err( Error::new( ErrorKind::Other, "File not encrypted (smaller than CCM header)" ) )
-    }
+        /// Treat all files in share directory as potential shares (not recommended)
+        #[clap(short, long)]
+        all: bool,
+
+        /// Write the secret to this file (only readable by you on Unix), rather than stdout
+        #[clap(parse(from_os_str), short, long)]
+        output: Option<PathBuf>,
+
+        /// Overwrite the output file if it already exists
+        #[clap(long)]
+        force: bool,
+    },
+    /// Generate a signing keypair and save it to a key file (for use with `encrypt --key-file`)
+    Keygen {
+        /// Path to save the key file to (must not already exist); the public key goes in <KEY_FILE>.pub
+        #[clap(parse(from_os_str), forbid_empty_values = true)]
+        key_file: PathBuf,
+
+        /// Print the public key of an existing key file instead of generating a new keypair
+        #[clap(long)]
+        print_pubkey: bool,
+    },
+    /// Check this build's crypto end to end (key splitting, encryption, signing, ...) in memory, before trusting it with real data
+    Doctor {},
+    /// Print license information
+    Licenses {},
+}
+
+/*----------+
+| constants |
+-----------*/
+
+// package version
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// progress is only shown for things taking longer than this, and redrawn at most this often
+const PROGRESS_DELAY: Duration = Duration::from_secs(1);
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+// most threshold-sized subsets of new shares --verify-subsets checks (every one, if there are no more than this)
+const VERIFY_SUBSETS: usize = 16;
+
+// share files that fail to read with a transient error (see is_transient) get another go, after a growing wait
+const DEFAULT_READ_RETRIES: u32 = 3;
+const DEFAULT_RETRY_BACKOFF_MS: u64 = 200;
+
+// with --verbose, how much of a file goes into each timing line
+const VERBOSE_RANGE_BYTES: u64 = 16 * 1024 * 1024;
+
+// how much of the plaintext to keep hold of for guessing its MIME type, when it's not going to a file
+const MIME_PEEK_BYTES: usize = 8192;
+
+// how share files are named, unless --share-name-template says otherwise
+const DEFAULT_SHARE_NAME_TEMPLATE: &str = "{index}-{nonce}-{checksum}.ccms";
+const SHARE_NAME_PLACEHOLDERS: [&str; 5] = ["index", "total", "threshold", "nonce", "checksum"];
+
+// {checksum} in a share's name: this much of the share file's BLAKE3 hash, so a corrupt or misnamed share shows up before it's parsed
+const SHARE_NAME_CHECKSUM_BYTES: usize = 4;
+
+// shares are secret, so by default only their owner gets to read them (Unix)
+const DEFAULT_SHARE_MODE: &str = "600";
+
+// largest QR code we'll make for a share -- past this they get hard to scan off paper
+const QR_MAX_VERSION: i16 = 20;
+
+// ASCII armored shares (--armor): base64 between these lines, wrapped at ARMOR_LINE_LENGTH, then "=" and a checksum
+const ARMOR_BEGIN: &str = "-----BEGIN CHACHAMIR SHARE-----";
+const ARMOR_END: &str = "-----END CHACHAMIR SHARE-----";
+const ARMOR_LINE_LENGTH: usize = 64;
+const ARMOR_CHECKSUM_BYTES: usize = 3; // of the share's BLAKE3 hash (four characters of base64, like OpenPGP's CRC24)
+
+// more than any armored share could be (base64 is 4/3 the size, plus line breaks and the BEGIN/END lines)
+const ARMOR_MAX_LENGTH: usize = 2 * SHARE_MAX_LENGTH + 256;
+
+/*---------+
+| structs  |
+----------*/
+
+struct EncryptOptions { // Settings shared by every file we encrypt in one go
+    schemes: Vec<(u8, u8)>, // players and threshold of each share scheme (PLAYERS THRESHOLD, then any --scheme)
+    sign_file: bool, // sign the encrypted file
+    sign_shares: bool, // sign its shares
+    key_file: Option<PathBuf>,
+    detached_sig: bool, // sign the file in a {name}.ccm.sig, rather than its header
+    output_format: OutputFormat, // keep the header in the encrypted file, or in a {name}.ccm.hdr of its own
+    archive: Option<Vec<ArchiveEntry>>, // encrypt an archive of these files, rather than the target file itself (--archive)
+    force: bool,
+    compression_level: Option<i32>,
+    metadata: bool, // store the original filename etc. in the encrypted file
+    checksum: bool, // store a BLAKE3 checksum of the plaintext in the header
+    label: Option<String>, // store this label in the header
+    aad: Option<String>, // bind the contents to this associated data of the user's own (never stored)
+    share_mac: bool, // MAC each share under the key, to check it belongs to the file once the key's recovered
+    share_output: ShareOutput,
+    share_mode: u32, // permissions for share files (Unix)
+    fast: bool, // skip the share recovery and round-trip checks
+    verify_subsets: bool, // check threshold-sized subsets of the shares recover the key too, not just all of them together
+    time: bool, // report how long each phase took
+    chunk_size: usize, // plaintext bytes per encrypted chunk
+    cipher: Cipher, // what to encrypt it with
+    share_name_template: String, // how to name share files
+    dry_run: bool, // go through the motions, but don't write anything
+    salt: Option<Vec<u8>>, // passphrase salt, if shares are passphrase protected
+    wrapping_key: Option<Zeroizing<Vec<u8>>>, // and the key derived from it
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OutputFormat { // How the encrypted file is laid out
+    Ccm, // header, then ciphertext
+    Raw, // ciphertext alone, with the header in {name}.ccm.hdr
+}
+
+enum ShareOutput { // Where new shares end up
+    Files { qr: bool, threads: Option<usize>, armor: bool }, // {i}-{nonce}.ccms files in the share directory, optionally with QR code PNGs (written this many at a time; None for one per CPU), or armored .ccms.asc ones
+    Stdout, // base64 lines on stdout
+    Bundle(PathBuf), // one .ccmbundle file holding every share
+    DryRun, // nowhere -- just say where they'd have gone
+}
+
+#[derive(Clone, Copy)]
+struct ReadRetries { // How hard to try reading a share file again after a transient error
+    retries: u32, // after the first attempt, so 0 never retries
+    backoff: Duration, // wait before the first retry, doubling after each one
+}
+
+const NO_READ_RETRIES: ReadRetries = ReadRetries { retries: 0, backoff: Duration::ZERO };
+
+struct GatherOptions { // How to go about gathering a file's shares
+    all: bool, // check every file, not just .ccms ones
+    strict: bool,
+    threshold: Option<u8>, // threshold to use if shares disagree with the file, rather than asking
+    threshold_from_shares: bool, // go by the shares' threshold (majority vote), not the file's
+    stdin: bool, // read base64 shares from stdin instead of the share directory
+    bundle: Option<PathBuf>, // or read them from a share bundle
+    max_scanned: Option<usize>, // give up on the share directory after this many files
+    read_retries: ReadRetries, // for share files that fail to read with a transient error
+    exclude: Vec<Pattern>, // files in the share directory not to bother with (--exclude)
+    list_shares: bool, // list the shares found, and check before recovering the key with them
+    verify_all_signatures: bool, // hold back share signature complaints for one report covering every share
+}
+
+struct DecryptOptions { // Settings shared by every file we decrypt in one go
+    gather: GatherOptions,
+    expect_pubkey: Option<Vec<u8>>,
+    verify_fingerprint: bool, // ask a human to confirm the signer's fingerprint
+    min_shares: Option<u8>, // policy minimum of shares to recover the key with (--min-shares), on top of the threshold
+    verify_only: bool,
+    force: bool, // overwrite existing files without asking
+    to_stdout: bool, // write the plaintext to stdout instead of a file
+    guess_extension: bool, // add an extension from the detected MIME type if the name hasn't got one
+    nonce: Option<Vec<u8>>, // use this instead of the nonce in the header (--nonce)
+    aad: Option<String>, // associated data the file was encrypted with (--aad)
+}
+
+struct PeekWriter<W: Write> { // Writer that keeps a copy of the first MIME_PEEK_BYTES written through it (for guessing the MIME type)
+    inner: W,
+    head: Zeroizing<Vec<u8>>,
+}
+
+impl<W: Write> Write for PeekWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = self.inner.write(buf)?;
+        let wanted = MIME_PEEK_BYTES.saturating_sub(self.head.len()).min(written);
+        self.head.extend_from_slice(&buf[..wanted]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct ShareProgress { // --progress-json events for a set of shares being written, from whichever thread finishes one
+    total: usize,
+    written: Mutex<(usize, Option<Instant>)>, // how many so far, and when the last event went out
+}
+
+impl ShareProgress {
+    fn new(total: usize) -> ShareProgress {
+        ShareProgress { total, written: Mutex::new( (0, None) ) }
+    }
+
+    fn written(&self) { // Count another share written, sending an event if it's been long enough (or that's all of them)
+        if !progress_json() {
+            return
+        }
+
+        let mut written = self.written.lock().unwrap_or_else(|poisoned| poisoned.into_inner() );
+        let now = Instant::now();
+
+        written.0 += 1;
+
+        if written.0 < self.total && written.1.is_some_and(|shown| now.duration_since(shown) < PROGRESS_INTERVAL) {
+            return
+        }
+
+        written.1 = Some(now);
+        progress_event( serde_json::json!({ "event": "progress", "phase": "shares", "done": written.0, "total": self.total }) );
+    }
+}
+
+struct Timings { // How long each phase of encrypting a file took (--time)
+    phases: Vec<(&'static str, Duration)>,
+    started: Instant,
+    phase_started: Instant,
+}
+
+impl Timings {
+    fn new() -> Timings {
+        Timings {
+            phases: vec![],
+            started: Instant::now(),
+            phase_started: Instant::now(),
+        }
+    }
+
+    fn lap(&mut self, phase: &'static str) { // The phase that's just finished took everything since the last one
+        self.lap_with(phase, &[]);
+    }
+
+    fn lap_with(&mut self, phase: &'static str, parts: &[(&'static str, Duration)]) { // lap, with some of its time picked out as phases of their own
+        let picked_out: Duration = parts.iter().map(|(_, duration)| *duration ).sum();
+
+        self.phases.push( (phase, self.phase_started.elapsed().saturating_sub(picked_out)) );
+        self.phases.extend(parts);
+        self.phase_started = Instant::now();
+    }
+
+    fn report(&self, target_file: &Path) { // A little table for humans, or one event with the lot
+        let total = self.started.elapsed();
+
+        report_event(
+            &format!("[-] Timings for {}:", stringify_path(target_file)),
+            serde_json::json!({
+                "event": "timings",
+                "path": stringify_path(target_file),
+                "phases": self.phases.iter().map(|(phase, duration)| serde_json::json!({ "phase": phase, "ms": duration.as_secs_f64() * 1000.0 }) ).collect::<Vec<_>>(),
+                "total_ms": total.as_secs_f64() * 1000.0,
+            })
+        );
+
+        if json_output() {
+            return
+        }
+
+        for (phase, duration) in &self.phases {
+            report!("[-]   {:<28} {:>10.3} ms", phase, duration.as_secs_f64() * 1000.0 );
+        }
+
+        report!("[-]   {:<28} {:>10.3} ms", "Total", total.as_secs_f64() * 1000.0 );
+    }
+}
+
+struct Progress<R> { // Reader that shows how far through a (large) file we are on stderr, as it's read
+    inner: R,
+    action: &'static str, // e.g. "Encrypting"
+    done: u64,
+    total: u64,
+    started: Instant,
+    last_shown: Option<Instant>,
+    json_shown: Option<Instant>, // when the last --progress-json event went out
+    enabled: bool, // only for humans watching a terminal
+    range_start: u64, // where the current --verbose timing range started
+    range_started: Instant,
+    reading: Duration, // time spent waiting on `inner` (for --time)
+}
+
+impl<R> Progress<R> {
+    fn new(inner: R, action: &'static str, filepath: &Path, offset: u64) -> Progress<R> { // Track reading `filepath` from `offset` onwards
+        let total = fs::metadata(filepath).map(|m| m.len().saturating_sub(offset) ).unwrap_or(0);
+
+        Progress {
+            inner,
+            action,
+            done: 0,
+            total,
+            started: Instant::now(),
+            reading: Duration::ZERO,
+            last_shown: None,
+            json_shown: None,
+            enabled: !json_output() && !quiet() && !progress_json() && io::stderr().is_terminal(), // a frontend gets the JSON instead
+            range_start: 0,
+            range_started: Instant::now(),
+        }
+    }
+
+    fn time_range(&mut self) { // Report how long the bytes since the last timing took (--verbose), and start a new range
+        let elapsed = self.range_started.elapsed().as_secs_f64();
+        let length = self.done - self.range_start;
+
+        if self.last_shown.is_some() { // clear the progress line out of the way first
+            eprint!("\r\x1b[K");
+        }
+
+        report!("[-] {}: bytes {}-{} in {:.1} ms ({:.1} MiB/s)",
+            self.action,
+            self.range_start,
+            self.done.saturating_sub(1),
+            elapsed * 1000.0,
+            length as f64 / 1048576.0 / elapsed.max(f64::EPSILON),
+        );
+
+        self.range_start = self.done;
+        self.range_started = Instant::now();
+    }
+
+    fn show_json(&mut self, finished: bool) { // Send a --progress-json event, if it's been long enough (or we're done)
+        let now = Instant::now();
+
+        if !finished && self.json_shown.is_some_and(|shown| now.duration_since(shown) < PROGRESS_INTERVAL) {
+            return
+        }
+
+        self.json_shown = Some(now);
+
+        let phase = match self.action {
+            "Encrypting" => "encrypt",
+            "Decrypting" => "decrypt",
+            "Re-encrypting" => "reencrypt",
+            "Hashing" => "checksum",
+            "Verifying signature" => "signature",
+            action => action,
+        };
+
+        progress_event( serde_json::json!({ "event": "progress", "phase": phase, "bytes_done": self.done, "bytes_total": self.total }) );
+    }
+
+    fn show(&mut self) { // Redraw the progress line, if it's been long enough
+        if progress_json() {
+            self.show_json(false);
+        }
+
+        let now = Instant::now();
+
+        let due = match self.last_shown {
+            Some(shown) => now.duration_since(shown) >= PROGRESS_INTERVAL,
+            None => now.duration_since(self.started) >= PROGRESS_DELAY, // quick jobs never show anything
+        };
+
+        if !self.enabled || !due || self.total == 0 {
+            return
+        }
+
+        self.last_shown = Some(now);
+
+        let done = self.done.min(self.total);
+        let elapsed = now.duration_since(self.started).as_secs_f64();
+        let remaining = elapsed * (self.total - done) as f64 / done.max(1) as f64;
+
+        eprint!("\r[-] {}: {:>3}% ({:.1} of {:.1} MiB, ~{:.0}s left)   ",
+            self.action,
+            done * 100 / self.total,
+            done as f64 / 1048576.0,
+            self.total as f64 / 1048576.0,
+            remaining,
+        );
+    }
+}
+
+impl<R: Read> Read for Progress<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let started = Instant::now();
+        let read = self.inner.read(buf)?;
+        self.reading += started.elapsed();
+        self.done += read as u64;
+
+        if verbose() && self.done - self.range_start >= VERBOSE_RANGE_BYTES {
+            self.time_range();
+        }
+
+        self.show();
+        Ok(read)
+    }
+}
+
+impl<R> Drop for Progress<R> {
+    fn drop(&mut self) { // finish the progress line off, if we started one
+        if verbose() && self.done > self.range_start { // whatever's left of the last timing range
+            self.time_range();
+        }
+
+        if self.last_shown.is_some() {
+            self.last_shown = Some(self.started); // force a final redraw
+            self.show();
+            eprintln!();
+        }
+
+        if progress_json() { // so a frontend always sees it reach the end
+            self.show_json(true);
+        }
+    }
+}
+
+/*----------+
+| functions |
+-----------*/
+
+fn absolute_path(path: impl AsRef<Path>) -> Result<PathBuf> { // absolute path, with any . and .. worked out (by component, so non-UTF-8 paths survive)
+    let path = path.as_ref();
+
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()?.join(path)
+    };
+
+    let mut cleaned = PathBuf::new();
+
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => { cleaned.pop(); }, // never goes above the root
+            component => cleaned.push(component),
+        }
+    }
+
+    Ok(cleaned)
+}
+
+fn strip_newline(input: &str) -> &str { // trailing newline stripper (also knicked from SO)
+    input
+        .strip_suffix("\r\n")
+        .or(input.strip_suffix("\n"))
+        .unwrap_or(input)
+}
+
+fn stringify_path(path: &Path) -> String { // turn path into string for printing (lossily -- paths needn't be UTF-8)
+    match absolute_path(path) {
+        Ok(absolute) => absolute.display().to_string(),
+        Err(_) => path.display().to_string(), // no working directory to resolve it against; show it as given
+    }
+}
+
+fn working_dir() -> Outcome<PathBuf> { // The current working directory (the default share directory), which may have gone
+    env::current_dir().map_err(|error| Error::failed(error, "Could not find the current working directory".to_string()) )
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Config { // Defaults from chachamir.toml, for when the command line doesn't say
+    players: Option<u8>,
+    threshold: Option<u8>,
+    share_dir: Option<PathBuf>, // relative to the config file
+    sign: Option<bool>,
+}
+
+const CONFIG_FILE_NAME: &str = "chachamir.toml";
+
+fn config_search_paths() -> Vec<PathBuf> { // Where a config file is looked for, in order: the working directory, then $XDG_CONFIG_HOME (or ~/.config)
+    let config_home = env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty() ).map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config") ) );
+
+    let mut paths = vec![PathBuf::from(CONFIG_FILE_NAME)];
+    paths.extend( config_home.map(|config_home| config_home.join(CONFIG_FILE_NAME) ) );
+
+    paths
+}
+
+fn load_config(config_path: Option<&Path>) -> Outcome<Option<(PathBuf, Config)>> { // Read the config file given with --config, or the first one found; None if there isn't one
+    let config_path = match config_path.map(Path::to_path_buf).or_else(|| config_search_paths().into_iter().find(|path| path.is_file() )) {
+        Some(config_path) => config_path, // if it was asked for, it had better be there
+        None => return Ok(None),
+    };
+
+    let config = fs::read_to_string(&config_path).and_then(|contents| {
+        toml::from_str::<Config>(&contents).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error) )
+    });
+
+    let mut config = match config {
+        Ok(config) => config,
+        Err(error) => return Err( Error::failed(error, format!("Could not read config file {}", config_path.display())) ),
+    };
+
+    for (name, count) in [("players", config.players), ("threshold", config.threshold)] {
+        if count == Some(0) {
+            return Err( Error::refused(format!("{} in config file {} must be between 1 and 255", name, config_path.display())) );
+        }
+    }
+
+    // a share directory in the config file means the same thing wherever we're run from
+    if let (Some(share_dir), Some(config_dir)) = (&config.share_dir, config_path.parent()) {
+        config.share_dir = Some( config_dir.join(share_dir) );
+    }
+
+    Ok( Some((config_path, config)) )
+}
+
+fn shift_positionals_for_input_list(command: &mut Commands) -> Outcome<()> { // With --input-list there's no FILE, so clap will have put PLAYERS there (and THRESHOLD in PLAYERS)
+    if let Commands::Encrypt { file, players, threshold, input_list: Some(_), .. } = command {
+        let first = match file.take() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+
+        if threshold.is_some() {
+            return Err( Error::refused("A file can't be given along with --input-list".to_string()) );
+        }
+
+        match parse_share_count( &first.to_string_lossy() ) {
+            Ok(count) => {
+                *threshold = players.take();
+                *players = Some(count);
+            },
+            Err(error) => return Err( Error::refused(format!("A file can't be given along with --input-list (or PLAYERS {})", error)) ),
+        }
+    }
+
+    Ok(())
+}
+
+fn merge_named_share_counts(command: &mut Commands) -> Outcome<()> { // --players/--threshold stand in for PLAYERS/THRESHOLD, but giving both is asking for a mix-up
+    if let Commands::Encrypt { players, threshold, named_players, named_threshold, .. } = command {
+        for (positional, named, name, flag) in [(players, named_players, "PLAYERS", "--players"), (threshold, named_threshold, "THRESHOLD", "--threshold")] {
+            if positional.is_some() && named.is_some() {
+                return Err( Error::refused(format!("{} was given both as a positional argument and with {}; use one or the other", name, flag)) );
+            }
+
+            *positional = positional.or( named.take() );
+        }
+    }
+
+    Ok(())
+}
+
+fn apply_config(command: &mut Commands, config: &Config) { // Fill in anything the command line left out from the config file
+    match command {
+        Commands::Encrypt { players, threshold, share_dir, sign, no_sign, .. } => {
+            *players = players.or(config.players);
+            *threshold = threshold.or(config.threshold);
+            *share_dir = share_dir.take().or_else(|| config.share_dir.clone() );
+            *sign = *sign || (!*no_sign && config.sign.unwrap_or(false));
+        },
+        Commands::Decrypt { share_dir, .. } if share_dir.is_empty() => {
+            share_dir.extend( config.share_dir.clone() );
+        },
+        Commands::Rekey { share_dir, .. } | Commands::Rotate { share_dir, .. } | Commands::AddShares { share_dir, .. } | Commands::Recombine { share_dir, .. } | Commands::Split { share_dir, .. } => {
+            *share_dir = share_dir.take().or_else(|| config.share_dir.clone() );
+        },
+        _ => {},
+    }
+}
+
+fn required_count(count: Option<u8>, name: &str) -> Outcome<u8> { // A players or threshold count that may have come from the config file, which has to have come from somewhere
+    match count {
+        Some(count) => Ok(count),
+        None => Err( Error::refused(format!("{} wasn't given, and there's no config file with a default for it", name)) ),
+    }
+}
+
+fn get_paths(share_dir: Option<PathBuf>, target_file: PathBuf) -> Outcome<[PathBuf; 2]> { // Resolve the targeted file and share directory
+    // Get target file from path buffer
+    report!("[+] File: {}", stringify_path(&target_file) );
+
+    let share_dir = match share_dir {
+        Some(val) => val, // directory provided
+        None => { // default to working dir
+            report!("[+] Shares directory not provided... using current working directory");
+            let default_dir = working_dir()?;
+
+            if non_interactive() { // nobody to ask
+                return Ok([target_file, default_dir])
+            }
+
+            nl();
+            report!("[#] Would you like to continue,");
+            report!("[#] using {} as the share directory?", stringify_path(&default_dir) );
+            report!("[#] (Ctrl+C to abort; provide path to use that instead; empty for default)");
+
+            // Wait for user confirmation
+            let confirm = read_answer()?;
+
+            if !confirm.is_empty() {
+                PathBuf::from(confirm)
+            } else {
+                default_dir
+            }
+        }
+    };
+
+    Ok([target_file, share_dir])
+}
+
+fn ensure_share_dir(shares_dir: &Path, force: bool, dry_run: bool) -> Outcome<()> { // Make sure new shares have a directory to go in, offering to create it if there isn't one yet
+    if shares_dir.is_dir() {
+        return Ok(())
+    }
+
+    if shares_dir.exists() { // creating it wouldn't help
+        nl();
+        report!("[!] Share directory {} is not a directory", stringify_path(shares_dir) );
+        return Err( Error::refused("Choose a different share directory".to_string()) );
+    }
+
+    if dry_run {
+        report!("[-] Would create share directory {}", stringify_path(shares_dir) );
+        return Ok(())
+    }
+
+    // creating it is the default answer, so --force and --non-interactive both go straight ahead
+    if !force && !non_interactive() {
+        nl();
+        report!("[#] Share directory {} does not exist", stringify_path(shares_dir) );
+        report!("[#] Would you like to create it?");
+        report!("[#] (Ctrl+C to abort; Enter to create it)");
+
+        read_answer()?;
+    }
+
+    if let Err(error) = fs::create_dir_all(shares_dir) {
+        return Err( Error::failed(error, format!("Could not create share directory {}", shares_dir.display())) );
+    }
+
+    report!("[+] Created share directory {}", stringify_path(shares_dir) );
+    Ok(())
+}
+
+fn get_output_path(output: Option<PathBuf>, derived_path: PathBuf) -> Outcome<PathBuf> { // Resolve where to write output, if the user chose somewhere themselves
+    let output = match output {
+        Some(val) => val, // path provided
+        None => return Ok(derived_path), // default to the path derived from the target file
+    };
+
+    let output = match absolute_path(&output) {
+        Ok(path) => path,
+        Err(error) => return Err( Error::failed(error, format!("Could not resolve output path {}", output.display())) ),
+    };
+
+    if output.is_dir() { // output to a directory, using the derived filename
+        let filename = derived_path.file_name().unwrap_or_default();
+        return Ok( output.join(filename) )
+    }
+
+    match output.parent() { // make sure we can actually create the file before we get going
+        Some(parent) if !parent.is_dir() => {
+            nl();
+            report!("[!] Output directory {} does not exist", parent.display() );
+            Err( Error::refused("Create it first, or choose a different output path".to_string()) )
+        },
+        _ => Ok(output),
+    }
+}
+
+fn nl(){ // Newline (to stderr, along with the rest of the status lines)
+    if !json_output() && !quiet() {
+        eprintln!();
+    }
+}
+
+fn json_output() -> bool { // are we reporting in JSON?
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+fn stdout_reserved() -> bool { // is stdout kept for shares or plaintext?
+    STDOUT_RESERVED.load(Ordering::Relaxed)
+}
+
+fn quiet() -> bool { // are we keeping status lines to ourselves?
+    QUIET.load(Ordering::Relaxed)
+}
+
+fn verbose() -> bool { // are we reporting timings?
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+fn progress_json() -> bool { // are we reporting progress as JSON?
+    PROGRESS_JSON.load(Ordering::Relaxed)
+}
+
+fn progress_event(progress: serde_json::Value) { // A --progress-json line -- on stderr, whatever else is going on, so it never gets mixed up with data on stdout
+    eprintln!("{}", progress);
+}
+
+fn non_interactive() -> bool { // are we allowed to wait on stdin?
+    NON_INTERACTIVE.load(Ordering::Relaxed)
+}
+
+fn read_answer() -> Outcome<String> { // A line typed in answer to a prompt (sans newline) -- stdin running dry counts as "no", rather than as an answer
+    let mut answer = String::new();
+
+    match io::stdin().read_line(&mut answer) {
+        Ok(0) => Err( Error::needs_confirmation("No answer given (stdin was closed)".to_string()) ),
+        Ok(_) => Ok( strip_newline(&answer).to_owned() ),
+        Err(error) => Err( Error::failed(error, "Could not read an answer from stdin".to_string()) ),
+    }
+}
+
+fn audit(record: serde_json::Value) -> Outcome<()> { // Append a record to the --audit-log (if there is one), with the time it happened
+    let mut audit_log = AUDIT_LOG.lock().unwrap_or_else(|poisoned| poisoned.into_inner() );
+
+    let file = match audit_log.as_mut() {
+        Some(file) => file,
+        None => return Ok(()),
+    };
+
+    let mut timestamped = serde_json::json!({ "time": utc_timestamp() });
+
+    if let (Some(timestamped), serde_json::Value::Object(record)) = (timestamped.as_object_mut(), record) {
+        timestamped.extend(record);
+    }
+
+    // synced straight away, so the record survives whatever happens next (including us bailing out under --strict)
+    writeln!(file, "{}", timestamped).and_then(|_| file.sync_data() )
+        .map_err(|error| Error::failed(error, "Could not write to the audit log".to_string()) ) // a log with gaps in it is no audit trail
+}
+
+fn track_partial(path: &Path) { // Remember a file we're about to write for the current encryption, so Ctrl+C can take it away again
+    PARTIAL_FILES.lock().unwrap_or_else(|poisoned| poisoned.into_inner() ).push( path.to_path_buf() );
+}
+
+fn finish_partial() { // The current encryption's done, so everything it wrote is a keeper
+    PARTIAL_FILES.lock().unwrap_or_else(|poisoned| poisoned.into_inner() ).clear();
+}
+
+fn remove_partial(partial_files: &mut Vec<PathBuf>) { // Remove (and forget) whatever the unfinished encryption has written so far, reporting what went
+    let mut removed: Vec<PathBuf> = vec![];
+
+    for path in partial_files.drain(..) {
+        // anything caught halfway through being written is still under its temp name
+        let finished = fs::remove_file(&path).is_ok();
+        let unfinished = fs::remove_file(temp_path(&path)).is_ok();
+
+        if finished || unfinished {
+            removed.push(path);
+        }
+    }
+
+    if !removed.is_empty() {
+        report!("[#] Removed {} file(s) written for the unfinished encryption:", removed.len() );
+
+        for path in removed {
+            report!("[#]   {}", stringify_path(&path) );
+        }
+    }
+}
+
+fn clean_up_interrupted() { // Ctrl+C handler: remove whatever the unfinished encryption has written so far, so nobody's handed shares for a file that was never written
+    // held until we exit, so nothing new gets tracked (and left behind) while we're at it
+    let mut partial_files = PARTIAL_FILES.lock().unwrap_or_else(|poisoned| poisoned.into_inner() );
+
+    nl();
+    report!("[!] Interrupted");
+
+    remove_partial(&mut partial_files);
+
+    nl();
+    process::exit(EXIT_INTERRUPTED);
+}
+
+fn utc_timestamp() -> String { // The time now, as RFC 3339 in UTC (e.g. 2024-05-01T12:34:56.789Z)
+    rfc3339_timestamp(SystemTime::now())
+}
+
+fn rfc3339_timestamp(time: SystemTime) -> String { // A time as RFC 3339 in UTC
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, seconds) = (since_epoch.as_secs() / 86400, since_epoch.as_secs() % 86400);
+
+    // days since 1970-01-01 to a date, from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153; // counting from March
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", year, month, day, seconds / 3600, seconds % 3600 / 60, seconds % 60, since_epoch.subsec_millis())
+}
+
+fn parse_since(value: &str) -> std::result::Result<SystemTime, String> { // clap parser for --since: RFC 3339 (2024-05-01T12:34:56Z, with any offset or fraction), or a date alone for midnight UTC
+    let error = || "must be an RFC 3339 time, e.g. 2024-05-01T12:34:56Z, or a date, e.g. 2024-05-01".to_string();
+    let value = value.trim();
+
+    let number = |range: std::ops::Range<usize>| -> std::result::Result<i64, String> {
+        value.get(range).filter(|digits| digits.bytes().all(|byte| byte.is_ascii_digit()) ).and_then(|digits| digits.parse().ok() ).ok_or_else(error)
+    };
+
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+
+    if value.get(4..5) != Some("-") || value.get(7..8) != Some("-") || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(error())
+    }
+
+    let mut seconds = 0;
+    let mut nanos = 0;
+
+    if value.len() > 10 { // the time, and which zone it's in
+        if !matches!(value.get(10..11), Some("T" | "t" | " ")) || value.get(13..14) != Some(":") || value.get(16..17) != Some(":") {
+            return Err(error())
+        }
+
+        let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+
+        if hour > 23 || minute > 59 || second > 60 { // 60 for a leap second
+            return Err(error())
+        }
+
+        seconds = hour * 3600 + minute * 60 + second;
+
+        let mut rest = &value[19..];
+
+        if let Some(fraction) = rest.strip_prefix('.') { // fractions of a second, to the nanosecond
+            let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+
+            if digits == 0 {
+                return Err(error())
+            }
+
+            nanos = fraction[..digits.min(9)].parse::<u32>().map_err(|_| error() )? * 10u32.pow(9 - digits.min(9) as u32);
+            rest = &fraction[digits..];
+        }
+
+        seconds -= match rest { // back to UTC
+            "Z" | "z" => 0,
+            _ if rest.len() == 6 && matches!(&rest[..1], "+" | "-") && &rest[3..4] == ":" => {
+                let offset = rest[1..3].parse::<i64>().map_err(|_| error() )? * 3600 + rest[4..6].parse::<i64>().map_err(|_| error() )? * 60;
+
+                if &rest[..1] == "-" { -offset } else { offset }
+            },
+            _ => return Err(error()),
+        };
+    }
+
+    // a date to days since 1970-01-01, from http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let since_epoch = days * 86400 + seconds;
+
+    match u64::try_from(since_epoch) {
+        Ok(since_epoch) => Ok(UNIX_EPOCH + Duration::new(since_epoch, nanos)),
+        Err(_) => Err("must be after 1970".to_string()),
+    }
+}
+
+fn modified_since(path: &Path, since: SystemTime) -> bool { // Has this file changed since --since? (if the filesystem can't say, assume it has, rather than leave it out of the backup)
+    match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified >= since,
+        Err(error) => {
+            report!("[#] Could not get the modification time of {}, so encrypting it anyway | {}", stringify_path(path), error );
+            true
+        }
+    }
+}
+
+fn report_line(line: &str) { // Print a status line for humans (on stderr), or as a JSON "message" event
+    if !json_output() {
+        // with --quiet, only errors ([!]), warnings ([#]) and results ([&]) get through
+        if !quiet() || !matches!(line.get(..4), Some("[*] " | "[+] " | "[-] " | "[%] " | "[^] ")) {
+            eprintln!("{}", line);
+        }
+        return
+    }
+
+    // our "[x] " prefixes say how serious a line is
+    let (level, message) = match line.get(..4) {
+        Some("[!] ") => ("error", &line[4..]),
+        Some("[#] ") => ("warning", &line[4..]),
+        Some(prefix) if prefix.starts_with('[') && prefix.ends_with("] ") => ("info", &line[4..]),
+        _ => ("info", line),
+    };
+
+    let event = serde_json::json!({
+        "event": "message",
+        "level": level,
+        "message": message,
+    });
+
+    print_event(&event);
+}
+
+fn print_event(event: &serde_json::Value) { // Print a JSON event -- everything goes to stdout in JSON mode, so there's one stream to parse (unless stdout is taken)
+    match stdout_reserved() {
+        true => eprintln!("{}", event),
+        false => println!("{}", event),
+    }
+}
+
+fn report_event(line: &str, event: serde_json::Value) { // Report a milestone: the line for humans, or the event itself in JSON mode
+    match json_output() {
+        true => print_event(&event),
+        false => report_line(line),
+    }
+}
+
+fn output_line(line: &str) { // Print data for humans on stdout (even with --quiet), or as a JSON "message" event
+    match json_output() || stdout_reserved() {
+        true => report_line(line),
+        false => println!("{}", line),
+    }
+}
+
+fn output_event(line: &str, event: serde_json::Value) { // report_event, for data: the line goes to stdout
+    match json_output() {
+        true => print_event(&event),
+        false => output_line(line),
+    }
+}
+
+#[derive(Debug)]
+enum Error { // Why a command gave up -- handed back up to main, which reports it and picks the exit code
+    Failed { diagnosis: String, error: Option<io::Error> }, // anything without a more specific code (mostly I/O)
+    NeedsConfirmation { diagnosis: String, error: Option<io::Error> }, // we'd have to ask a human, but can't (or they said no)
+    NotEncrypted { diagnosis: String, error: Option<io::Error> },
+    NoShares { diagnosis: String, error: Option<io::Error> },
+    RecoveryFailed { diagnosis: String, error: Option<io::Error> },
+    DecryptionFailed { diagnosis: String, error: Option<io::Error> },
+    Tampered { diagnosis: String, error: Option<io::Error> }, // a signature, public key or share that doesn't check out
+    Stopped { diagnosis: String, error: Option<io::Error> }, // not an error at all: stopping early, as asked (e.g. after --list-shares)
+}
+
+type Outcome<T> = std::result::Result<T, Error>; // Result is io::Result here, for what the library hands back
+
+impl Error {
+    fn failed(error: io::Error, diagnosis: String) -> Error {
+        Error::Failed { diagnosis, error: Some(error) }
+    }
+
+    fn decryption(error: io::Error, diagnosis: String) -> Error { // Failing to authenticate is a decryption failure; anything else is just I/O
+        match error.kind() {
+            io::ErrorKind::InvalidData => Error::DecryptionFailed { diagnosis, error: Some(error) },
+            _ => Error::Failed { diagnosis, error: Some(error) },
+        }
+    }
+
+    // the rest are for giving up of our own accord, where there's no io::Error behind it -- just the diagnosis
+
+    fn refused(diagnosis: String) -> Error { // Something we won't do, e.g. a bad argument
+        Error::Failed { diagnosis, error: None }
+    }
+
+    fn needs_confirmation(diagnosis: String) -> Error {
+        Error::NeedsConfirmation { diagnosis, error: None }
+    }
+
+    fn no_shares(diagnosis: String) -> Error {
+        Error::NoShares { diagnosis, error: None }
+    }
+
+    fn recovery_failed(diagnosis: String) -> Error {
+        Error::RecoveryFailed { diagnosis, error: None }
+    }
+
+    fn tampered(diagnosis: String) -> Error {
+        Error::Tampered { diagnosis, error: None }
+    }
+
+    fn stopped(diagnosis: String) -> Error {
+        Error::Stopped { diagnosis, error: None }
+    }
+
+    fn exit_code(&self) -> i32 {
+        match self {
+            Error::Failed { .. } => EXIT_FAILURE,
+            Error::NeedsConfirmation { .. } => EXIT_NEEDS_CONFIRMATION,
+            Error::NotEncrypted { .. } => EXIT_NOT_ENCRYPTED,
+            Error::NoShares { .. } => EXIT_NO_SHARES,
+            Error::RecoveryFailed { .. } => EXIT_RECOVERY_FAILED,
+            Error::DecryptionFailed { .. } => EXIT_DECRYPTION_FAILED,
+            Error::Tampered { .. } => EXIT_TAMPERED,
+            Error::Stopped { .. } => 0,
+        }
+    }
+
+    fn parts(&self) -> (&str, Option<&io::Error>) { // what we were doing, and what went wrong (if it was more than our own say-so)
+        match self {
+            Error::Failed { diagnosis, error } | Error::NeedsConfirmation { diagnosis, error } | Error::NotEncrypted { diagnosis, error } | Error::NoShares { diagnosis, error }
+                | Error::RecoveryFailed { diagnosis, error } | Error::DecryptionFailed { diagnosis, error } | Error::Tampered { diagnosis, error } | Error::Stopped { diagnosis, error } => (diagnosis, error.as_ref()),
+        }
+    }
+}
+
+fn report_error(error: &Error) { // Report the error that ended a command: two [!] lines for humans, or one "error" event
+    let (diagnosis, cause) = error.parts();
+
+    if let Error::Stopped { .. } = error { // just a status line, like any other
+        report!("[#] {}", diagnosis);
+        return
+    }
+
+    nl();
+    match json_output() {
+        true => print_event(&serde_json::json!({ "event": "error", "message": diagnosis, "error": cause.map_or_else(|| diagnosis.to_string(), |cause| cause.to_string() ), "exit_code": error.exit_code() })),
+        false => {
+            report!("[!] {}", diagnosis);
+
+            if let Some(cause) = cause {
+                report!("[!] {}", cause);
+            }
+        }
+    }
+    nl();
+}
+
+// the outer error ends the run; the inner one only means this isn't a share we can use
+fn share_from_file(file: &Path, nonce: Option<&[u8]>, wrapping_keys: &mut Vec<(Vec<u8>, Zeroizing<Vec<u8>>)>, read_retries: ReadRetries) -> Outcome<Result<ShareFromFile>> { // Pull shares back out of share files
+    // with --all, this could be any old (huge) file, so never read more than a share could possibly be
+    if !file.is_file() {
+        return Ok( Err(io::Error::other("Not a file")) )
+    }
+
+    let share_contents = match read_share_file_retrying(file, read_retries) {
+        Ok(Some(contents)) if contents.starts_with(ARMOR_BEGIN.as_bytes()) => match dearmor_share(&contents) {
+            Ok(contents) => contents,
+            Err(error) => return Ok( Err(error) ),
+        },
+        Ok(Some(contents)) => contents,
+        Ok(None) => return Ok( Err(io::Error::other("Invalid share (CCMS header missing, or larger than any share)")) ),
+        Err(error) => return Ok( Err(io::Error::new(error.kind(), format!("Could not read share file ({})", error))) ), // one bad share (say, on a flaky drive) shouldn't sink the rest
+    };
+
+    let stem = share_stem(file).unwrap_or_default();
+
+    if let (_, Some(checksum)) = split_name_checksum(&stem) { // names without one (older shares, or custom templates) go unchecked
+        if !share_checksum_matches(&share_contents, checksum) {
+            return Ok( Err(io::Error::other("Share file may be corrupt or truncated (it doesn't match the checksum in its name)")) )
+        }
+    }
+
+    share_from_bytes(share_contents, &file.display().to_string(), nonce, wrapping_keys)
+}
+
+fn share_from_bytes(share_contents: Vec<u8>, source: &str, nonce: Option<&[u8]>, wrapping_keys: &mut Vec<(Vec<u8>, Zeroizing<Vec<u8>>)>) -> Outcome<Result<ShareFromFile>> { // Parse a share (from a file or stdin), unwrapping it if passphrase protected
+    let mut prompt_error: Option<Error> = None; // parse_share only knows about io::Errors, so a failed prompt is kept to one side
+
+    let share = parse_share(share_contents, nonce, &mut |salt: &[u8]| { // only called for passphrase protected shares
+        // shares from the same encryption share a salt, so only ask (and run Argon2) once per salt
+        if let Some((_, key)) = wrapping_keys.iter().find(|(known_salt, _)| known_salt == salt) {
+            return Ok( key.clone() )
+        }
+
+        report!("[^] Share {} is passphrase protected", source);
+        let passphrase = ask_for_passphrase(false).map_err(|error| {
+            prompt_error = Some(error);
+            io::Error::other("Could not read passphrase")
+        })?;
+        let key = derive_wrapping_key(passphrase.as_bytes(), salt)?;
+
+        wrapping_keys.push( (salt.to_vec(), key.clone()) );
+        Ok(key)
+    });
+
+    match prompt_error {
+        Some(error) => Err(error),
+        None => Ok(share),
+    }
+}
+
+fn armor_share(share: &[u8]) -> Zeroizing<String> { // Wrap a complete share file in ASCII armor (see ARMOR_BEGIN), for --armor
+    let encoded = Zeroizing::new( BASE64.encode(share) );
+    let checksum = blake3::hash(share);
+
+    let mut armored = Zeroizing::new( format!("{}\n", ARMOR_BEGIN) );
+
+    for line in encoded.as_bytes().chunks(ARMOR_LINE_LENGTH) { // base64 is all ASCII, so any split is on a character
+        armored.push_str( &String::from_utf8_lossy(line) );
+        armored.push('\n');
+    }
+
+    armored.push_str( &format!("={}\n{}\n", BASE64.encode(&checksum.as_bytes()[..ARMOR_CHECKSUM_BYTES]), ARMOR_END) );
+
+    armored
+}
+
+fn dearmor_share(armored: &[u8]) -> Result<Vec<u8>> { // Get a share file back out of its ASCII armor, checking nothing went missing along the way
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid armored share ({})", message) );
+
+    let text = std::str::from_utf8(armored).map_err(|_| invalid("not text") )?;
+    let mut lines = text.lines().map(str::trim).skip_while(|line| *line != ARMOR_BEGIN).skip(1); // CRLFs and indentation from mail clients are fine
+
+    let mut encoded = Zeroizing::new( String::new() );
+    let mut checksum: Option<&str> = None;
+
+    loop {
+        match lines.next() {
+            Some(ARMOR_END) => break,
+            Some(line) if line.starts_with('=') => checksum = Some(&line[1..]),
+            Some(line) if checksum.is_some() && !line.is_empty() => return Err( invalid("text after the checksum line") ),
+            Some(line) => encoded.push_str(line),
+            None => return Err( invalid("END line missing -- was it cut short when it was copied?") ),
+        }
+    }
+
+    let checksum = checksum.ok_or_else(|| invalid("checksum line missing -- was it cut short when it was copied?") )?;
+    let share = BASE64.decode(encoded.as_bytes()).map_err(|error| invalid( &format!("bad base64: {}", error) ) )?;
+
+    if BASE64.encode(&blake3::hash(&share).as_bytes()[..ARMOR_CHECKSUM_BYTES]) != checksum {
+        return Err( invalid("checksum mismatch -- was it changed or cut short when it was copied?") )
+    }
+
+    Ok(share)
+}
+
+fn read_stdin_shares() -> Outcome<Vec<(String, Vec<u8>)>> { // Read base64 shares (as printed by --shares-stdout) from stdin, one per line
+    let mut shares: Vec<(String, Vec<u8>)> = vec![];
+
+    for (line_i, line) in io::stdin().lock().lines().enumerate() {
+        let source = format!("stdin line {}", line_i + 1);
+
+        let line = match line {
+            Ok(line) => Zeroizing::new(line),
+            Err(error) => return Err( Error::failed(error, "Could not read shares from stdin".to_string()) ),
+        };
+
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue
+        }
+
+        // lines are "index:share", but the index is just for humans -- the share knows its own
+        let encoded = match line.split_once(':') {
+            Some((index, encoded)) if index.chars().all(|c| c.is_ascii_digit()) => encoded,
+            _ => line,
+        };
+
+        match BASE64.decode(encoded) {
+            Ok(contents) => shares.push( (source, contents) ),
+            Err(error) => report!("[^] Skipping {} | Not a base64 share ({})", source, error),
+        }
+    }
+
+    Ok(shares)
+}
+
+fn read_share_bundle(bundle_path: &Path) -> Outcome<Vec<(String, Vec<u8>)>> { // Split a share bundle (as written by --share-bundle) back into its shares, each with a name for messages
+    // up to one byte more than the largest bundle, to tell if it's too big
+    let contents = fs::File::open(bundle_path).and_then(|open| {
+        let mut contents = vec![];
+        open.take(SHARE_BUNDLE_MAX_LENGTH as u64 + 1).read_to_end(&mut contents)?;
+
+        Ok( Zeroizing::new(contents) )
+    });
+
+    let shares = match contents {
+        Ok(contents) if contents.len() > SHARE_BUNDLE_MAX_LENGTH => Err( io::Error::new(io::ErrorKind::InvalidData, "File is too big to be a share bundle") ),
+        Ok(contents) => parse_share_bundle(&contents),
+        Err(error) => Err(error),
+    };
+
+    match shares {
+        Ok(shares) => Ok( shares.iter().enumerate()
+            .map(|(share_i, share)| (format!("{} share {}", stringify_path(bundle_path), share_i + 1), share.to_vec()) )
+            .collect() ),
+        Err(error) => Err( Error::failed(error, format!("Could not read share bundle {}", bundle_path.display())) ),
+    }
+}
+
+fn read_file(filepath: &Path) -> Result<Vec<u8>> { // Raw function for reading files
+    let mut contents = vec![];
+    let mut open = fs::File::open(filepath)?;
+
+    open.read_to_end(&mut contents)?;
+
+    Ok(contents)
+}
+
+fn read_file_contents(filepath: &Path, header_length: usize) -> Result<Vec<u8>> { // read_file, minus the header (which may have been parsed from an earlier read)
+    let mut contents = read_file(filepath)?;
+
+    if contents.len() < header_length { // the file's shrunk since its header was read
+        return Err( io::Error::new(io::ErrorKind::UnexpectedEof, "File is shorter than its header") )
+    }
+
+    Ok( contents.split_off(header_length) )
+}
+
+fn read_share_file(filepath: &Path) -> Result<Option<Vec<u8>>> { // Read a share file, bailing out (with None) as soon as it can't be one
+    read_share( fs::File::open(filepath)? )
+}
+
+fn read_share<R: Read>(mut open: R) -> Result<Option<Vec<u8>>> { // read_share_file, from anything readable
+    // check the magic first, so junk is turned away after a few bytes
+    let mut contents = vec![];
+    (&mut open).take(HEADER_SHARE.len() as u64).read_to_end(&mut contents)?;
+
+    let max_length = match contents == HEADER_SHARE {
+        true => SHARE_MAX_LENGTH + SHARE_TRAILING_MAX_BYTES, // whitespace it's picked up, too
+        false if ARMOR_BEGIN.as_bytes().starts_with(&contents) => ARMOR_MAX_LENGTH, // an armored share, most likely
+        false => return Ok(None),
+    };
+
+    // then the rest, up to one byte more than the largest share (to tell if it's too big)
+    open.take( (max_length + 1 - HEADER_SHARE.len()) as u64 )
+        .read_to_end(&mut contents)?;
+
+    match contents.len() > max_length {
+        true => Ok(None),
+        false => Ok(Some(contents)),
+    }
+}
+
+fn read_share_file_retrying(filepath: &Path, read_retries: ReadRetries) -> Result<Option<Vec<u8>>> { // read_share_file, trying again (after a wait) if it fails with a transient error
+    retrying(filepath, read_retries, || read_share_file(filepath), thread::sleep)
+}
+
+fn retrying<T>(filepath: &Path, read_retries: ReadRetries, mut read: impl FnMut() -> Result<T>, mut sleep: impl FnMut(Duration)) -> Result<T> { // Call read until it works or fails for good, sleeping (doubling the wait each time) between tries
+    let mut backoff = read_retries.backoff;
+
+    for retry in 1..=read_retries.retries {
+        match read() {
+            Err(error) if is_transient(&error) => {
+                report!("[^] Reading {} failed | {} (retry {} of {} in {}ms)", filepath.display(), error, retry, read_retries.retries, backoff.as_millis() );
+                sleep(backoff);
+                backoff = backoff.saturating_mul(2);
+            },
+            result => return result,
+        }
+    }
+
+    read()
+}
+
+fn is_transient(error: &io::Error) -> bool { // might well work next time, e.g. a network drive that didn't answer in time (unlike a missing file, or one we can't read)
+    matches!(error.kind(), io::ErrorKind::TimedOut | io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock)
+}
+
+fn read_file_header(filepath: &Path) -> Result<Vec<u8>> { // Read only as many bytes as the largest possible file or share header
+    let mut contents = vec![];
+    let open = fs::File::open(filepath)?;
+
+    open.take( HEADER_MAX_LENGTH_FILE.max(HEADER_MAX_LENGTH_SHARE) as u64 )
+        .read_to_end(&mut contents)?;
+
+    Ok(contents)
+}
+
+fn dir_entries(dir: &Path) -> Result<Vec<PathBuf>> { // Everything directly inside a directory, sorted by name (read_dir's own order is arbitrary)
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok() )
+        .map(|entry| entry.path() )
+        .collect();
+
+    paths.sort();
+    Ok(paths)
+}
+
+fn secrets_in_share_dir(shares_dir: &Path, all: bool) -> Outcome<Vec<(Vec<u8>, u8)>> { // The secrets split with `split` that a directory holds shares of, by ID (nonce) with their threshold -- there's no file to say
+    let paths = match dir_entries(shares_dir) {
+        Ok(paths) => paths,
+        Err(error) => return Err( Error::failed(error, format!("Could not read share directory {}", shares_dir.display())) ),
+    };
+
+    let mut secrets: Vec<(Vec<u8>, u8)> = vec![];
+
+    // only the headers, so passphrase protected shares of files are passed over without asking for anything
+    let headers = paths.iter()
+        .filter(|path| path.is_file() && (all || is_share_path(path)) )
+        .filter_map(|path| read_file_header(path).ok().and_then(|header| parse_header_share(&header).ok() ) )
+        .filter(|share_header| share_header.secret_length.is_some() );
+
+    for share_header in headers {
+        if !secrets.iter().any(|(nonce, _)| *nonce == share_header.nonce) {
+            secrets.push( (share_header.nonce, share_header.threshold) );
+        }
+    }
+
+    Ok(secrets)
+}
+
+fn existing_shares_for_nonce(shares_dir: &Path, hex_nonce: &str) -> Vec<PathBuf> { // Find shares in a directory that have the given nonce in their filename
+    let suffix = format!("-{}", hex_nonce);
+
+    dir_entries(shares_dir).unwrap_or_default().into_iter()
+        .filter(|path| is_share_path(path) )
+        .filter(|path| share_stem(path).is_some_and(|stem| split_name_checksum(&stem).0.ends_with(&suffix)) )
+        .collect()
+}
+
+fn parse_share_count(value: &str) -> std::result::Result<u8, String> { // clap parser for share counts (1-255), so a bad one gets a clear error
+    match value.parse::<u64>() {
+        Ok(count) if (1..=255).contains(&count) => Ok(count as u8),
+        Ok(_) => Err("must be between 1 and 255 (shares are indexed by a single byte)".to_string()),
+        Err(_) => Err("must be a whole number between 1 and 255".to_string()),
+    }
+}
+
+fn parse_scheme(value: &str) -> std::result::Result<(u8, u8), String> { // clap parser for extra share schemes ("5:3", i.e. players:threshold)
+    let (players, threshold) = value.split_once(':').ok_or_else(|| "must be PLAYERS:THRESHOLD, e.g. 5:3".to_string() )?;
+
+    Ok( (parse_share_count(players)?, parse_share_count(threshold)?) )
+}
+
+fn parse_thread_count(value: &str) -> std::result::Result<usize, String> { // clap parser for thread counts, so a bad one gets a clear error
+    match value.parse::<usize>() {
+        Ok(count) if count >= 1 => Ok(count),
+        _ => Err("must be a whole number, at least 1".to_string()),
+    }
+}
+
+fn parse_chunk_size(value: &str) -> std::result::Result<usize, String> { // clap parser for chunk sizes ("64k", "1M", or plain bytes), so a bad one gets a clear error
+    let value = value.trim().to_ascii_lowercase();
+    let (number, suffix) = value.split_at( value.find(|c: char| !c.is_ascii_digit() ).unwrap_or(value.len()) );
+
+    let multiplier: usize = match suffix {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        _ => return Err("must be a number of bytes, optionally with a k or M suffix (e.g. 64k, 1M)".to_string()),
+    };
+
+    let chunk_size = match number.parse::<usize>().ok().and_then(|number| number.checked_mul(multiplier) ) {
+        Some(chunk_size) => chunk_size,
+        None => return Err("must be a number of bytes, optionally with a k or M suffix (e.g. 64k, 1M)".to_string()),
+    };
+
+    // the header stores it as a power of two, and the STREAM construction needs it to be sane
+    match check_chunk_size(chunk_size) {
+        Ok(_) => Ok(chunk_size),
+        Err(_) => Err("must be a power of two between 4k and 16M".to_string()),
+    }
+}
+
+fn parse_label(value: &str) -> std::result::Result<String, String> { // clap parser for --label, so one that won't fit in the header is turned away up front
+    check_label(value).map(|_| value.to_owned() ).map_err(|error| error.to_string() )
+}
+
+fn parse_exclude(value: &str) -> std::result::Result<Pattern, String> { // clap parser for --exclude globs (a trailing / is just for show -- directories and files alike are matched)
+    Pattern::new(value.trim_end_matches('/')).map_err(|error| error.to_string() )
+}
+
+#[cfg(feature = "test-rng")]
+fn parse_seed(value: &str) -> std::result::Result<[u8; 32], String> { // clap parser for --seed, so a bad one gets a clear error
+    hex::decode(value.trim()).ok()
+        .and_then(|seed| <[u8; 32]>::try_from(seed).ok() )
+        .ok_or_else(|| "must be 32 bytes of hex".to_string() )
+}
+
+fn parse_mode(value: &str) -> std::result::Result<u32, String> { // clap parser for file permissions in octal ("600", "0640")
+    match u32::from_str_radix(value.trim(), 8) {
+        Ok(mode) if mode <= 0o777 => Ok(mode),
+        _ => Err("must be octal permissions, e.g. 600 or 640".to_string()),
+    }
+}
+
+fn parse_cipher(value: &str) -> std::result::Result<Cipher, String> { // clap parser for --algo, so an unknown cipher gets the list of ones we have
+    match Cipher::from_name(value.trim()) {
+        Some(cipher) => Ok(cipher),
+        None => {
+            let supported: Vec<&str> = Cipher::SUPPORTED.iter().map(|cipher| cipher.name() ).collect();
+            Err( format!("unknown cipher (supported: {})", supported.join(", ")) )
+        },
+    }
+}
+
+fn parse_output_format(value: &str) -> std::result::Result<OutputFormat, String> { // clap parser for --output-format
+    match value.trim().to_ascii_lowercase().as_str() {
+        "ccm" => Ok(OutputFormat::Ccm),
+        "raw" => Ok(OutputFormat::Raw),
+        _ => Err( "unknown format (use ccm or raw)".to_string() ),
+    }
+}
+
+fn parse_share_name_template(value: &str) -> std::result::Result<String, String> { // clap parser for share filename templates, so a bad one gets a clear error
+    let mut rest = value;
+
+    while let Some(open) = rest.find('{') { // every {...} has to be a placeholder we know
+        let close = match rest[open..].find('}') {
+            Some(close) => open + close,
+            None => return Err("has a { without a matching }".to_string()),
+        };
+
+        let placeholder = &rest[open + 1..close];
+
+        if !SHARE_NAME_PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!("has an unknown placeholder {{{}}} (use {{index}}, {{total}}, {{threshold}}, {{nonce}} or {{checksum}})", placeholder));
+        }
+
+        rest = &rest[close + 1..];
+    }
+
+    if rest.contains('}') {
+        return Err("has a } without a matching {".to_string());
+    }
+
+    if !value.contains("{index}") { // the only thing that tells one share's name from another's
+        return Err("must contain {index}, so every share gets its own name".to_string());
+    }
+
+    if value.contains('/') || value.contains('\\') {
+        return Err("must be a file name, not a path (use --share-dir to choose where shares go)".to_string());
+    }
+
+    if !has_extension(Path::new(value), "ccms") { // decrypt only looks at .ccms files unless told otherwise
+        return Err("must end in .ccms, so decrypt can find the shares".to_string());
+    }
+
+    Ok(value.to_string())
+}
+
+fn check_players_threshold(players: u8, threshold: u8) -> Outcome<()> { // Refuse share counts that make no sense, and warn about risky ones
+    if players < threshold {
+        return Err( Error::refused("Share threshold exceeds maximum number of players. File would be unrecoverable!".to_string()) );
+    } else if players < 1 {
+        return Err( Error::refused("Number of shares cannot be zero".to_string()) );
+    } else if threshold < 1 {
+        return Err( Error::refused("Threshold of shares cannot be zero".to_string()) );
+    }
+
+    if threshold == 1 { // every share is effectively the key itself
+        report!("[#] A threshold of 1 means any single share reveals the key -- each share is as sensitive as the key");
+        nl();
+    }
+    else if threshold == players { // no room for losing any
+        report!("[#] Threshold equals the number of shares, so there's no redundancy -- losing any one share makes the file unrecoverable");
+        nl();
+    }
+
+    Ok(())
+}
+
+fn share_paths(shares_dir: &Path, template: &str, total: usize, threshold: u8, hex_nonce: &str) -> Outcome<Vec<PathBuf>> { // Work out where each of a set of shares goes, by filling in the share name template
+    share_paths_at(shares_dir, template, 1..=total, total, threshold, hex_nonce)
+}
+
+fn share_paths_at(shares_dir: &Path, template: &str, indices: impl Iterator<Item = usize>, total: usize, threshold: u8, hex_nonce: &str) -> Outcome<Vec<PathBuf>> { // share_paths, for shares with these indices (out of `total`)
+    let paths: Vec<PathBuf> = indices.map(|share_i| {
+        let share_filename = template
+            .replace("{index}", &share_i.to_string())
+            .replace("{total}", &total.to_string())
+            .replace("{threshold}", &threshold.to_string())
+            .replace("{nonce}", hex_nonce);
+
+        shares_dir.join(share_filename)
+    }).collect();
+
+    // {index} should see to this, but two shares landing on one file would lose one of them
+    let unique: HashSet<&PathBuf> = paths.iter().collect();
+
+    if unique.len() != paths.len() {
+        return Err( Error::refused(format!("Share name template \"{}\" gives more than one share the same file name", template)) );
+    }
+
+    Ok(paths)
+}
+
+fn scheme_share_dir(shares_dir: &Path, scheme: u8) -> PathBuf { // Where a scheme's shares go when there's more than one scheme
+    shares_dir.join(format!("scheme-{}", scheme))
+}
+
+fn die_on_existing_shares(shares_dir: &Path, hex_nonce: &str, share_paths: &[PathBuf]) -> Outcome<()> { // Give up rather than overwrite shares already in the share directory
+    let mut existing_shares = existing_shares_for_nonce(shares_dir, hex_nonce);
+
+    for path in share_paths { // a custom template may not have the nonce in its names
+        let taken = match path.exists() {
+            true => vec![path.clone()],
+            false => checksum_siblings(path),
+        };
+
+        for path in taken {
+            if !existing_shares.contains(&path) {
+                existing_shares.push(path);
+            }
+        }
+    }
+
+    if !existing_shares.is_empty() {
+        nl();
+        report!("[!] Share directory already contains shares with this file's nonce (or the same names):");
+
+        for existing in &existing_shares {
+            report!("[!]   {}", existing.display() );
+        }
+
+        return Err( Error::refused("Writing shares would overwrite them (use --force if you really mean to)".to_string()) );
+    }
+
+    Ok(())
+}
+
+type NewWrappingKey = (Option<Vec<u8>>, Option<Zeroizing<Vec<u8>>>); // salt and key, if shares are to be passphrase protected
+
+fn new_wrapping_key(passphrase: bool) -> Outcome<NewWrappingKey> { // Ask for a passphrase and derive a key to wrap new shares under (if we're using one); returns salt and key
+    if !passphrase {
+        return Ok( (None, None) )
+    }
+
+    let salt = generate_salt();
+    let passphrase = ask_for_passphrase(true)?;
+
+    match derive_wrapping_key(passphrase.as_bytes(), &salt) {
+        Ok(key) => {
+            report!("[-] Share key derived from passphrase");
+            Ok( (Some(salt), Some(key)) )
+        },
+        Err(error) => Err( Error::failed(error, "Could not derive key from passphrase".to_string()) ),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_shares( // Write out a set of shares to the given paths (or print them as base64), wrapping and signing them as needed
+    shares: &[Vec<u8>],
+    share_paths: &[PathBuf],
+    share_header: &[u8],
+    keypair: Option<&Keypair>,
+    wrapping_key: Option<&[u8]>,
+    mac_key: Option<&[u8]>, // the key the shares recover, to MAC them under (the header must have SHARE_FLAG_MAC to match)
+    output: &ShareOutput,
+    mode: u32 ) -> Outcome<u64> { // returns the number of bytes of shares written (or that would have been)
+
+    let mut total_bytes: u64 = 0;
+    let mut pending = Vec::new(); // shares to write to files (or bundle): index, path, contents, base64 (for QR codes) and the old shares they replace
+
+    for (s, this_share_path) in shares.iter().zip(share_paths) { // iterate through shares
+        let share_i = s.first().copied().unwrap_or_default() as usize; // every share starts with its own index
+
+        let mac: Option<Vec<u8>> = mac_key.map(|key| share_mac(key, s) ); // of the share itself, so wrapping doesn't come into it
+
+        let mut s: Zeroizing<Vec<u8>> = match wrapping_key { // wrap share under passphrase key
+            Some(wrapping_key) => match wrap_share(wrapping_key, s) {
+                Ok(wrapped) => Zeroizing::new(wrapped),
+                Err(error) => return Err( Error::failed(error, format!("Could not wrap share # {}", share_i)) ),
+            },
+            None => Zeroizing::new( s.clone() ),
+        };
+
+        if let Some(mac) = mac { // after the share, where the signature covers it too
+            s.extend(mac);
+        }
+
+        let share_full: Zeroizing<Vec<u8>> = Zeroizing::new( construct_share(share_header, keypair, &s) );
+
+        if keypair.is_some() {
+            report!("[-] Signed share # {share_i}");
+        }
+
+        let replaced: Vec<PathBuf> = checksum_siblings(this_share_path); // (only there if forced) shares under the same name but another checksum
+        let this_share_path = &with_share_checksum(this_share_path, &share_full);
+        let replaced: Vec<PathBuf> = replaced.into_iter().filter(|path| path != this_share_path ).collect();
+
+        total_bytes += share_full.len() as u64;
+
+        if let ShareOutput::DryRun = output {
+            report_event(
+                &format!("[&] Would write share # {} to {} ({} bytes)", share_i, stringify_path(this_share_path), share_full.len()),
+                serde_json::json!({ "event": "planned_file", "kind": "share", "index": share_i, "path": stringify_path(this_share_path), "bytes": share_full.len() })
+            );
+
+            continue
+        }
+
+        let encoded = Zeroizing::new( BASE64.encode(&share_full[..]) );
+
+        if let ShareOutput::Stdout = output { // "index:share" for humans, a "share" event for JSON
+            match json_output() {
+                true => println!("{}", serde_json::json!({ "event": "share", "index": share_i, "share": encoded.as_str() })),
+                false => println!("{}:{}", share_i, encoded.as_str()),
+            }
+
+            continue
+        }
+
+        let share_full = match output { // the text goes in the file instead
+            ShareOutput::Files { armor: true, .. } => Zeroizing::new( armor_share(&share_full).as_bytes().to_vec() ),
+            _ => share_full,
+        };
+
+        pending.push( (share_i, this_share_path.clone(), share_full, encoded, replaced) );
+    }
+
+    if let ShareOutput::Bundle(share_bundle) = output { // all in one file, rather than one each
+        let share_files: Vec<&[u8]> = pending.iter().map(|(_, _, share_full, _, _)| share_full.as_slice() ).collect();
+
+        track_partial(share_bundle);
+
+        if let Err(error) = write_file(share_bundle, &construct_share_bundle(&share_files), Some(mode)) {
+            return Err( Error::failed(error, format!("Could not write share bundle {}", share_bundle.display())) );
+        }
+
+        report_event(
+            &format!("[&] {} share(s) bundled into {}", share_files.len(), stringify_path(share_bundle)),
+            serde_json::json!({ "event": "file_written", "kind": "bundle", "path": stringify_path(share_bundle), "shares": share_files.len() })
+        );
+
+        nl();
+        report!("[#] The bundle holds every share, so whoever has it can recover the key on their own");
+        report!("[#] It's for getting the shares to wherever they're handed out -- give each holder their share, then destroy it");
+
+        return Ok(total_bytes)
+    }
+
+    let threads = match output {
+        ShareOutput::Files { threads, .. } => threads.unwrap_or(0), // 0 lets rayon pick (one per CPU)
+        _ => 0,
+    };
+
+    // every share file is synced to disk before it's renamed into place, so writing 255 of them one at a time is slow on
+    // slow disks -- write them side by side, then report on them in order
+    let share_progress = ShareProgress::new(pending.len());
+
+    let written: Vec<Result<&Path>> = match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool.install(|| pending.par_iter().map(|(_, this_share_path, share_full, _, _)| {
+            track_partial(this_share_path);
+            let written = write_file(this_share_path, share_full, Some(mode));
+            share_progress.written();
+            written
+        }).collect() ),
+        Err(error) => return Err( Error::failed(io::Error::other(error), "Could not start threads for writing shares".to_string()) ),
+    };
+
+    for ((share_i, this_share_path, _, encoded, replaced), written) in pending.iter().zip(written) {
+        if let Err(error) = written {
+            return Err( Error::failed(error, format!("Could not write share # {} to {}", share_i, this_share_path.display())) );
+        }
+
+        for old_share in replaced { // its name won't be overwritten when the checksum changes, so it's left to mislead
+            if let Err(error) = fs::remove_file(old_share) {
+                report!("[!] Could not remove replaced share {} | {}", old_share.display(), error);
+            }
+        }
+
+        report_event(
+            &format!("[&] Share # {} written to {}", share_i, stringify_path(this_share_path)),
+            serde_json::json!({ "event": "share_written", "index": share_i, "path": stringify_path(this_share_path) })
+        );
+
+        if let ShareOutput::Files { qr: true, .. } = output { // QR code goes next to the share
+            let qr_path = this_share_path.with_extension("png");
+            track_partial(&qr_path);
+
+            if write_share_qr(&qr_path, encoded, *share_i, mode)? {
+                report_event(
+                    &format!("[&] QR code for share # {} written to {}", share_i, stringify_path(&qr_path)),
+                    serde_json::json!({ "event": "file_written", "kind": "qr", "path": stringify_path(&qr_path) })
+                );
+            }
+        }
+    }
+
+    Ok(total_bytes)
+}
+
+fn write_share_qr(qr_path: &Path, encoded: &str, share_i: usize, mode: u32) -> Outcome<bool> { // Render a (base64) share as a QR code PNG; false if it's too big to
+    let code = match QrCode::with_error_correction_level(encoded.as_bytes(), EcLevel::M) {
+        Ok(code) if matches!(code.version(), Version::Normal(version) if version <= QR_MAX_VERSION) => code,
+        _ => {
+            report!("[#] Share # {} is too large for a scannable QR code ({} bytes of base64); skipping its QR code", share_i, encoded.len() );
+            return Ok(false)
+        }
+    };
+
+    let image = code.render::<image::Luma<u8>>().quiet_zone(true).build();
+
+    let mut png: Zeroizing<Vec<u8>> = Zeroizing::new(vec![]);
+
+    if let Err(error) = image::DynamicImage::ImageLuma8(image).write_to(&mut Cursor::new(&mut *png), image::ImageFormat::Png) {
+        return Err( Error::failed(io::Error::other(error), format!("Could not render QR code for share # {}", share_i)) );
+    }
+
+    if let Err(error) = write_file(qr_path, &png, Some(mode)) {
+        return Err( Error::failed(error, format!("Could not write file {}", qr_path.display())) );
+    }
+
+    Ok(true)
+}
+
+fn pub_key_path(key_file: &Path) -> PathBuf { // Where keygen puts a key file's public key: {key file}.pub
+    let mut pub_path = key_file.as_os_str().to_os_string();
+    pub_path.push(".pub");
+
+    PathBuf::from(pub_path)
+}
+
+fn describe_pub_key(pub_key: &[u8]) -> String { // A public key in full, with its short fingerprint for comparing by eye (or ear)
+    format!("{} (fingerprint {})", hex::encode(pub_key), pubkey_fingerprint(pub_key))
+}
+
+fn load_keypair(key_file: &Path) -> Outcome<Keypair> { // Load a signing keypair from a key file
+    let contents = match fs::read_to_string(key_file) {
+        Ok(contents) => Zeroizing::new(contents),
+        Err(error) => return Err( Error::failed(error, format!("Could not read key file {}", key_file.display())) ),
+    };
+
+    keypair_from_hex(&contents).map_err(|error| Error::failed(error, format!("Could not load keypair from {}", key_file.display())) )
+}
+
+type TargetFile = (u8, bool, Option<PublicKey>, Option<Signature>, FileHeader); // threshold, whether it's signed, public key, signature and header
+
+fn process_target_file(target_file: &Path, strict: bool) -> Outcome<TargetFile> { // Parse an encrypted file's header, incl. its public key and signature (from a detached signature, if it has one)
+    let header_bytes: Vec<u8> = match read_target_header(target_file) { // from {name}.hdr, if it's a raw file
+        Ok(contents) => contents,
+        Err(error) => return Err( Error::failed(error, format!("Could not read file {}", target_file.display())) ),
+    };
+
+    let file_header = match parse_header_file(&header_bytes) { // give up if file is not encrypted
+        Ok(head) => head, // extract header if it is
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => { // encrypted, but not in a way we know how to undo
+            return Err( Error::failed(err, format!("Could not parse file {}", target_file.display())) );
+        },
+        Err(err) => { 
+            report!("[!] Target file failed validation: {}", err );
+            let diagnosis = "It doesn't look like a file encrypted with chachamir -- if it's already been decrypted, there's nothing left to do".to_string();
+            return Err( Error::NotEncrypted { diagnosis, error: None } );
+        }
+    };
+
+    let mut file_is_signed: bool = false;
+
+    let mut file_pubkey: Option<PublicKey> = None; // public key, if it exists
+    let mut file_signature: Option<Signature> = None; // signature, if it exists
+    
+    if file_header.is_signed { // Retrieve public key and signature from file
+        file_is_signed = true;
+
+        let file_pubkey_res = PublicKey::from_bytes(&file_header.pub_key_bytes);
+
+        file_pubkey = match file_pubkey_res {
+            Ok(pk) => Some(pk),
+            Err(error) => {
+                report!("[!] Target file has a bad public key" );
+                report!("[!] {}", error );
+
+                audit( serde_json::json!({ "event": "file_signature", "file": stringify_path(target_file), "outcome": "bad_public_key", "public_key": hex::encode(&file_header.pub_key_bytes), "error": error.to_string() }) )?;
+                
+                file_is_signed = false;
+                
+                die_on_strict(strict)?;
+                ask_to_continue()?;
+
+                None
+            }
+        };
+
+        let file_signature_res = Signature::from_bytes(&file_header.signature_bytes);
+
+        file_signature = match file_signature_res {
+            Ok(sig) => Some(sig),
+            Err(error) => {
+                report!("[!] Target file has a bad signature" );
+                report!("[!] {}", error );
+
+                audit( serde_json::json!({ "event": "file_signature", "file": stringify_path(target_file), "outcome": "malformed_signature", "public_key": hex::encode(&file_header.pub_key_bytes), "error": error.to_string() }) )?;
+
+                file_is_signed = false;
+                
+                die_on_strict(strict)?;
+                ask_to_continue()?;
+
+                None
+            }
+        };
+
+        if let Some(pub_key) = file_pubkey.filter(|_| file_is_signed) {
+            report!("[+] Target file is signed (fingerprint {})", pubkey_fingerprint(pub_key.as_bytes()) );
+        }
+    }
+    else if let Some((pub_key, signature)) = detached_signature_for(target_file, strict)? { // signed, but not in the header
+        file_is_signed = true;
+        file_pubkey = Some(pub_key);
+        file_signature = Some(signature);
+
+        report!("[+] Target file is signed (detached signature {}; fingerprint {})", stringify_path(&detached_sig_path(target_file)), pubkey_fingerprint(pub_key.as_bytes()) );
+    }
+
+    Ok( (file_header.threshold, file_is_signed, file_pubkey, file_signature, file_header) )
+}
+
+fn detached_signature_for(target_file: &Path, strict: bool) -> Outcome<Option<(PublicKey, Signature)>> { // Look for a detached signature next to an unsigned-looking file, and pull its public key and signature out
+    let sig_path = detached_sig_path(target_file);
+
+    if !sig_path.is_file() {
+        return Ok(None)
+    }
+
+    // never read more than a detached signature could possibly be
+    let detached_sig = fs::File::open(&sig_path).and_then(|sig_file| {
+        let mut detached_sig: Vec<u8> = vec![];
+        sig_file.take(DETACHED_SIG_LENGTH as u64 + 1).read_to_end(&mut detached_sig).map(|_| detached_sig)
+    });
+
+    match detached_sig.and_then(|detached_sig| parse_detached_signature(&detached_sig) ) {
+        Ok(parsed) => Ok( Some(parsed) ),
+        Err(error) => {
+            report!("[!] Target file has a bad detached signature {}", stringify_path(&sig_path) );
+            report!("[!] {}", error );
+
+            die_on_strict(strict)?;
+            ask_to_continue()?;
+
+            Ok(None)
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn gather_shares( // Gather the shares for a file (matched by nonce) from the share directories, checking them along the way
+    shares_dirs: &[PathBuf],
+    nonce: &[u8],
+    threshold: &mut u8, // the user may choose to override the file's threshold if shares disagree
+    schemes: Option<u8>, // how many share schemes the file's key was split between, if more than one
+    is_signed: bool,
+    shares_unsigned: bool, // the file says its shares were left unsigned on purpose (FLAG_SHARES_UNSIGNED)
+    pub_key: Option<PublicKey>,
+    options: &GatherOptions,
+    share_macs: &mut Vec<GatheredMac> ) -> Outcome<Vec<Share>> { // MACs of the shares that have them, to check once the key's recovered
+
+    let mut shares: Vec<Share> = Vec::new();
+    let mut share_thresholds: Vec<u8> = Vec::new(); // what each gathered share says the threshold is
+    let mut share_schemes: Vec<u8> = Vec::new(); // and which scheme it's from
+    let mut listed: Vec<ListedShare> = Vec::new(); // for --list-shares
+    let mut signature_checks: Vec<ListedShare> = Vec::new(); // for --verify-all-signatures (duplicates too)
+
+    if options.verify_all_signatures && !is_signed { // unsigned shares of an unsigned file would all "pass"
+        return Err( Error::tampered("Target file is not signed, so there are no share signatures to verify".to_string()) );
+    }
+
+    let shares_dirs: Vec<PathBuf> = shares_dirs.iter().map(|shares_dir| {
+        absolute_path(shares_dir).map_err(|error| Error::failed(error, format!("Could not resolve share directory {}", shares_dir.display())) )
+    }).collect::<Outcome<_>>()?;
+
+    let mut wrapping_keys: Vec<(Vec<u8>, Zeroizing<Vec<u8>>)> = vec![]; // passphrase keys for protected shares, by salt
+
+    // shares come from the share directory, stdin or a bundle, each with a name for messages
+    let gathered: Box<dyn Iterator<Item = (String, Outcome<Result<ShareFromFile>>)> + '_> = match (options.stdin, &options.bundle) {
+        (true, _) => Box::new( read_stdin_shares()?.into_iter().map(|(source, contents)| {
+            let share_f = share_from_bytes(contents, &source, Some(nonce), &mut wrapping_keys);
+            (source, share_f)
+        }) ),
+        (false, Some(bundle)) => Box::new( read_share_bundle(bundle)?.into_iter().map(|(source, contents)| {
+            let share_f = share_from_bytes(contents, &source, Some(nonce), &mut wrapping_keys);
+            (source, share_f)
+        }) ),
+        (false, None) => Box::new( shares_dirs.iter().flat_map(|shares_dir| {
+            // each scheme's shares are written to a scheme-N directory of their own (but may have been gathered up since)
+            let mut dirs = vec![shares_dir.clone()];
+            dirs.extend( (1..=schemes.unwrap_or(0)).map(|scheme| scheme_share_dir(shares_dir, scheme) ).filter(|dir| dir.is_dir() ) );
+
+            dirs
+        })
+        .flat_map(|shares_dir| {
+            match dir_entries(&shares_dir) {
+                Ok(paths) => paths,
+                Err(e) => {
+                    report!("[^] Reading share directory {} failed | {}", shares_dir.display(), &e );
+                    vec![]
+                },
+            }
+        })
+        .filter(|path| options.all || is_share_path(path) ) // only .ccms (and .ccms.asc) files, unless we've been told to try everything
+        .filter(|path| !path.parent().is_some_and(|shares_dir| is_excluded(path, shares_dir, &options.exclude)) )
+        .enumerate()
+        .map_while(|(scanned, path)| { // a directory full of junk shouldn't keep us going forever
+            match options.max_scanned {
+                Some(max_scanned) if scanned >= max_scanned => {
+                    report!("[#] Stopped looking for shares after {} file(s) (--max-shares-scanned)", max_scanned );
+                    None
+                },
+                _ => Some(path),
+            }
+        })
+        .map(|path| (path.display().to_string(), share_from_file(&path, Some(nonce), &mut wrapping_keys, options.read_retries)) ) ),
+    };
+
+    // pull every share in first (passphrase prompts and all), so their signatures can be checked in one go
+    let mut candidates: Vec<(String, ShareFromFile)> = Vec::new();
+
+    for (source, share_f) in gathered {
+        match share_f? { // did the share grab fail?
+            Ok(shf) => {
+                report!("[%] Share # {} retrieved from {}", shf.share_data.x.0, &source);
+                candidates.push( (source, shf) );
+            },
+            Err(err) => report!("[^] Skipping {} | {}", &source, &err )
+        }
+    }
+
+    // ed25519 verification is the slow part with a lot of signed shares -- results come back in share order,
+    // so anything wrong is still reported (and asked about) in the same order as before
+    let verifications: Vec<Option<std::result::Result<(), SignatureError>>> = candidates.par_iter()
+        .map(|(_, shf)| match (shf.is_signed, &shf.pub_key, &shf.signature) {
+            (true, Some(share_pub_key), Some(share_signature)) => Some( verify_share_signature(shf, share_pub_key, share_signature) ),
+            _ => None,
+        })
+        .collect();
+
+    // threshold mismatch (either the file or shares have been tampered with) -- unless we're going by the shares anyway
+    // (each scheme has a threshold of its own, so those are sorted out once every share is in)
+    if !options.threshold_from_shares && schemes.is_none() {
+        resolve_threshold_mismatches(&candidates, threshold, options)?;
+    }
+
+    // processing the shares we pulled
+    for ((source, mut shf), share_verification) in candidates.into_iter().zip(verifications) { // Push shares to vector
+        let signature = signature_status(is_signed, pub_key, &shf, &share_verification);
+
+        if options.verify_all_signatures { // every share gets its say before anything's decided
+            signature_checks.push( ListedShare { source: source.clone(), index: shf.share_data.x.0, is_signed: shf.is_signed, signature } );
+        }
+        else if is_signed || shf.is_signed { // share is signed, therefore more checks!
+            share_signature_verification(is_signed, shares_unsigned, pub_key, /*signature,*/ &shf, share_verification, &source, options.strict)?;
+        }
+
+        let scheme = shf.scheme.unwrap_or(1);
+
+        if let Some(mac) = shf.mac.take() { // the key vouches for every scheme's shares alike, duplicates too
+            share_macs.push( GatheredMac { source: source.clone(), share: Zeroizing::new( Vec::from(&shf.share_data) ), mac } );
+        }
+
+        // a copy of a share we already have would count towards the threshold twice without adding anything
+        // (other schemes number their shares from 1 too, so only the same scheme's count)
+        if let Some((existing, _)) = shares.iter().zip(&share_schemes).find(|(share, &share_scheme)| share_scheme == scheme && share.x == shf.share_data.x) {
+            if !shares_match(existing, &shf.share_data) { // same index, different share -- one of them is wrong
+                report!("[#] Share # {} in {} conflicts with one already gathered", shf.share_data.x.0, &source);
+            }
+
+            report!("[^] Skipping {} | Duplicate of share # {}", &source, shf.share_data.x.0);
+
+            let mut duplicate = shf.share_data;
+            zeroize_share(&mut duplicate);
+            continue
+        }
+
+        if options.list_shares {
+            listed.push( ListedShare { source, index: shf.share_data.x.0, is_signed: shf.is_signed, signature } );
+        }
+
+        share_thresholds.push(shf.threshold);
+        share_schemes.push(scheme);
+        shares.push(shf.share_data);
+    }
+
+    if shares.is_empty() { // No shares to reconstruct the secret with
+        nl();
+        report!("[!] Zero shares located");
+        return Err( Error::no_shares("Cannot recover the key with zero shares!".to_string()) );
+    }
+
+    if options.verify_all_signatures {
+        report_share_signatures(&signature_checks, options.strict)?;
+    }
+
+    nl();
+    report!("[+] Located {} distinct share(s)", shares.len() );
+
+    if options.list_shares { // a last look at who the shares came from, before they're put to use
+        list_gathered_shares(&listed)?;
+    }
+
+    if let Some(schemes) = schemes { // only one scheme's shares can go towards the key
+        return pick_scheme(shares, &share_thresholds, &share_schemes, schemes, threshold)
+    }
+
+    if options.threshold_from_shares { // a corrupt threshold byte in the file shouldn't stop the shares recovering the key
+        let inferred = majority_threshold(&share_thresholds, *threshold);
+        let disagreeing = share_thresholds.iter().filter(|&&share_threshold| share_threshold != inferred).count();
+
+        if disagreeing > 0 {
+            report!("[#] {} of {} share(s) disagree with the others about the threshold", disagreeing, share_thresholds.len() );
+        }
+
+        if inferred != *threshold {
+            report!("[#] Shares say {} shares are needed, but the file says {} -- using the shares' threshold", inferred, threshold );
+            *threshold = inferred;
+        } else {
+            report!("[+] Shares agree with the file on the threshold ({})", inferred );
+        }
+    }
+
+    Ok(shares)
+}
+
+struct GatheredMac { // A gathered share's MAC, to check against the key once it's been recovered
+    source: String,
+    share: Zeroizing<Vec<u8>>, // the share (x and y) the MAC covers
+    mac: Vec<u8>,
+}
+
+fn check_share_macs(key: &[u8], share_macs: &[GatheredMac], strict: bool) -> Outcome<()> { // Make sure every share with a MAC really was split from the recovered key
+    if share_macs.is_empty() { // nothing to go on (they're optional)
+        return Ok(())
+    }
+
+    let matches: Vec<bool> = share_macs.iter().map(|share_mac| share_mac_matches(key, &share_mac.share, &share_mac.mac) ).collect();
+
+    for (share_mac, &matched) in share_macs.iter().zip(&matches) {
+        audit( serde_json::json!({
+            "event": "share_mac",
+            "share": &share_mac.source,
+            "outcome": if matched { "ok" } else { "mismatch" },
+        }) )?;
+    }
+
+    let mismatched: Vec<&str> = share_macs.iter().zip(&matches)
+        .filter(|(_, &matched)| !matched )
+        .map(|(share_mac, _)| share_mac.source.as_str() )
+        .collect();
+
+    nl();
+
+    if mismatched.is_empty() {
+        report_event(
+            &format!("[+] All {} share MAC(s) match the recovered key", share_macs.len()),
+            serde_json::json!({ "event": "share_macs", "checked": share_macs.len(), "mismatched": [] })
+        );
+        return Ok(())
+    }
+
+    for source in &mismatched {
+        report!("[#] Share {} may belong to a different file (its MAC does not match the recovered key)", source );
+    }
+
+    report_event(
+        &format!("[#] {} of {} share MAC(s) do not match the recovered key", mismatched.len(), share_macs.len()),
+        serde_json::json!({ "event": "share_macs", "checked": share_macs.len(), "mismatched": mismatched })
+    );
+
+    die_on_strict(strict)?;
+
+    Ok(())
+}
+
+struct ListedShare { // A gathered share, as --list-shares shows it
+    source: String,
+    index: u8,
+    is_signed: bool,
+    signature: &'static str, // see signature_status
+}
+
+fn signature_status( // How a share's signature held up, in a word: "valid", "invalid", "wrong key" or "-" (unsigned)
+    is_signed: bool, // whether the FILE is signed
+    pub_key: Option<PublicKey>, // the FILE'S public key
+    shf: &ShareFromFile,
+    share_verification: &Option<std::result::Result<(), SignatureError>> ) -> &'static str {
+
+    match (shf.is_signed, share_verification) {
+        (false, _) => "-",
+        (true, Some(Ok(()))) if is_signed && pub_key != shf.pub_key => "wrong key",
+        (true, Some(Ok(()))) => "valid",
+        (true, _) => "invalid",
+    }
+}
+
+fn report_share_signatures(checks: &[ListedShare], strict: bool) -> Outcome<()> { // Say how every share's signature held up (--verify-all-signatures), and stop if any didn't
+    let failed: Vec<&ListedShare> = checks.iter().filter(|check| check.signature != "valid" ).collect();
+
+    for check in checks {
+        audit( serde_json::json!({
+            "event": "share_signature",
+            "share": &check.source,
+            "outcome": match check.signature { "valid" => "ok", "-" => "unsigned", status => status },
+        }) )?;
+    }
+
+    nl();
+
+    if failed.is_empty() {
+        report_event(
+            &format!("[+] All {} share signature(s) verified", checks.len()),
+            serde_json::json!({ "event": "share_signatures", "checked": checks.len(), "failed": [] })
+        );
+        return Ok(())
+    }
+
+    report_event(
+        &format!("[!] {} of {} share signature(s) failed verification:", failed.len(), checks.len()),
+        serde_json::json!({
+            "event": "share_signatures",
+            "checked": checks.len(),
+            "failed": failed.iter().map(|check| serde_json::json!({ "path": &check.source, "index": check.index, "signature": check.signature }) ).collect::<Vec<_>>(),
+        })
+    );
+
+    for check in &failed {
+        let status = match check.signature {
+            "-" => "unsigned",
+            status => status,
+        };
+
+        report!("[!]   share # {} in {}: {}", check.index, check.source, status );
+    }
+
+    die_on_strict(strict)?;
+    ask_to_continue()?;
+
+    Ok(())
+}
+
+fn list_gathered_shares(listed: &[ListedShare]) -> Outcome<()> { // Show the shares we're about to recover the key with (--list-shares), then stop unless told to go on
+    nl();
+
+    if !json_output() {
+        output!("{:>5}  {:<6}  {:<9}  {}", "Index", "Signed", "Signature", "Share" );
+    }
+
+    for share in listed {
+        output_event(
+            &format!("{:>5}  {:<6}  {:<9}  {}", share.index, yes_no(share.is_signed), share.signature, share.source),
+            serde_json::json!({ "event": "listed_share", "path": &share.source, "index": share.index, "is_signed": share.is_signed, "signature": share.signature })
+        );
+    }
+
+    nl();
+
+    if non_interactive() { // nobody to say go ahead, so the listing is all we do
+        return Err( Error::stopped("Stopping after listing the shares (non-interactive)".to_string()) );
+    }
+
+    report!("[#] Recover the key from these shares and carry on? (y/N)");
+
+    if !matches!( read_answer()?.to_lowercase().as_str(), "y" | "yes" ) {
+        return Err( Error::stopped("Stopping without recovering the key".to_string()) );
+    }
+
+    Ok(())
+}
+
+fn resolve_threshold_mismatches(candidates: &[(String, ShareFromFile)], threshold: &mut u8, options: &GatherOptions) -> Outcome<()> { // Deal with shares disagreeing with the file about the threshold -- all of them at once, rather than asking per share
+    let mismatched: Vec<(&str, u8)> = candidates.iter()
+        .filter(|(_, shf)| shf.threshold != *threshold)
+        .map(|(source, shf)| (source.as_str(), shf.threshold) )
+        .collect();
+
+    if mismatched.is_empty() {
+        return Ok(())
+    }
+
+    nl();
+    report!("[#] Threshold mismatch from {} of {} share(s)", mismatched.len(), candidates.len() );
+    report!("[#] File:  {}", threshold );
+
+    for (source, share_threshold) in &mismatched {
+        report!("[#] Share: {} ({})", share_threshold, source );
+        audit( serde_json::json!({ "event": "threshold_mismatch", "share": source, "file_threshold": *threshold, "share_threshold": share_threshold }) )?;
+    }
+
+    let mut claimed: Vec<u8> = mismatched.iter().map(|(_, share_threshold)| *share_threshold).collect();
+    claimed.sort_unstable();
+    claimed.dedup();
+
+    if claimed.len() > 1 { // not even the shares agree, so more than one thing is corrupt
+        report!("[#] The shares don't agree with each other either ({} different thresholds) -- some are likely corrupt", claimed.len() );
+    }
+
+    if let Some(number) = options.threshold { // already told which threshold to use
+        *threshold = number;
+        report!("[#] Using threshold of {} from --threshold -- this might fail!", threshold );
+    }
+    else if non_interactive() { // nobody to ask: a mismatch is tampering, so treat it like any other
+        die_on_strict(options.strict)?;
+        report!("[#] Keeping the file's threshold (non-interactive)");
+    }
+    else {
+        nl();
+        report!("[#] Would you like to continue?");
+        report!("[#] If so, which threshold should we use?" );
+        report!("[#] (Ctrl+C to abort; provide threshold to use instead; empty for file's threshold)");
+
+        // Wait for user confirmation
+        let confirm = read_answer()?;
+
+        if confirm.is_empty() { // user gave no input
+            report!("[#] Okay. Continuing...");
+        } else {
+            let confirm = confirm.parse::<u8>();
+
+            match confirm {
+                Ok(0) => return Err( Error::refused("A threshold of 0 can't recover anything".to_string()) ),
+                Ok(number) => {
+                    *threshold = number;
+                    report!("[#] Using threshold of {} -- this might fail!", threshold );
+                },
+                Err(err) => return Err( Error::refused(format!("That's not a threshold number ({})", err)) ),
+            };
+
+        }
+    }
+
+    Ok(())
+}
+
+fn report_shares_needed(threshold: u8, schemes: Option<u8>, action: &str) { // How many shares a file needs (only the first of several schemes' threshold is in its header)
+    match schemes {
+        Some(schemes) => report!("[+] Shares from any one of {} schemes needed to {} ({} for the first)", schemes, action, threshold ),
+        None => report!("[+] {} shares needed to {}", threshold, action ),
+    }
+}
+
+fn pick_scheme( // Keep the shares of the first scheme with enough of them to recover the key (wiping the rest), and set `threshold` to its threshold
+    shares: Vec<Share>,
+    share_thresholds: &[u8],
+    share_schemes: &[u8],
+    schemes: u8,
+    threshold: &mut u8 ) -> Outcome<Vec<Share>> {
+
+    let mut chosen: Option<(u8, u8)> = None; // scheme and threshold
+    let mut fullest: Option<(u8, u8, usize)> = None; // scheme, threshold and share count of the scheme with the most shares
+
+    for scheme in 1..=schemes {
+        let thresholds: Vec<u8> = share_thresholds.iter().zip(share_schemes)
+            .filter(|(_, &share_scheme)| share_scheme == scheme)
+            .map(|(&share_threshold, _)| share_threshold)
+            .collect();
+
+        if thresholds.is_empty() {
+            report!("[+] Scheme {}: no shares found", scheme );
+            continue
+        }
+
+        // only the first scheme's threshold is in the file header; the others go by what their shares say
+        let scheme_threshold = match scheme {
+            1 => majority_threshold(&thresholds, *threshold),
+            _ => majority_threshold(&thresholds, thresholds[0]),
+        };
+
+        report!("[+] Scheme {}: {} share(s), {} needed", scheme, thresholds.len(), scheme_threshold );
+
+        if chosen.is_none() && thresholds.len() >= scheme_threshold as usize {
+            chosen = Some( (scheme, scheme_threshold) );
+        }
+
+        if fullest.is_none_or(|(_, _, count)| thresholds.len() > count) {
+            fullest = Some( (scheme, scheme_threshold, thresholds.len()) );
+        }
+    }
+
+    let (scheme, scheme_threshold) = match (chosen, fullest) {
+        (Some(chosen), _) => {
+            report!("[+] Using scheme {}", chosen.0 );
+            chosen
+        },
+        (None, Some((scheme, scheme_threshold, _))) => { // recovery will say so, but this is why
+            report!("[#] No scheme has enough shares to recover the key on its own -- trying scheme {}", scheme );
+            (scheme, scheme_threshold)
+        },
+        (None, None) => { // none of the shares are from a scheme the file knows about
+            shares.into_iter().for_each(|mut share| zeroize_share(&mut share) );
+            return Err( Error::no_shares(format!("None of the shares belong to any of the file's {} schemes", schemes)) );
+        }
+    };
+
+    *threshold = scheme_threshold;
+
+    Ok( shares.into_iter().zip(share_schemes).filter_map(|(mut share, &share_scheme)| match share_scheme == scheme {
+        true => Some(share),
+        false => {
+            zeroize_share(&mut share);
+            None
+        },
+    }).collect() )
+}
+
+fn majority_threshold(share_thresholds: &[u8], file_threshold: u8) -> u8 { // The threshold most shares agree on (the file's wins a tie)
+    let mut votes = [0usize; 256];
+
+    for share_threshold in share_thresholds {
+        votes[*share_threshold as usize] += 1;
+    }
+
+    let most_votes = votes.iter().max().copied().unwrap_or(0);
+
+    match votes[file_threshold as usize] == most_votes {
+        true => file_threshold,
+        false => votes.iter().position(|&count| count == most_votes).map_or(file_threshold, |threshold| threshold as u8),
+    }
+}
+
+fn file_signature_verification( // verification of an encrypted file's signature
+    target_file: &Path,
+    file_header: &FileHeader,
+    threshold: u8, // threshold we're using (the signature covers the header, so a tampered threshold will fail)
+    pub_key: PublicKey,
+    signature: Signature,
+    strict: bool ) -> Outcome<()> {
+
+    // with several schemes, the header has the first one's threshold whichever scheme we went with
+    let threshold = match file_header.schemes {
+        Some(_) => file_header.threshold,
+        None => threshold,
+    };
+
+    // Reconstruct the conditions for the original file's signing
+    let mut reconstructed_header: Vec<u8> = construct_header_file(file_header.algo_version, threshold, file_header.is_signed, file_header.has_aad, file_header.flags, file_header.chunk_size, file_header.cipher, &file_header.nonce, file_header.checksum.as_deref(), file_header.schemes, file_header.label.as_deref());
+    // public key
+    reconstructed_header.extend( pub_key.to_bytes() );
+
+    let file_verification = match (file_header.is_signed, file_header.flags & FLAG_STREAMED != 0) {
+        (false, _) => { // signed, but not in the header -- a detached signature covers the file exactly as it is
+            let digest = open_target_contents(target_file, "Verifying signature", 0)
+                .and_then(|mut file_contents| detached_signature_digest(&pub_key, &mut file_contents) );
+
+            let digest = match digest {
+                Ok(digest) => digest,
+                Err(error) => return Err( Error::failed(error, format!("Could not read file {}", target_file.display())) ),
+            };
+
+            pub_key.verify_prehashed(digest, Some(DETACHED_SIG_CONTEXT), &signature)
+        },
+        (true, true) => { // streamed files are signed over a SHA-512 prehash, so we never need the whole thing in memory
+            let digest = open_target_contents(target_file, "Verifying signature", file_header.length as u64)
+                .and_then(|mut file_contents| file_signature_digest(&reconstructed_header, &mut file_contents) );
+
+            let digest = match digest {
+                Ok(digest) => digest,
+                Err(error) => return Err( Error::failed(error, format!("Could not read file {}", target_file.display())) ),
+            };
+
+            pub_key.verify_prehashed(digest, None, &signature)
+        },
+        (true, false) => { // contents
+            let mut reconstructed_file = reconstructed_header;
+
+            match read_file_contents(target_file, file_header.length) {
+                Ok(contents) => reconstructed_file.extend(contents),
+                Err(error) => return Err( Error::failed(error, format!("Could not read file {}", target_file.display())) ),
+            };
+
+            pub_key.verify(&reconstructed_file, &signature)
+        }
+    };
+
+    audit( serde_json::json!({
+        "event": "file_signature",
+        "file": stringify_path(target_file),
+        "outcome": if file_verification.is_ok() { "ok" } else { "bad_signature" },
+        "detached": !file_header.is_signed,
+        "public_key": hex::encode( pub_key.to_bytes() ),
+        "error": file_verification.as_ref().err().map(|error| error.to_string() ),
+    }) )?;
+
+    if let Err(error) = file_verification { // File verification failed. Uh oh spaghetti-os
+        nl();
+        report!("[#] Signing mismatch with encrypted file!");
+        report!("[#] {}", &target_file.display());
+        report!("[#] Signature verification against file's public key failed!");
+        nl();
+        report!("[#] File public key:  {}", describe_pub_key(pub_key.as_bytes()) );
+        nl();
+        report!("[#] -----------------------------------------------------" );
+        report!("[#] WARNING: THIS FILE MAY BE CORRUPTED OR TAMPERED WITH " );
+        report!("[#] -----------------------------------------------------" );
+        nl();
+        report!("[#] More information:" );
+        report!("[#] {}", error );
+
+        die_on_strict(strict)?;
+        ask_to_continue()?;
+    }
+
+    Ok(())
+}
+
+fn enforce_min_shares(shares: &mut [Share], min_shares: u8, threshold: u8) -> Outcome<()> { // Stop (wiping the shares) unless there are at least --min-shares distinct ones, whatever the threshold
+    let mut indices: Vec<u8> = shares.iter().map(|share| share.x.0).collect();
+    indices.sort_unstable();
+    indices.dedup();
+
+    if indices.len() >= min_shares as usize {
+        report!("[+] {} distinct share(s) meets the policy minimum of {} (--min-shares)", indices.len(), min_shares );
+        return Ok(())
+    }
+
+    audit( serde_json::json!({ "event": "key_recovery", "outcome": "below_min_shares", "shares": indices, "threshold": threshold, "min_shares": min_shares }) )?;
+
+    for share in shares.iter_mut() {
+        zeroize_share(share);
+    }
+
+    nl();
+    report_event(
+        &format!("[!] Only {} distinct share(s) found, but policy requires at least {} (--min-shares)", indices.len(), min_shares),
+        serde_json::json!({ "event": "below_min_shares", "found": indices, "min_shares": min_shares, "threshold": threshold })
+    );
+
+    match indices.len() >= threshold as usize {
+        true => report!("[!] That's enough to recover the key (the file's threshold is {}), but not enough by policy -- refusing to", threshold ),
+        false => report!("[!] That's not even enough to recover the key (the file's threshold is {})", threshold ),
+    }
+
+    Err( Error::recovery_failed(format!("{} more distinct share(s) needed", min_shares as usize - indices.len())) )
+}
+
+fn recover_key(shares: &mut [Share], threshold: u8) -> Outcome<Zeroizing<Vec<u8>>> { // Recover the key from gathered shares (aborting if we can't), then wipe the shares
+    recover_from_shares(shares, threshold, "key", recover_key_from_shares)
+}
+
+type Recovery = fn(&[Share], u8) -> Result<Zeroizing<Vec<u8>>>; // recover_key_from_shares, or recover_secret_from_shares
+
+fn recover_from_shares( // recover_key, for a key or a secret split on its own (`what` the shares recover, for messages)
+    shares: &mut [Share],
+    threshold: u8,
+    what: &str,
+    recover: Recovery ) -> Outcome<Zeroizing<Vec<u8>>> {
+
+    // sharks would only say there aren't enough -- say which ones we have, so it's clear whether (and how many) to go looking for
+    let mut indices: Vec<u8> = shares.iter().map(|share| share.x.0).collect();
+    indices.sort_unstable();
+    indices.dedup();
+
+    let found = indices.iter().map(|index| index.to_string() ).collect::<Vec<String>>().join(",");
+
+    if indices.len() < threshold as usize {
+        audit( serde_json::json!({ "event": format!("{}_recovery", what), "outcome": "not_enough_shares", "shares": indices, "threshold": threshold }) )?;
+
+        nl();
+        report_event(
+            &format!("[!] Found share(s) {}; need any {}", found, threshold),
+            serde_json::json!({ "event": "not_enough_shares", "found": indices, "threshold": threshold })
+        );
+        shares.iter_mut().for_each(zeroize_share);
+        return Err( Error::recovery_failed(format!("{} more distinct share(s) needed to recover the {}", threshold as usize - indices.len(), what)) );
+    }
+
+    report!("[+] Found share(s) {}; need any {}", found, threshold );
+    report!("[-] Attempting {} recovery with {} share(s)...", what, &shares.len() );
+
+    let recovered_key = match recover(shares, threshold) {
+        Ok(key) => {
+            audit( serde_json::json!({ "event": format!("{}_recovery", what), "outcome": "ok", "shares": indices, "threshold": threshold }) )?;
+
+            report!("[%] Recovery successful!");
+            key
+        },
+        Err(sss_err) => {
+            audit( serde_json::json!({ "event": format!("{}_recovery", what), "outcome": "failed", "shares": indices, "threshold": threshold, "error": sss_err.to_string() }) )?;
+
+            // there were enough of them (that's checked above), so it's what's in them that's wrong
+            nl();
+            report_event(
+                &format!("[!] Could not recover the {} from {} distinct share(s) (threshold {}): {}", what, indices.len(), threshold, sss_err),
+                serde_json::json!({ "event": "recovery_failed", "found": indices, "threshold": threshold, "error": sss_err.to_string() })
+            );
+            report!("[!] That's enough shares, so at least one of them is corrupt or belongs to a different file or split");
+
+            shares.iter_mut().for_each(zeroize_share);
+            return Err( Error::recovery_failed("Try --strict with signed shares to find out which, or leave shares out to narrow it down".to_string()) );
+        }
+    };
+
+    // only the recovered key is needed from here on
+    shares.iter_mut().for_each(zeroize_share);
+
+    Ok(recovered_key)
+}
+
+fn recover_own_shares(shares: &[Vec<u8>], threshold: u8, key: &[u8]) -> Outcome<Zeroizing<Vec<u8>>> { // Make sure shares we've just made give back the key (or secret) they were split from (aborting if not); returns the recovered key
+    let mut recovered_shares: Vec<Share> = match shares.iter().map(|s| Share::try_from(s.as_slice()) ).collect() {
+        Ok(recovered_shares) => recovered_shares,
+        Err(error) => return Err( Error::failed(io::Error::other(error), "Could not read back the shares we just made!".to_string()) ),
+    };
+
+    let recovered_key = recover_secret_from_shares(&recovered_shares, threshold); // checked against the original below, whatever its length
+    recovered_shares.iter_mut().for_each(zeroize_share);
+
+    match recovered_key {
+        Ok(recovered_key) if bool::from( recovered_key.as_slice().ct_eq(key) ) => { // should always be the case
+            report!("[-] Share recovery succeeded");
+            Ok(recovered_key)
+        },
+        Ok(_) => Err( Error::failed(io::Error::other("Recovered key does not match the original"), "Unable to recover the key from the shares we just made!".to_string()) ),
+        Err(error) => Err( Error::failed(error, "Unable to recover the key from the shares we just made!".to_string()) ),
+    }
+}
+
+fn verify_share_subsets(shares: &[Vec<u8>], threshold: u8, key: &[u8]) -> Outcome<()> { // Make sure threshold-sized subsets of shares we've just made each give back the key too (aborting if not)
+    let subsets = share_subsets(shares.len(), threshold as usize);
+
+    for subset in &subsets {
+        let mut subset_shares: Vec<Share> = match subset.iter().map(|&i| Share::try_from(shares[i].as_slice()) ).collect() {
+            Ok(subset_shares) => subset_shares,
+            Err(error) => return Err( Error::failed(io::Error::other(error), "Could not read back the shares we just made!".to_string()) ),
+        };
+
+        let recovered_key = recover_secret_from_shares(&subset_shares, threshold);
+        subset_shares.iter_mut().for_each(zeroize_share);
+
+        if !recovered_key.is_ok_and(|recovered_key| bool::from( recovered_key.as_slice().ct_eq(key) )) {
+            let indices = subset.iter().map(|i| (i + 1).to_string() ).collect::<Vec<String>>().join(",");
+            return Err( Error::failed(io::Error::other("Recovered key does not match the original"), format!("Unable to recover the key from share(s) {} of the ones we just made!", indices)) );
+        }
+    }
+
+    report!("[-] Share subset recovery succeeded ({} subset(s) of {} share(s))", subsets.len(), threshold );
+
+    Ok(())
+}
+
+fn share_subsets(count: usize, size: usize) -> Vec<Vec<usize>> { // Subsets of `size` share indices (from 0) to check, out of `count`: all of them if there are few enough, otherwise a random selection
+    let mut combinations: u128 = 1; // count choose size (the same as count choose count - size), stopping once it's clearly too many
+    for i in 0..size.min(count - size) {
+        combinations = combinations * (count - i) as u128 / (i + 1) as u128;
+
+        if combinations > VERIFY_SUBSETS as u128 {
+            break
+        }
+    }
+
+    if combinations <= VERIFY_SUBSETS as u128 { // every one, in order
+        let mut subsets = vec![];
+        let mut subset: Vec<usize> = (0..size).collect();
+
+        loop {
+            subsets.push( subset.clone() );
+
+            // move the last index that can go up, and reset everything after it
+            match (0..size).rev().find(|&i| subset[i] < count - size + i) {
+                Some(i) => {
+                    subset[i] += 1;
+                    for j in (i + 1)..size {
+                        subset[j] = subset[j - 1] + 1;
+                    }
+                },
+                None => return subsets,
+            }
+        }
+    }
+
+    (0..VERIFY_SUBSETS).map(|_| { // a partial Fisher-Yates shuffle for each
+        let mut indices: Vec<usize> = (0..count).collect();
+
+        for i in 0..size {
+            let mut random = [0u8; 8];
+            fill_random(&mut random);
+
+            let j = i + (u64::from_le_bytes(random) % (count - i) as u64) as usize; // negligible bias with at most 255 shares
+            indices.swap(i, j);
+        }
+
+        indices.truncate(size);
+        indices.sort_unstable();
+        indices
+    }).collect()
+}
+
+fn trial_decryption(target_file: &Path, file_header: &FileHeader, key: &[u8]) -> Outcome<()> { // Decrypt a file without keeping any of the plaintext, to check the key and contents are good
+    let trial_result = match file_header.flags & FLAG_STREAMED != 0 {
+        true => open_target_contents(target_file, "Decrypting", file_header.length as u64)
+            .and_then(|mut file_contents| decrypt_contents(file_header, key, &mut file_contents, &mut io::sink()) )
+            .map(|_| () ),
+        false => read_file_contents(target_file, file_header.length) // version 1 files are decrypted in one go
+            .and_then(|contents| chacha_decrypt_v1(key, &file_header.nonce, &contents) )
+            .map(|mut plaintext| plaintext.zeroize() ),
+    };
+
+    if let Err(error) = trial_result {
+        return Err( Error::decryption(error, "File failed to decrypt with the recovered key!".to_string()) );
+    }
+
+    report!("[%] File decrypts with the recovered key");
+    report_checksum_matched(file_header);
+
+    Ok(())
+}
+
+fn report_checksum_matched(file_header: &FileHeader) { // decrypt_contents fails if the checksum doesn't match, so once it's done there's only good news to give
+    if file_header.checksum.is_some() {
+        report_event(
+            "[%] Integrity OK (blake3 matched)",
+            serde_json::json!({ "event": "checksum_matched", "algorithm": "blake3", "checksum": file_header.checksum.as_ref().map(hex::encode) })
+        );
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool { // does this path have the given extension? (ignoring case, so .CCMS counts)
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(extension) )
+}
+
+fn is_share_path(path: &Path) -> bool { // a .ccms share file, or an armored .ccms.asc one
+    has_extension(path, "ccms") || (has_extension(path, "asc") && path.file_stem().is_some_and(|stem| has_extension(Path::new(stem), "ccms")))
+}
+
+fn armored_path(share_path: &Path) -> PathBuf { // where an armored share goes: its usual name, plus .asc
+    let mut path = share_path.as_os_str().to_owned();
+    path.push(".asc");
+    PathBuf::from(path)
+}
+
+fn share_stem(path: &Path) -> Option<String> { // a share's name, less .ccms (or .ccms.asc)
+    let stem = match has_extension(path, "asc") { // name.ccms.asc -> name
+        true => path.file_stem().map(Path::new).and_then(Path::file_stem),
+        false => path.file_stem(),
+    };
+
+    stem.map(|stem| stem.to_string_lossy().into_owned() )
+}
+
+fn is_hex_of_length(text: &str, lengths: &[usize]) -> bool { // is this hex for one of these numbers of bytes?
+    lengths.iter().any(|length| text.len() == 2 * length ) && text.bytes().all(|byte| byte.is_ascii_hexdigit() )
+}
+
+fn split_name_checksum(stem: &str) -> (&str, Option<&str>) { // A share name's {checksum}, and the rest of the name -- only if it comes straight after the {nonce}, so nothing else is mistaken for one
+    match stem.rsplit_once('-') {
+        Some((rest, checksum)) if is_hex_of_length(checksum, &[SHARE_NAME_CHECKSUM_BYTES])
+            && rest.rsplit(['-', '_']).next().is_some_and(|nonce| is_hex_of_length(nonce, &[NONCE_LENGTH_BYTES, NONCE_LENGTH_BYTES_V2]) ) => (rest, Some(checksum)),
+        _ => (stem, None),
+    }
+}
+
+fn share_checksum(share: &[u8]) -> String { // what {checksum} is filled in with (of the share file as it's built, before any armor)
+    hex::encode( &blake3::hash(share).as_bytes()[..SHARE_NAME_CHECKSUM_BYTES] )
+}
+
+fn share_checksum_matches(share: &[u8], checksum: &str) -> bool { // whitespace a share file has picked up after the share isn't part of it
+    let trailing = share.iter().rev().take(SHARE_TRAILING_MAX_BYTES).take_while(|byte| matches!(byte, b'\n' | b'\r' | b' ' | b'\t' | 0) ).count();
+
+    (0..=trailing).any(|dropped| share_checksum(&share[..share.len() - dropped]).eq_ignore_ascii_case(checksum) )
+}
+
+fn with_share_checksum(share_path: &Path, share: &[u8]) -> PathBuf { // fill in a share path's {checksum}, once the share's been made
+    match share_path.file_name().map(|name| name.to_string_lossy() ) {
+        Some(name) if name.contains("{checksum}") => share_path.with_file_name( name.replace("{checksum}", &share_checksum(share)) ),
+        _ => share_path.to_path_buf(),
+    }
+}
+
+fn checksum_siblings(share_path: &Path) -> Vec<PathBuf> { // shares already at a share path that has yet to have its {checksum} filled in, whatever their checksum
+    let name = match share_path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return vec![],
+    };
+
+    let (before, after) = match name.split_once("{checksum}") {
+        Some(parts) => parts,
+        None => return vec![],
+    };
+
+    dir_entries( share_path.parent().unwrap_or(Path::new(".")) ).unwrap_or_default().into_iter()
+        .filter(|sibling| {
+            sibling.file_name()
+                .and_then(|name| name.to_str() )
+                .and_then(|name| name.strip_prefix(before)?.strip_suffix(after) )
+                .is_some_and(|checksum| is_hex_of_length(checksum, &[SHARE_NAME_CHECKSUM_BYTES]) )
+        })
+        .collect()
+}
+
+fn share_path_taken(share_path: &Path) -> bool { // is there a share at this path already (with any checksum)?
+    share_path.exists() || !checksum_siblings(share_path).is_empty()
+}
+
+fn archive_entries(paths: &[PathBuf], exclude: &[Pattern]) -> Outcome<Vec<ArchiveEntry>> { // The files to pack with --archive, named by their path within the archive
+    // the first path, if it's a directory, is the archive's root (its files keep their paths within it); everything else goes in under its own name
+    let mut entries: Vec<ArchiveEntry> = vec![];
+    let mut names: HashSet<String> = HashSet::new();
+
+    for (path_i, path) in paths.iter().enumerate() {
+        let (root, files) = match path.is_dir() {
+            true if path_i == 0 => (path.as_path(), files_in_tree(path, exclude)),
+            true => (path.parent().unwrap_or(Path::new("")), files_in_tree(path, exclude)),
+            false if path.is_file() => (path.parent().unwrap_or(Path::new("")), vec![path.clone()]),
+            false => return Err( Error::refused(format!("{} is not a file or directory (--archive packs up files, and the files in directories)", path.display())) ),
+        };
+
+        for file in files {
+            if has_extension(&file, "ccm") || is_share_path(&file) || is_detached_sig(&file) || is_header_sidecar(&file) || is_excluded(&file, root, exclude) { // don't encrypt our own output
+                continue
+            }
+
+            // stored with / between directories, whatever the platform
+            let name: Option<Vec<&str>> = file.strip_prefix(root).unwrap_or(&file).components()
+                .filter(|component| matches!(component, Component::Normal(_)) )
+                .map(|component| component.as_os_str().to_str() )
+                .collect();
+
+            let name = match name {
+                Some(name) => name.join("/"),
+                None => return Err( Error::refused(format!("{} isn't valid UTF-8, so it can't be named in an archive", file.display())) ),
+            };
+
+            if !names.insert(name.clone()) { // unpacking would have to put both in the same place
+                return Err( Error::refused(format!("More than one file would be named {} in the archive", name)) );
+            }
+
+            entries.push( ArchiveEntry { name, path: file } );
+        }
+    }
+
+    if entries.is_empty() {
+        return Err( Error::refused("No files to archive".to_string()) );
+    }
+
+    report!("[+] Archiving {} file(s)", entries.len() );
+
+    Ok(entries)
+}
+
+fn files_in_tree(dir: &Path, exclude: &[Pattern]) -> Vec<PathBuf> { // Every regular file under a directory, recursively (collected up front, so files we create aren't picked up), bar any excluded
+    files_in_subtree(dir, dir, exclude)
+}
+
+fn files_in_subtree(root: &Path, dir: &Path, exclude: &[Pattern]) -> Vec<PathBuf> { // files_in_tree, for one directory somewhere under root
+    let mut files: Vec<PathBuf> = vec![];
+
+    for path in dir_entries(dir).unwrap_or_default() {
+        if is_excluded(&path, root, exclude) { // an excluded directory isn't even looked in
+            continue
+        }
+
+        match fs::symlink_metadata(&path) { // don't follow symlinked directories (they could loop back on themselves)
+            Ok(metadata) if metadata.is_dir() => files.extend( files_in_subtree(root, &path, exclude) ),
+            _ if path.is_file() => files.push(path),
+            _ => {},
+        }
+    }
+
+    files
+}
+
+fn is_excluded(path: &Path, root: &Path, exclude: &[Pattern]) -> bool { // Does an --exclude pattern match this path (relative to root), or just its name?
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let name = path.file_name().map(Path::new);
+
+    exclude.iter().any(|pattern| pattern.matches_path(relative) || name.is_some_and(|name| pattern.matches_path(name) ) )
+}
+
+fn mirrored_share_dir(target_dir: &Path, file: &Path, shares_dir: &Path) -> PathBuf { // Where a file's shares live when working on a whole directory
+    let target_dir = absolute_path(target_dir).unwrap_or_else(|_| target_dir.to_path_buf() );
+    let file = absolute_path(file).unwrap_or_else(|_| file.to_path_buf() );
+
+    match file.strip_prefix(&target_dir).ok().and_then(|relative| relative.parent() ) {
+        Some(relative_dir) => shares_dir.join(relative_dir),
+        None => shares_dir.to_path_buf(),
+    }
+}
+
+fn encrypted_file_path(target_file: &Path) -> PathBuf { // Where a file is encrypted to by default (its name, plus .ccm)
+    let mut target_enc_file = PathBuf::from(target_file);
+
+    match target_enc_file.extension() { // add .ccm extension
+        Some(ext) => {
+            let mut ext = ext.to_os_string();
+            ext.push(".ccm");
+            target_enc_file.set_extension(ext)
+        }
+        None => target_enc_file.set_extension("ccm"), // set_extension adds the dot itself (".ccm" would give "name..ccm")
+    };
+
+    target_enc_file
+}
+
+fn read_input_list(list_path: &Path) -> Outcome<Vec<PathBuf>> { // The paths in an --input-list manifest: one per line, bar blank lines and #comments
+    let contents = match fs::read(list_path) {
+        Ok(contents) => contents,
+        Err(error) => return Err( Error::failed(error, format!("Could not read input list {}", list_path.display())) ),
+    };
+
+    Ok( contents.split(|&byte| byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line) )
+        .filter(|line| !line.iter().all(u8::is_ascii_whitespace) && !line.starts_with(b"#") )
+        .map(path_from_bytes)
+        .collect() )
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf { // paths needn't be UTF-8 on Unix, so take the bytes as they are
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from( std::ffi::OsStr::from_bytes(bytes) )
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from( String::from_utf8_lossy(bytes).into_owned() )
+}
+
+fn check_batch_file(path: &Path, options: &EncryptOptions) -> std::result::Result<(), String> { // Catch what would stop encrypt_file (or make it ask) before it gets the chance, so one bad file doesn't end a batch
+    let metadata = fs::metadata(path).map_err(|error| error.to_string() )?;
+
+    if !metadata.is_file() {
+        return Err( "Not a file".to_string() )
+    }
+
+    fs::File::open(path).map_err(|error| error.to_string() )?;
+
+    if metadata.len() == 0 {
+        return Err( "Empty file (0 bytes)".to_string() )
+    }
+
+    if metadata.len() > max_stream_length(options.chunk_size) {
+        return Err( format!("Too big to encrypt in {} byte chunks; use a bigger --chunk-size", options.chunk_size) )
+    }
+
+    if options.force { // happy to encrypt again, and overwrite
+        return Ok(())
+    }
+
+    if read_file_header(path).is_ok_and(|header| is_encrypted(&header).is_ok() ) {
+        return Err( "Already a chachamir file (--force to encrypt it again)".to_string() )
+    }
+
+    if encrypted_file_path(path).exists() {
+        return Err( format!("{} already exists (--force to overwrite it)", stringify_path(&encrypted_file_path(path)) ) )
+    }
+
+    Ok(())
+}
+
+fn encrypt_listed_files(list_path: &Path, shares_dir: &Path, options: &EncryptOptions) -> Outcome<()> { // Encrypt every file in an --input-list, each with its own key and its shares in a directory of its own, carrying on past any that can't be
+    let files = read_input_list(list_path)?;
+
+    if files.is_empty() {
+        return Err( Error::refused(format!("No files listed in {}", stringify_path(list_path))) );
+    }
+
+    let mut share_dir_names: HashSet<std::ffi::OsString> = HashSet::new();
+    let mut failures: Vec<(PathBuf, String)> = vec![];
+
+    for (file_i, path) in files.iter().enumerate() {
+        nl();
+        report!("[*] File {} of {}: {}", file_i + 1, files.len(), stringify_path(path) );
+
+        if let Err(error) = check_batch_file(path, options) {
+            report!("[!] Skipping {} | {}", stringify_path(path), error );
+            failures.push( (path.to_path_buf(), error) );
+            continue
+        }
+
+        // shares go in a directory named after the file (numbered if another listed file has the same name)
+        let name = path.file_name().unwrap_or_default().to_os_string();
+        let mut dir_name = name.clone();
+        let mut copy = 1;
+
+        while !share_dir_names.insert(dir_name.clone()) {
+            copy += 1;
+            dir_name = name.clone();
+            dir_name.push(format!("-{}", copy));
+        }
+
+        let file_shares_dir = shares_dir.join(dir_name);
+
+        if !options.dry_run {
+            if let Err(error) = fs::create_dir_all(&file_shares_dir) {
+                report!("[!] Skipping {} | Could not create share directory {} ({})", stringify_path(path), file_shares_dir.display(), error );
+                failures.push( (path.to_path_buf(), error.to_string()) );
+                continue
+            }
+        }
+
+        nl();
+        encrypt_file(path, &file_shares_dir, None, options)?;
+    }
+
+    nl();
+    report_event(
+        &format!("[&] Encrypted {} of {} listed file(s)", files.len() - failures.len(), files.len()),
+        serde_json::json!({
+            "event": "batch_summary",
+            "encrypted": files.len() - failures.len(),
+            "failed": failures.iter().map(|(path, error)| serde_json::json!({ "path": stringify_path(path), "error": error }) ).collect::<Vec<_>>(),
+        })
+    );
+
+    if !failures.is_empty() {
+        report!("[!] {} file(s) could not be encrypted:", failures.len() );
+
+        for (path, error) in &failures {
+            report!("[!]   {} | {}", stringify_path(path), error );
+        }
+
+        return Err( Error::refused("Not every listed file could be encrypted".to_string()) );
+    }
+
+    Ok(())
+}
+
+fn encrypt_file(target_file: &Path, shares_dir: &Path, output: Option<PathBuf>, options: &EncryptOptions) -> Outcome<PathBuf> { // Encrypt a single file with a new key, writing its shares; returns where the encrypted file went
+    // Encrypting our own output again works, but getting the original back would then take two sets of shares and two recoveries
+    if let Ok(header) = read_file_header(target_file) {
+        if let Ok(header) = is_encrypted(&header) {
+            nl();
+            report!("[#] {} already appears to be a chachamir file (algorithm version {})", target_file.display(), header[HEADER_FILE.len()] );
+
+            match options.force {
+                true => report!("[#] Encrypting it again anyway (--force)"),
+                false => {
+                    report!("[#] Encrypt it again anyway? Decrypting it would then take two full recoveries");
+                    ask_to_continue()?;
+                },
+            }
+
+            nl();
+        }
+    }
+
+    // with --archive, the archive of files is what's encrypted
+    let plaintext_length: Option<u64> = match &options.archive {
+        Some(entries) => archive_length(entries).ok(),
+        None => fs::metadata(target_file).ok().map(|metadata| metadata.len() ),
+    };
+
+    // An empty file encrypts fine, but is almost certainly a mistake (e.g. a truncated copy) -- check before making shares for it
+    if let Some(plaintext_length) = plaintext_length {
+        if plaintext_length == 0 {
+            nl();
+            report!("[#] {} is empty (0 bytes)", target_file.display() );
+            report!("[#] Decrypting it will only ever give back an empty file");
+            ask_to_continue()?;
+            nl();
+        }
+
+        // the STREAM counter only goes so far, so really big files need bigger chunks
+        if plaintext_length > max_stream_length(options.chunk_size) {
+            return Err( Error::refused(format!("{} is too big to encrypt in {} byte chunks (at most {} bytes); use a bigger --chunk-size", target_file.display(), options.chunk_size, max_stream_length(options.chunk_size))) );
+        }
+    }
+
+    let mut timings = Timings::new();
+
+    // Generate 256-bit key (wiped from memory when we're done with it)
+    let mut key = Zeroizing::new( [0u8; KEY_LENGTH_BYTES] );
+    fill_random(key.as_mut_slice() );
+    report!("[-] Key generated");
+
+    // Generate nonce for the cipher (192-bit for XChaCha20; also used to ID files)
+    let mut nonce = vec![0u8; options.cipher.nonce_length()];
+    fill_random(&mut nonce);
+    report!("[-] Nonce generated");
+    timings.lap("Key and nonce generation");
+
+    let hex_nonce = hex::encode(&nonce); // hex representation of the nonce
+
+    // Creating a keypair doesn't cause that much overhead (benchmarked in the millisecond range)
+    let mut ed25519_keypair: Keypair = match &options.key_file {
+        Some(key_file) => {
+            let keypair = load_keypair(key_file)?;
+            report!("[-] Signing keypair loaded from {}", stringify_path(key_file) );
+
+            keypair
+        },
+        None => generate_keypair(),
+    };
+    let ed25519_bytes_pub: [u8; PUBLIC_KEY_LENGTH] = ed25519_keypair.public.to_bytes();
+    timings.lap("Signing keypair");
+
+    // Split into shares of the secret -- separately for each scheme, so any one of them recovers the key
+    let multi_scheme = options.schemes.len() > 1;
+
+    let scheme_shares: Vec<Zeroizing<Vec<Vec<u8>>>> = options.schemes.iter().enumerate().map(|(scheme_i, &(players, threshold))| {
+        let shares = split_key_into_shares(key.as_slice(), players, threshold);
+
+        match multi_scheme {
+            true => report!("[-] Scheme {}: derived {} share(s) from key | threshold {}", scheme_i + 1, shares.len(), threshold),
+            false => report!("[-] Derived {} share(s) from key | threshold {}", shares.len(), threshold),
+        }
+
+        shares
+    }).collect();
+
+    timings.lap("Share splitting");
+
+    // Recover the shares again for good measure (unless we've been told not to bother)
+    let recovered_key: Zeroizing<Vec<u8>> = match options.fast {
+        true => {
+            report!("[-] Skipping share recovery and round-trip checks (--fast)");
+            Zeroizing::new( key.to_vec() )
+        },
+        false => {
+            let mut recovered_key = Zeroizing::new( vec![] );
+
+            for (shares, &(_, threshold)) in scheme_shares.iter().zip(&options.schemes) { // every scheme is checked against the key, so the last one's is as good as any
+                recovered_key = recover_own_shares(shares, threshold, key.as_slice())?;
+
+                if options.verify_subsets { // any quorum should do, not just everyone together
+                    verify_share_subsets(shares, threshold, key.as_slice())?;
+                }
+            }
+
+            recovered_key
+        },
+    };
+
+    timings.lap("Share recovery check");
+
+    // open plaintext file to make sure we aren't saving useless shares if this fails
+    let mut file_plaintext = match open_plaintext(target_file, options.archive.as_deref(), "Encrypting") {
+        Ok(reader) => reader,
+        Err(error) => return Err( Error::failed(error, format!("Could not open file {}", target_file.display())) ),
+    };
+
+    // the checksum goes in the header, so it has to be worked out before anything's encrypted -- an extra pass over the file
+    let checksum: Option<Vec<u8>> = match (options.checksum, options.dry_run) {
+        (true, false) => {
+            let checksum = open_plaintext(target_file, options.archive.as_deref(), "Hashing")
+                .and_then(|mut reader| plaintext_checksum(&mut reader) );
+
+            match checksum {
+                Ok(checksum) => {
+                    report!("[-] Plaintext checksum (blake3): {}", hex::encode(&checksum) );
+                    Some(checksum)
+                },
+                Err(error) => return Err( Error::failed(error, format!("Could not read file {}", target_file.display())) ),
+            }
+        },
+        (true, true) => Some( vec![0u8; CHECKSUM_LENGTH_BYTES] ), // only its length matters for a dry run
+        (false, _) => None,
+    };
+
+    if options.checksum {
+        timings.lap("Checksum");
+    }
+
+    // Work out where to save the encrypted file
+    let target_enc_file = get_output_path(output, encrypted_file_path(target_file) )?;
+
+    // Make sure we aren't about to overwrite an earlier encryption (dry runs just mention it further down)
+    if !options.dry_run {
+        guard_overwrite(&target_enc_file, options.force, None)?;
+    }
+
+    // each scheme's shares get their own directory, if there's more than one
+    let scheme_dirs: Vec<PathBuf> = (1..=options.schemes.len()).map(|scheme_i| match multi_scheme {
+        true => scheme_share_dir(shares_dir, scheme_i as u8),
+        false => shares_dir.to_path_buf(),
+    }).collect();
+
+    // Make sure we aren't about to overwrite somebody else's shares
+    let armor = matches!(options.share_output, ShareOutput::Files { armor: true, .. });
+
+    let scheme_paths: Vec<Vec<PathBuf>> = scheme_dirs.iter().zip(scheme_shares.iter().zip(&options.schemes))
+        .map(|(scheme_dir, (shares, &(_, threshold)))| share_paths(scheme_dir, &options.share_name_template, shares.len(), threshold, &hex_nonce) )
+        .map(|paths| paths.map(|paths| match armor { // name.ccms.asc, so it's still plainly a share
+            true => paths.iter().map(|path| armored_path(path) ).collect(),
+            false => paths,
+        }))
+        .collect::<Outcome<_>>()?;
+
+    if !options.force && matches!(options.share_output, ShareOutput::Files { .. }) {
+        for (scheme_dir, share_paths) in scheme_dirs.iter().zip(&scheme_paths) {
+            die_on_existing_shares(scheme_dir, &hex_nonce, share_paths)?;
+        }
+    }
+
+    if let (ShareOutput::Bundle(share_bundle), false) = (&options.share_output, options.dry_run) {
+        guard_overwrite(share_bundle, options.force, None)?;
+    }
+
+    // Save shares to folder
+    nl();
+
+    let share_keypair = match options.sign_shares {
+        true => Some(&ed25519_keypair),
+        false => None,
+    };
+
+    let share_output = match options.dry_run {
+        true => &ShareOutput::DryRun,
+        false => &options.share_output,
+    };
+
+    let mut share_bytes: u64 = 0;
+
+    for (scheme_i, (shares, &(_, threshold))) in scheme_shares.iter().zip(&options.schemes).enumerate() {
+        let scheme_number = scheme_i as u8 + 1;
+        let scheme_dir = &scheme_dirs[scheme_i];
+
+        if multi_scheme {
+            report!("[+] Scheme {}: {} share(s), threshold {}, in {}", scheme_number, shares.len(), threshold, stringify_path(scheme_dir) );
+
+            if !options.dry_run {
+                if let Err(error) = fs::create_dir_all(scheme_dir) {
+                    return Err( Error::failed(error, format!("Could not create share directory {}", scheme_dir.display())) );
+                }
+            }
+        }
+
+        // --- Construct share header
+        let share_header: Vec<u8> = construct_header_share(ALGO_VERSION, threshold, options.sign_shares, options.sign_shares && !options.sign_file, &nonce, multi_scheme.then_some(scheme_number), options.salt.as_deref(), options.share_mac, None);
+
+        share_bytes += write_shares(shares, &scheme_paths[scheme_i], &share_header, share_keypair, options.wrapping_key.as_ref().map(|key| key.as_slice()), options.share_mac.then_some(key.as_slice()), share_output, options.share_mode )?;
+    }
+
+    timings.lap("Share writing");
+
+    // Done with share stuff
+    nl();
+
+    // --- Construct encrypted file header
+
+    let mut flags = match options.compression_level {
+        Some(_) => FLAG_STREAMED | FLAG_COMPRESSED,
+        None => FLAG_STREAMED,
+    };
+
+    if options.archive.is_some() {
+        flags |= FLAG_ARCHIVE;
+    }
+
+    // the metadata block goes in front of the plaintext, so it's encrypted (and authenticated) along with it
+    let metadata_block: Vec<u8> = match options.metadata {
+        true => {
+            flags |= FLAG_METADATA;
+            metadata_block_for(target_file)?
+        },
+        false => vec![],
+    };
+
+    // a detached signature leaves the header as it would be unsigned
+    let embed_signature = options.sign_file && !options.detached_sig;
+
+    if options.sign_file && !options.sign_shares { // so decryption doesn't take the unsigned shares for a mismatch
+        flags |= FLAG_SHARES_UNSIGNED;
+    }
+
+    // the header's threshold is the first scheme's; the rest are only in their shares
+    let schemes = multi_scheme.then_some(options.schemes.len() as u8);
+
+    let mut enc_header: Vec<u8> = construct_header_file(ALGO_VERSION, options.schemes[0].1, embed_signature, options.aad.is_some(), flags, options.chunk_size, options.cipher, &nonce, checksum.as_deref(), schemes, options.label.as_deref());
+
+    // public key (signature gets filled in once the contents are written)
+    if embed_signature {
+        enc_header.extend( ed25519_bytes_pub );
+    }
+
+    if options.dry_run { // say what we'd have written, and stop there
+        let plaintext_length = metadata_block.len() as u64 + plaintext_length.unwrap_or(0);
+        let signature_length = if embed_signature { SIGNATURE_LENGTH } else { 0 };
+        let enc_length = (enc_header.len() + signature_length) as u64 + ciphertext_length(plaintext_length, options.chunk_size);
+
+        report_event(
+            &format!("[&] Would write encrypted file to {} ({} bytes{})", stringify_path(&target_enc_file), enc_length,
+                if options.compression_level.is_some() { " before compression" } else { "" } ),
+            serde_json::json!({ "event": "planned_file", "kind": "encrypted", "path": stringify_path(&target_enc_file), "bytes": enc_length })
+        );
+
+        if target_enc_file.exists() {
+            report!("[#] {} already exists, and would be overwritten", stringify_path(&target_enc_file) );
+        }
+
+        if options.detached_sig {
+            let sig_path = detached_sig_path(&target_enc_file);
+
+            report_event(
+                &format!("[&] Would write detached signature to {} ({} bytes)", stringify_path(&sig_path), DETACHED_SIG_LENGTH),
+                serde_json::json!({ "event": "planned_file", "kind": "signature", "path": stringify_path(&sig_path), "bytes": DETACHED_SIG_LENGTH })
+            );
+        }
+
+        if options.output_format == OutputFormat::Raw { // same bytes in all, just split in two
+            let header_path = header_sidecar_path(&target_enc_file);
+            let header_length = enc_header.len() + signature_length;
+
+            report_event(
+                &format!("[&] Would write header to {} ({} bytes), leaving only the ciphertext in {}", stringify_path(&header_path), header_length, stringify_path(&target_enc_file)),
+                serde_json::json!({ "event": "planned_file", "kind": "header", "path": stringify_path(&header_path), "bytes": header_length })
+            );
+        }
+
+        report!("[-] Signing: {}", match (options.sign_file, options.sign_shares) {
+            (true, true) => "enabled",
+            (true, false) => "file only",
+            (false, true) => "shares only",
+            (false, false) => "disabled",
+        });
+        report!("[-] Would write {} bytes in total", share_bytes + enc_length + if options.detached_sig { DETACHED_SIG_LENGTH as u64 } else { 0 } );
+
+        ed25519_keypair.secret.zeroize();
+        return Ok(target_enc_file)
+    }
+
+    let mut plaintext = Cursor::new(metadata_block).chain(&mut file_plaintext);
+
+    // written under a temp name until it's complete, which is all Ctrl+C would need to tidy away
+    track_partial(&temp_path(&target_enc_file));
+
+    // Encrypt file
+    let settings = EncryptSettings { compression_level: options.compression_level, aad: options.aad.as_ref().map(|aad| aad.as_bytes().to_vec() ), verify: !options.fast, verify_time: Cell::new(Duration::ZERO) };
+
+    let enc_result = encrypt_to_file(
+        &target_enc_file, &enc_header, Some(&ed25519_keypair).filter(|_| embed_signature), &recovered_key, &nonce,
+        &settings,
+        &mut plaintext
+    );
+
+    // reading and checking happen a chunk at a time along with the encryption, so they're picked back out of it
+    match settings.verify {
+        true => timings.lap_with("Encryption", &[("File read", file_plaintext.reading), ("Verify decryption", settings.verify_time.get())]),
+        false => timings.lap_with("Encryption", &[("File read", file_plaintext.reading)]),
+    }
+
+    drop(file_plaintext); // finishes off the progress line
+
+    let ciphertext_length = match enc_result {
+        Ok(length) => {
+            finish_partial(); // the shares have a file to go with now, so Ctrl+C leaves them be
+            length
+        },
+        Err(error) => {
+            // write_signed_file has already taken its temp file away (and left anything already at target_enc_file alone); the shares are no use without the file
+            nl();
+            remove_partial(&mut PARTIAL_FILES.lock().unwrap_or_else(|poisoned| poisoned.into_inner() ));
+
+            return Err( Error::failed(error, format!("Could not encrypt to file {}", target_enc_file.display())) );
+        }
+    };
+
+    if let Some(compress_level) = options.compression_level {
+        // ciphertext includes a tag per chunk, but that's close enough for a ratio
+        let plaintext_length = plaintext_length.unwrap_or(0);
+        let ratio = plaintext_length as f64 / ciphertext_length.max(1) as f64;
+
+        report!("[-] Compressed {} bytes to {} bytes ({:.2}x, level {})", plaintext_length, ciphertext_length, ratio, compress_level );
+    }
+
+    // the encrypted file's done, so a detached signature can cover all of it -- and an old one mustn't be left to contradict it
+    match options.detached_sig {
+        true => write_detached_signature(&target_enc_file, &ed25519_keypair)?,
+        false => remove_stale_detached_signature(&target_enc_file),
+    }
+
+    if options.detached_sig {
+        timings.lap("Detached signature");
+    }
+
+    // last of all, so the signature (embedded or detached) covers the file just as it'd be with its header -- which is how it's read back
+    match options.output_format {
+        OutputFormat::Raw => {
+            split_off_header(&target_enc_file)?;
+            timings.lap("Header split");
+        },
+        OutputFormat::Ccm => remove_stale_header_sidecar(&target_enc_file),
+    }
+
+    // done with the signing key (everything else is wiped as it goes out of scope)
+    ed25519_keypair.secret.zeroize();
+
+    if options.sign_file {
+        report!("[-] Signed encrypted file");
+    }
+
+    if options.metadata {
+        report!("[-] Stored original filename, size and modification time");
+    }
+
+    if let Some(entries) = &options.archive {
+        report!("[-] Packed {} file(s) into one archive", entries.len() );
+    }
+
+    report_event(
+        &format!("[&] Encrypted file written to {}", stringify_path(&target_enc_file)),
+        serde_json::json!({ "event": "file_written", "kind": "encrypted", "path": stringify_path(&target_enc_file) })
+    );
+
+    if options.time {
+        nl();
+        timings.report(target_file);
+    }
+
+    Ok(target_enc_file)
+}
+
+fn decrypt_file(target_file: &Path, shares_dirs: &[PathBuf], output: Option<PathBuf>, options: &DecryptOptions) -> Outcome<Option<PathBuf>> { // Decrypt (or just verify) a single file using the shares in the share directories; returns where the decrypted file went
+    // Work out where to write the file
+    let mut decrypted_path = PathBuf::from(target_file);
+
+    if has_extension(&decrypted_path, "ccm") { // remove .ccm extension (compared as an OsStr, so odd non-UTF-8 extensions are just left alone)
+        decrypted_path.set_extension("");
+    }
+
+    // a name the user picked is left as it is, extension or not
+    let output_named = output.as_ref().is_some_and(|output| !output.is_dir() );
+
+    let mut decrypted_path = get_output_path(output.clone(), decrypted_path)?;
+
+    nl();
+
+    let (mut threshold, is_signed, pub_key, signature, mut file_header) = process_target_file(target_file, options.gather.strict)?;
+
+    if let Some(nonce) = &options.nonce { // the header's nonce can't be trusted, so this one's used for finding shares and decrypting alike
+        if nonce.len() != file_header.nonce.len() {
+            return Err( Error::refused(format!("Nonce for a version {} file must be {} bytes of hex (got {})", file_header.algo_version, file_header.nonce.len(), nonce.len())) );
+        }
+
+        if *nonce != file_header.nonce {
+            report!("[!] Using the given nonce instead of the one in the file's header ({})", hex::encode(&file_header.nonce) );
+            file_header.nonce = nonce.clone();
+        }
+    }
+
+    fill_in_aad(&mut file_header, options.aad.as_deref())?;
+
+    if let Some(expected) = &options.expect_pubkey { // does the file's key match the one we trust?
+        let file_pub_key: Option<Vec<u8>> = pub_key.map(|pub_key| pub_key.to_bytes().to_vec() ); // from the header or a detached signature
+
+        let matched = is_signed && file_pub_key.as_ref() == Some(expected);
+
+        audit( serde_json::json!({
+            "event": "expected_public_key",
+            "file": stringify_path(target_file),
+            "outcome": if matched { "ok" } else { "mismatch" },
+            "expected_public_key": hex::encode(expected),
+            "file_public_key": file_pub_key.as_ref().map(hex::encode),
+        }) )?;
+
+        if !matched {
+            nl();
+            report!("[!] Target file is not signed with the expected public key!");
+            report!("[!] Expected: {}", describe_pub_key(expected) );
+
+            match file_pub_key {
+                Some(file_pub_key) => report!("[!] File:     {}", describe_pub_key(&file_pub_key) ),
+                None => report!("[!] File:     (unsigned)"),
+            }
+
+            return Err( Error::tampered("Target file is not signed with the expected public key -- aborting".to_string()) );
+        }
+
+        report!("[+] Target file public key matches the expected key" );
+    }
+
+    if options.verify_fingerprint { // a human checks the key, e.g. against a fingerprint read out over the phone
+        confirm_fingerprint(target_file, pub_key.filter(|_| is_signed))?;
+    }
+
+    let target_algo_version = file_header.algo_version;
+    let nonce = &file_header.nonce;
+
+    // contents start straight after the header (incl. public key and signature, if present)
+    let content_offset = file_header.length as u64;
+    let is_streamed = file_header.flags & FLAG_STREAMED != 0;
+
+    report!("[+] Target file is encrypted; algorithm version {}", target_algo_version );
+
+    if let Some(label) = &file_header.label {
+        report!("[+] Target file is labelled \"{}\"", label );
+    }
+
+    if file_header.flags & FLAG_COMPRESSED != 0 {
+        report!("[+] Target file is compressed" );
+    }
+
+    let has_metadata = file_header.flags & FLAG_METADATA != 0;
+
+    if has_metadata {
+        report!("[+] Target file has its original filename stored" );
+    }
+
+    let is_archive = file_header.flags & FLAG_ARCHIVE != 0;
+
+    if is_archive {
+        report!("[+] Target file is an archive of several files, to be unpacked into a directory" );
+
+        if options.to_stdout { // no one stream to send them down
+            return Err( Error::refused("An archive can't be decrypted to stdout; decrypt it to a directory instead".to_string()) );
+        }
+    }
+
+    // don't go to the trouble of gathering shares just to clobber something (files with metadata are checked once we know their name)
+    if is_archive && !options.verify_only {
+        if decrypted_path.exists() && !is_empty_dir(&decrypted_path) { // unpacking alongside whatever's there would mix the two up (--force or not)
+            return Err( Error::refused(format!("{} already exists, and isn't an empty directory -- unpack the archive somewhere else (e.g. with --output)", stringify_path(&decrypted_path))) );
+        }
+    }
+    else if !options.verify_only && !options.to_stdout && !has_metadata {
+        guard_overwrite(&decrypted_path, options.force, None)?;
+    }
+
+    nl();
+    report_shares_needed(threshold, file_header.schemes, "decrypt");
+    report!("[+] Target file nonce: {}", hex::encode(nonce) );
+
+    nl();
+
+    // Gather shares
+    let mut share_macs: Vec<GatheredMac> = vec![];
+    let mut shares: Vec<Share> = gather_shares(shares_dirs, nonce, &mut threshold, file_header.schemes, is_signed, file_header.flags & FLAG_SHARES_UNSIGNED != 0, pub_key, &options.gather, &mut share_macs)?;
+
+    nl();
+
+    if is_signed { // Check file signature
+        file_signature_verification(target_file, &file_header, threshold, pub_key.unwrap(), signature.unwrap(), options.gather.strict)?;
+    }
+
+    if let Some(min_shares) = options.min_shares { // policy can ask for more than the maths does
+        enforce_min_shares(&mut shares, min_shares, threshold)?;
+    }
+
+    // Attempt to recover key from shares
+    let recovered_key = recover_key(&mut shares, threshold)?;
+    check_share_macs(&recovered_key, &share_macs, options.gather.strict)?;
+
+    if options.verify_only { // check the key really does decrypt the file, but throw the plaintext away
+        nl();
+        report!("[-] Trial decrypting file (nothing will be written)...");
+
+        trial_decryption(target_file, &file_header, &recovered_key)?;
+
+        nl();
+        report_event(
+            "[%] File is intact and recoverable with these shares",
+            serde_json::json!({ "event": "verified", "path": stringify_path(target_file) })
+        );
+
+        Ok(None)
+    }
+    else if options.to_stdout { // plaintext goes down the pipe, and nowhere else
+        nl();
+        report!("[-] Decrypting file to stdout...");
+
+        decrypt_to_stdout(target_file, &file_header, &recovered_key)?;
+
+        Ok(None)
+    }
+    else if is_archive { // its files go back into a directory of their own
+        nl();
+        report!("[-] Decrypting and unpacking archive...");
+
+        unpack_archive(target_file, &file_header, &recovered_key, &decrypted_path)?;
+
+        nl();
+
+        report_event(
+            &format!("[&] Decrypted archive unpacked into {}", stringify_path(&decrypted_path)),
+            serde_json::json!({ "event": "file_written", "kind": "archive", "path": stringify_path(&decrypted_path) })
+        );
+
+        Ok( Some(decrypted_path) )
+    }
+    else {
+        nl();
+        report!("[-] Decrypting file...");
+
+        // Decrypt file
+        if is_streamed {
+            let mut file_contents = match open_target_contents(target_file, "Decrypting", content_offset) {
+                Ok(reader) => reader,
+                Err(error) => return Err( Error::failed(error, format!("Could not read file {}", target_file.display())) ),
+            };
+
+            // plaintext only takes the real name once all of it has authenticated, so a failure (or Ctrl+C) never costs whatever was there;
+            // we don't know the real name of a file with metadata until it's decrypted at all, so that goes somewhere temporary of its own
+            let writing_path = match has_metadata {
+                true => {
+                    let mut partial = decrypted_path.clone().into_os_string();
+                    partial.push(".part");
+                    PathBuf::from(partial)
+                },
+                false => temp_path(&decrypted_path),
+            };
+
+            let decrypted_file = match fs::File::create(&writing_path) {
+                Ok(res) => res,
+                Err(error) => return Err( Error::failed(error, format!("Could not create file {}", writing_path.display())) ),
+            };
+
+            let mut decrypted_file = BufWriter::new(decrypted_file);
+
+            let decrypt_result = decrypt_contents(&file_header, &recovered_key, &mut file_contents, &mut decrypted_file)
+                .and_then(|metadata| decrypted_file.flush().map(|_| metadata) );
+
+            let metadata = match decrypt_result {
+                Ok(metadata) => metadata,
+                Err(error) => {
+                    let _ = fs::remove_file(&writing_path); // don't leave partial plaintext lying around
+                    return Err( Error::decryption(error, "Failed to decrypt file!".to_string()) );
+                }
+            };
+
+            drop(file_contents); // finishes off the progress line
+            drop(decrypted_file);
+
+            report_checksum_matched(&file_header);
+
+            match metadata {
+                Some(metadata) => decrypted_path = restore_from_metadata(&writing_path, &metadata, target_file, output, options.force)?,
+                None => if let Err(error) = fs::rename(&writing_path, &decrypted_path) {
+                    let _ = fs::remove_file(&writing_path);
+                    return Err( Error::failed(error, format!("Could not write file {}", decrypted_path.display())) );
+                },
+            }
+        }
+        else { // version 1 files are decrypted in one go
+            let file_contents: Vec<u8> = match read_file_contents(target_file, content_offset as usize) {
+                Ok(contents) => contents,
+                Err(error) => return Err( Error::failed(error, format!("Could not read file {}", target_file.display())) ),
+            };
+
+            let file_plaintext: Vec<u8> = match chacha_decrypt_v1(&recovered_key, nonce, &file_contents) {
+                Ok(plain) => plain,
+                Err(error) => return Err( Error::decryption(error, "Failed to decrypt file!".to_string()) ),
+            };
+
+            if let Err(error) = write_file(&decrypted_path, &file_plaintext, None) {
+                return Err( Error::failed(error, format!("Could not write file {}", decrypted_path.display())) );
+            }
+        }
+
+        nl();
+
+        // Try to guess MIME type cuz why not
+        let mimetype = infer::get_from_path(&decrypted_path).ok().flatten();
+
+        match mimetype {
+            Some(mimetype) => {
+                report!("[-] File decrypted -- MIME type: {}", mimetype.mime_type() );
+            },
+            None => {
+                report!("[-] File decrypted -- MIME type: unknown (text? binary?)");
+            }
+        };
+
+        // the original name (or at least its extension) may have been lost along the way, e.g. if the .ccm file was renamed
+        if options.guess_extension && !has_metadata && !output_named && decrypted_path.extension().is_none() {
+            decrypted_path = add_guessed_extension(&decrypted_path, mimetype, options.force);
+        }
+
+        nl();
+
+        report_event(
+            &format!("[&] Decrypted file written to {}", stringify_path(&decrypted_path)),
+            serde_json::json!({ "event": "file_written", "kind": "decrypted", "path": stringify_path(&decrypted_path) })
+        );
+
+        Ok( Some(decrypted_path) )
+    }
+}
+
+fn unpack_archive(target_file: &Path, file_header: &FileHeader, key: &[u8], dir: &Path) -> Outcome<()> { // Decrypt an --archive file, unpacking its files into a directory as they come out
+    // into a directory of our own first, renamed into place once it's all there -- so a failure only ever clears away what we wrote
+    let temp_dir = temp_path(dir);
+
+    if let Err(error) = fs::create_dir(&temp_dir) {
+        return Err( Error::failed(error, format!("Could not create directory {} (if it's left from an earlier unpacking, remove it)", temp_dir.display())) );
+    }
+
+    let mut unpacker = ArchiveUnpacker::new(&temp_dir);
+
+    let unpack_result = open_target_contents(target_file, "Decrypting", file_header.length as u64)
+        .and_then(|mut file_contents| decrypt_contents(file_header, key, &mut file_contents, &mut unpacker) )
+        .and_then(|_| unpacker.finish() );
+
+    if let Err(error) = unpack_result {
+        let _ = fs::remove_dir_all(&temp_dir); // don't leave partial plaintext lying around
+        return Err( Error::decryption(error, "Failed to decrypt archive!".to_string()) );
+    }
+
+    // an empty directory can make way for it (remove_dir won't touch one that's had anything put in it since)
+    let moved = match dir.is_dir() {
+        true => fs::remove_dir(dir),
+        false => Ok(()),
+    }.and_then(|_| fs::rename(&temp_dir, dir) );
+
+    if let Err(error) = moved {
+        let _ = fs::remove_dir_all(&temp_dir);
+        return Err( Error::failed(error, format!("Could not move the unpacked files into {}", dir.display())) );
+    }
+
+    report_checksum_matched(file_header);
+    report!("[-] Unpacked {} file(s)", unpacker.unpacked.len() );
+
+    Ok(())
+}
+
+fn decrypt_to_stdout(target_file: &Path, file_header: &FileHeader, key: &[u8]) -> Outcome<()> { // Decrypt a file straight to stdout, guessing its MIME type on the way
+    let mut stdout = PeekWriter { inner: BufWriter::new( io::stdout().lock() ), head: Zeroizing::new(vec![]) };
+
+    let decrypt_result = match file_header.flags & FLAG_STREAMED != 0 {
+        true => open_target_contents(target_file, "Decrypting", file_header.length as u64)
+            .and_then(|mut file_contents| decrypt_contents(file_header, key, &mut file_contents, &mut stdout) ),
+        false => read_file_contents(target_file, file_header.length) // version 1 files are decrypted in one go
+            .and_then(|contents| chacha_decrypt_v1(key, &file_header.nonce, &contents) )
+            .map(Zeroizing::new)
+            .and_then(|plaintext| stdout.write_all(&plaintext) )
+            .map(|_| None ),
+    };
+
+    // anything already written can't be taken back, but at least say so
+    let metadata = match decrypt_result.and_then(|metadata| stdout.flush().map(|_| metadata) ) {
+        Ok(metadata) => metadata,
+        Err(error) => return Err( Error::decryption(error, "Failed to decrypt file! (stdout may have had some of it already)".to_string()) ),
+    };
+
+    report_checksum_matched(file_header);
+
+    if let Some(metadata) = metadata {
+        report!("[+] Original filename: {}", metadata.name );
+    }
+
+    match infer::get(&stdout.head) {
+        Some(mimetype) => report!("[-] File decrypted -- MIME type: {}", mimetype.mime_type() ),
+        None => report!("[-] File decrypted -- MIME type: unknown (text? binary?)"),
+    }
+
+    report_event(
+        "[&] Decrypted file written to stdout",
+        serde_json::json!({ "event": "file_written", "kind": "decrypted", "path": "-" })
+    );
+
+    Ok(())
+}
+
+fn reencrypt_file(target_file: &Path, new_header: &[u8], keypair: Option<&Keypair>, key: &[u8], new_key: &[u8], file_header: &FileHeader) -> Outcome<()> { // Re-encrypt a file's contents under a new header (and key), replacing the file once it's done
+    let mut partial_path = target_file.to_path_buf().into_os_string();
+    partial_path.push(".part");
+    let partial_path = PathBuf::from(partial_path);
+
+    let reencrypt_result = open_file_contents(target_file, file_header.length as u64)
+        .map(|file_contents| Progress::new(file_contents, "Re-encrypting", target_file, file_header.length as u64) )
+        .and_then(|mut file_contents| reencrypt_to_file(&partial_path, new_header, keypair, key, new_key, file_header, &mut file_contents) )
+        .and_then(|_| fs::rename(&partial_path, target_file) );
+
+    if let Err(error) = reencrypt_result {
+        let _ = fs::remove_file(&partial_path); // the original is still intact
+        return Err( Error::decryption(error, format!("Could not re-encrypt {}", target_file.display())) );
+    }
+
+    report!("[-] Re-encrypted contents under the new header");
+
+    Ok(())
+}
+
+fn metadata_block_for(target_file: &Path) -> Outcome<Vec<u8>> { // Build the metadata block for a file we're about to encrypt
+    let file_metadata = match fs::metadata(target_file) {
+        Ok(file_metadata) => file_metadata,
+        Err(error) => return Err( Error::failed(error, format!("Could not read metadata of {}", target_file.display())) ),
+    };
+
+    let name = target_file.file_name().unwrap_or_default();
+
+    if name.to_str().is_none() { // the metadata block only holds UTF-8
+        report!("[#] {} isn't valid UTF-8, so the stored name will only be an approximation of it", Path::new(name).display() );
+    }
+
+    let metadata = FileMetadata {
+        name: name.to_string_lossy().into_owned(),
+        size: file_metadata.len(),
+        mtime: file_metadata.modified().ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok() )
+            .map(|since_epoch| since_epoch.as_secs() )
+            .unwrap_or(0),
+    };
+
+    match construct_metadata(&metadata) {
+        Ok(block) => Ok(block),
+        Err(error) => Err( Error::failed(error, format!("Could not store metadata of {}", target_file.display())) ),
+    }
+}
+
+fn restore_from_metadata(partial_path: &Path, metadata: &FileMetadata, target_file: &Path, output: Option<PathBuf>, force: bool) -> Outcome<PathBuf> { // Move a decrypted file to its original name, and put its mtime back
+    // only ever take the name itself -- a crafted file could otherwise try to write anywhere
+    let derived_path = match Path::new(&metadata.name).file_name() {
+        Some(name) => target_file.with_file_name(name),
+        None => {
+            report!("[#] Stored filename {:?} is unusable; keeping the usual name", metadata.name);
+            target_file.with_extension("")
+        }
+    };
+
+    let decrypted_path = get_output_path(output, derived_path)?;
+
+    guard_overwrite(&decrypted_path, force, Some(partial_path) )?;
+
+    if let Err(error) = fs::rename(partial_path, &decrypted_path) {
+        let _ = fs::remove_file(partial_path);
+        return Err( Error::failed(error, format!("Could not move decrypted file to {}", decrypted_path.display())) );
+    }
+
+    report!("[+] Restored original filename: {}", metadata.name );
+
+    let mtime = UNIX_EPOCH + Duration::from_secs(metadata.mtime);
+    let restore_mtime = fs::File::options().write(true).open(&decrypted_path)
+        .and_then(|file| file.set_modified(mtime) );
+
+    if let Err(error) = restore_mtime {
+        report!("[#] Could not restore modification time | {}", error );
+    }
+
+    let size = fs::metadata(&decrypted_path).map(|file_metadata| file_metadata.len() ).unwrap_or(0);
+
+    if size != metadata.size {
+        report!("[#] Decrypted file is {} bytes, but the original was {} bytes", size, metadata.size );
+    }
+
+    Ok(decrypted_path)
+}
+
+fn add_guessed_extension(decrypted_path: &Path, mimetype: Option<infer::Type>, force: bool) -> PathBuf { // Give a decrypted file the extension its MIME type usually has (if we know it); returns where the file ended up
+    let mimetype = match mimetype {
+        Some(mimetype) => mimetype,
+        None => {
+            report!("[#] Could not detect the file's type, so it keeps its name without an extension");
+            return decrypted_path.to_path_buf()
+        }
+    };
+
+    let guessed_path = decrypted_path.with_extension(mimetype.extension());
+
+    if guessed_path.exists() && !force { // the guess isn't worth clobbering something for
+        report!("[#] {} already exists, so the decrypted file keeps its name without an extension", stringify_path(&guessed_path) );
+        return decrypted_path.to_path_buf()
+    }
+
+    if let Err(error) = fs::rename(decrypted_path, &guessed_path) {
+        report!("[#] Could not add the .{} extension | {}", mimetype.extension(), error );
+        return decrypted_path.to_path_buf()
+    }
+
+    report!("[+] Added .{} extension from the detected MIME type ({})", mimetype.extension(), mimetype.mime_type() );
+
+    guessed_path
+}
+
+fn write_file<'a>(filepath: &'a Path, contents: &[u8], mode: Option<u32>) -> Result<&'a Path> { // Raw function for writing out files (via a temp file, so a crash never leaves half of one), optionally with these permissions (Unix)
+    let temp_filepath = temp_path(filepath);
+
+    let written = create_with_mode(&temp_filepath, mode)
+        .and_then(|mut file| file.write_all(contents).and_then(|_| file.sync_all() ) )
+        .and_then(|_| fs::rename(&temp_filepath, filepath) );
+
+    if let Err(error) = written {
+        let _ = fs::remove_file(&temp_filepath);
+        return Err(error)
+    }
+
+    Ok(filepath)
+}
+
+fn create_with_mode(filepath: &Path, mode: Option<u32>) -> Result<fs::File> { // File::create, but with the given permissions from the start (Unix; elsewhere they're ignored)
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(mode); // so it's never readable by anyone else, not even briefly
+
+        // the umask only ever takes permissions away, so set them exactly once it's there
+        return options.open(filepath)
+            .and_then(|file| file.set_permissions(fs::Permissions::from_mode(mode)).map(|_| file ) )
+    }
+
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    options.open(filepath)
+}
+
+fn is_detached_sig(path: &Path) -> bool { // Is this one of our {name}.ccm.sig files?
+    has_extension(path, "sig") && path.file_stem().is_some_and(|stem| has_extension(Path::new(stem), "ccm") )
+}
+
+fn write_detached_signature(enc_file: &Path, keypair: &Keypair) -> Outcome<()> { // Sign an encrypted file as it is on disk, writing the signature to {name}.sig next to it
+    let sig_path = detached_sig_path(enc_file);
+
+    let detached_sig = open_file_contents(enc_file, 0)
+        .and_then(|mut contents| detached_signature_digest(&keypair.public, &mut contents) )
+        .and_then(|digest| construct_detached_signature(keypair, digest) )
+        .and_then(|detached_sig| write_file(&sig_path, &detached_sig, None) );
+
+    if let Err(error) = detached_sig {
+        return Err( Error::failed(error, format!("Could not write detached signature {}", sig_path.display())) );
+    }
+
+    report_event(
+        &format!("[&] Detached signature written to {}", stringify_path(&sig_path)),
+        serde_json::json!({ "event": "file_written", "kind": "signature", "path": stringify_path(&sig_path) })
+    );
+
+    Ok(())
+}
+
+fn remove_stale_detached_signature(enc_file: &Path) { // Remove a detached signature left over from an earlier version of a file we've just rewritten
+    let sig_path = detached_sig_path(enc_file);
+
+    if sig_path.is_file() {
+        match fs::remove_file(&sig_path) {
+            Ok(_) => report!("[-] Removed old detached signature {}", stringify_path(&sig_path) ),
+            Err(error) => report!("[^] Could not remove old detached signature {} | {}", stringify_path(&sig_path), error ),
+        }
+    }
+}
+
+fn open_plaintext(target_file: &Path, archive: Option<&[ArchiveEntry]>, action: &'static str) -> Result<Progress<Box<dyn Read>>> { // Open what's to be encrypted, with progress: the file itself, or the archive of files standing in for it (--archive)
+    match archive {
+        Some(entries) => {
+            let mut reader = Progress::new(Box::new( ArchiveReader::new(entries.to_vec()) ) as Box<dyn Read>, action, target_file, 0);
+            reader.total = archive_length(entries)?; // not the directory's own size
+
+            Ok(reader)
+        },
+        None => Ok( Progress::new(Box::new( open_file_contents(target_file, 0)? ), action, target_file, 0) ),
+    }
+}
+
+fn header_sidecar_path(enc_file: &Path) -> PathBuf { // Where a raw encrypted file's header goes: {name}.hdr next to it
+    let mut header_path = enc_file.as_os_str().to_os_string();
+    header_path.push(".hdr");
+
+    PathBuf::from(header_path)
+}
+
+fn is_header_sidecar(path: &Path) -> bool { // Is this one of our {name}.ccm.hdr files?
+    has_extension(path, "hdr") && path.file_stem().is_some_and(|stem| has_extension(Path::new(stem), "ccm") )
+}
+
+fn split_off_header(enc_file: &Path) -> Outcome<()> { // Move an encrypted file's header out into {name}.hdr, leaving only the ciphertext behind (--output-format raw)
+    let header_path = header_sidecar_path(enc_file);
+
+    let header = read_file_header(enc_file)
+        .and_then(|header| parse_header_file(&header).map(|file_header| header[..file_header.length].to_vec() ) );
+
+    let header = match header {
+        Ok(header) => header,
+        Err(error) => return Err( Error::failed(error, format!("Could not read header of {}", enc_file.display())) ),
+    };
+
+    // written before the header comes off the file, so it's never only in memory -- if we're stopped in between,
+    // the file still starts with its header, and the sidecar is ignored (see has_header_sidecar)
+    if let Err(error) = write_file(&header_path, &header, None) {
+        return Err( Error::failed(error, format!("Could not write header file {}", header_path.display())) );
+    }
+
+    // the ciphertext goes through a temp file like anything else we write, so a crash leaves the whole file behind, not half of it
+    let temp_filepath = temp_path(enc_file);
+
+    let split = open_file_contents(enc_file, header.len() as u64)
+        .and_then(|mut ciphertext| create_with_mode(&temp_filepath, None).and_then(|mut raw_file| {
+            io::copy(&mut ciphertext, &mut raw_file)?;
+            raw_file.sync_all()
+        }))
+        .and_then(|_| fs::rename(&temp_filepath, enc_file) );
+
+    if let Err(error) = split {
+        let _ = fs::remove_file(&temp_filepath);
+        let _ = fs::remove_file(&header_path); // the file still has its header, so this would only confuse decryption
+        return Err( Error::failed(error, format!("Could not strip the header from {}", enc_file.display())) );
+    }
+
+    report_event(
+        &format!("[&] Header written to {} ({} bytes)", stringify_path(&header_path), header.len()),
+        serde_json::json!({ "event": "file_written", "kind": "header", "path": stringify_path(&header_path), "bytes": header.len() })
+    );
+
+    Ok(())
+}
+
+fn remove_stale_header_sidecar(enc_file: &Path) { // Remove a {name}.hdr left over from an earlier raw version of a file we've just rewritten with its header
+    let header_path = header_sidecar_path(enc_file);
+
+    if header_path.is_file() {
+        match fs::remove_file(&header_path) {
+            Ok(_) => report!("[-] Removed old header file {}", stringify_path(&header_path) ),
+            Err(error) => report!("[^] Could not remove old header file {} | {}", stringify_path(&header_path), error ),
+        }
+    }
+}
+
+fn has_header_sidecar(target_file: &Path) -> bool { // Is this a raw encrypted file, with its header in {name}.hdr? (a file that still starts with a header of its own isn't, whatever's next to it)
+    header_sidecar_path(target_file).is_file()
+        && !read_file_header(target_file).is_ok_and(|header| parse_header_file(&header).is_ok() )
+}
+
+fn read_header_sidecar(target_file: &Path) -> Result<Option<Vec<u8>>> { // The header of a raw encrypted file, from the {name}.hdr next to it (None if it hasn't got one)
+    let header_path = header_sidecar_path(target_file);
+
+    if !has_header_sidecar(target_file) {
+        return Ok(None)
+    }
+
+    // never read more than a header could possibly be
+    let mut header: Vec<u8> = vec![];
+    fs::File::open(&header_path)?.take(HEADER_MAX_LENGTH_FILE as u64 + 1).read_to_end(&mut header)?;
+
+    match header.len() > HEADER_MAX_LENGTH_FILE {
+        true => Err( io::Error::other(format!("{} is larger than any header", header_path.display())) ),
+        false => Ok( Some(header) ),
+    }
+}
+
+fn read_target_header(target_file: &Path) -> Result<Vec<u8>> { // read_file_header for a file we're decrypting, whose header may be in {name}.hdr instead (--output-format raw)
+    match read_header_sidecar(target_file)? {
+        Some(header) => Ok(header),
+        None => read_file_header(target_file),
+    }
+}
+
+fn is_empty_dir(path: &Path) -> bool { // a directory with nothing in it (so nothing to lose)
+    fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none() )
+}
+
+fn open_target_contents(target_file: &Path, action: &'static str, offset: u64) -> Result<Progress<Box<dyn Read>>> { // open_file_contents (with progress) for a file we're decrypting, putting its header back in front if it's in {name}.hdr
+    let (contents, file_offset): (Box<dyn Read>, u64) = match read_header_sidecar(target_file)? {
+        Some(header) => { // as if the file had it at the start, so offsets are the same either way
+            let header_length = header.len() as u64;
+            let file_offset = offset.saturating_sub(header_length);
+
+            let mut header = Cursor::new(header);
+            header.set_position( offset.min(header_length) );
+
+            (Box::new( header.chain(open_file_contents(target_file, file_offset)?) ), file_offset)
+        },
+        None => (Box::new( open_file_contents(target_file, offset)? ), offset),
+    };
+
+    Ok( Progress::new(contents, action, target_file, file_offset) )
+}
+
+fn open_file_contents(filepath: &Path, offset: u64) -> Result<BufReader<fs::File>> { // Open a file for streaming, skipping the first `offset` bytes (i.e. the header)
+    let mut open = fs::File::open(filepath)?;
+
+    open.seek(SeekFrom::Start(offset))?; // skip past the header
+
+    Ok(BufReader::new(open))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn share_signature_verification( // verification of share signatures
+    is_signed: bool, // whether the FILE is signed
+    shares_unsigned: bool, // whether the FILE says its shares were left unsigned on purpose
+    pub_key: Option<PublicKey>, // the FILE'S public key
+    //signature: Option<Signature>, // the FILE'S signature
+    shf: &ShareFromFile, // the share retrieved from a file
+    share_verification: Option<std::result::Result<(), SignatureError>>, // result of checking the share's own signature, if it has one
+    source: &str, // where the share came from
+
+    strict: bool ) -> Outcome<()> { // verifies signatures between a file and a share
+
+    // every outcome goes in the audit log (if there is one), good or bad
+    let audit_share = |outcome: &str, error: Option<String>| audit( serde_json::json!({
+        "event": "share_signature",
+        "share": source,
+        "outcome": outcome,
+        "file_public_key": pub_key.filter(|_| is_signed).map(|pub_key| hex::encode( pub_key.to_bytes() ) ),
+        "share_public_key": shf.pub_key.map(|pub_key| hex::encode( pub_key.to_bytes() ) ),
+        "error": error,
+    }) );
+
+    let mut mismatched = false;
+
+    if shares_unsigned && !shf.is_signed { // only the file was signed (--sign-file), and it says so
+        return audit_share("unsigned_share", None)
+    }
+
+    let share_pub_key = match shf.pub_key { // Check if share has public key
+        Some(pk) => pk,
+        None => { // Share is missing a public key
+            audit_share("missing_public_key", None)?;
+
+            nl();
+            report!("[#] Signing mismatch from share {}", source);
+            report!("[#] Share is missing a public key,");
+            report!("[#] its integrity cannot be verified.");
+
+            die_on_strict(strict)?;
+            ask_to_continue()?;
+            return Ok(())
+        }
+    };
+
+    if shf.signature.is_none() { // Check if share has signature (it really should if we're in this function)
+        audit_share("missing_signature", None)?;
+
+        nl();
+        report!("[#] Signing mismatch from share {}", source);
+        report!("[#] Share is missing a signature,");
+        report!("[#] its integrity cannot be verified.");
+
+        die_on_strict(strict)?;
+        ask_to_continue()?;
+        return Ok(())
+    }
+
+    if !is_signed && shf.is_signed && shf.file_unsigned { // only the shares were signed (--sign-shares), and this one says so
+        audit_share("unsigned_file", None)?;
+        mismatched = true; // already audited, though its own signature is still checked below
+    }
+
+    else if !is_signed && shf.is_signed { // file itself is not signed?
+        audit_share("unsigned_file", None)?;
+        mismatched = true;
+
+        nl();
+        report!("[#] Signing mismatch from share {}", source);
+        report!("[#] Encrypted file is not signed,");
+        report!("[#] but this share believes it should be.");
+
+        die_on_strict(strict)?;
+        ask_to_continue()?;
+    }
+
+    else { // Check signature public key against file's public key, if we have both
+        let file_pub_key = pub_key.unwrap(); // Unwrap file's public key *now* (we'd panic if we did it earlier)
+        //let signature = signature.unwrap();
+
+        if share_pub_key.to_bytes() != file_pub_key.to_bytes() { // share and file use differing public keys 
+            audit_share("public_key_mismatch", None)?;
+            mismatched = true;
+
+            nl();
+            report!("[#] Signing mismatch from share {}", source);
+            report!("[#] File and share do not use the same public key!");
+            nl();
+            report!("[#] File public key:  {}", describe_pub_key(file_pub_key.as_bytes()) );
+            report!("[#] Share public key: {}", describe_pub_key(share_pub_key.as_bytes()) );
+    
+            die_on_strict(strict)?;
+            ask_to_continue()?;
+        }
+
+    }
+
+    if shf.is_signed { // Verify a share's signature (already done, alongside the others)
+        if let Some(Err(error)) = share_verification { // Share verification failed. Uh oh spaghetti-os
+            audit_share("bad_signature", Some( error.to_string() ))?;
+            mismatched = true;
+
+            let mut file_pub_key = String::from("[ABSENT]");
+
+            if is_signed { // give file public key in error msg
+                file_pub_key = describe_pub_key( pub_key.unwrap().as_bytes() );
+            }
+
+            nl();
+            report!("[#] Signing mismatch from share {}", source);
+            report!("[#] Share verification from public key failed!");
+            nl();
+            report!("[#] File public key:  {}", &file_pub_key );
+            report!("[#] Share public key: {}", describe_pub_key(share_pub_key.as_bytes()) );
+            nl();
+            report!("[#] -----------------------------------------------------" );
+            report!("[#] WARNING: THIS SHARE MAY BE CORRUPTED OR TAMPERED WITH" );
+            report!("[#]    FILE RECOVERY IS UNLIKELY WHEN USING THIS SHARE   " );
+            report!("[#]   ANY EXISTING FILE MAY BE OVERWRITTEN WITH GARBAGE  " );
+            report!("[#] -----------------------------------------------------" );
+            nl();
+            report!("[#] More information:" );
+            report!("[#] {}", error );
+            
+            die_on_strict(strict)?;
+            ask_to_continue()?;
+        }
+    }
+
+    if !mismatched {
+        audit_share("ok", None)?;
+    }
+
+    Ok(())
+}
+
+fn logo(){ // prints CCM logo
+    //   ___  _  _   __    ___  _  _   __   _  _  __  ____ 
+    //  / __)/ )( \ / _\  / __)/ )( \ / _\ ( \/ )(  )(  _ \
+    // ( (__ ) __ (/    \( (__ ) __ (/    \/ \/ \ )(  )   /
+    //  \___)\_)(_/\_/\_/ \___)\_)(_/\_/\_/\_)(_/(__)(__\_)
+
+    eprintln!("  ___  _  _   __    ___  _  _   __   _  _  __  ____ ");
+    eprintln!(" / __)/ )( \\ / _\\  / __)/ )( \\ / _\\ ( \\/ )(  )(  _ \\");
+    eprintln!("( (__ ) __ (/    \\( (__ ) __ (/    \\/ \\/ \\ )(  )   /");
+    eprintln!(" \\___)\\_)(_/\\_/\\_/ \\___)\\_)(_/\\_/\\_/\\_)(_/(__)(__\\_)");
+    eprintln!("----");
+    eprintln!("version {}", VERSION);
+    nl();
+}
+
+fn die_on_strict(is_strict: bool) -> Outcome<()> { // Give up if a validation issue occurs with shares or files in strict mode
+    match is_strict {
+        true => Err( Error::tampered("Will not decrypt using tampered data in strict mode!".to_string()) ),
+        false => Ok(()),
+    }
+}
+
+fn ask_for_passphrase(confirm: bool) -> Outcome<Zeroizing<String>> { // Prompt for a share passphrase (w/o echoing it), optionally asking twice
+    if non_interactive() {
+        return Err( Error::needs_confirmation("A passphrase is needed, but running non-interactively".to_string()) );
+    }
+
+    let passphrase = match rpassword::prompt_password("[^] Passphrase: ") {
+        Ok(passphrase) => Zeroizing::new(passphrase),
+        Err(error) => return Err( Error::failed(error, "Could not read passphrase".to_string()) ),
+    };
+
+    if confirm {
+        let passphrase_again = match rpassword::prompt_password("[^] Confirm passphrase: ") {
+            Ok(passphrase) => Zeroizing::new(passphrase),
+            Err(error) => return Err( Error::failed(error, "Could not read passphrase".to_string()) ),
+        };
+
+        if passphrase != passphrase_again {
+            return Err( Error::refused("Passphrases do not match".to_string()) );
+        }
+    }
+
+    Ok(passphrase)
+}
+
+fn fill_in_aad(file_header: &mut FileHeader, aad: Option<&str>) -> Outcome<()> { // Fill in the associated data a file was encrypted with (--aad), asking for it if it's needed and wasn't given
+    if !file_header.has_aad {
+        if aad.is_some() { // it'd be ignored, and whatever it was meant to tie the file to, it doesn't
+            return Err( Error::refused("Target file isn't bound to any associated data, so it can't be checked against --aad".to_string()) );
+        }
+
+        return Ok(())
+    }
+
+    report!("[+] Target file is bound to associated data of its own (--aad), which is needed to decrypt it");
+
+    let aad: String = match aad {
+        Some(aad) => aad.to_owned(),
+        None => {
+            if non_interactive() {
+                return Err( Error::needs_confirmation("Associated data is needed, but running non-interactively (give it with --aad)".to_string()) );
+            }
+
+            nl();
+            report!("[#] Enter the associated data the file was encrypted with");
+
+            read_answer()?
+        },
+    };
+
+    file_header.aad = Some( aad.into_bytes() );
+
+    Ok(())
+}
+
+fn guard_overwrite(path: &Path, force: bool, partial_path: Option<&Path>) -> Outcome<()> { // Ask before overwriting an existing file (unless forced), removing any partial output if we don't
+    if force || !path.exists() {
+        return Ok(())
+    }
+
+    nl();
+    report!("[#] {} already exists, and would be overwritten", stringify_path(path) );
+    report!("[#] (use --force to overwrite without asking)");
+
+    if non_interactive() { // ask_to_continue will give up, so tidy up first
+        if let Some(partial_path) = partial_path {
+            let _ = fs::remove_file(partial_path);
+        }
+    }
+
+    ask_to_continue()
+}
+
+fn ask_to_continue() -> Outcome<()> { // Ask the user to confirm they wish to proceed (used for strict-killing errors in non-strict mode)
+    nl();
+
+    if non_interactive() { // can't confirm, so don't continue
+        return Err( Error::needs_confirmation("Confirmation needed to continue, but running non-interactively".to_string()) );
+    }
+
+    report!("[#] Are you certain you wish to continue?");
+    report!("[#] (Ctrl+C to abort; Enter to continue)");
+
+    // Wait for user confirmation
+    read_answer()?;
+
+    Ok(())
+}
+
+fn confirm_fingerprint(target_file: &Path, pub_key: Option<PublicKey>) -> Outcome<()> { // Have a human check the signer's fingerprint against the one they expect (for --verify-pubkey-fingerprint)
+    let pub_key = match pub_key {
+        Some(pub_key) => pub_key,
+        None => return Err( Error::tampered("Target file is not signed, so there's no fingerprint to verify".to_string()) ),
+    };
+
+    let fingerprint = pubkey_fingerprint( pub_key.as_bytes() );
+
+    nl();
+    report_event(
+        &format!("[#] Signer's public key:  {}\n[#] Signer's fingerprint: {}", hex::encode( pub_key.as_bytes() ), fingerprint),
+        serde_json::json!({ "event": "fingerprint", "path": stringify_path(target_file), "public_key": hex::encode( pub_key.as_bytes() ), "public_key_fingerprint": &fingerprint })
+    );
+
+    if non_interactive() { // nobody to compare it
+        return Err( Error::needs_confirmation("The fingerprint needs confirming, but running non-interactively".to_string()) );
+    }
+
+    report!("[#] Does this fingerprint match the one you expect for the signer? (y/N)");
+
+    let confirmed = matches!( read_answer()?.to_lowercase().as_str(), "y" | "yes" );
+
+    audit( serde_json::json!({
+        "event": "fingerprint_confirmation",
+        "file": stringify_path(target_file),
+        "public_key_fingerprint": &fingerprint,
+        "outcome": if confirmed { "confirmed" } else { "rejected" },
+    }) )?;
+
+    if !confirmed {
+        return Err( Error::tampered("Fingerprint not confirmed".to_string()) );
+    }
+
+    report!("[+] Fingerprint confirmed");
+
+    Ok(())
+}
+
+/*----------+
+|  doctor   |
+-----------*/
+
+type DoctorCheck = fn() -> std::result::Result<(), String>; // one stage of `doctor`: Err says what went wrong
+
+const DOCTOR_CHECKS: [(&str, DoctorCheck); 7] = [
+    ("Randomness", doctor_randomness),
+    ("Key splitting and recovery", doctor_key_splitting),
+    ("Dealing extra shares", doctor_share_extension),
+    ("Encryption and decryption", doctor_encryption),
+    ("Tamper detection", doctor_tamper_detection),
+    ("Share signing", doctor_signing),
+    ("Passphrase wrapping", doctor_passphrase_wrapping),
+];
+
+fn doctor_check(passed: bool, failure: &str) -> std::result::Result<(), String> { // assert!, for doctor checks
+    match passed {
+        true => Ok(()),
+        false => Err( failure.to_string() ),
+    }
+}
+
+fn doctor_key() -> Zeroizing<Vec<u8>> { // a fresh key to put through its paces
+    let mut key = Zeroizing::new( vec![0u8; KEY_LENGTH_BYTES] );
+    fill_random(&mut key);
+
+    key
+}
+
+fn doctor_shares(share_bytes: &[Vec<u8>]) -> std::result::Result<Vec<Share>, String> {
+    share_bytes.iter().map(|share| Share::try_from(share.as_slice()).map_err(|error| error.to_string() ) ).collect()
+}
+
+fn doctor_randomness() -> std::result::Result<(), String> {
+    let (first, second) = (doctor_key(), doctor_key());
+
+    doctor_check(first.iter().any(|&byte| byte != 0), "the RNG gave all zeroes")?;
+    doctor_check(first != second, "the RNG gave the same bytes twice")
+}
+
+fn doctor_key_splitting() -> std::result::Result<(), String> {
+    let key = doctor_key();
+    let shares = doctor_shares( &split_key_into_shares(&key, 5, 3) )?;
+
+    doctor_check(shares.len() == 5, "wrong number of shares dealt")?;
+
+    for quorum in [&shares[0..3], &shares[2..5]] {
+        let recovered = recover_key_from_shares(quorum, 3).map_err(|error| error.to_string() )?;
+        doctor_check(recovered.as_slice().ct_eq(key.as_slice()).into(), "recovered key differs from the original")?;
+    }
+
+    // and one short of the threshold mustn't give the key away
+    let short = recover_key_from_shares(&shares[0..2], 3);
+    doctor_check(!short.is_ok_and(|recovered| recovered.as_slice() == key.as_slice()), "key recovered from fewer shares than the threshold")
+}
+
+fn doctor_share_extension() -> std::result::Result<(), String> {
+    let key = doctor_key();
+    let share_bytes = split_key_into_shares(&key, 4, 3);
+    let shares = doctor_shares(&share_bytes)?;
+
+    // any three shares pin down the split, so dealing #4 again from the first three must give the same share
+    let dealt = extend_shares(&shares[0..3], 3, &[shares[3].x.0]).map_err(|error| error.to_string() )?;
+    doctor_check(dealt[0].as_slice().ct_eq(share_bytes[3].as_slice()).into(), "dealt share differs from the original")
+}
+
+fn doctor_plaintext() -> Vec<u8> { // known plaintext spanning a few chunks (the last one partial)
+    (0..(STREAM_CHUNK_BYTES_MIN * 3 + 17)).map(|i| (i % 251) as u8 ).collect()
+}
+
+fn doctor_encryption() -> std::result::Result<(), String> {
+    let key = doctor_key();
+    let plaintext = doctor_plaintext();
+
+    for cipher in Cipher::SUPPORTED {
+        let mut nonce = vec![0u8; cipher.nonce_length()];
+        fill_random(&mut nonce);
+
+        let ciphertext = chacha_encrypt(cipher, STREAM_CHUNK_BYTES_MIN, &key, &nonce, b"doctor", &plaintext).map_err(|error| format!("{}: {}", cipher.name(), error) )?;
+        doctor_check(ciphertext.len() as u64 == ciphertext_length(plaintext.len() as u64, STREAM_CHUNK_BYTES_MIN), "ciphertext is the wrong length")?;
+        doctor_check(ciphertext[..plaintext.len()] != plaintext[..], "ciphertext is the plaintext")?;
+
+        let decrypted = chacha_decrypt(cipher, STREAM_CHUNK_BYTES_MIN, &key, &nonce, b"doctor", &ciphertext).map_err(|error| format!("{}: {}", cipher.name(), error) )?;
+        doctor_check(decrypted == plaintext, "decrypted plaintext differs from the original")?;
+    }
+
+    Ok(())
+}
+
+fn doctor_tamper_detection() -> std::result::Result<(), String> {
+    let key = doctor_key();
+    let plaintext = doctor_plaintext();
+
+    for cipher in Cipher::SUPPORTED {
+        let mut nonce = vec![0u8; cipher.nonce_length()];
+        fill_random(&mut nonce);
+
+        let ciphertext = chacha_encrypt(cipher, STREAM_CHUNK_BYTES_MIN, &key, &nonce, b"doctor", &plaintext).map_err(|error| format!("{}: {}", cipher.name(), error) )?;
+
+        let mut flipped = ciphertext.clone();
+        flipped[STREAM_CHUNK_BYTES_MIN + 1] ^= 1;
+        doctor_check(chacha_decrypt(cipher, STREAM_CHUNK_BYTES_MIN, &key, &nonce, b"doctor", &flipped).is_err(), "a flipped bit went unnoticed")?;
+
+        let truncated = &ciphertext[..ciphertext.len() - 1];
+        doctor_check(chacha_decrypt(cipher, STREAM_CHUNK_BYTES_MIN, &key, &nonce, b"doctor", truncated).is_err(), "a truncated ciphertext went unnoticed")?;
+
+        doctor_check(chacha_decrypt(cipher, STREAM_CHUNK_BYTES_MIN, &key, &nonce, b"header", &ciphertext).is_err(), "a changed header went unnoticed")?;
+
+        let mut wrong_key = key.clone();
+        wrong_key[0] ^= 1;
+        doctor_check(chacha_decrypt(cipher, STREAM_CHUNK_BYTES_MIN, &wrong_key, &nonce, b"doctor", &ciphertext).is_err(), "the wrong key decrypted the file")?;
+    }
+
+    Ok(())
+}
+
+fn doctor_signing() -> std::result::Result<(), String> {
+    let keypair = generate_keypair();
+    let share_bytes = split_key_into_shares(&doctor_key(), 2, 2);
+
+    let mut nonce = vec![0u8; NONCE_LENGTH_BYTES];
+    fill_random(&mut nonce);
+
+    let share_header = construct_header_share(ALGO_VERSION, 2, true, false, &nonce, None, None, false, None);
+    let share_file = construct_share(&share_header, Some(&keypair), &share_bytes[0]);
+
+    let shf = parse_share(share_file.clone(), Some(&nonce), &mut |_| Err( io::Error::other("not passphrase protected") ) ).map_err(|error| error.to_string() )?;
+    let (pub_key, signature) = match (shf.pub_key, shf.signature) {
+        (Some(pub_key), Some(signature)) => (pub_key, signature),
+        _ => return Err( "signed share came back unsigned".to_string() ),
+    };
+
+    doctor_check(pub_key == keypair.public, "share came back with the wrong public key")?;
+    doctor_check(verify_share_signature(&shf, &pub_key, &signature).is_ok(), "share's own signature doesn't verify")?;
+
+    // and a share that's been meddled with mustn't
+    let mut tampered_file = share_file;
+    *tampered_file.last_mut().unwrap() ^= 1;
+
+    let tampered = parse_share(tampered_file, Some(&nonce), &mut |_| Err( io::Error::other("not passphrase protected") ) ).map_err(|error| error.to_string() )?;
+    doctor_check(verify_share_signature(&tampered, &pub_key, &signature).is_err(), "a tampered share's signature still verifies")
+}
+
+fn doctor_passphrase_wrapping() -> std::result::Result<(), String> {
+    let share_bytes = split_key_into_shares(&doctor_key(), 2, 2);
+    let salt = generate_salt();
+
+    let wrapping_key = derive_wrapping_key(b"correct horse battery staple", &salt).map_err(|error| error.to_string() )?;
+    let wrapped = wrap_share(&wrapping_key, &share_bytes[0]).map_err(|error| error.to_string() )?;
+    let unwrapped = unwrap_share(&wrapping_key, &wrapped).map_err(|error| error.to_string() )?;
+
+    doctor_check(unwrapped.as_slice().ct_eq(share_bytes[0].as_slice()).into(), "unwrapped share differs from the original")?;
+
+    let wrong_key = derive_wrapping_key(b"incorrect horse battery staple", &salt).map_err(|error| error.to_string() )?;
+    doctor_check(unwrap_share(&wrong_key, &wrapped).is_err(), "the wrong passphrase unwrapped a share")
+}
+
+fn run_doctor() -> usize { // Run every doctor check, reporting each; returns how many failed
+    let mut failed = 0;
+
+    for (stage, check) in DOCTOR_CHECKS {
+        // a miscompiled dependency is as likely to panic as to give a wrong answer
+        let outcome = std::panic::catch_unwind(check).unwrap_or_else(|_| Err( "panicked".to_string() ) );
+
+        match outcome {
+            Ok(()) => report_event(
+                &format!("[+] {}: ok", stage),
+                serde_json::json!({ "event": "doctor_check", "stage": stage, "outcome": "ok" })
+            ),
+            Err(error) => {
+                failed += 1;
+                report_event(
+                    &format!("[!] {}: FAILED ({})", stage, error),
+                    serde_json::json!({ "event": "doctor_check", "stage": stage, "outcome": "failed", "error": error })
+                );
+            },
+        }
+    }
+
+    failed
+}
+
+/*----------+
+|   main    |
+-----------*/
+
+fn yes_no(value: bool) -> &'static str { // bools for humans
+    match value {
+        true => "yes",
+        false => "no",
+    }
+}
+
+fn main() {
+    let args = Arguments::parse();
+
+    JSON_OUTPUT.store(args.json, Ordering::Relaxed);
+    NON_INTERACTIVE.store(args.non_interactive, Ordering::Relaxed);
+
+    // stdout is for the shares alone with `encrypt --shares-stdout`, the plaintext alone with `decrypt --stdout`, and the secret alone with `combine`
+    let stdout_reserved = matches!(args.command, Commands::Encrypt { shares_stdout: true, .. } | Commands::Decrypt { stdout: true, .. } | Commands::Combine { output: None, .. });
+    STDOUT_RESERVED.store(stdout_reserved, Ordering::Relaxed);
+
+    QUIET.store(args.quiet, Ordering::Relaxed);
+    VERBOSE.store(args.verbose, Ordering::Relaxed);
+    PROGRESS_JSON.store(args.progress_json, Ordering::Relaxed);
+
+    if let Err(error) = run(args) { // whatever went wrong, it's reported the same way, here
+        report_error(&error);
+        process::exit( error.exit_code() );
+    }
+
+    process::exit(0);
+}
+
+fn run(mut args: Arguments) -> Outcome<()> { // Run the command we were given
+    shift_positionals_for_input_list(&mut args.command)?;
+    merge_named_share_counts(&mut args.command)?;
+
+    if let Some(audit_log) = &args.audit_log { // appended to, never truncated -- it's a record of every run
+        match fs::OpenOptions::new().append(true).create(true).open(audit_log) {
+            Ok(file) => *AUDIT_LOG.lock().unwrap_or_else(|poisoned| poisoned.into_inner() ) = Some(file),
+            Err(error) => return Err( Error::failed(error, format!("Could not open audit log {}", audit_log.display())) ),
+        }
+
+        audit( serde_json::json!({ "event": "started", "version": VERSION, "args": env::args().collect::<Vec<String>>() }) )?;
+    }
+
+    if !args.json && !args.quiet { // keep JSON output clean (the logo goes to stderr, so it never gets mixed up with data)
+        logo(); // print logo
+    }
+
+    #[cfg(feature = "test-rng")]
+    if let Some(seed) = args.seed { // before anything random is made
+        seed_test_rng(seed);
+        report!("[#] Using a seeded RNG (--seed) -- keys and nonces are predictable, so only use this for tests");
+    }
+
+    if !args.no_config { // defaults for whatever wasn't given on the command line
+        if let Some((config_path, config)) = load_config(args.config.as_deref())? {
+            report!("[+] Using defaults from {}", stringify_path(&config_path) );
+            apply_config(&mut args.command, &config);
+        }
+    }
+
+    match args.command { // which command are we running?
+        
+        Commands::Info { ref file } => { // Print header info
+            let header = match read_target_header(file) { // a raw file's is in {name}.hdr
+                Ok(contents) if contents.starts_with(ARMOR_BEGIN.as_bytes()) => { // an armored share -- unwrap it to get at the header
+                    read_share_file(file).and_then(|contents| dearmor_share(&contents.unwrap_or_default()) )
+                }
+                result => result,
+            };
+
+            let header = match header {
+                Ok(contents) => contents,
+                Err(error) => return Err( Error::failed(error, format!("Could not read file {}", file.display())) ),
+            };
+
+            // "CCMS" also starts with "CCM", so check for shares first
+            let is_share = header.len() >= HEADER_SHARE.len() && header[0..HEADER_SHARE.len()] == HEADER_SHARE;
+
+            if is_share {
+                let share_header = match parse_header_share(&header) {
+                    Ok(share_header) => share_header,
+                    Err(error) => return Err( Error::failed(error, format!("Could not parse share {}", file.display())) ),
+                };
+
+                let is_passphrase_protected = share_header.flags & SHARE_FLAG_PASSPHRASE != 0;
+
+                if args.json {
+                    let info = serde_json::json!({
+                        "path": stringify_path(file),
+                        "type": "share",
+                        "algo_version": share_header.algo_version,
+                        "threshold": share_header.threshold,
+                        "scheme": share_header.scheme,
+                        "is_signed": share_header.is_signed,
+                        "is_passphrase_protected": is_passphrase_protected,
+                        "has_mac": share_header.has_mac,
+                        "secret_length": share_header.secret_length,
+                        "nonce": hex::encode(&share_header.nonce),
+                        "public_key": share_header.is_signed.then(|| hex::encode(&share_header.pub_key_bytes) ),
+                        "public_key_fingerprint": share_header.is_signed.then(|| pubkey_fingerprint(&share_header.pub_key_bytes) ),
+                    });
+
+                    println!("{}", info);
+                } else {
+                    output!("[+] Share: {}", stringify_path(file) );
+                    output!("[+] Algorithm version: {}", share_header.algo_version );
+                    output!("[+] Threshold: {} shares needed to decrypt", share_header.threshold );
+
+                    if let Some(scheme) = share_header.scheme {
+                        output!("[+] Scheme: {}", scheme );
+                    }
+
+                    output!("[+] Nonce: {}", hex::encode(&share_header.nonce) );
+                    output!("[+] Signed: {}", yes_no(share_header.is_signed) );
+
+                    if share_header.is_signed {
+                        output!("[+] Public key: {}", hex::encode(&share_header.pub_key_bytes) );
+                        output!("[+] Fingerprint: {}", pubkey_fingerprint(&share_header.pub_key_bytes) );
+                    }
+
+                    output!("[+] Passphrase protected: {}", yes_no(is_passphrase_protected) );
+                    output!("[+] MAC: {}", yes_no(share_header.has_mac) );
+
+                    if let Some(secret_length) = share_header.secret_length {
+                        output!("[+] Secret: {} bytes, split on its own (see `combine`)", secret_length );
+                    }
+                }
+            } else {
+                let file_header = match parse_header_file(&header) {
+                    Ok(file_header) => file_header,
+                    Err(error) => {
+                        let diagnosis = format!("Could not parse file {}", file.display());
+
+                        return Err( match error.kind() { // a newer version's file is still encrypted
+                            io::ErrorKind::Unsupported => Error::failed(error, diagnosis),
+                            _ => Error::NotEncrypted { diagnosis, error: Some(error) },
+                        })
+                    }
+                };
+
+                let is_streamed = file_header.flags & FLAG_STREAMED != 0;
+                let is_compressed = file_header.flags & FLAG_COMPRESSED != 0;
+                let has_metadata = file_header.flags & FLAG_METADATA != 0;
+
+                // only mentioned, not checked -- that needs the whole file
+                let sig_path = detached_sig_path(file);
+                let has_detached_sig = !file_header.is_signed && sig_path.is_file();
+
+                if args.json {
+                    let info = serde_json::json!({
+                        "path": stringify_path(file),
+                        "type": "file",
+                        "label": file_header.label,
+                        "algo_version": file_header.algo_version,
+                        "threshold": file_header.threshold,
+                        "schemes": file_header.schemes.unwrap_or(1),
+                        "is_signed": file_header.is_signed,
+                        "is_streamed": is_streamed,
+                        "chunk_size": is_streamed.then_some(file_header.chunk_size),
+                        "cipher": file_header.cipher.name(),
+                        "is_compressed": is_compressed,
+                        "has_metadata": has_metadata,
+                        "is_archive": file_header.flags & FLAG_ARCHIVE != 0,
+                        "has_aad": file_header.has_aad,
+                        "nonce": hex::encode(&file_header.nonce),
+                        "checksum": file_header.checksum.as_ref().map(hex::encode),
+                        "public_key": file_header.is_signed.then(|| hex::encode(&file_header.pub_key_bytes) ),
+                        "public_key_fingerprint": file_header.is_signed.then(|| pubkey_fingerprint(&file_header.pub_key_bytes) ),
+                        "detached_signature": has_detached_sig.then(|| stringify_path(&sig_path) ),
+                    });
+
+                    println!("{}", info);
+                } else {
+                    output!("[+] File: {}", stringify_path(file) );
+
+                    if let Some(label) = &file_header.label {
+                        output!("[+] Label: {}", label );
+                    }
+
+                    output!("[+] Algorithm version: {}", file_header.algo_version );
+                    output!("[+] Cipher: {}", file_header.cipher.name() );
+                    match file_header.schemes {
+                        Some(schemes) => output!("[+] Threshold: {} shares needed to decrypt (first of {} share schemes; any one will do)", file_header.threshold, schemes ),
+                        None => output!("[+] Threshold: {} shares needed to decrypt", file_header.threshold ),
+                    }
+
+                    output!("[+] Nonce: {}", hex::encode(&file_header.nonce) );
+                    output!("[+] Signed: {}", yes_no(file_header.is_signed) );
+
+                    if file_header.is_signed {
+                        output!("[+] Public key: {}", hex::encode(&file_header.pub_key_bytes) );
+                        output!("[+] Fingerprint: {}", pubkey_fingerprint(&file_header.pub_key_bytes) );
+                    }
+
+                    if has_detached_sig {
+                        output!("[+] Detached signature: {}", stringify_path(&sig_path) );
+                    }
+
+                    output!("[+] Streamed: {}", yes_no(is_streamed) );
+
+                    if is_streamed {
+                        output!("[+] Chunk size: {} bytes", file_header.chunk_size );
+                    }
+
+                    output!("[+] Compressed: {}", yes_no(is_compressed) );
+                    output!("[+] Original filename stored: {}", yes_no(has_metadata) );
+                    output!("[+] Archive of several files: {}", yes_no(file_header.flags & FLAG_ARCHIVE != 0) );
+                    output!("[+] Needs associated data (--aad): {}", yes_no(file_header.has_aad) );
+
+                    match &file_header.checksum {
+                        Some(checksum) => output!("[+] Checksum (blake3): {}", hex::encode(checksum) ),
+                        None => output!("[+] Checksum: none"),
+                    }
+                }
+            }
+        },
+
+        Commands::VerifyShare { ref share } => { // Check a share in isolation
+            report!("[*] Chose to verify a share...");
+            nl();
+
+            report!("[+] Share: {}", stringify_path(share) );
+
+            // not matching it to a file, so any nonce will do
+            let mut shf = match share_from_file(share, None, &mut vec![], NO_READ_RETRIES)? {
+                Ok(shf) => shf,
+                Err(error) => {
+                    nl();
+                    report_event(
+                        &format!("[!] Share is structurally invalid: {}", error),
+                        serde_json::json!({ "event": "share_checked", "path": stringify_path(share), "status": "invalid", "error": error.to_string() })
+                    );
+                    return Err( Error::refused(format!("{} is not a usable share", stringify_path(share))) );
+                }
+            };
+
+            report!("[+] Algorithm version: {}", shf.algo_version );
+            report!("[+] Share index: {}", shf.share_data.x.0 );
+
+            if let Some(scheme) = shf.scheme {
+                report!("[+] Scheme: {}", scheme );
+            }
+            report!("[+] Threshold: {} shares needed to decrypt", shf.threshold );
+            report!("[+] Nonce: {}", hex::encode(&shf.nonce) );
+            report!("[+] Signed: {}", yes_no(shf.is_signed) );
+            report!("[+] Passphrase protected: {}", yes_no(shf.salt.is_some()) );
+            report!("[+] MAC: {} (only checked once the key is recovered)", yes_no(shf.mac.is_some()) );
+
+            // a share signs itself, so this only shows it's intact -- not who made it
+            let (status, line) = match (shf.pub_key, shf.signature) {
+                (Some(pub_key), Some(signature)) => match verify_share_signature(&shf, &pub_key, &signature) {
+                    Ok(_) => ("signed", "[+] Share is valid, and its signature checks out against its own public key"),
+                    Err(_) => ("bad_signature", "[!] Share parses, but its signature does NOT match its own public key -- it may be corrupted or tampered with"),
+                },
+                _ => ("unsigned", "[+] Share is valid, but unsigned -- only its structure could be checked"),
+            };
+
+            nl();
+            report_event(line, serde_json::json!({
+                "event": "share_checked",
+                "path": stringify_path(share),
+                "status": status,
+                "index": shf.share_data.x.0,
+                "threshold": shf.threshold,
+                "nonce": hex::encode(&shf.nonce),
+                "public_key": shf.pub_key.map(|pub_key| hex::encode(pub_key.to_bytes()) ),
+            }) );
+
+            if let Some(pub_key) = shf.pub_key.filter(|_| status == "signed") {
+                report!("[-] Compare its public key with the encrypted file's (see `info`) to be sure they belong together:");
+                report!("[-] {}", describe_pub_key(pub_key.as_bytes()) );
+            }
+
+            zeroize_share(&mut shf.share_data);
+
+            if status == "bad_signature" {
+                return Err( Error::tampered(format!("{} does not match its own signature", stringify_path(share))) );
+            }
+        },
+
+        Commands::Rekey { ref file, players, threshold: new_threshold, share_dir, new_share_dir, all, strict, passphrase, key_file, force, mode, aad } => { // Re-splitting
+            report!("[*] Chose to rekey a file...");
+            nl();
+
+            check_players_threshold(players, new_threshold)?;
+
+            let paths = get_paths(share_dir, file.to_owned() )?;
+            let target_file = &paths[0];
+            let shares_dir = &paths[1];
+
+            report!("[+] Shares directory: {}", stringify_path(shares_dir) );
+            report!("[+] Storing new shares at {}", stringify_path(&new_share_dir) );
+
+            // the old shares could still be mixed in with the new ones (they'd recover garbage together)
+            if absolute_path(&new_share_dir).ok() == absolute_path(shares_dir).ok() {
+                return Err( Error::refused("New shares must go in a different directory to the current ones".to_string()) );
+            }
+
+            ensure_share_dir(&new_share_dir, force, false)?;
+
+            nl();
+
+            let (mut threshold, is_signed, pub_key, signature, mut file_header) = process_target_file(target_file, strict)?;
+
+            if has_header_sidecar(target_file) { // the new header would have to go in the file, which is only ciphertext
+                return Err( Error::refused(format!("{} is a raw encrypted file (its header is in {}), which can't be rewritten in place", target_file.display(), stringify_path(&header_sidecar_path(target_file)))) );
+            }
+
+            if key_file.is_some() && !is_signed {
+                return Err( Error::refused("Target file is not signed, so there is nothing to re-sign with --key-file".to_string()) );
+            }
+
+            fill_in_aad(&mut file_header, aad.as_deref())?; // the contents are re-encrypted under it again
+
+            let nonce = &file_header.nonce;
+
+            report!("[+] Target file is encrypted; algorithm version {}", file_header.algo_version );
+
+            nl();
+            report_shares_needed(threshold, file_header.schemes, "recover the key");
+            report!("[+] Target file nonce: {}", hex::encode(nonce) );
+
+            // from v4 the header is authenticated along with the contents, so changing the threshold means
+            // re-encrypting them -- under a new nonce, which the new shares get too
+            let reencrypt = file_header.algo_version >= ALGO_VERSION_HEADER_AAD;
+
+            let new_nonce: Vec<u8> = match reencrypt {
+                true => {
+                    let mut new_nonce = vec![0u8; nonce.len()];
+                    fill_random(&mut new_nonce);
+
+                    report!("[+] Contents will be re-encrypted under new nonce {}", hex::encode(&new_nonce) );
+                    new_nonce
+                },
+                false => nonce.clone(),
+            };
+
+            let hex_nonce = hex::encode(&new_nonce);
+
+            nl();
+
+            let new_share_paths = share_paths(&new_share_dir, DEFAULT_SHARE_NAME_TEMPLATE, players as usize, new_threshold, &hex_nonce)?;
+
+            if !force {
+                die_on_existing_shares(&new_share_dir, &hex_nonce, &new_share_paths)?;
+            }
+
+            // Gather shares
+            let mut share_macs: Vec<GatheredMac> = vec![];
+            let mut shares: Vec<Share> = gather_shares(&paths[1..], nonce, &mut threshold, file_header.schemes, is_signed, file_header.flags & FLAG_SHARES_UNSIGNED != 0, pub_key, &GatherOptions { all, strict, threshold: None, threshold_from_shares: false, stdin: false, bundle: None, max_scanned: None, read_retries: NO_READ_RETRIES, exclude: vec![], list_shares: false, verify_all_signatures: false }, &mut share_macs)?;
+
+            nl();
+
+            // we're about to re-sign this file, so make sure we aren't vouching for something that's been tampered with
+            if is_signed {
+                file_signature_verification(target_file, &file_header, threshold, pub_key.unwrap(), signature.unwrap(), strict)?;
+            }
+
+            // Attempt to recover key from shares
+            let recovered_key = recover_key(&mut shares, threshold)?;
+            check_share_macs(&recovered_key, &share_macs, strict)?;
+
+            nl();
+
+            // Split into a new set of shares
+            let new_shares: Zeroizing<Vec<Vec<u8>>> = split_key_into_shares(&recovered_key, players, new_threshold);
+
+            report!("[-] Derived {} new share(s) from key | threshold {}", &new_shares.len(), &new_threshold);
+
+            // Recover the shares again for good measure
+            recover_own_shares(&new_shares, new_threshold, recovered_key.as_slice())?;
+
+            let mut ed25519_keypair: Option<Keypair> = match (is_signed, &key_file) {
+                (true, Some(key_file)) => {
+                    let keypair = load_keypair(key_file)?;
+                    report!("[-] Signing keypair loaded from {}", stringify_path(key_file) );
+
+                    Some(keypair)
+                },
+                (true, None) => Some( generate_keypair() ),
+                (false, _) => None,
+            };
+
+            // Save new shares to folder
+            nl();
+
+            let (salt, wrapping_key) = new_wrapping_key(passphrase)?;
+
+            // new shares keep the file's algorithm version, so they match its nonce (and MACs, if the old ones had them)
+            let keep_mac = !share_macs.is_empty();
+            let share_header: Vec<u8> = construct_header_share(file_header.algo_version, new_threshold, is_signed, false, &new_nonce, None, salt.as_deref(), keep_mac, None);
+
+            write_shares(&new_shares, &new_share_paths, &share_header, ed25519_keypair.as_ref(), wrapping_key.as_ref().map(|key| key.as_slice()), keep_mac.then_some(recovered_key.as_slice()), &ShareOutput::Files { qr: false, threads: None, armor: false }, mode )?;
+
+            nl();
+
+            // --- Rewrite the file header with the new threshold (and public key + signature, if signed)
+            // a detached signature stays detached (the header says unsigned, but we found a signature)
+            let is_detached = is_signed && !file_header.is_signed;
+            let header_keypair = ed25519_keypair.as_ref().filter(|_| !is_detached);
+
+            // the new shares are a single scheme, whatever the old ones were
+            let mut new_header: Vec<u8> = construct_header_file(file_header.algo_version, new_threshold, header_keypair.is_some(), file_header.has_aad, file_header.flags & !(FLAG_SCHEMES | FLAG_SHARES_UNSIGNED), file_header.chunk_size, file_header.cipher, &new_nonce, file_header.checksum.as_deref(), None, file_header.label.as_deref());
+
+            if let Some(keypair) = header_keypair {
+                new_header.extend( keypair.public.to_bytes() );
+            }
+
+            if reencrypt { // write the re-encrypted file alongside, then swap it in
+                reencrypt_file(target_file, &new_header, header_keypair, &recovered_key, &recovered_key, &file_header)?;
+            }
+            else if let Err(error) = rewrite_file_header(target_file, &new_header, header_keypair ) {
+                return Err( Error::failed(error, format!("Could not rewrite header of {}", target_file.display())) );
+            }
+
+            if let (true, Some(keypair)) = (is_detached, &ed25519_keypair) { // the old one no longer matches
+                write_detached_signature(target_file, keypair)?;
+            }
+
+            if let Some(keypair) = ed25519_keypair.as_mut() {
+                keypair.secret.zeroize(); // done with the signing key
+
+                report!("[-] Re-signed encrypted file");
+                report!("[+] New public key: {}", describe_pub_key(keypair.public.as_bytes()) );
+            }
+
+            report!("[&] Header of {} updated | threshold {}", stringify_path(target_file), new_threshold );
+
+            nl();
+            report!("[#] The old shares can still recover the key -- destroy them if they are no longer needed");
+
+            // Done!
+            nl();
+            report_event(
+                "[*] Rekey complete! Have a nice day.",
+                serde_json::json!({ "event": "complete", "command": "rekey", "output": stringify_path(target_file) })
+            );
+        },
+
+        Commands::Rotate { ref file, share_dir, new_share_dir, players, threshold: new_threshold, all, strict, passphrase, key_file, force, mode, aad } => { // New key, same plaintext
+            report!("[*] Chose to rotate a file's key...");
+            nl();
+
+            let paths = get_paths(share_dir, file.to_owned() )?;
+            let target_file = &paths[0];
+            let shares_dir = &paths[1];
+
+            report!("[+] Shares directory: {}", stringify_path(shares_dir) );
+            report!("[+] Storing new shares at {}", stringify_path(&new_share_dir) );
+
+            // the old shares could still be mixed in with the new ones (they'd recover garbage together)
+            if absolute_path(&new_share_dir).ok() == absolute_path(shares_dir).ok() {
+                return Err( Error::refused("New shares must go in a different directory to the current ones".to_string()) );
+            }
+
+            ensure_share_dir(&new_share_dir, force, false)?;
+
+            nl();
+
+            let (mut threshold, is_signed, pub_key, signature, mut file_header) = process_target_file(target_file, strict)?;
+
+            if has_header_sidecar(target_file) { // the new header would have to go in the file, which is only ciphertext
+                return Err( Error::refused(format!("{} is a raw encrypted file (its header is in {}), which can't be rewritten in place", target_file.display(), stringify_path(&header_sidecar_path(target_file)))) );
+            }
+
+            if key_file.is_some() && !is_signed {
+                return Err( Error::refused("Target file is not signed, so there is nothing to re-sign with --key-file".to_string()) );
+            }
+
+            // the contents are decrypted and encrypted again chunk by chunk, which needs a header that's authenticated with them
+            if file_header.algo_version < ALGO_VERSION_HEADER_AAD {
+                report!("[!] Only files from algorithm version {} onwards can be rotated (this one is version {})", ALGO_VERSION_HEADER_AAD, file_header.algo_version );
+                return Err( Error::refused("Decrypt it and encrypt it again instead".to_string()) );
+            }
+
+            fill_in_aad(&mut file_header, aad.as_deref())?; // the contents are re-encrypted under it again
+
+            let nonce = &file_header.nonce;
+
+            report!("[+] Target file is encrypted; algorithm version {}", file_header.algo_version );
+
+            nl();
+            report_shares_needed(threshold, file_header.schemes, "recover the key");
+            report!("[+] Target file nonce: {}", hex::encode(nonce) );
+
+            // Gather shares
+            let mut share_macs: Vec<GatheredMac> = vec![];
+            let mut shares: Vec<Share> = gather_shares(&paths[1..], nonce, &mut threshold, file_header.schemes, is_signed, file_header.flags & FLAG_SHARES_UNSIGNED != 0, pub_key, &GatherOptions { all, strict, threshold: None, threshold_from_shares: false, stdin: false, bundle: None, max_scanned: None, read_retries: NO_READ_RETRIES, exclude: vec![], list_shares: false, verify_all_signatures: false }, &mut share_macs)?;
+
+            nl();
+
+            // we're about to re-sign this file, so make sure we aren't vouching for something that's been tampered with
+            if is_signed {
+                file_signature_verification(target_file, &file_header, threshold, pub_key.unwrap(), signature.unwrap(), strict)?;
+            }
+
+            // same threshold as before, unless told otherwise
+            let new_threshold = new_threshold.unwrap_or(threshold);
+
+            check_players_threshold(players, new_threshold)?;
+
+            // Attempt to recover the old key from shares
+            let recovered_key = recover_key(&mut shares, threshold)?;
+            check_share_macs(&recovered_key, &share_macs, strict)?;
+
+            nl();
+
+            // Generate the new key and nonce
+            let mut new_key = Zeroizing::new( [0u8; KEY_LENGTH_BYTES] );
+            fill_random(new_key.as_mut_slice() );
+            report!("[-] New key generated");
+
+            let mut new_nonce = vec![0u8; nonce.len()];
+            fill_random(&mut new_nonce);
+            report!("[-] New nonce generated: {}", hex::encode(&new_nonce) );
+
+            let hex_nonce = hex::encode(&new_nonce);
+
+            let new_share_paths = share_paths(&new_share_dir, DEFAULT_SHARE_NAME_TEMPLATE, players as usize, new_threshold, &hex_nonce)?;
+
+            if !force {
+                die_on_existing_shares(&new_share_dir, &hex_nonce, &new_share_paths)?;
+            }
+
+            // Split the new key into shares
+            let new_shares: Zeroizing<Vec<Vec<u8>>> = split_key_into_shares(new_key.as_slice(), players, new_threshold);
+
+            report!("[-] Derived {} new share(s) from the new key | threshold {}", &new_shares.len(), &new_threshold);
+
+            // Recover the shares again for good measure
+            recover_own_shares(&new_shares, new_threshold, new_key.as_slice())?;
+
+            let mut ed25519_keypair: Option<Keypair> = match (is_signed, &key_file) {
+                (true, Some(key_file)) => {
+                    let keypair = load_keypair(key_file)?;
+                    report!("[-] Signing keypair loaded from {}", stringify_path(key_file) );
+
+                    Some(keypair)
+                },
+                (true, None) => Some( generate_keypair() ),
+                (false, _) => None,
+            };
+
+            // Save new shares to folder
+            nl();
+
+            let (salt, wrapping_key) = new_wrapping_key(passphrase)?;
+
+            // the file keeps its algorithm version (its chunks are re-encrypted one for one), and so do the new shares (MACs and all)
+            let keep_mac = !share_macs.is_empty();
+            let share_header: Vec<u8> = construct_header_share(file_header.algo_version, new_threshold, is_signed, false, &new_nonce, None, salt.as_deref(), keep_mac, None);
+
+            write_shares(&new_shares, &new_share_paths, &share_header, ed25519_keypair.as_ref(), wrapping_key.as_ref().map(|key| key.as_slice()), keep_mac.then_some(new_key.as_slice()), &ShareOutput::Files { qr: false, threads: None, armor: false }, mode )?;
+
+            nl();
+
+            // --- New header, with the new nonce and threshold (a detached signature stays detached, as with rekey)
+            let is_detached = is_signed && !file_header.is_signed;
+            let header_keypair = ed25519_keypair.as_ref().filter(|_| !is_detached);
+
+            // the new shares are a single scheme, whatever the old ones were
+            let mut new_header: Vec<u8> = construct_header_file(file_header.algo_version, new_threshold, header_keypair.is_some(), file_header.has_aad, file_header.flags & !(FLAG_SCHEMES | FLAG_SHARES_UNSIGNED), file_header.chunk_size, file_header.cipher, &new_nonce, file_header.checksum.as_deref(), None, file_header.label.as_deref());
+
+            if let Some(keypair) = header_keypair {
+                new_header.extend( keypair.public.to_bytes() );
+            }
+
+            // old key in, new key out -- no plaintext touches the disk
+            reencrypt_file(target_file, &new_header, header_keypair, &recovered_key, new_key.as_slice(), &file_header)?;
+
+            if let (true, Some(keypair)) = (is_detached, &ed25519_keypair) { // the old one no longer matches
+                write_detached_signature(target_file, keypair)?;
+            }
+
+            if let Some(keypair) = ed25519_keypair.as_mut() {
+                keypair.secret.zeroize(); // done with the signing key
+
+                report!("[-] Re-signed encrypted file");
+                report!("[+] New public key: {}", describe_pub_key(keypair.public.as_bytes()) );
+            }
+
+            report!("[&] {} rotated to a new key | threshold {}", stringify_path(target_file), new_threshold );
+
+            nl();
+            report!("[#] -----------------------------------------------------");
+            report!("[#] WARNING: THE OLD SHARES NO LONGER DECRYPT THIS FILE  ");
+            report!("[#] -----------------------------------------------------");
+            nl();
+            report_event(
+                &format!("[#] Shares for the old nonce {} are stale -- only the new shares in {} can decrypt it now", hex::encode(nonce), stringify_path(&new_share_dir)),
+                serde_json::json!({ "event": "stale_shares", "nonce": hex::encode(nonce), "new_nonce": hex_nonce, "new_share_dir": stringify_path(&new_share_dir) })
+            );
+            report!("[#] Send the new shares out, and have the old ones destroyed (along with any older copies of the file)");
+
+            // Done!
+            nl();
+            report_event(
+                "[*] Rotation complete! Have a nice day.",
+                serde_json::json!({ "event": "complete", "command": "rotate", "output": stringify_path(target_file) })
+            );
+        },
+
+        Commands::AddShares { ref file, count, share_dir, new_share_dir, first_index, all, strict, passphrase, key_file, force, mode } => { // More shares, same key
+            report!("[*] Chose to add shares for a file...");
+            nl();
+
+            let paths = get_paths(share_dir, file.to_owned() )?;
+            let target_file = &paths[0];
+            let shares_dir = &paths[1];
+            let new_share_dir = new_share_dir.unwrap_or_else(|| shares_dir.clone() );
+
+            report!("[+] Shares directory: {}", stringify_path(shares_dir) );
+            report!("[+] Storing new shares at {}", stringify_path(&new_share_dir) );
+
+            ensure_share_dir(&new_share_dir, force, false)?;
+
+            nl();
+
+            let (mut threshold, is_signed, pub_key, signature, file_header) = process_target_file(target_file, strict)?;
+
+            // each scheme is a polynomial of its own, and the new shares would need to know which one they're from
+            if let Some(schemes) = file_header.schemes {
+                return Err( Error::refused(format!("Target file's key is split between {} share schemes; adding shares only works for files with one", schemes)) );
+            }
+
+            // a signed file's shares are checked against its public key, so new ones have to be signed with the same keypair
+            let mut ed25519_keypair: Option<Keypair> = match (is_signed, &key_file) {
+                (true, Some(key_file)) => {
+                    let keypair = load_keypair(key_file)?;
+
+                    if pub_key.is_some_and(|pub_key| pub_key != keypair.public) {
+                        return Err( Error::refused(format!("Key file {} is not the keypair the target file was signed with", stringify_path(key_file))) );
+                    }
+
+                    report!("[-] Signing keypair loaded from {}", stringify_path(key_file) );
+                    Some(keypair)
+                },
+                (true, None) => {
+                    return Err( Error::refused("Target file is signed, so new shares have to be signed with its keypair -- give its key file with --key-file".to_string()) );
+                },
+                (false, Some(_)) => {
+                    return Err( Error::refused("Target file is not signed, so there is nothing to sign new shares for with --key-file".to_string()) );
+                },
+                (false, None) => None,
+            };
+
+            let nonce = &file_header.nonce;
+            let hex_nonce = hex::encode(nonce);
+
+            report!("[+] Target file is encrypted; algorithm version {}", file_header.algo_version );
+
+            nl();
+            report_shares_needed(threshold, None, "recover the key");
+            report!("[+] Target file nonce: {}", hex_nonce );
+
+            // Gather shares
+            let mut share_macs: Vec<GatheredMac> = vec![];
+            let mut shares: Vec<Share> = gather_shares(&paths[1..], nonce, &mut threshold, None, is_signed, file_header.flags & FLAG_SHARES_UNSIGNED != 0, pub_key, &GatherOptions { all, strict, threshold: None, threshold_from_shares: false, stdin: false, bundle: None, max_scanned: None, read_retries: NO_READ_RETRIES, exclude: vec![], list_shares: false, verify_all_signatures: false }, &mut share_macs)?;
+
+            nl();
+
+            // we're about to sign shares for this file, so make sure we aren't vouching for something that's been tampered with
+            if is_signed {
+                file_signature_verification(target_file, &file_header, threshold, pub_key.unwrap(), signature.unwrap(), strict)?;
+            }
+
+            // new shares mustn't reuse an index -- they'd be copies of shares somebody already has
+            let highest_index = shares.iter().map(|share| share.x.0 ).max().unwrap_or(0);
+            let no_room_after_highest = first_index.is_none() && highest_index == u8::MAX; // an explicit --first-index can still fit below it
+            let first_index = first_index.unwrap_or( highest_index.saturating_add(1) ) as usize;
+            let last_index = first_index + count as usize - 1;
+
+            if no_room_after_highest || last_index > u8::MAX as usize {
+                return Err( Error::refused(format!("Shares are numbered 1-255, so there's no room for {} more from # {}", count, first_index)) );
+            }
+
+            if let Some(taken) = shares.iter().map(|share| share.x.0 as usize ).find(|index| (first_index..=last_index).contains(index)) {
+                return Err( Error::refused(format!("Share # {} is already in the share directory; choose a different --first-index", taken)) );
+            }
+
+            let indices: Vec<u8> = (first_index..=last_index).map(|index| index as u8 ).collect();
+
+            report!("[+] New shares will be numbered {} to {}", first_index, last_index );
+            report!("[#] Shares handed out earlier that aren't in the share directory must be numbered below {}, or they'll be dealt again", first_index );
+
+            let new_share_paths = share_paths_at(&new_share_dir, DEFAULT_SHARE_NAME_TEMPLATE, first_index..=last_index, last_index, threshold, &hex_nonce)?;
+
+            let existing: Vec<&PathBuf> = new_share_paths.iter().filter(|path| share_path_taken(path) ).collect();
+
+            if !force && !existing.is_empty() {
+                nl();
+                report!("[!] New shares would overwrite files already in {}:", stringify_path(&new_share_dir) );
+
+                for existing in existing {
+                    report!("[!]   {}", existing.display() );
+                }
+
+                return Err( Error::refused("Not overwriting them (use --force if you really mean to)".to_string()) );
+            }
+
+            nl();
+
+            // Deal the new shares from the old ones (before recovering the key wipes them), keeping enough old ones back to check each new one with
+            let new_shares: Zeroizing<Vec<Vec<u8>>> = match extend_shares(&shares, threshold, &indices) {
+                Ok(new_shares) => new_shares,
+                Err(error) => return Err( Error::RecoveryFailed { diagnosis: "Could not deal new shares from the current ones!".to_string(), error: Some(error) } ),
+            };
+
+            let companions: Zeroizing<Vec<Vec<u8>>> = Zeroizing::new( shares.iter().take(threshold.saturating_sub(1) as usize).map(Vec::from).collect() );
+
+            // Attempt to recover key from shares
+            let recovered_key = recover_key(&mut shares, threshold)?;
+            check_share_macs(&recovered_key, &share_macs, strict)?;
+
+            report!("[-] Derived {} new share(s) from the current ones | threshold {}", new_shares.len(), threshold );
+
+            // every new share has to recover the key alongside the old ones, or it's no use to whoever gets it
+            for new_share in new_shares.iter() {
+                let mut combined: Zeroizing<Vec<Vec<u8>>> = companions.clone();
+                combined.push( new_share.clone() );
+
+                recover_own_shares(&combined, threshold, recovered_key.as_slice())?;
+            }
+
+            // Save new shares to folder
+            nl();
+
+            let (salt, wrapping_key) = new_wrapping_key(passphrase)?;
+
+            // new shares keep the file's algorithm version, so they match its nonce (and MACs, if the old ones had them)
+            let keep_mac = !share_macs.is_empty();
+            let share_header: Vec<u8> = construct_header_share(file_header.algo_version, threshold, is_signed, false, nonce, None, salt.as_deref(), keep_mac, None);
+
+            write_shares(&new_shares, &new_share_paths, &share_header, ed25519_keypair.as_ref(), wrapping_key.as_ref().map(|key| key.as_slice()), keep_mac.then_some(recovered_key.as_slice()), &ShareOutput::Files { qr: false, threads: None, armor: false }, mode )?;
+
+            if let Some(keypair) = ed25519_keypair.as_mut() {
+                keypair.secret.zeroize(); // done with the signing key
+            }
+
+            nl();
+            report!("[#] Any {} shares, old or new, can now recover the key -- the file itself is unchanged", threshold );
+
+            // Done!
+            nl();
+            report_event(
+                "[*] Shares added! Have a nice day.",
+                serde_json::json!({ "event": "complete", "command": "add-shares", "output": stringify_path(&new_share_dir) })
+            );
+        },
+
+        Commands::Recombine { ref file, all, share_dir, strict } => { // Key recovery only
+            report!("[*] Chose to recover a file's key...");
+            nl();
+
+            let paths = get_paths(share_dir, file.to_owned() )?;
+            let target_file = &paths[0];
+            let shares_dir = &paths[1];
+
+            report!("[+] Shares directory: {}", stringify_path(shares_dir) );
+
+            nl();
+
+            let (mut threshold, is_signed, pub_key, _, file_header) = process_target_file(target_file, strict)?;
+            let nonce = &file_header.nonce;
+
+            report!("[+] Target file is encrypted; algorithm version {}", file_header.algo_version );
+
+            nl();
+            report_shares_needed(threshold, file_header.schemes, "recover the key");
+            report!("[+] Target file nonce: {}", hex::encode(nonce) );
+
+            nl();
+
+            // Gather shares
+            let mut share_macs: Vec<GatheredMac> = vec![];
+            let mut shares: Vec<Share> = gather_shares(&paths[1..], nonce, &mut threshold, file_header.schemes, is_signed, file_header.flags & FLAG_SHARES_UNSIGNED != 0, pub_key, &GatherOptions { all, strict, threshold: None, threshold_from_shares: false, stdin: false, bundle: None, max_scanned: None, read_retries: NO_READ_RETRIES, exclude: vec![], list_shares: false, verify_all_signatures: false }, &mut share_macs)?;
+
+            nl();
+
+            // Attempt to recover key from shares (the file's contents are never touched)
+            let recovered_key = recover_key(&mut shares, threshold)?;
+            check_share_macs(&recovered_key, &share_macs, strict)?;
+            let hex_key = Zeroizing::new( hex::encode(recovered_key.as_slice()) );
+
+            nl();
+            report!("[%] Recovered key (anyone with this can decrypt the file -- keep it secret!):");
+            output_event(hex_key.as_str(), serde_json::json!({ "event": "key_recovered", "key": hex_key.as_str() }) );
+        },
 
-    if file[0..HEADER_FILE.len()].to_vec() != HEADER_FILE { // file is missing header
-        return Err( Error::new( ErrorKind::Other, "File not encrypted (CCM header missing)" ) )
-    }
+        Commands::Split { ref secret_file, players, threshold, share_dir, mode } => { // Shares of a secret, without a file
+            report!("[*] Chose to split a secret...");
+            nl();
 
-    let file_is_signed_u8 = file[HEADER_IS_SIGNED_BYTE_FILE - 1]; // is the file signed?
+            check_players_threshold(players, threshold)?;
 
-    if file_is_signed_u8 != 0{
-        if file.len() < (HEADER_LENGTH_FILE + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH) { // File claims to be signed but is too small 
-            return Err( Error::new( ErrorKind::Other, "File not encrypted (smaller than signed CCM header)" ) )
-        }
-    }
+            let secret: Zeroizing<Vec<u8>> = match fs::read(secret_file) {
+                Ok(secret) => Zeroizing::new(secret),
+                Err(error) => return Err( Error::failed(error, format!("Could not read secret file {}", secret_file.display())) ),
+            };
 
-    // Returns full header if successful
-    if file[HEADER_IS_SIGNED_BYTE_FILE] != 0 { // signed header
-        Ok( file[0..HEADER_LENGTH_FILE + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH].to_vec() ) 
-    }
-    else { // unsigned header
-        Ok( file[0..HEADER_LENGTH_FILE].to_vec() ) 
-    }
-}
+            // its length goes in a byte of the share header
+            if secret.is_empty() || secret.len() > SECRET_MAX_LENGTH_BYTES {
+                return Err( Error::refused(format!("Secret is {} bytes; it has to be 1-{} (encrypt anything bigger, and its key is split instead)", secret.len(), SECRET_MAX_LENGTH_BYTES)) );
+            }
 
-fn read_file(filepath: &Path) -> Vec<u8> { // Raw function for reading files
-    let mut contents = vec![];
-    let open = fs::File::open(&filepath);
+            let shares_dir = match share_dir {
+                Some(share_dir) => share_dir,
+                None => working_dir()?,
+            };
 
-    let mut open = match open { // handle file open
-        Ok(file) => file,
-        Err(error) => { // error out
-            fatal_error(&error, format!("Could not open file {}", filepath.display()) );
-            panic!("");
-        }
-    };
+            report!("[+] Secret file: {} ({} bytes)", stringify_path(secret_file), secret.len() );
+            report!("[+] Storing shares at {}", stringify_path(&shares_dir) );
+            ensure_share_dir(&shares_dir, false, false)?;
 
-    let read_result = open.read_to_end(&mut contents);
+            // there's no file for the nonce to belong to, so it's only there to tell which shares go together
+            let mut nonce = vec![0u8; NONCE_LENGTH_BYTES];
+            fill_random(&mut nonce);
+            let hex_nonce = hex::encode(&nonce);
 
-    match read_result { // handle file read
-        Ok(_res) => (),
-        Err(error) => { // error out
-            fatal_error(&error, format!("Could not read file {}", filepath.display()) );
-            panic!("");
-        }
-    };
+            nl();
 
-    contents
-}
+            let shares = split_key_into_shares(secret.as_slice(), players, threshold);
+            report!("[-] Derived {} share(s) from secret | threshold {}", shares.len(), threshold );
 
-fn write_file<'a>(filepath: &'a Path, contents: &Vec<u8>) -> &'a Path { // Raw function for writing out files
-    let file = fs::File::create(&filepath);
+            recover_own_shares(&shares[..threshold as usize], threshold, secret.as_slice())?;
 
-    let mut file = match file { // handle file creation
-        Ok(res) => res,
-        Err(error) => { // error out
-            fatal_error(&error, format!("Could not create file {}", filepath.display()) );
-            panic!("");
-        }
-    };
+            nl();
 
-    let write_result = file.write_all(&contents);
-    
-    match write_result { // handle file write
-        Ok(_res) => (),
-        Err(error) => { // error out
-            fatal_error(&error, format!("Could not write file {}", filepath.display()) );
-            panic!("");
-        }
-    };
+            let share_paths = share_paths(&shares_dir, DEFAULT_SHARE_NAME_TEMPLATE, players as usize, threshold, &hex_nonce)?;
+            let share_header: Vec<u8> = construct_header_share(ALGO_VERSION, threshold, false, false, &nonce, None, None, false, Some(secret.len()));
 
-    filepath
-}
+            write_shares(&shares, &share_paths, &share_header, None, None, None, &ShareOutput::Files { qr: false, threads: None, armor: false }, mode )?;
 
-fn chacha_encrypt(u8_key: Vec<u8>, u8_nonce: Vec<u8>, plaintext: &[u8] ) -> Vec<u8> { // encrypt plaintext with chacha20
-    let key = Key::from_slice(&u8_key);
-    let cc20 = ChaCha20Poly1305::new(key);
+            nl();
+            report!("[#] Any {} of the shares recover the secret with `combine`; fewer say nothing about it", threshold );
 
-    let nonce = Nonce::from_slice(&u8_nonce);
+            // Done!
+            nl();
+            report_event(
+                "[*] Secret split! Have a nice day.",
+                serde_json::json!({ "event": "complete", "command": "split", "output": stringify_path(&shares_dir) })
+            );
+        },
 
-    let ciphertext = cc20.encrypt(nonce, plaintext)
-        .expect("Failure when encrypting file");
-    
-    // Decrypt the ciphertext to ensure that it works
-    let chk_plaintext = chacha_decrypt(u8_key, u8_nonce, ciphertext.as_ref()).unwrap();
+        Commands::Combine { ref share_dir, all, output, force } => { // Secret recovery, from `split`'s shares
+            report!("[*] Chose to recover a secret...");
+            nl();
 
-    if &plaintext == &chk_plaintext { // if everything is good
-        ciphertext
-    } else { // oh noes
-        panic!("[!] Critical error in encryption process - decrypted ciphertext does not match plaintext!");
-    }
-}
+            report!("[+] Shares directory: {}", stringify_path(share_dir) );
 
-fn chacha_decrypt(u8_key: Vec<u8>, u8_nonce: Vec<u8>, ciphertext: &[u8] ) -> Result<Vec<u8>> { // decrypt ciphertext with chacha20
-    let key = Key::from_slice(&u8_key);
-    let cc20 = ChaCha20Poly1305::new(key);
+            // the shares have to say which secret they're of, as there's no file to
+            let (nonce, mut threshold) = match &secrets_in_share_dir(share_dir, all)?[..] {
+                [] => {
+                    return Err( Error::no_shares(format!("No shares of a split secret in {}", stringify_path(share_dir))) );
+                },
+                [secret] => secret.clone(),
+                secrets => {
+                    nl();
+                    report!("[!] {} holds shares of {} different secrets:", stringify_path(share_dir), secrets.len() );
 
-    let nonce = Nonce::from_slice(&u8_nonce);
-    
-    // Decrypt the ciphertext
-    let plaintext = match cc20.decrypt(nonce, ciphertext) {
-        Ok(plain) => Ok(plain),
-        Err(_error) => { // aead doesn't use a normal Error to avoid side-channel leaks
-            Err( Error::new( ErrorKind::Other, "[reason obfuscated]" ) )
-        } 
-    };
+                    for (nonce, _) in secrets {
+                        report!("[!]   {}", hex::encode(nonce) );
+                    }
 
-    plaintext
-}
+                    return Err( Error::refused("Put each one's shares in a directory of its own".to_string()) );
+                },
+            };
 
-fn construct_header_share(threshold: u8, is_signed: bool, nonce: &Vec<u8> ) -> Vec<u8> { // Construct a share header
-    let mut share_header: Vec<u8> = HEADER_SHARE.to_vec(); 
-    // algorithm version
-    share_header.push(ALGO_VERSION);
-    // threshold
-    share_header.push(threshold);
-    // is signed?
-    if is_signed {
-        share_header.push(1);
-    }
-    else {
-        share_header.push(0);
-    }
+            nl();
+            report_shares_needed(threshold, None, "recover the secret");
+            report!("[+] Secret ID: {}", hex::encode(&nonce) );
 
-    // nonce
-    share_header.extend(nonce);
+            nl();
 
-    // padding
-    share_header.push(0);
+            // Gather shares (never signed, so there's nothing to check them against)
+            let mut shares: Vec<Share> = gather_shares(slice::from_ref(share_dir), &nonce, &mut threshold, None, false, false, None, &GatherOptions { all, strict: false, threshold: None, threshold_from_shares: false, stdin: false, bundle: None, max_scanned: None, read_retries: NO_READ_RETRIES, exclude: vec![], list_shares: false, verify_all_signatures: false }, &mut vec![])?;
 
-    return share_header
-}
+            nl();
 
-fn share_signature_verification( // verification of share signatures
-    is_signed: bool, // whether the FILE is signed
-    pub_key: Option<PublicKey>, // the FILE'S public key
-    //signature: Option<Signature>, // the FILE'S signature
-    shf: &ShareFromFile, // the share retrieved from a file
-    path: &Path, // share path
+            let secret = recover_from_shares(&mut shares, threshold, "secret", recover_secret_from_shares)?;
 
-    strict: bool ){ // verifies signatures between a file and a share
+            nl();
 
-    let share_pub_key = match shf.pub_key { // Check if share has public key
-        Some(pk) => pk,
-        None => { // Share is missing a public key
-            enl();
-            eprintln!("[#] Signing mismatch from share {}", &path.display());
-            eprintln!("[#] Share is missing a public key,");
-            eprintln!("[#] its integrity cannot be verified.");
+            match output {
+                Some(output) => {
+                    guard_overwrite(&output, force, None)?;
 
-            die_on_strict(strict);
-            ask_to_continue();
-            return
-        }
-    };
+                    if let Err(error) = write_file(&output, &secret, Some(0o600)) {
+                        return Err( Error::failed(error, format!("Could not write secret to {}", output.display())) );
+                    }
 
-    let share_signature = match shf.signature { // Check if share has signature (it really should if we're in this function)
-        Some(pk) => pk,
-        None => { // Share is missing a signature
-            enl();
-            eprintln!("[#] Signing mismatch from share {}", &path.display());
-            eprintln!("[#] Share is missing a signature,");
-            eprintln!("[#] its integrity cannot be verified.");
-
-            die_on_strict(strict);
-            ask_to_continue();
-            return
-        }
-    };
+                    report_event(
+                        &format!("[&] Secret written to {}", stringify_path(&output)),
+                        serde_json::json!({ "event": "file_written", "kind": "secret", "path": stringify_path(&output) })
+                    );
+                },
+                None => { // byte for byte, so it round-trips through a redirect
+                    report!("[%] Recovered secret (keep it secret!):");
 
-    if !is_signed && shf.is_signed { // file itself is not signed?
-        enl();
-        eprintln!("[#] Signing mismatch from share {}", &path.display());
-        eprintln!("[#] Encrypted file is not signed,");
-        eprintln!("[#] but this share believes it should be.");
+                    let mut stdout = io::stdout().lock();
 
-        die_on_strict(strict);
-        ask_to_continue();
-    }
+                    if let Err(error) = stdout.write_all(&secret).and_then(|_| stdout.flush() ) {
+                        return Err( Error::failed(error, "Could not write secret to stdout".to_string()) );
+                    }
+                },
+            }
+        },
 
-    else { // Check signature public key against file's public key, if we have both
-        let file_pub_key = pub_key.unwrap(); // Unwrap file's public key *now* (we'd panic if we did it earlier)
-        //let signature = signature.unwrap();
+        Commands::Keygen { ref key_file, print_pubkey: true } => { // Public key of a keypair we already have
+            let pub_key = load_keypair(key_file)?.public;
+            let hex_pub_key = hex::encode( pub_key.to_bytes() );
+            let fingerprint = pubkey_fingerprint( pub_key.as_bytes() );
 
-        if share_pub_key.to_bytes() != file_pub_key.to_bytes() { // share and file use differing public keys 
-            enl();
-            eprintln!("[#] Signing mismatch from share {}", &path.display());
-            eprintln!("[#] File and share do not use the same public key!");
-            enl();
-            eprintln!("[#] File public key:  {}", hex::encode( file_pub_key.to_bytes() ) );
-            eprintln!("[#] Share public key: {}", hex::encode( share_pub_key.to_bytes() ) );
-    
-            die_on_strict(strict);
-            ask_to_continue();
-        }
+            output_event(
+                &format!("[+] Public key: {}\n[+] Fingerprint: {}", hex_pub_key, fingerprint),
+                serde_json::json!({ "event": "public_key", "path": stringify_path(key_file), "public_key": hex_pub_key, "public_key_fingerprint": fingerprint })
+            );
+        },
 
-    }
+        Commands::Keygen { ref key_file, print_pubkey: false } => { // Generate a keypair to sign with
+            let pub_key_file = pub_key_path(key_file);
 
-    if shf.is_signed { // Verify a share's signature
-        // Reconstruct the conditions for the original share's signing
-        let mut reconstructed_share = construct_header_share(shf.threshold, shf.is_signed, &shf.nonce);
+            // checked first, so we never leave a secret key behind without its public key
+            for path in [key_file, &pub_key_file] {
+                if path.exists() {
+                    return Err( Error::refused(format!("{} already exists", path.display())) );
+                }
+            }
 
-        reconstructed_share.extend( share_pub_key.to_bytes() );
-        reconstructed_share.extend(Vec::from(&shf.share_data) );
+            let ed25519_keypair: Keypair = generate_keypair();
 
-        let share_verification = share_pub_key.verify(&reconstructed_share, &share_signature);
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true); // never clobber an existing key
 
-        let _share_verification = match share_verification {
-            Ok(v) => v,
-            Err(error) => { // Share verification failed. Uh oh spaghetti-os
-                let mut file_pub_key = String::from("[ABSENT]");
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600); // only we get to read the secret key
+            }
 
-                if is_signed { // give file public key in error msg
-                    file_pub_key = hex::encode( pub_key.unwrap().to_bytes() );
-                }
+            let write_result = options.open(key_file)
+                .and_then(|mut open| writeln!(open, "{}", keypair_to_hex(&ed25519_keypair).as_str()) );
 
-                enl();
-                eprintln!("[#] Signing mismatch from share {}", &path.display());
-                eprintln!("[#] Share verification from public key failed!");
-                enl();
-                eprintln!("[#] File public key:  {}", &file_pub_key );
-                eprintln!("[#] Share public key: {}", hex::encode( share_pub_key.to_bytes() ) );
-                enl();
-                eprintln!("[#] -----------------------------------------------------" );
-                eprintln!("[#] WARNING: THIS SHARE MAY BE CORRUPTED OR TAMPERED WITH" );
-                eprintln!("[#]    FILE RECOVERY IS UNLIKELY WHEN USING THIS SHARE   " );
-                eprintln!("[#]   ANY EXISTING FILE MAY BE OVERWRITTEN WITH GARBAGE  " );
-                eprintln!("[#] -----------------------------------------------------" );
-                enl();
-                eprintln!("[#] More information:" );
-                eprintln!("[#] {}", error.to_string() );
-                
-                die_on_strict(strict);
-                ask_to_continue();
+            if let Err(error) = write_result {
+                return Err( Error::failed(error, format!("Could not write key file {}", key_file.display())) );
             }
-        };
-    }
-}
 
-fn logo(){ // prints CCM logo
-    //   ___  _  _   __    ___  _  _   __   _  _  __  ____ 
-    //  / __)/ )( \ / _\  / __)/ )( \ / _\ ( \/ )(  )(  _ \
-    // ( (__ ) __ (/    \( (__ ) __ (/    \/ \/ \ )(  )   /
-    //  \___)\_)(_/\_/\_/ \___)\_)(_/\_/\_/\_)(_/(__)(__\_)
+            let hex_pub_key = hex::encode(ed25519_keypair.public.to_bytes());
 
-    println!("  ___  _  _   __    ___  _  _   __   _  _  __  ____ ");
-    println!(" / __)/ )( \\ / _\\  / __)/ )( \\ / _\\ ( \\/ )(  )(  _ \\");
-    println!("( (__ ) __ (/    \\( (__ ) __ (/    \\/ \\/ \\ )(  )   /");
-    println!(" \\___)\\_)(_/\\_/\\_/ \\___)\\_)(_/\\_/\\_/\\_)(_/(__)(__\\_)");
-    println!("----");
-    println!("version {}", VERSION);
-    nl();
-}
+            // the public key is for handing out, so it gets the usual permissions
+            let write_result = fs::OpenOptions::new().write(true).create_new(true).open(&pub_key_file)
+                .and_then(|mut open| writeln!(open, "{}", hex_pub_key) );
 
-fn die_on_strict(is_strict: bool){ // Exit the program if a validation issue occurs with shares or files
-    if is_strict {
-        enl();
-        eprintln!("[!] Will not decrypt using tampered data in strict mode!");
-        eprintln!("[!] Aborting");
-        process::exit(1);
-    }
-}
+            if let Err(error) = write_result {
+                return Err( Error::failed(error, format!("Could not write public key file {}", pub_key_file.display())) );
+            }
 
-fn ask_to_continue(){ // Ask the user to confirm they wish to proceed (used for strict-killing errors in non-strict mode)
-    eprintln!("");
-    eprintln!("[#] Are you certain you wish to continue?");
-    eprintln!("[#] (Ctrl+C to abort; Enter to continue)");
+            report!("[&] Keypair written to {}", stringify_path(key_file) );
+            report!("[&] Public key written to {}", stringify_path(&pub_key_file) );
+            let fingerprint = pubkey_fingerprint( ed25519_keypair.public.as_bytes() );
+            output_event(
+                &format!("[+] Public key: {}\n[+] Fingerprint: {}", hex_pub_key, fingerprint),
+                serde_json::json!({ "event": "keypair_written", "path": stringify_path(key_file), "public_key_path": stringify_path(&pub_key_file), "public_key": hex_pub_key, "public_key_fingerprint": fingerprint })
+            );
+            nl();
+            report!("[#] Keep this file secret -- anyone with it can sign files as you");
+        },
 
-    // Wait for user confirmation
-    let mut confirm = String::new();
-    io::stdin().read_line(&mut confirm).expect("[!] Critical error with input");
-}
+        Commands::Doctor {} => { // Self-test
+            report!("[*] Checking the crypto in this build...");
+            nl();
 
-/*----------+
-|   main    |
------------*/
+            let failed = run_doctor();
 
-fn main() {
-    let args = Arguments::parse();
-    logo(); // print logo
+            nl();
+
+            if failed > 0 {
+                return Err( Error::refused(format!("{} of {} check(s) failed -- don't trust this build with real data", failed, DOCTOR_CHECKS.len())) );
+            }
+
+            report_event(
+                &format!("[*] All {} checks passed", DOCTOR_CHECKS.len()),
+                serde_json::json!({ "event": "complete", "command": "doctor" })
+            );
+        },
 
-    match args.command { // which command are we running?
-        
         Commands::Licenses {} => { // Print license info
             let ccm_license = include_str!("../LICENSE");
             let licenses = include_str!("../COPYING.md");
@@ -617,488 +6034,433 @@ fn main() {
             //nl();
         },
 
-        Commands::Encrypt { ref file, players, threshold, share_dir, sign } => { // Encryption
-            println!("[*] Chose to encrypt a file...");
+        Commands::Encrypt { ref file, players, threshold, named_players: _, named_threshold: _, share_dir, sign, sign_file, sign_shares, no_sign: _, passphrase, key_file, detached_sig, output_format, force, compress, compress_level, metadata, checksum, label, aad, share_mac, recursive, archive, with, input_list, shares_stdout, qr, fast, verify_subsets, time, dry_run, chunk_size, algo, share_name_template, scheme, share_bundle, mode, threads, armor, exclude, since, output } => { // Encryption
+            report!("[*] Chose to encrypt a file...");
             nl();
 
-            // Take ownership of args
-            let players = players.to_owned();
-            let threshold = threshold.to_owned();
-
-            // Checking against bad things
-            if players < threshold {
-                println!("[!] Share threshold exceeds maximum number of players. File would be unrecoverable!");
-                process::exit(1);
-            } else if players < 1 {
-                println!("[!] Number of shares cannot be zero");
-                process::exit(1);
-            } else if threshold < 1 {
-                println!("[!] Threshold of shares cannot be zero");
-                process::exit(1);
-            }
-
-            let paths = get_paths(share_dir, file.to_owned() );
-            let target_file = &paths[0];
-            let shares_dir = &paths[1];
-
-            // print share dir being used
-            println!("[+] Storing shares at {}", stringify_path(&shares_dir) );
+            // shares written before Ctrl+C are no use without the encrypted file, and only mislead whoever finds them
+            if let Err(error) = ctrlc::set_handler(clean_up_interrupted) {
+                report!("[#] Could not set up Ctrl+C handling, so an interrupted encryption may leave shares behind | {}", error );
+            }
 
-            // Generate 256-bit key
-            let mut key = [0u8; KEY_LENGTH_BYTES];
-            OsRng.fill_bytes(&mut key);
-            println!("[-] Key generated");
+            // Take ownership of args (the config file has had its say by now)
+            let players = required_count(players, "PLAYERS (or --players)")?;
+            let threshold = required_count(threshold, "THRESHOLD (or --threshold)")?;
 
-            // Generate 86-bit nonce (also used to ID files)
-            let mut nonce = [0u8; NONCE_LENGTH_BYTES];
-            OsRng.fill_bytes(&mut nonce);
-            println!("[-] Nonce generated");
+            // --sign is both halves at once
+            let sign_file = sign || sign_file;
+            let sign_shares = sign || sign_shares;
 
-            let hex_nonce = hex::encode(nonce); // hex representation of the nonce
+            // checked here rather than by clap, as signing may have been turned on by the config file
+            if !sign_file && !sign_shares && key_file.is_some() {
+                return Err( Error::refused("--key-file needs --sign, --sign-file or --sign-shares (or sign = true in the config file)".to_string()) );
+            }
 
-            // Creating a keypair doesn't cause that much overhead (benchmarked in the millisecond range)
-            let mut ed25519_rng = OsRng{};
-            let ed25519_keypair: Keypair = Keypair::generate( &mut ed25519_rng );
-            let ed25519_bytes_pub: [u8; PUBLIC_KEY_LENGTH] = ed25519_keypair.public.to_bytes();
+            if !sign_file && detached_sig {
+                return Err( Error::refused("--detached-sig needs --sign or --sign-file (or sign = true in the config file)".to_string()) );
+            }
 
-            // Split into shares of the secret
-            let sss = Sharks(threshold); // init sharks and set threshold
-            let dealer = sss.dealer(&key);
+            // every scheme's shares are dealt from the same key; the positional players and threshold are the first
+            let mut schemes: Vec<(u8, u8)> = vec![(players, threshold)];
+            schemes.extend(scheme);
 
-            // push all the generated shares into a 2d vector
-            let mut shares: Vec<Vec<u8>> = Vec::new();
+            // Checking against bad things
+            for &(players, threshold) in &schemes {
+                check_players_threshold(players, threshold)?;
+            }
 
-            for s in dealer.take(<usize as From<u8>>::from(players) ) {
-                shares.push(Vec::from(&s) );
-            };
+            if schemes.len() > u8::MAX as usize { // the header counts them in a single byte
+                return Err( Error::refused(format!("Too many share schemes ({}; at most {})", schemes.len(), u8::MAX)) );
+            }
 
-            println!("[-] Derived {} share(s) from key | threshold {}", &shares.len(), &threshold);
+            if !exclude.is_empty() && !recursive && !archive { // checked here rather than by clap, as it'll do for either
+                return Err( Error::refused("--exclude needs --recursive or --archive".to_string()) );
+            }
 
-            // Recover the shares again for good measure
-            let recovered_shares: Vec<Share> = shares.iter().map(|s| Share::try_from(s.as_slice()).unwrap()).collect();
-            let recovered_key = sss.recover(&recovered_shares).unwrap(); // REMINDER: this is a Result, handle this later
-            
-            if recovered_key != key { // handle unrecoverable shares (should never happen?)
-                panic!("[!] Unable to recover the key from our shares?!");
+            if !zstd::compression_level_range().contains(&compress_level) {
+                return Err( Error::refused(format!("Compression level must be between {} and {}", 1, zstd::compression_level_range().end())) );
             }
 
-            println!("[-] Share recovery succeeded");
+            // no share directory needed when shares go to stdout (or a bundle), so don't ask for one
+            let share_dir = match shares_stdout || share_bundle.is_some() {
+                true => Some( working_dir()? ),
+                false => share_dir,
+            };
 
-            // read plaintext file to make sure we aren't saving useless shares if this fails
-            let file_plaintext: Vec<u8> = read_file(&target_file);
+            // with --input-list, the list stands in for the file
+            let paths = get_paths(share_dir, input_list.clone().or_else(|| file.clone() ).unwrap_or_default() )?;
+            let target_file = &paths[0];
+            let shares_dir = &paths[1];
 
-            // Save shares to folder
-            nl();
+            let share_output = match (shares_stdout, share_bundle) {
+                (true, _) => ShareOutput::Stdout,
+                (false, Some(share_bundle)) => ShareOutput::Bundle(share_bundle),
+                (false, None) => ShareOutput::Files { qr, threads, armor },
+            };
 
-            // --- Construct share header
-            let share_header: Vec<u8> = construct_header_share(threshold, sign, &Vec::from(nonce));
+            // print share dir being used
+            match &share_output {
+                ShareOutput::Stdout => report!("[+] Printing shares to stdout"),
+                ShareOutput::Bundle(share_bundle) => report!("[+] Bundling shares into {}", stringify_path(share_bundle) ),
+                _ => report!("[+] Storing shares at {}", stringify_path(shares_dir) ),
+            }
 
-            let mut share_i: i32 = 1;
+            if let ShareOutput::Files { .. } = share_output {
+                ensure_share_dir(shares_dir, force, dry_run)?;
+            }
 
-            for s in shares { // iterate through shares
-                println!("[&] Writing share # {}...", share_i);
-                // we do not include the share number or totals as that is encoded within the share data itself,
-                // so just push the universal header and the share data
+            // the whole directory (and anything else given) goes into the one file
+            let archive: Option<Vec<ArchiveEntry>> = match archive {
+                true => Some( archive_entries(&[slice::from_ref(target_file), &with].concat(), &exclude)? ),
+                false => None,
+            };
 
-                let mut this_share_path = PathBuf::from(&shares_dir);
+            // --- Derive the key shares are wrapped under (if passphrase protected) -- once, even for a whole directory
+            let (salt, wrapping_key) = new_wrapping_key(passphrase)?;
+
+            let options = EncryptOptions {
+                schemes,
+                sign_file,
+                sign_shares,
+                key_file,
+                detached_sig,
+                output_format,
+                archive,
+                force,
+                compression_level: compress.then_some(compress_level),
+                metadata,
+                checksum,
+                label,
+                aad,
+                share_mac,
+                share_output,
+                share_mode: mode,
+                fast,
+                verify_subsets,
+                time,
+                chunk_size,
+                cipher: algo,
+                share_name_template,
+                dry_run,
+                salt,
+                wrapping_key,
+            };
 
-                let share_filename: String = share_i.to_string() 
-                + "-" 
-                + &hex_nonce;
+            let output_path = if input_list.is_some() { // likewise, for the files in the list
+                encrypt_listed_files(target_file, shares_dir, &options)?;
 
-                this_share_path.push(share_filename);
-                this_share_path.set_extension("ccms");
+                shares_dir.to_path_buf()
+            }
+            else if recursive { // every file gets its own key, nonce and shares
+                if !target_file.is_dir() {
+                    return Err( Error::refused(format!("{} is not a directory", target_file.display())) );
+                }
 
-                let mut share_full: Vec<u8> = share_header.iter().cloned().collect();
+                let files: Vec<PathBuf> = files_in_tree(target_file, &exclude).into_iter()
+                    .filter(|path| !has_extension(path, "ccm") && !is_share_path(path) && !is_detached_sig(path) && !is_header_sidecar(path) ) // don't encrypt our own output
+                    .collect();
 
-                if sign { // are we signing shares?
-                    share_full.extend(&ed25519_bytes_pub);
+                if files.is_empty() {
+                    return Err( Error::refused(format!("No files to encrypt in {}", target_file.display())) );
+                }
 
-                    // sign the contents of the header (incl public key) + share content
-                    
-                    let share_signable = &mut share_full.clone();
-                    share_signable.extend(&s);
-                    
-                    let share_ed25519_signature: Signature = ed25519_keypair.sign( &share_signable[..] );
-                    // then add it to the file in between the header and contents
+                let found = files.len();
 
-                    share_full.extend(&share_ed25519_signature.to_bytes() );
+                let files: Vec<PathBuf> = match since { // only what's changed since the last backup
+                    Some(since) => files.into_iter().filter(|path| modified_since(path, since) ).collect(),
+                    None => files,
+                };
 
-                    println!("[-] Signed share # {share_i}");
+                if let Some(since) = since {
+                    nl();
+                    report_event(
+                        &format!("[+] {} of {} file(s) modified since {}, skipping the other {}", files.len(), found, rfc3339_timestamp(since), found - files.len()),
+                        serde_json::json!({ "event": "since", "since": rfc3339_timestamp(since), "modified": files.len(), "skipped": found - files.len() })
+                    );
                 }
 
-                // write share content in
-                let share_full: Vec<u8> = share_full.iter().cloned().chain(s).collect();
-
-                write_file(&this_share_path, &share_full);
-                share_i += 1;
-            };
-            // Done with share stuff
-            nl();
-
-            // Encrypt file
-            let mut file_encrypted: Vec<u8> = chacha_encrypt(recovered_key, nonce.to_vec(), &file_plaintext);
+                for (file_i, path) in files.iter().enumerate() {
+                    nl();
+                    report!("[*] File {} of {}: {}", file_i + 1, files.len(), stringify_path(path) );
 
-            // --- Construct encrypted file for saving
+                    // shares go in the same place relative to the share directory as the file is to the target directory
+                    let file_shares_dir = mirrored_share_dir(target_file, path, shares_dir);
 
-            // header "CCM"
-            let mut enc_file: Vec<u8> = HEADER_FILE.to_vec(); 
+                    if !dry_run {
+                        if let Err(error) = fs::create_dir_all(&file_shares_dir) {
+                            return Err( Error::failed(error, format!("Could not create share directory {}", file_shares_dir.display())) );
+                        }
+                    }
 
-            // algorithm version
-            enc_file.push(ALGO_VERSION);
+                    nl();
+                    encrypt_file(path, &file_shares_dir, None, &options)?;
+                }
 
-            // threshold
-            enc_file.push(threshold);
+                nl();
+                match since {
+                    Some(_) => report!("[&] Encrypted {} file(s) in {}, skipped {} unmodified", files.len(), stringify_path(target_file), found - files.len() ),
+                    None => report!("[&] Encrypted {} file(s) in {}", files.len(), stringify_path(target_file) ),
+                }
 
-            // is signed?
-            if sign {
-                enc_file.push(1);
+                target_file.to_path_buf()
             }
             else {
-                enc_file.push(0);
-            }
-
-            // nonce
-            enc_file.extend(&nonce);
+                encrypt_file(target_file, shares_dir, output, &options)?
+            };
 
-            // ----- signatures ---------------------
+            // Done!
+            nl();
+            report_event(
+                match dry_run {
+                    true => "[*] Dry run complete, nothing was written. Have a nice day.",
+                    false => "[*] Encryption complete! Have a nice day.",
+                },
+                serde_json::json!({ "event": "complete", "command": "encrypt", "output": stringify_path(&output_path) })
+            );
+        },
 
-            if sign {
-                enc_file.extend( ed25519_bytes_pub );
+        Commands::Decrypt { ref file, all, share_dir, strict, expect_pubkey, verify_pubkey_fingerprint, list_shares, verify_all_signatures, min_shares, verify_only, threshold, threshold_from_shares, recursive, max_shares_scanned, read_retries, retry_backoff, shares_stdin, share_bundle, stdout, force, guess_extension, nonce, aad, exclude, output } => { // Decryption
+            report!("[*] Chose to decrypt a file...");
+            nl();
 
-                let mut enc_file_signable: Vec<u8> = enc_file.clone();
-                enc_file_signable.extend(&file_encrypted);
+            // Check the public key we're expecting makes sense before we go any further
+            let expect_pubkey: Option<Vec<u8>> = expect_pubkey.map(|expected| {
+                match hex::decode(expected.trim()) {
+                    Ok(bytes) if bytes.len() == PUBLIC_KEY_LENGTH => Ok(bytes),
+                    _ => Err( Error::refused(format!("Expected public key must be {} bytes of hex", PUBLIC_KEY_LENGTH)) ),
+                }
+            }).transpose()?;
 
-                let file_ed25519_signature: Signature = ed25519_keypair.sign( &enc_file_signable[..] );
+            // likewise the nonce, though how long it should be depends on the file's version
+            let nonce: Option<Vec<u8>> = nonce.map(|nonce| {
+                match hex::decode(nonce.trim()) {
+                    Ok(bytes) if bytes.len() == NONCE_LENGTH_BYTES || bytes.len() == NONCE_LENGTH_BYTES_V2 => Ok(bytes),
+                    _ => Err( Error::refused(format!("Nonce must be {} bytes of hex ({} for files before algorithm version 3)", NONCE_LENGTH_BYTES, NONCE_LENGTH_BYTES_V2)) ),
+                }
+            }).transpose()?;
 
-                // add signature
-                enc_file.extend(&file_ed25519_signature.to_bytes() );
-                println!("[-] Signed encrypted file");
+            // a pinned key means nothing if we'd carry on past a bad signature anyway
+            let strict = strict || expect_pubkey.is_some();
 
+            // stdin is taken up by shares, so there's nothing left to answer prompts with
+            if shares_stdin {
+                NON_INTERACTIVE.store(true, Ordering::Relaxed);
             }
 
-            // --------------------------------------
+            // and there's no share directory to ask about (nor with a bundle)
+            let first_share_dir = match shares_stdin || share_bundle.is_some() {
+                true => Some( working_dir()? ),
+                false => share_dir.first().cloned(),
+            };
 
-            // encrypted file contents
-            enc_file.append(&mut file_encrypted);
+            let paths = get_paths(first_share_dir, file.to_owned() )?;
+            let target_file = &paths[0];
 
-            // Save to file
-            let mut target_enc_file = PathBuf::from(&target_file);
+            // shares can be spread over several directories (e.g. one per custodian)
+            let mut shares_dirs: Vec<PathBuf> = vec![ paths[1].clone() ];
+            shares_dirs.extend( share_dir.iter().skip(1).cloned() );
 
-            match target_enc_file.extension() { // add .ccm extension
-                Some(ext) => {
-                    let mut ext = ext.to_os_string();
-                    ext.push(".ccm");
-                    target_enc_file.set_extension(ext)
-                }
-                None => target_enc_file.set_extension(".ccm"),
-            };
+            // print share dirs being used
+            match (shares_stdin, &share_bundle) {
+                (true, _) => report!("[+] Reading shares from stdin"),
+                (false, Some(share_bundle)) => report!("[+] Reading shares from bundle {}", stringify_path(share_bundle) ),
+                (false, None) => for shares_dir in &shares_dirs {
+                    report!("[+] Shares directory: {}", stringify_path(shares_dir) );
+                },
+            }
 
-            write_file(&target_enc_file, &enc_file);
-            println!("[&] Encrypted file written to {}", stringify_path(&target_enc_file) );
+            let options = DecryptOptions {
+                gather: GatherOptions { all, strict, threshold, threshold_from_shares, stdin: shares_stdin, bundle: share_bundle, max_scanned: max_shares_scanned, read_retries: ReadRetries { retries: read_retries, backoff: Duration::from_millis(retry_backoff) }, exclude: exclude.clone(), list_shares, verify_all_signatures },
+                expect_pubkey,
+                verify_fingerprint: verify_pubkey_fingerprint,
+                min_shares,
+                verify_only,
+                force,
+                to_stdout: stdout,
+                guess_extension,
+                nonce,
+                aad,
+            };
 
-            // Done!
-            nl();
-            println!("[*] Encryption complete! Have a nice day." );
-        },
+            let output_path = if recursive { // decrypt every .ccm file, using the shares in the matching part of the share directory
+                if !target_file.is_dir() {
+                    return Err( Error::refused(format!("{} is not a directory", target_file.display())) );
+                }
 
-        Commands::Decrypt { ref file, all, share_dir, strict } => { // Decryption
-            println!("[*] Chose to decrypt a file...");
-            nl();
+                let files: Vec<PathBuf> = files_in_tree(target_file, &exclude).into_iter()
+                    .filter(|path| has_extension(path, "ccm") )
+                    .collect();
 
-            let paths = get_paths(share_dir, file.to_owned() );
-            let target_file = &paths[0];
-            let shares_dir = &paths[1];
+                if files.is_empty() {
+                    return Err( Error::refused(format!("No encrypted files in {}", target_file.display())) );
+                }
 
-            // print share dir being used
-            println!("[+] Shares directory: {}", stringify_path(&shares_dir) );
+                let mut failures: Vec<(PathBuf, String)> = vec![];
 
-            nl();
+                for (file_i, path) in files.iter().enumerate() {
+                    nl();
+                    report!("[*] File {} of {}: {}", file_i + 1, files.len(), stringify_path(path) );
 
-            let (target_algo_version, mut threshold, is_signed, nonce, pub_key, signature, file_contents, file_header) = { // Process target file
-                let mut target_file: Vec<u8> = read_file(&target_file);
+                    let file_shares_dirs: Vec<PathBuf> = shares_dirs.iter().map(|shares_dir| mirrored_share_dir(target_file, path, shares_dir) ).collect();
 
-                let target_header = match is_encrypted(&target_file) { // exit if file is not encrypted
-                    Ok(head) => head, // extract header if it is
-                    Err(err) => { 
-                        println!("[!] Target file failed validation: {}", err.to_string() );
-                        process::exit(1);
+                    for file_shares_dir in &file_shares_dirs {
+                        report!("[+] Shares directory: {}", stringify_path(file_shares_dir) );
                     }
-                };
-
-                let file_algo_version: u8 = target_header[HEADER_FILE.len()]; // Algorithm version
-                let file_threshold: u8 = target_header[HEADER_FILE.len() + 1]; // Threshold
-                let file_nonce: Vec<u8> = (&target_header[HEADER_PRE_NONCE_BYTES_FILE..(HEADER_PRE_NONCE_BYTES_FILE + NONCE_LENGTH_BYTES)]).to_vec(); // Nonce
-
-                let file_is_signed_u8 = target_file[HEADER_IS_SIGNED_BYTE_FILE - 1]; // is the file signed?
-                let mut file_is_signed: bool = false;
 
-                let mut file_pubkey: Option<PublicKey> = None; // public key, if it exists
-                let mut file_signature: Option<Signature> = None; // signature, if it exists
-                
-                if file_is_signed_u8 != 0 { // Retrieve public key and signature from file
-                    file_is_signed = true;
-        
-                    let file_pubkey_res = PublicKey::from_bytes(
-                        &target_header[HEADER_LENGTH_FILE..(HEADER_LENGTH_FILE + PUBLIC_KEY_LENGTH)]
-                    );
-        
-                    file_pubkey = match file_pubkey_res {
-                        Ok(pk) => Some(pk),
+                    // one file that can't be decrypted (say, one not bound to the --aad given) shouldn't stop the rest
+                    match decrypt_file(path, &file_shares_dirs, None, &options) {
+                        Ok(_) => (),
+                        Err(error @ Error::Stopped { .. }) => return Err(error),
                         Err(error) => {
-                            eprintln!("[!] Target file has a bad public key" );
-                            eprintln!("[!] {}", error.to_string() );
-                            
-                            file_is_signed = false;
-                            
-                            die_on_strict(strict);
-                            ask_to_continue();
-
-                            None
-                        }
-                    };
-
-                    let file_signature_res = Signature::from_bytes(
-                        &target_header[(HEADER_LENGTH_FILE + PUBLIC_KEY_LENGTH)..(HEADER_LENGTH_FILE + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH)]
-                    );
+                            let reason = match error.parts() {
+                                (diagnosis, Some(cause)) => format!("{} ({})", diagnosis, cause),
+                                (diagnosis, None) => diagnosis.to_string(),
+                            };
+
+                            report!("[!] Skipping {} | {}", stringify_path(path), reason );
+                            failures.push( (path.to_path_buf(), reason) );
+                        },
+                    }
+                }
 
-                    file_signature = match file_signature_res {
-                        Ok(sig) => Some(sig),
-                        Err(error) => {
-                            eprintln!("[!] Target file has a bad signature" );
-                            eprintln!("[!] {}", error.to_string() );
+                let done = if verify_only { "Verified" } else { "Decrypted" };
 
-                            file_is_signed = false;
-                            
-                            die_on_strict(strict);
-                            ask_to_continue();
+                nl();
+                report_event(
+                    &format!("[&] {} {} of {} file(s) in {}", done, files.len() - failures.len(), files.len(), stringify_path(target_file)),
+                    serde_json::json!({
+                        "event": "batch_summary",
+                        "decrypted": files.len() - failures.len(),
+                        "failed": failures.iter().map(|(path, error)| serde_json::json!({ "path": stringify_path(path), "error": error }) ).collect::<Vec<_>>(),
+                    })
+                );
 
-                            None
-                        }
-                    };
+                if !failures.is_empty() {
+                    report!("[!] {} file(s) could not be {}:", failures.len(), done.to_lowercase() );
 
-                    if file_is_signed {
-                        println!("[+] Target file is signed" );
+                    for (path, error) in &failures {
+                        report!("[!]   {} | {}", stringify_path(path), error );
                     }
+
+                    return Err( Error::refused(format!("Not every file in {} could be {}", stringify_path(target_file), done.to_lowercase())) );
                 }
-        
-                let split_length = match file_is_signed { // change header length depending on if signed or unsigned
-                    false => HEADER_LENGTH_FILE,
-                    true => (HEADER_LENGTH_FILE + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH)
-                };
 
-                let file_contents: Vec<u8> = target_file.split_off(split_length); // Separate contents from header
+                (!verify_only).then(|| target_file.to_path_buf() )
+            }
+            else {
+                decrypt_file(target_file, &shares_dirs, output, &options)?
+            };
+
+            // Done!
+            nl();
 
-                (file_algo_version, file_threshold, file_is_signed, file_nonce, file_pubkey, file_signature, file_contents, target_header)
+            let line = match verify_only {
+                true => "[*] Verification complete! Have a nice day.",
+                false => "[*] Decryption complete! Have a nice day.",
             };
 
-            println!("[+] Target file is encrypted; algorithm version {}", target_algo_version.to_string() );
+            report_event(line, serde_json::json!({
+                "event": "complete",
+                "command": if verify_only { "verify" } else { "decrypt" },
+                "output": output_path.as_deref().map(stringify_path),
+            }) );
+        }
+    }
 
-            nl();
-            println!("[+] {} shares needed to decrypt", threshold.to_string() );
-            println!("[+] Target file nonce: {}", hex::encode(&nonce) );
+    Ok(())
+}
 
-            nl();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            // Gather shares
-            let mut shares: Vec<Share> = Vec::new();
-            let glob_pattern: String;
-            
-            let mut path_str = stringify_path(&shares_dir).to_owned();
+    struct Endless { // a reader that never runs out, but fails if asked for more than `limit` bytes in all
+        byte: u8,
+        read: usize,
+        limit: usize,
+    }
 
-            if path_str.chars().last().unwrap() != '/' && path_str.chars().last().unwrap() != '\\' {
-                path_str += "/" 
-            }
-            
-            if all { // If we've set to search all files
-                glob_pattern = path_str + "*"; 
-            }
-            else { // otherwise, only grab .ccms files
-                glob_pattern = path_str + "*.ccms"; 
-            } 
-
-            // horrible nesting incoming -- pulling shares from file and processing them
-            for file in glob(&glob_pattern).expect("[!] Failed to read share file directory. Is it invalid?") { // Push shares to vector
-                match file {
-                    Ok(path) => {
-                        let share_f = share_from_file(&path, &nonce);
-
-                        match share_f { // did the share grab fail?
-                            Ok(shf) => {
-                                println!("[%] Share retrieved from {}", &path.display());
-
-                                if shf.threshold != threshold { // threshold mismatch (either the file or share has been tampered with)
-                                    enl();
-                                    eprintln!("[#] Threshold mismatch from share {}", &path.display());
-                                    eprintln!("[#] File:  {}", threshold.to_string() );
-                                    eprintln!("[#] Share: {}", shf.threshold.to_string() );
-                                    enl();
-                                    eprintln!("[#] Would you like to continue?");
-                                    eprintln!("[#] If so, which threshold should we use?" );
-                                    eprintln!("[#] (Ctrl+C to abort; provide threshold to use instead; empty for file's threshold)");
-
-                                    // Wait for user confirmation
-                                    let mut confirm = String::new();
-                                    io::stdin().read_line(&mut confirm).expect("[!] Critical error with input");
-
-                                    let confirm: &str = strip_newline(&confirm[..]);
-
-                                    if confirm.is_empty() { // user gave no input
-                                        eprintln!("[#] Okay. Continuing...");
-                                    } else {
-                                        let confirm = confirm.parse::<u8>();
-
-                                        match confirm {
-                                            Ok(number) => {
-                                                threshold = number;
-                                                eprintln!("[#] Using threshold of {} -- this might fail!", threshold.to_string() );
-                                            },
-                                            Err(err) => {
-                                                eprintln!("[!] That's not a threshold number");
-                                                eprintln!("[!] {}", err.to_string() );
-                                                eprintln!("[!] Aborting...");
-                                                process::exit(1);
-                                            }
-                                        };
-
-                                    }
-                                }
-
-                                if is_signed || shf.is_signed { // share is signed, therefore more checks!
-                                    share_signature_verification(is_signed, pub_key, /*signature,*/ &shf, &path, strict);
-                                }
-
-                                shares.push(shf.share_data);
-                            },
-                            Err(err) => eprintln!("[^] Skipping {} | {}", &path.display(), &err.to_string() )
-                        }
-                    },
-                    Err(e) => {
-                        eprintln!("[^] Reading something in share directory failed | {}", &e.to_string() );
-                    },
-                }
+    impl Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            if self.read + buf.len() > self.limit {
+                return Err( io::Error::other(format!("read up to byte {}, past the limit of {}", self.read + buf.len(), self.limit)) )
             }
 
-            if shares.len() < 1 { // No shares to reconstruct the secret with
-                println!("");
-                println!("[!] Zero shares located");
-                println!("[!] Cannot decrypt file with zero shares!");
-                process::exit(1);
-            }
-
-            nl();
-
-            if is_signed { // Check file signature
-                let pub_key = pub_key.unwrap();
-                let signature = signature.unwrap();
-
-                // Reconstruct the conditions for the original file's signing
-                // header "CCM"
-                let mut reconstructed_file: Vec<u8> = HEADER_FILE.to_vec(); 
-                // algorithm version
-                reconstructed_file.push(target_algo_version);
-                // threshold
-                reconstructed_file.push(threshold);
-                // signing bit
-                // this is probably bad memory wise but works for now until I go for refactoring :/
-                reconstructed_file.push(file_header[HEADER_IS_SIGNED_BYTE_FILE - 1]);
-                // nonce
-                reconstructed_file.extend(&nonce);
-                // public key
-                reconstructed_file.extend( &pub_key.to_bytes() );
-                // contents
-                reconstructed_file.extend(&file_contents);
-
-                let file_verification = pub_key.verify(&reconstructed_file, &signature);
-
-                let _file_verification = match file_verification {
-                    Ok(v) => v,
-                    Err(error) => { // File verification failed. Uh oh spaghetti-os
-                        enl();
-                        eprintln!("[#] Signing mismatch with encrypted file!");
-                        eprintln!("[#] {}", &file.display());
-                        eprintln!("[#] Signature verification against file's public key failed!");
-                        enl();
-                        eprintln!("[#] File public key:  {}", hex::encode( pub_key.to_bytes() ) );
-                        enl();
-                        eprintln!("[#] -----------------------------------------------------" );
-                        eprintln!("[#] WARNING: THIS FILE MAY BE CORRUPTED OR TAMPERED WITH " );
-                        eprintln!("[#] -----------------------------------------------------" );
-                        enl();
-                        eprintln!("[#] More information:" );
-                        eprintln!("[#] {}", error.to_string() );
-
-                        die_on_strict(strict);
-                        ask_to_continue();
-                    }
-                };
-            }
+            buf.fill(self.byte);
+            self.read += buf.len();
+            Ok(buf.len())
+        }
+    }
 
-            // Attempt to recover key from shares
-            println!("[-] Attempting key recovery with {} share(s)...", &shares.len() );
+    #[test]
+    fn retrying_backs_off_twice_as_long_each_time() {
+        let read_retries = ReadRetries { retries: 5, backoff: Duration::from_millis(10) };
 
-            let sss = Sharks(threshold);
-            let recovered_key = match sss.recover(&shares) {
-                Ok(key) => {
-                    println!("[%] Recovery successful!");
-                    key
-                },
-                Err(sss_err) => {
-                    fatal_error( &Error::new(ErrorKind::Other, sss_err), "Could not recover the key from your shares!".to_string() );
-                    process::exit(1);
-                }
-            };
+        for failures in [0, 1, 3, 5, 6] {
+            let mut attempts = 0;
+            let mut waits = vec![];
 
-            nl();
-            println!("[-] Decrypting file...");
+            let result = retrying(Path::new("share.ccms"), read_retries, || {
+                attempts += 1;
 
-            // Decrypt file
-            let file_plaintext: Vec<u8> = match chacha_decrypt(recovered_key, nonce.to_vec(), &file_contents) {
-                Ok(plain) => plain,
-                Err(error) => {
-                    fatal_error(&error, "Failed to decrypt file!".to_string() );
-                    process::exit(1);
+                match attempts > failures {
+                    true => Ok(attempts),
+                    false => Err(io::Error::from(io::ErrorKind::TimedOut)),
                 }
-            };
+            }, |wait| waits.push(wait) );
 
-            nl();
+            let retries = failures.min(read_retries.retries);
+            assert_eq!(attempts, retries + 1);
+            assert_eq!(waits, (0..retries).map(|retry| read_retries.backoff * 2u32.pow(retry) ).collect::<Vec<_>>());
 
-            // Try to guess MIME type cuz why not
-            match infer::get(&file_plaintext){
-                Some(mimetype) => {
-                    println!("[-] File decrypted -- MIME type: {}", mimetype.mime_type() );
-                },
-                None => {
-                    println!("[-] File decrypted -- MIME type: unknown (text? binary?)");
-                }
-            };
+            match failures > read_retries.retries { // out of retries, it's the last error that counts
+                true => assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut),
+                false => assert_eq!(result.unwrap(), failures + 1),
+            }
+        }
+    }
 
-            nl();
+    #[test]
+    fn retrying_gives_up_at_once_on_errors_that_wont_go_away() {
+        let mut attempts = 0;
 
-            // Write out file
-            let mut decrypted_path = PathBuf::from(target_file);
+        let result: Result<()> = retrying(Path::new("share.ccms"), ReadRetries { retries: 5, backoff: Duration::from_millis(10) }, || {
+            attempts += 1;
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        }, |_| panic!("slept before retrying a missing file") );
 
-            let decrypted_path = match decrypted_path.extension() { // remove .ccm extension
-                Some(ext) => {
-                    if ext.to_str().unwrap() == "ccm" {
-                        decrypted_path.set_extension("");
-                        decrypted_path
-                    }
-                    else {
-                        decrypted_path
-                    }
-                },
-                None => decrypted_path,
-            };
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(attempts, 1);
+    }
 
-            write_file(Path::new(&decrypted_path), &file_plaintext);
-            println!("[&] Decrypted file written to {}", stringify_path( &PathBuf::from(&decrypted_path) ) );
+    #[test]
+    fn read_share_stops_after_the_magic_for_junk() {
+        let junk = Endless { byte: 0, read: 0, limit: HEADER_SHARE.len() };
+        assert!(matches!(read_share(junk), Ok(None)));
+    }
 
-            // Done!
-            nl();
-            println!("[*] Decryption complete! Have a nice day." );
-        }
+    #[test]
+    fn read_share_stops_just_past_the_largest_share() {
+        let oversized = HEADER_SHARE.chain( Endless { byte: 0, read: 0, limit: SHARE_MAX_LENGTH + SHARE_TRAILING_MAX_BYTES + 1 - HEADER_SHARE.len() } );
+        assert!(matches!(read_share(oversized), Ok(None)));
     }
 
-    process::exit(0);
-}
\ No newline at end of file
+    #[test]
+    fn recover_own_shares_refuses_corrupted_shares() {
+        let key = [0x42u8; KEY_LENGTH_BYTES];
+        let shares = split_key_into_shares(&key, 3, 2);
+        assert!(recover_own_shares(&shares, 2, &key).is_ok());
+
+        let mut flipped: Vec<Vec<u8>> = shares.to_vec();
+        *flipped[0].last_mut().unwrap() ^= 0xff;
+        assert!(recover_own_shares(&flipped, 2, &key).is_err());
+
+        let mut truncated: Vec<Vec<u8>> = shares.to_vec();
+        truncated[1].truncate(KEY_LENGTH_BYTES / 2);
+        assert!(recover_own_shares(&truncated, 2, &key).is_err());
+
+        let mut emptied: Vec<Vec<u8>> = shares.to_vec();
+        emptied[2].clear();
+        assert!(recover_own_shares(&emptied, 2, &key).is_err());
+    }
+}
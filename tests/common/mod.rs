@@ -0,0 +1,72 @@
+// Helpers shared by the integration tests -- everything drives the real binary, the same way a user would
+
+#![allow(dead_code)] // each test binary only uses some of these
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use assert_cmd::assert::Assert;
+use assert_cmd::Command;
+
+pub fn chachamir() -> Command { // the binary, never waiting on stdin (nor picking up a chachamir.toml from your config dir)
+    let mut cmd = Command::cargo_bin("chachamir").unwrap();
+    cmd.arg("--non-interactive").env("XDG_CONFIG_HOME", env!("CARGO_TARGET_TMPDIR"));
+    cmd
+}
+
+pub fn fixture(dir: &Path, name: &str, length: usize) -> PathBuf { // a file of (deterministic) junk to encrypt
+    let mut state: u32 = 0x9e37_79b9;
+    let contents: Vec<u8> = (0..length).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+    }).collect();
+
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+pub fn encrypted_path(file: &Path) -> PathBuf { // where encrypt puts {name}.ccm by default
+    let mut path = file.as_os_str().to_owned();
+    path.push(".ccm");
+    PathBuf::from(path)
+}
+
+pub fn encrypt(file: &Path, players: u8, threshold: u8, share_dir: &Path, extra: &[&str]) -> Assert {
+    fs::create_dir_all(share_dir).unwrap();
+
+    chachamir()
+        .arg("encrypt")
+        .arg(file)
+        .arg(players.to_string())
+        .arg(threshold.to_string())
+        .arg("--share-dir").arg(share_dir)
+        .args(extra)
+        .assert()
+}
+
+pub fn decrypt(file: &Path, share_dir: &Path, output: &Path, extra: &[&str]) -> Assert {
+    chachamir()
+        .arg("decrypt")
+        .arg(file)
+        .arg("--share-dir").arg(share_dir)
+        .arg("--output").arg(output)
+        .args(extra)
+        .assert()
+}
+
+pub fn share_files(share_dir: &Path) -> Vec<PathBuf> { // share files in a directory, in index order
+    let mut shares: Vec<PathBuf> = fs::read_dir(share_dir).unwrap()
+        .map(|entry| entry.unwrap().path() )
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ccms") )
+        .collect();
+
+    shares.sort();
+    shares
+}
+
+pub fn stderr(assert: &Assert) -> String {
+    String::from_utf8_lossy(&assert.get_output().stderr).into_owned()
+}
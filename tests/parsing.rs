@@ -0,0 +1,128 @@
+// Mangled headers, shares and bundles should be turned away with an error, never a panic
+// (a deterministic stand-in for the fuzz target in fuzz/, which needs nightly)
+
+use zeroize::Zeroizing;
+
+use chachamir::{construct_header_file, construct_header_share, construct_share, construct_share_bundle, split_key_into_shares, share_mac};
+use chachamir::{is_encrypted, parse_header_file, parse_header_share, parse_share, parse_share_bundle, parse_metadata, parse_detached_signature};
+use chachamir::{Cipher, ALGO_VERSION, KEY_LENGTH_BYTES, SALT_LENGTH_BYTES, STREAM_CHUNK_BYTES, FLAG_STREAMED, FLAG_COMPRESSED, FLAG_METADATA, HEADER_SHARE};
+
+fn parse_everything(data: &[u8]) {
+    let _ = is_encrypted(data);
+    let _ = parse_header_file(data);
+    let _ = parse_header_share(data);
+    let _ = parse_metadata(data);
+    let _ = parse_detached_signature(data);
+
+    let mut wrapping_key = |_: &[u8]| Ok( Zeroizing::new( vec![0u8; KEY_LENGTH_BYTES] ) );
+    let _ = parse_share(data.to_vec(), None, &mut wrapping_key);
+
+    if let Ok(shares) = parse_share_bundle(data) {
+        for share in shares {
+            let _ = parse_share(share.to_vec(), None, &mut wrapping_key);
+        }
+    }
+}
+
+fn mangle(sample: &[u8]) { // every truncation, and a handful of values in every position
+    for length in 0..=sample.len() {
+        parse_everything(&sample[..length]);
+    }
+
+    for position in 0..sample.len() {
+        for value in [0x00, 0x01, 0x07, 0x0c, 0x1f, 0x80, 0xff, sample[position] ^ 0xff] {
+            let mut mangled = sample.to_vec();
+            mangled[position] = value;
+            parse_everything(&mangled);
+        }
+    }
+}
+
+fn file_headers() -> Vec<Vec<u8>> { // one of each version, signed or not, with and without the optional bits
+    let mut headers = vec![];
+
+    for algo_version in 1..=ALGO_VERSION {
+        let nonce = vec![0x42u8; Cipher::for_version(algo_version).nonce_length()];
+        let flags = FLAG_STREAMED | FLAG_COMPRESSED | FLAG_METADATA;
+
+        for is_signed in [false, true] {
+            for (checksum, schemes, label, has_aad) in [(None, None, None, false), (Some(&[0x17u8; 32][..]), Some(2), Some("Q3 financials"), true)] {
+                let mut header = construct_header_file(algo_version, 2, is_signed, has_aad, flags, STREAM_CHUNK_BYTES, Cipher::for_version(algo_version), &nonce, checksum, schemes, label);
+
+                if is_signed { // parsing never checks they're a real key and signature, only that they're there
+                    header.extend([0x33u8; 32 + 64]);
+                }
+
+                header.extend(b"contents");
+                headers.push(header);
+            }
+        }
+    }
+
+    headers
+}
+
+fn shares() -> Vec<Vec<u8>> {
+    let mut shares = vec![];
+    let key = [0x99u8; KEY_LENGTH_BYTES];
+    let share_data = split_key_into_shares(&key, 3, 2);
+
+    for algo_version in 1..=ALGO_VERSION {
+        let nonce = vec![0x42u8; Cipher::for_version(algo_version).nonce_length()];
+
+        for (scheme, salt, mac) in [(None, None, None), (Some(2), Some(&[0x55u8; SALT_LENGTH_BYTES][..]), Some(share_mac(&key, &share_data[0])))] {
+            let header = construct_header_share(algo_version, 2, false, false, &nonce, scheme, salt, mac.is_some(), None);
+            let mut content = share_data[0].clone();
+            content.extend(mac.unwrap_or_default());
+            shares.push( construct_share(&header, None, &content) );
+        }
+    }
+
+    shares
+}
+
+#[test]
+fn mangled_file_headers_never_panic() {
+    for header in file_headers() {
+        assert!(parse_header_file(&header).is_ok());
+        mangle(&header);
+    }
+}
+
+#[test]
+fn mangled_shares_never_panic() {
+    for share in shares() {
+        assert!(parse_header_share(&share).is_ok());
+        mangle(&share);
+    }
+}
+
+#[test]
+fn mangled_bundles_never_panic() {
+    let bundle = construct_share_bundle(&shares()[..2]);
+    assert!(parse_share_bundle(&bundle).is_ok());
+    mangle(&bundle);
+}
+
+#[test]
+fn junk_never_panics() {
+    let mut state: u32 = 0x2545_f491;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+    };
+
+    for length in 0..512 {
+        let junk: Vec<u8> = (0..length).map(|_| next() ).collect();
+        parse_everything(&junk);
+
+        // and the same again behind each magic number, so it gets past the first check
+        for magic in [&b"CCM"[..], &HEADER_SHARE[..], b"CCMB", b"CCMG"] {
+            let mut prefixed = magic.to_vec();
+            prefixed.extend(&junk);
+            parse_everything(&prefixed);
+        }
+    }
+}
@@ -0,0 +1,789 @@
+// Encrypting then decrypting from the shares should always give back exactly what went in
+
+mod common;
+
+use std::fs;
+
+use assert_cmd::Command;
+
+use common::{chachamir, decrypt, encrypt, encrypted_path, fixture, share_files, stderr};
+
+use tempfile::tempdir;
+
+const EXIT_NEEDS_CONFIRMATION: i32 = 2;
+const EXIT_DECRYPTION_FAILED: i32 = 6;
+const EXIT_TAMPERED: i32 = 7;
+
+#[test]
+fn round_trips_player_threshold_combos() {
+    for (players, threshold) in [(1, 1), (2, 2), (3, 2), (5, 3), (10, 10), (255, 2)] {
+        for sign in [false, true] {
+            let dir = tempdir().unwrap();
+            let file = fixture(dir.path(), "plain.bin", 200_000); // a few chunks, and a partial one
+            let share_dir = dir.path().join("shares");
+            let output = dir.path().join("out.bin");
+
+            let extra: &[&str] = match sign {
+                true => &["--sign"],
+                false => &[],
+            };
+
+            encrypt(&file, players, threshold, &share_dir, extra).success();
+            assert_eq!(share_files(&share_dir).len(), players as usize);
+
+            decrypt(&encrypted_path(&file), &share_dir, &output, &["--strict"]).success();
+            assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap(), "{} of {} (signed: {})", threshold, players, sign);
+        }
+    }
+}
+
+#[test]
+fn round_trips_with_only_threshold_shares() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 5, 3, &share_dir, &["--sign"]).success();
+
+    // any 3 will do
+    let shares = share_files(&share_dir);
+    for share in [&shares[0], &shares[2]] {
+        fs::remove_file(share).unwrap();
+    }
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &["--strict"]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn round_trips_with_named_share_counts() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    // either way round, and no mistaking which is which
+    chachamir().arg("encrypt").arg(&file).args(["--threshold", "3", "--players", "5"]).arg("--share-dir").arg(&share_dir).assert().success();
+    assert_eq!(share_files(&share_dir).len(), 5);
+
+    let assert = chachamir().arg("info").arg(&share_files(&share_dir)[0]).assert().success();
+    assert!(String::from_utf8_lossy(&assert.get_output().stdout).contains("Threshold: 3"));
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &[]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+
+    // but not both ways at once
+    let other = fixture(dir.path(), "other.bin", 16);
+    let refused = encrypt(&other, 5, 3, &share_dir, &["-t", "2"]).failure();
+    assert!(stderr(&refused).contains("THRESHOLD was given both"), "{}", stderr(&refused));
+}
+
+#[cfg(unix)]
+#[test]
+fn round_trips_file_with_non_utf8_name() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = tempdir().unwrap();
+    let file = dir.path().join( OsStr::from_bytes(b"caf\xe9.bin") );
+    let share_dir = dir.path().join("shares");
+    fs::rename(fixture(dir.path(), "plain.bin", 4096), &file).unwrap();
+    let contents = fs::read(&file).unwrap();
+
+    encrypt(&file, 3, 2, &share_dir, &["--sign"]).success();
+    chachamir().arg("info").arg(encrypted_path(&file)).assert().success();
+
+    // decrypted back to the very same (non-UTF-8) name
+    fs::remove_file(&file).unwrap();
+    let assert = chachamir().arg("decrypt").arg(encrypted_path(&file)).arg("--share-dir").arg(&share_dir).assert().success();
+
+    assert!(!stderr(&assert).contains("panicked"));
+    assert_eq!(fs::read(&file).unwrap(), contents);
+}
+
+#[test]
+fn round_trips_file_without_extension() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "Makefile", 4096);
+    let share_dir = dir.path().join("shares");
+    let contents = fs::read(&file).unwrap();
+
+    encrypt(&file, 3, 2, &share_dir, &[]).success();
+
+    let encrypted = dir.path().join("Makefile.ccm");
+    assert!(encrypted.is_file());
+    assert!(!dir.path().join("Makefile..ccm").exists());
+
+    // and back to plain Makefile, not Makefile. or Makefile.ccm.out
+    fs::remove_file(&file).unwrap();
+    chachamir().arg("decrypt").arg(&encrypted).arg("--share-dir").arg(&share_dir).assert().success();
+    assert_eq!(fs::read(&file).unwrap(), contents);
+}
+
+#[test]
+fn round_trips_empty_file() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "empty.txt", 0);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.txt");
+    fs::create_dir(&share_dir).unwrap();
+
+    // encrypting nothing wants confirming first
+    let refused = encrypt(&file, 3, 2, &share_dir, &["--sign"]).code(EXIT_NEEDS_CONFIRMATION);
+    assert!(stderr(&refused).contains("empty"));
+
+    Command::cargo_bin("chachamir").unwrap()
+        .arg("encrypt").arg(&file).args(["3", "2", "--sign", "--share-dir"]).arg(&share_dir)
+        .write_stdin("y\n")
+        .assert()
+        .success();
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &["--strict"]).success();
+    assert_eq!(fs::read(&output).unwrap(), Vec::<u8>::new());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn failed_encryption_leaves_existing_output_and_no_shares() {
+    let dir = tempdir().unwrap();
+    let existing = fixture(dir.path(), "old.ccm", 64);
+    let original = fs::read(&existing).unwrap();
+    let share_dir = dir.path().join("shares");
+    fs::create_dir(&share_dir).unwrap();
+
+    // reading from the start of a process's memory is an I/O error, partway into encrypting (it's "empty", so say yes to that first)
+    Command::cargo_bin("chachamir").unwrap()
+        .arg("encrypt").arg("/proc/self/mem").args(["3", "2", "--force", "--output"]).arg(&existing).arg("--share-dir").arg(&share_dir)
+        .write_stdin("y\n")
+        .assert()
+        .code(1);
+
+    assert_eq!(fs::read(&existing).unwrap(), original);
+    assert!(share_files(&share_dir).is_empty());
+}
+
+#[test]
+fn closed_stdin_counts_as_no() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "empty.txt", 0);
+    let share_dir = dir.path().join("shares");
+    fs::create_dir(&share_dir).unwrap();
+
+    // nothing to read the answer from, so it's the same as refusing (not a panic)
+    let refused = Command::cargo_bin("chachamir").unwrap()
+        .arg("encrypt").arg(&file).args(["3", "2", "--share-dir"]).arg(&share_dir)
+        .write_stdin("")
+        .assert()
+        .code(EXIT_NEEDS_CONFIRMATION);
+
+    assert!(stderr(&refused).contains("stdin was closed"), "{}", stderr(&refused));
+    assert!(!stderr(&refused).contains("panicked"), "{}", stderr(&refused));
+    assert!(!encrypted_path(&file).exists());
+}
+
+#[test]
+fn strict_refuses_corrupted_signed_share() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    // without a checksum in its name, so the share's signature is what catches it
+    encrypt(&file, 3, 2, &share_dir, &["--sign", "--share-name-template", "{index}-{nonce}.ccms"]).success();
+
+    // flip the last byte of the first share's content, leaving its signature alone
+    let share = &share_files(&share_dir)[0];
+    let mut contents = fs::read(share).unwrap();
+    *contents.last_mut().unwrap() ^= 0xff;
+    fs::write(share, contents).unwrap();
+
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &["--strict"]).code(EXIT_TAMPERED);
+    let stderr = stderr(&assert);
+
+    assert!(stderr.contains("THIS SHARE MAY BE CORRUPTED OR TAMPERED WITH"), "{}", stderr);
+    assert!(stderr.contains("Will not decrypt using tampered data in strict mode!"), "{}", stderr);
+    assert!(!output.exists());
+}
+
+#[test]
+fn refuses_tampered_contents() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 100_000);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 3, 2, &share_dir, &[]).success();
+
+    // unsigned, but every chunk is still authenticated
+    let encrypted = encrypted_path(&file);
+    let mut contents = fs::read(&encrypted).unwrap();
+    contents[50_000] ^= 0x01;
+    fs::write(&encrypted, contents).unwrap();
+
+    decrypt(&encrypted, &share_dir, &output, &[]).code(EXIT_DECRYPTION_FAILED);
+    assert!(!output.exists());
+
+    // and a file already at the output path is only ever replaced by plaintext that checks out
+    fs::write(&output, b"keep me").unwrap();
+    decrypt(&encrypted, &share_dir, &output, &["--force"]).code(EXIT_DECRYPTION_FAILED);
+    assert_eq!(fs::read(&output).unwrap(), b"keep me");
+    assert!(!dir.path().join(".tmp-out.bin").exists());
+}
+
+#[test]
+fn label_is_shown_and_authenticated() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 3, 2, &share_dir, &["--label", "Q3 financials"]).success();
+
+    let encrypted = encrypted_path(&file);
+    let assert = chachamir().arg("info").arg(&encrypted).assert().success();
+    assert!(String::from_utf8_lossy(&assert.get_output().stdout).contains("[+] Label: Q3 financials"));
+
+    let decrypted = decrypt(&encrypted, &share_dir, &output, &[]).success();
+    assert!(stderr(&decrypted).contains("Q3 financials"));
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+    fs::remove_file(&output).unwrap();
+
+    // the header is the associated data, so the label can't be quietly changed
+    let mut contents = fs::read(&encrypted).unwrap();
+    let at = contents.windows(2).position(|bytes| bytes == b"Q3" ).unwrap();
+    contents[at] = b'Q' + 1;
+    fs::write(&encrypted, contents).unwrap();
+
+    decrypt(&encrypted, &share_dir, &output, &[]).code(EXIT_DECRYPTION_FAILED);
+    assert!(!output.exists());
+
+    // nor can a label go in that won't fit, or has control characters
+    let other = fixture(dir.path(), "other.bin", 16);
+    encrypt(&other, 3, 2, &share_dir, &["--label", &"x".repeat(256)]).failure();
+    encrypt(&other, 3, 2, &share_dir, &["--label", "line\nbreak"]).failure();
+}
+
+#[test]
+fn aad_is_needed_to_decrypt() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 100_000);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 3, 2, &share_dir, &["--aad", "hostname=vault01"]).success();
+
+    // the header only says it's needed, not what it is
+    let encrypted = encrypted_path(&file);
+    assert!(!fs::read(&encrypted).unwrap().windows(7).any(|bytes| bytes == b"vault01" ));
+
+    let assert = chachamir().arg("info").arg(&encrypted).assert().success();
+    assert!(String::from_utf8_lossy(&assert.get_output().stdout).contains("[+] Needs associated data (--aad): yes"));
+
+    // nobody to ask for it
+    decrypt(&encrypted, &share_dir, &output, &[]).code(EXIT_NEEDS_CONFIRMATION);
+
+    decrypt(&encrypted, &share_dir, &output, &["--aad", "hostname=vault02"]).code(EXIT_DECRYPTION_FAILED);
+    assert!(!output.exists());
+
+    decrypt(&encrypted, &share_dir, &output, &["--aad", "hostname=vault01"]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+
+    // a file that isn't bound to any can't be checked against it
+    let other = fixture(dir.path(), "other.bin", 16);
+    let other_shares = dir.path().join("other-shares");
+    encrypt(&other, 3, 2, &other_shares, &[]).success();
+    decrypt(&encrypted_path(&other), &other_shares, &dir.path().join("other.out"), &["--aad", "hostname=vault01"]).failure();
+
+    // ...which, with -r, skips just that one file
+    let tree = dir.path().join("tree");
+    let tree_shares = dir.path().join("tree-shares");
+    fs::create_dir(&tree).unwrap();
+    let bound = fixture(&tree, "bound.bin", 64);
+    chachamir().arg("encrypt").arg(&tree).args(["3", "2", "--recursive", "--aad", "hostname=vault01", "--share-dir"]).arg(&tree_shares).assert().success();
+    let unbound = fixture(&tree, "unbound.bin", 64);
+    encrypt(&unbound, 3, 2, &tree_shares, &[]).success();
+    fs::remove_file(&bound).unwrap();
+    fs::remove_file(&unbound).unwrap();
+
+    let assert = chachamir().arg("decrypt").arg(&tree).args(["--recursive", "--aad", "hostname=vault01", "--share-dir"]).arg(&tree_shares).assert().code(1);
+    assert!(stderr(&assert).contains("Skipping"), "{}", stderr(&assert));
+    assert!(bound.is_file());
+    assert!(!unbound.exists());
+}
+
+#[test]
+fn rotate_moves_a_file_onto_new_shares() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 100_000);
+    let share_dir = dir.path().join("shares");
+    let new_share_dir = dir.path().join("new-shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 5, 3, &share_dir, &["--sign"]).success();
+    let encrypted = encrypted_path(&file);
+
+    // a quorum of the old shares says nothing about how many new ones there should be
+    for share in &share_files(&share_dir)[3..] {
+        fs::remove_file(share).unwrap();
+    }
+
+    let rotate = |extra: &[&str]| chachamir().arg("rotate").arg(&encrypted).arg("--share-dir").arg(&share_dir).arg("--new-share-dir").arg(&new_share_dir).args(extra).assert();
+
+    let missing = rotate(&[]).failure();
+    assert!(stderr(&missing).contains("--players"), "{}", stderr(&missing));
+    assert!(share_files(&share_dir).len() == 3 && !new_share_dir.exists());
+
+    rotate(&["--players", "4", "--threshold", "2"]).success();
+    assert_eq!(share_files(&new_share_dir).len(), 4);
+
+    // the old shares are no use any more...
+    decrypt(&encrypted, &share_dir, &output, &[]).failure();
+    assert!(!output.exists());
+
+    // ...but any 2 of the new ones are
+    for share in &share_files(&new_share_dir)[2..] {
+        fs::remove_file(share).unwrap();
+    }
+
+    decrypt(&encrypted, &new_share_dir, &output, &["--strict"]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn refuses_threshold_above_players() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+
+    // it could never be recovered, so nothing should be written (and nothing should panic)
+    let assert = encrypt(&file, 2, 3, &share_dir, &[]).failure();
+    let stderr = stderr(&assert);
+
+    assert!(stderr.contains("Share threshold exceeds maximum number of players"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    assert!(!encrypted_path(&file).exists());
+    assert!(share_files(&share_dir).is_empty());
+
+    chachamir().arg("encrypt").arg(&file).args(["0", "0"]).assert().failure();
+}
+
+#[test]
+fn reports_timings() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+
+    let assert = encrypt(&file, 3, 2, &share_dir, &["--time"]).success();
+    assert!(stderr(&assert).contains("Share splitting"));
+
+    fs::remove_file(encrypted_path(&file)).unwrap();
+    let assert = chachamir().arg("--json").arg("encrypt").arg(&file).args(["3", "2", "--force", "--share-dir"]).arg(&share_dir).arg("--time").assert().success();
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout).into_owned();
+
+    let timings: serde_json::Value = stdout.lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap() )
+        .find(|event| event["event"] == "timings" )
+        .expect("no timings event");
+    assert!(timings["phases"].as_array().unwrap().iter().any(|phase| phase["phase"] == "Encryption" ));
+    assert!(timings["total_ms"].as_f64().unwrap() >= 0.0);
+}
+
+#[test]
+fn errors_are_reported_with_their_exit_code() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let missing = dir.path().join("missing.ccm");
+
+    let assert = chachamir().arg("info").arg(&missing).assert().code(1);
+    assert!(stderr(&assert).contains(&format!("[!] Could not read file {}", missing.display())), "{}", stderr(&assert));
+
+    // one event per error, with the code we exit with
+    for (target, code) in [(&missing, 1), (&file, 3)] {
+        let assert = chachamir().arg("--json").arg("info").arg(target).assert().code(code);
+        let stdout = String::from_utf8_lossy(&assert.get_output().stdout).into_owned();
+
+        let errors: Vec<serde_json::Value> = stdout.lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap() )
+            .filter(|event| event["event"] == "error" )
+            .collect();
+        assert_eq!(errors.len(), 1, "{}", stdout);
+        assert_eq!(errors[0]["exit_code"], code);
+        assert!(errors[0]["message"].as_str().unwrap().contains(&target.display().to_string()), "{}", stdout);
+    }
+}
+
+#[test]
+fn decrypts_damaged_nonce_with_nonce_from_share_names() {
+    for sign in [false, true] {
+        let dir = tempdir().unwrap();
+        let file = fixture(dir.path(), "plain.bin", 100_000);
+        let share_dir = dir.path().join("shares");
+        let output = dir.path().join("out.bin");
+
+        let extra: &[&str] = match sign {
+            true => &["--sign"],
+            false => &[],
+        };
+
+        encrypt(&file, 3, 2, &share_dir, extra).success();
+
+        // shares are named {index}-{nonce}-{checksum}.ccms, so they still know it
+        let share_name = share_files(&share_dir)[0].file_stem().unwrap().to_string_lossy().into_owned();
+        let nonce = share_name.split('-').nth(1).unwrap().to_owned();
+
+        // the nonce starts 9 bytes into a current header
+        let encrypted = encrypted_path(&file);
+        let mut contents = fs::read(&encrypted).unwrap();
+        contents[9..33].fill(0);
+        fs::write(&encrypted, contents).unwrap();
+
+        decrypt(&encrypted, &share_dir, &output, &["--strict"]).failure();
+        assert!(!output.exists());
+
+        decrypt(&encrypted, &share_dir, &output, &["--strict", "--nonce", &nonce]).success();
+        assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap(), "signed: {}", sign);
+
+        // and a nonce of the wrong length is turned away before anything else
+        let assert = decrypt(&encrypted, &share_dir, &output, &["--force", "--nonce", &nonce[..24]]).failure();
+        assert!(stderr(&assert).contains("must be 24 bytes of hex"));
+    }
+}
+
+#[test]
+fn asks_before_encrypting_twice() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 2, 2, &share_dir, &[]).success();
+    let encrypted = encrypted_path(&file);
+
+    let refused = encrypt(&encrypted, 2, 2, &share_dir, &[]).code(EXIT_NEEDS_CONFIRMATION);
+    assert!(stderr(&refused).contains("already appears to be a chachamir file"));
+    assert!(!encrypted_path(&encrypted).exists());
+
+    // with --force it's done regardless, and takes two decryptions to undo
+    encrypt(&encrypted, 2, 2, &share_dir, &["--force"]).success();
+
+    decrypt(&encrypted_path(&encrypted), &share_dir, &encrypted, &["--force"]).success();
+    decrypt(&encrypted, &share_dir, &output, &[]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn round_trips_directory_skipping_excluded() {
+    let dir = tempdir().unwrap();
+    let tree = dir.path().join("tree");
+    let share_dir = dir.path().join("shares");
+    fs::create_dir_all(tree.join("sub")).unwrap();
+    fs::create_dir_all(tree.join(".git")).unwrap();
+
+    let kept = fixture(&tree.join("sub"), "kept.bin", 4096);
+    let skipped = [fixture(&tree, "scratch.tmp", 10), fixture(&tree.join("sub"), "more.tmp", 10), fixture(&tree.join(".git"), "config", 10)];
+
+    chachamir().arg("encrypt").arg(&tree).args(["3", "2", "--recursive", "--exclude", "*.tmp", "--exclude", ".git/", "--share-dir"]).arg(&share_dir).assert().success();
+
+    assert!(encrypted_path(&kept).exists());
+    for path in &skipped {
+        assert!(!encrypted_path(path).exists(), "{}", path.display());
+    }
+
+    let original = fs::read(&kept).unwrap();
+    fs::remove_file(&kept).unwrap();
+
+    chachamir().arg("decrypt").arg(&tree).args(["--recursive", "--share-dir"]).arg(&share_dir).assert().success();
+    assert_eq!(fs::read(&kept).unwrap(), original);
+}
+
+#[test]
+fn encrypts_only_files_modified_since() {
+    let dir = tempdir().unwrap();
+    let tree = dir.path().join("tree");
+    let share_dir = dir.path().join("shares");
+    fs::create_dir_all(&tree).unwrap();
+
+    let changed = fixture(&tree, "changed.bin", 4096);
+    let unchanged = fixture(&tree, "unchanged.bin", 4096);
+    let in_1998 = std::time::UNIX_EPOCH + std::time::Duration::from_secs(900_000_000);
+    fs::File::options().write(true).open(&unchanged).unwrap().set_modified(in_1998).unwrap();
+
+    let assert = chachamir().arg("encrypt").arg(&tree).args(["3", "2", "--recursive", "--since", "2000-01-01T00:00:00.5+05:30", "--share-dir"]).arg(&share_dir).assert().success();
+
+    assert!(stderr(&assert).contains("1 of 2 file(s) modified since"), "{}", stderr(&assert));
+    assert!(stderr(&assert).contains("skipped 1 unmodified"), "{}", stderr(&assert));
+    assert!(encrypted_path(&changed).exists());
+    assert!(!encrypted_path(&unchanged).exists());
+
+    chachamir().arg("encrypt").arg(&tree).args(["3", "2", "--recursive", "--since", "last tuesday"]).assert().failure();
+}
+
+#[test]
+fn audit_log_records_checks() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+    let audit_log = dir.path().join("audit.log");
+
+    encrypt(&file, 3, 2, &share_dir, &["--sign", "--share-name-template", "{index}-{nonce}.ccms"]).success();
+
+    let share = &share_files(&share_dir)[0];
+    let mut contents = fs::read(share).unwrap();
+    *contents.last_mut().unwrap() ^= 0xff;
+    fs::write(share, contents).unwrap();
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &["--strict", "--audit-log", audit_log.to_str().unwrap()]).code(EXIT_TAMPERED);
+
+    let records: Vec<serde_json::Value> = fs::read_to_string(&audit_log).unwrap().lines()
+        .map(|line| serde_json::from_str(line).unwrap() )
+        .collect();
+
+    assert_eq!(records[0]["event"], "started");
+    assert!(records.iter().all(|record| record["time"].as_str().is_some_and(|time| time.ends_with('Z')) ));
+
+    // the bad share is the last thing recorded, since --strict stops there
+    let last = records.last().unwrap();
+    assert_eq!(last["event"], "share_signature");
+    assert_eq!(last["outcome"], "bad_signature");
+    assert_eq!(last["share"], share.to_str().unwrap());
+    assert_eq!(last["file_public_key"], last["share_public_key"]);
+}
+
+#[cfg(feature = "test-rng")]
+#[test]
+fn seeded_rng_gives_identical_output() {
+    let seed = "00".repeat(31) + "2a";
+    let mut outputs: Vec<Vec<Vec<u8>>> = vec![];
+
+    for _ in 0..2 {
+        let dir = tempdir().unwrap();
+        let file = fixture(dir.path(), "plain.bin", 200_000);
+        let share_dir = dir.path().join("shares");
+
+        encrypt(&file, 5, 3, &share_dir, &["--sign", "--seed", &seed]).success();
+
+        let mut written = vec![fs::read(encrypted_path(&file)).unwrap()];
+        written.extend( share_files(&share_dir).iter().map(|share| fs::read(share).unwrap() ) );
+        outputs.push(written);
+    }
+
+    // the encrypted file, every share and the signatures on them, byte for byte
+    assert_eq!(outputs[0], outputs[1]);
+}
+
+#[test]
+fn doctor_passes() {
+    let assert = chachamir().arg("doctor").assert().success();
+    let stderr = stderr(&assert);
+
+    assert!(stderr.contains("checks passed"), "{}", stderr);
+    assert!(!stderr.contains("FAILED"), "{}", stderr);
+}
+
+#[test]
+fn config_file_supplies_defaults() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("keys");
+    let output = dir.path().join("out.bin");
+    fs::write(dir.path().join("chachamir.toml"), "players = 5\nthreshold = 3\nshare_dir = \"keys\"\nsign = true\n").unwrap();
+
+    let in_dir = |args: &[&str]| {
+        let mut cmd = chachamir();
+        cmd.current_dir(dir.path()).args(args);
+        cmd
+    };
+
+    // players, threshold, share directory and signing all from the config file
+    in_dir(&["encrypt", "plain.bin"]).assert().success();
+    assert_eq!(share_files(&share_dir).len(), 5);
+
+    let assert = in_dir(&["info", "plain.bin.ccm"]).assert().success();
+    assert!(String::from_utf8_lossy(&assert.get_output().stdout).contains("Signed: yes"));
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &["--strict"]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+
+    // but the command line has the last word
+    fs::remove_file(encrypted_path(&file)).unwrap();
+    in_dir(&["encrypt", "plain.bin", "3", "2", "--no-sign", "--share-dir", "others"]).assert().success();
+    assert_eq!(share_files(&dir.path().join("others")).len(), 3);
+
+    let assert = in_dir(&["info", "plain.bin.ccm"]).assert().success();
+    assert!(String::from_utf8_lossy(&assert.get_output().stdout).contains("Signed: no"));
+
+    // and without one, players and threshold are needed
+    fs::remove_file(encrypted_path(&file)).unwrap();
+    in_dir(&["--no-config", "encrypt", "plain.bin"]).assert().failure();
+}
+
+#[test]
+fn encrypts_files_from_input_list() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("a")).unwrap();
+    fs::create_dir(dir.path().join("b")).unwrap();
+    let first = fixture(&dir.path().join("a"), "x.bin", 4096);
+    let second = fixture(&dir.path().join("b"), "x.bin", 100);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    let list = dir.path().join("list.txt");
+    fs::write(&list, format!("{}\n# not a file\n\n{}\n{}\n", first.display(), dir.path().join("missing.bin").display(), second.display())).unwrap();
+
+    // the missing file doesn't stop the others, but does fail the batch
+    let assert = chachamir().arg("encrypt").arg("--input-list").arg(&list).args(["3", "2", "--share-dir"]).arg(&share_dir).assert().failure();
+    let stderr = stderr(&assert);
+    assert!(stderr.contains("Encrypted 2 of 3 listed file(s)"), "{}", stderr);
+    assert!(stderr.contains("missing.bin"), "{}", stderr);
+
+    // each file's shares in a directory of their own
+    for (file, file_share_dir) in [(&first, "x.bin"), (&second, "x.bin-2")] {
+        decrypt(&encrypted_path(file), &share_dir.join(file_share_dir), &output, &[]).success();
+        assert_eq!(fs::read(&output).unwrap(), fs::read(file).unwrap());
+        fs::remove_file(&output).unwrap();
+    }
+}
+
+#[test]
+fn raw_output_keeps_the_header_alongside() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 200_000);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    let encrypted = encrypted_path(&file);
+    let mut header = encrypted.as_os_str().to_owned();
+    header.push(".hdr");
+    let header = std::path::PathBuf::from(header);
+
+    encrypt(&file, 3, 2, &share_dir, &["--sign", "--output-format", "raw"]).success();
+
+    // nothing but ciphertext: the plaintext, plus a tag for each 64 KiB chunk (and the empty one that ends it)
+    assert!(header.is_file());
+    assert!(!fs::read(&encrypted).unwrap().starts_with(b"CCM"));
+    assert_eq!(fs::metadata(&encrypted).unwrap().len(), 200_000 + 4 * 16);
+
+    decrypt(&encrypted, &share_dir, &output, &["--strict"]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+
+    // the header can't be rewritten into a file that hasn't got one
+    chachamir().arg("rekey").arg(&encrypted).args(["3", "2", "--share-dir"]).arg(&share_dir).arg("--new-share-dir").arg(dir.path().join("new")).assert().failure();
+
+    // and without it, there's nothing to go on
+    fs::remove_file(&header).unwrap();
+    fs::remove_file(&output).unwrap();
+    decrypt(&encrypted, &share_dir, &output, &[]).failure();
+    assert!(!output.exists());
+
+    // back to the usual format, which takes a stale header with it
+    encrypt(&file, 3, 2, &share_dir, &["--output-format", "raw", "--force"]).success();
+    encrypt(&file, 3, 2, &share_dir, &["--force"]).success();
+    assert!(!header.exists());
+
+    // a header left next to a file that still has its own (split off, but stopped before the file was rewritten) is ignored
+    fs::write(&header, b"CCM not a header").unwrap();
+    decrypt(&encrypted, &share_dir, &output, &["--strict"]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+
+    chachamir().arg("encrypt").arg(&file).args(["3", "2", "--output-format", "json"]).assert().failure();
+}
+
+#[test]
+fn archive_packs_a_directory_into_one_file() {
+    let dir = tempdir().unwrap();
+    let tree = dir.path().join("project");
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("unpacked");
+
+    fs::create_dir_all(tree.join("src/deep")).unwrap();
+    fixture(&tree, "data.bin", 100_000);
+    fixture(&tree.join("src"), "main.rs", 300);
+    fixture(&tree.join("src/deep"), "empty.txt", 0);
+
+    chachamir().arg("encrypt").arg(&tree).args(["3", "2", "--archive", "--sign", "--checksum", "--share-dir"]).arg(&share_dir).assert().success();
+
+    // one file, and one set of shares for the lot
+    let encrypted = dir.path().join("project.ccm");
+    assert!(encrypted.is_file());
+    assert_eq!(share_files(&share_dir).len(), 3);
+
+    decrypt(&encrypted, &share_dir, &output, &["--strict"]).success();
+
+    for name in ["data.bin", "src/main.rs", "src/deep/empty.txt"] {
+        assert_eq!(fs::read(output.join(name)).unwrap(), fs::read(tree.join(name)).unwrap(), "{}", name);
+    }
+
+    // there's no one file to send down stdout
+    let assert = chachamir().arg("decrypt").arg(&encrypted).args(["--stdout", "--share-dir"]).arg(&share_dir).assert().failure();
+    assert!(stderr(&assert).contains("can't be decrypted to stdout"), "{}", stderr(&assert));
+
+    // an empty directory is fine to unpack into...
+    let again = dir.path().join("again");
+    fs::create_dir_all(again.join("project")).unwrap();
+    decrypt(&encrypted, &share_dir, &again, &[]).success();
+    assert_eq!(fs::read(again.join("project/data.bin")).unwrap(), fs::read(tree.join("data.bin")).unwrap());
+
+    // ...but unpacking never mixes its files in with (or clears away) ones already there
+    let mine = fixture(&again.join("project"), "mine.txt", 64);
+    fs::remove_file(again.join("project/data.bin")).unwrap();
+    let refused = decrypt(&encrypted, &share_dir, &again, &["--force"]).failure();
+    assert!(stderr(&refused).contains("isn't an empty directory"), "{}", stderr(&refused));
+    assert!(mine.is_file());
+    assert!(!again.join("project/data.bin").exists());
+}
+
+#[test]
+fn archive_packs_several_paths_into_one_file() {
+    let dir = tempdir().unwrap();
+    let notes = fixture(dir.path(), "notes.txt", 500);
+    let photos = dir.path().join("photos");
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("unpacked");
+
+    fs::create_dir(&photos).unwrap();
+    fixture(&photos, "cat.jpg", 40_000);
+    fixture(&photos, "cat.jpg.tmp", 10);
+    let budget = fixture(dir.path(), "budget.xlsx", 2_000);
+
+    chachamir().arg("encrypt").arg(&notes).args(["3", "2", "--archive", "--exclude", "*.tmp", "--with"]).arg(&photos).arg("--with").arg(&budget)
+        .arg("--share-dir").arg(&share_dir)
+        .assert()
+        .success();
+
+    decrypt(&encrypted_path(&notes), &share_dir, &output, &[]).success();
+
+    for (name, original) in [("notes.txt", &notes), ("photos/cat.jpg", &photos.join("cat.jpg")), ("budget.xlsx", &budget)] {
+        assert_eq!(fs::read(output.join(name)).unwrap(), fs::read(original).unwrap(), "{}", name);
+    }
+
+    assert!(!output.join("photos/cat.jpg.tmp").exists());
+
+    // two files can't both be unpacked to the same name
+    let other = dir.path().join("other");
+    fs::create_dir(&other).unwrap();
+    fixture(&other, "notes.txt", 10);
+
+    let refused = chachamir().arg("encrypt").arg(&other).args(["3", "2", "--archive", "--force", "--with"]).arg(&notes).arg("--share-dir").arg(&share_dir).assert().failure();
+    assert!(stderr(&refused).contains("More than one file would be named notes.txt"), "{}", stderr(&refused));
+}
+
+#[test]
+fn progress_json_reports_to_the_end() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 300_000);
+    let share_dir = dir.path().join("shares");
+
+    let assert = encrypt(&file, 5, 2, &share_dir, &["--progress-json"]).success();
+
+    let events: Vec<serde_json::Value> = stderr(&assert).lines()
+        .filter(|line| line.starts_with('{') )
+        .map(|line| serde_json::from_str(line).unwrap() )
+        .collect();
+
+    let last_of = |phase: &str| events.iter().rfind(|event| event["event"] == "progress" && event["phase"] == phase ).cloned().unwrap();
+
+    assert_eq!(last_of("shares")["done"], 5);
+    assert_eq!(last_of("shares")["total"], 5);
+    assert_eq!(last_of("encrypt")["bytes_done"], 300_000);
+    assert_eq!(last_of("encrypt")["bytes_total"], 300_000);
+}
@@ -0,0 +1,588 @@
+// Where shares go, and finding (and not being fooled by) them again when decrypting
+
+mod common;
+
+use std::fs;
+
+use assert_cmd::Command;
+use common::{chachamir, decrypt, encrypt, encrypted_path, fixture, share_files, stderr};
+
+use tempfile::tempdir;
+
+const EXIT_RECOVERY_FAILED: i32 = 5;
+const EXIT_TAMPERED: i32 = 7;
+
+const SHARE_THRESHOLD_BYTE: usize = 5; // after "CCMS" and the algorithm version
+const SHARE_NONCE_BYTES: std::ops::Range<usize> = 7..31; // after the is-signed byte
+
+#[test]
+fn counts_duplicate_shares_once() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 3, 3, &share_dir, &[]).success();
+
+    // two distinct shares and a copy of one of them is still only two
+    let shares = share_files(&share_dir);
+    fs::copy(&shares[0], share_dir.join("copy.ccms")).unwrap();
+    fs::remove_file(&shares[2]).unwrap();
+
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &[]).code(EXIT_RECOVERY_FAILED);
+    let stderr = stderr(&assert);
+
+    assert!(stderr.contains("Located 2 distinct share(s)"), "{}", stderr);
+    assert!(stderr.contains("1 more distinct share(s) needed"), "{}", stderr);
+
+    // the copy's name says nothing, but it's reported as the share it really is
+    assert!(stderr.contains(&format!("Share # 1 retrieved from {}", share_dir.join("copy.ccms").display())), "{}", stderr);
+    assert!(!output.exists());
+}
+
+#[test]
+fn skips_huge_non_share_files() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+
+    encrypt(&file, 3, 2, &share_dir, &[]).success();
+
+    // 1 GB (sparse) of not-a-share, which only ever gets as far as its first few bytes being read
+    for name in ["huge.ccms", "huge.bin"] {
+        fs::File::create(share_dir.join(name)).unwrap().set_len(1 << 30).unwrap();
+    }
+
+    for extra in [&[][..], &["--all"][..]] {
+        let output = dir.path().join("out.bin");
+        let _ = fs::remove_file(&output);
+
+        decrypt(&encrypted_path(&file), &share_dir, &output, extra).success();
+        assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+    }
+}
+
+#[test]
+fn finds_shares_in_directory_with_glob_characters() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("my[shares]");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 3, 2, &share_dir, &[]).success();
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &[]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn finds_shares_with_uppercase_extension() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 2, 2, &share_dir, &[]).success();
+
+    for share in share_files(&share_dir) {
+        fs::rename(&share, share.with_extension("CCMS")).unwrap();
+    }
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &[]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn refuses_incompatible_shares() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    // named without a checksum, or they'd be skipped as corrupt before they're parsed
+    encrypt(&file, 3, 2, &share_dir, &["--share-name-template", "{index}-{nonce}.ccms"]).success();
+
+    // shares that still parse, but of a shorter secret than any key
+    for share in share_files(&share_dir) {
+        let mut contents = fs::read(&share).unwrap();
+        contents.pop();
+        fs::write(&share, contents).unwrap();
+    }
+
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &[]).code(EXIT_RECOVERY_FAILED);
+    let stderr = stderr(&assert);
+
+    assert!(stderr.contains("shares are incompatible"), "{}", stderr);
+    assert!(stderr.contains("from 3 distinct share(s) (threshold 2)"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    assert!(!output.exists());
+}
+
+#[test]
+fn refuses_shares_from_different_schemes() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let mixed_dir = dir.path().join("mixed");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 3, 2, &share_dir, &["--scheme", "3:2"]).success();
+
+    // one share from each scheme makes two shares, but not two of the same key split
+    fs::create_dir(&mixed_dir).unwrap();
+    for scheme in ["scheme-1", "scheme-2"] {
+        let share = &share_files(&share_dir.join(scheme))[0];
+        fs::copy(share, mixed_dir.join(format!("{}.ccms", scheme))).unwrap();
+    }
+
+    let assert = decrypt(&encrypted_path(&file), &mixed_dir, &output, &[]).code(EXIT_RECOVERY_FAILED);
+    assert!(!stderr(&assert).contains("panicked"));
+    assert!(!output.exists());
+
+    // whereas either scheme on its own is fine
+    decrypt(&encrypted_path(&file), &share_dir.join("scheme-2"), &output, &[]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn creates_missing_share_dir() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("not").join("yet");
+    let output = dir.path().join("out.bin");
+
+    // taking the default answer under --non-interactive
+    chachamir().arg("encrypt").arg(&file).args(["3", "2", "--share-dir"]).arg(&share_dir).assert().success();
+    assert_eq!(share_files(&share_dir).len(), 3);
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &[]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn refuses_file_as_share_dir() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let not_a_dir = fixture(dir.path(), "shares", 1);
+
+    let assert = chachamir().arg("encrypt").arg(&file).args(["3", "2", "--share-dir"]).arg(&not_a_dir).assert().failure();
+    assert!(stderr(&assert).contains("is not a directory"));
+    assert!(!encrypted_path(&file).exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn shares_only_readable_by_owner() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+
+    for (extra, mode) in [(&[][..], 0o600), (&["--mode", "640"][..], 0o640)] {
+        let share_dir = dir.path().join(format!("shares-{:o}", mode));
+        encrypt(&file, 3, 2, &share_dir, &[&["--force"], extra].concat()).success();
+
+        for share in share_files(&share_dir) {
+            assert_eq!(fs::metadata(&share).unwrap().permissions().mode() & 0o777, mode, "{}", share.display());
+        }
+    }
+
+    chachamir().arg("encrypt").arg(&file).args(["3", "2", "--mode", "999"]).assert().failure();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn skips_unreadable_shares() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 3, 2, &share_dir, &[]).success();
+
+    // reading from the start of a process's memory is an I/O error, even for root
+    std::os::unix::fs::symlink("/proc/self/mem", share_dir.join("9-unreadable.ccms")).unwrap();
+
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &[]).success();
+    assert!(stderr(&assert).contains("9-unreadable.ccms | Could not read share file"), "{}", stderr(&assert));
+    assert!(!stderr(&assert).contains("retry"), "an I/O error won't go away by itself, so it isn't retried");
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn skips_excluded_files_in_share_dir() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 3, 2, &share_dir, &[]).success();
+    fixture(&share_dir, "notes.tmp.ccms", 100);
+
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &["--all"]).success();
+    assert!(stderr(&assert).contains("notes.tmp.ccms"));
+
+    fs::remove_file(&output).unwrap();
+
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &["--all", "--exclude", "*.tmp.*"]).success();
+    assert!(!stderr(&assert).contains("notes.tmp.ccms"));
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn added_shares_work_with_the_old_ones() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let key_file = dir.path().join("mykey");
+    let share_dir = dir.path().join("shares");
+    let new_share_dir = dir.path().join("new-shares");
+    let output = dir.path().join("out.bin");
+
+    chachamir().arg("keygen").arg(&key_file).assert().success();
+    encrypt(&file, 3, 3, &share_dir, &["--sign", "--key-file", key_file.to_str().unwrap()]).success();
+
+    // signed shares need the file's own keypair
+    chachamir().arg("add-shares").arg(encrypted_path(&file)).arg("2").arg("--share-dir").arg(&share_dir).arg("--new-share-dir").arg(&new_share_dir).assert().failure();
+
+    chachamir().arg("add-shares").arg(encrypted_path(&file)).arg("2")
+        .arg("--share-dir").arg(&share_dir)
+        .arg("--new-share-dir").arg(&new_share_dir)
+        .arg("--key-file").arg(&key_file)
+        .assert().success();
+
+    let new_shares = share_files(&new_share_dir);
+    assert_eq!(new_shares.len(), 2);
+    assert!(new_shares[0].file_name().unwrap().to_string_lossy().starts_with("4-"));
+
+    // one old share and both new ones
+    let old_shares = share_files(&share_dir);
+    for share in &old_shares[1..] {
+        fs::remove_file(share).unwrap();
+    }
+
+    let assert = chachamir().arg("decrypt").arg(encrypted_path(&file))
+        .arg("--share-dir").arg(&share_dir)
+        .arg("--share-dir").arg(&new_share_dir)
+        .arg("--output").arg(&output)
+        .arg("--strict")
+        .assert().success();
+
+    assert!(stderr(&assert).contains("Found share(s) 1,4,5"), "{}", stderr(&assert));
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn added_shares_can_fit_below_share_255() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+
+    encrypt(&file, 3, 2, &share_dir, &[]).success();
+
+    let add_shares = |count: &str, extra: &[&str]| chachamir().arg("add-shares").arg(encrypted_path(&file)).arg(count).arg("--share-dir").arg(&share_dir).args(extra).assert();
+
+    add_shares("1", &["--first-index", "255"]).success();
+
+    // nothing comes after 255...
+    add_shares("2", &[]).failure();
+
+    // ...but there's still room below it
+    add_shares("2", &["--first-index", "4"]).success();
+
+    let names: Vec<String> = share_files(&share_dir).iter().map(|share| share.file_name().unwrap().to_string_lossy().into_owned() ).collect();
+    for index in ["1-", "2-", "3-", "4-", "5-", "255-"] {
+        assert!(names.iter().any(|name| name.starts_with(index) ), "{:?}", names);
+    }
+}
+
+#[test]
+fn skips_shares_with_zero_threshold_and_reports_mismatches_together() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 5, 2, &share_dir, &["--share-name-template", "{index}-{nonce}.ccms"]).success();
+
+    let shares = share_files(&share_dir);
+    let corrupt_threshold = |share: &std::path::Path, threshold: u8| {
+        let mut contents = fs::read(share).unwrap();
+        contents[SHARE_THRESHOLD_BYTE] = threshold;
+        fs::write(share, contents).unwrap();
+    };
+
+    corrupt_threshold(&shares[0], 0);
+    corrupt_threshold(&shares[1], 3);
+    corrupt_threshold(&shares[2], 200);
+
+    // the zero is no threshold at all, so that share is skipped; the other two are mentioned in one go
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &["--strict"]).code(EXIT_TAMPERED);
+    let stderr = stderr(&assert);
+
+    assert!(stderr.contains("threshold of 0"), "{}", stderr);
+    assert_eq!(stderr.matches("Threshold mismatch").count(), 1, "{}", stderr);
+    assert!(stderr.contains("Threshold mismatch from 2 of 4 share(s)"), "{}", stderr);
+    assert!(stderr.contains("don't agree with each other"), "{}", stderr);
+
+    // and keeping the file's threshold still gets there
+    decrypt(&encrypted_path(&file), &share_dir, &output, &[]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn lists_shares_without_decrypting() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 3, 2, &share_dir, &["--sign"]).success();
+
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &["--list-shares"]).success();
+    let listing = String::from_utf8_lossy(&assert.get_output().stdout).into_owned();
+
+    assert!(listing.starts_with("Index"), "{}", listing);
+
+    for (share_i, share) in share_files(&share_dir).iter().enumerate() {
+        let row = listing.lines().find(|line| line.ends_with(share.to_str().unwrap()) ).unwrap();
+        let columns: Vec<&str> = row.split_whitespace().collect();
+
+        assert_eq!(&columns[..3], &[(share_i + 1).to_string().as_str(), "yes", "valid"], "{}", listing);
+    }
+
+    // only a preview -- without anyone to say go ahead, nothing is decrypted
+    assert!(!output.exists());
+
+    Command::cargo_bin("chachamir").unwrap()
+        .arg("decrypt").arg(encrypted_path(&file))
+        .arg("--share-dir").arg(&share_dir)
+        .arg("--output").arg(&output)
+        .arg("--list-shares")
+        .write_stdin("y\n")
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn min_shares_is_enforced_on_top_of_the_threshold() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 5, 2, &share_dir, &[]).success();
+
+    // two would do cryptographically, but policy says three
+    for share in &share_files(&share_dir)[2..] {
+        fs::remove_file(share).unwrap();
+    }
+
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &["--min-shares", "3"]).code(EXIT_RECOVERY_FAILED);
+    let stderr = stderr(&assert);
+
+    assert!(stderr.contains("policy requires at least 3"), "{}", stderr);
+    assert!(stderr.contains("enough to recover the key"), "{}", stderr);
+    assert!(!output.exists());
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &["--min-shares", "2"]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn shares_with_trailing_whitespace_still_parse() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 3, 3, &share_dir, &["--sign"]).success();
+
+    // a newline from an editor, CRLF from text-mode FTP, and some zero padding
+    for (share, trailing) in share_files(&share_dir).iter().zip([&b"\n"[..], b"\r\n", b"\0\0\0\0"]) {
+        let mut contents = fs::read(share).unwrap();
+        contents.extend_from_slice(trailing);
+        fs::write(share, contents).unwrap();
+
+        chachamir().arg("verify-share").arg(share).assert().success();
+    }
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &["--strict"]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn share_macs_catch_shares_from_another_file() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let other = fixture(dir.path(), "other.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let other_share_dir = dir.path().join("other_shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 3, 2, &share_dir, &["--share-mac"]).success();
+    encrypt(&other, 3, 2, &other_share_dir, &["--share-mac"]).success();
+
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &[]).success();
+    assert!(stderr(&assert).contains("All 3 share MAC(s) match the recovered key"), "{}", stderr(&assert));
+    fs::remove_file(&output).unwrap();
+
+    // the other file's share, passed off as one of ours with our nonce (nothing else binds an unsigned share to its file)
+    let ours = fs::read(&share_files(&share_dir)[0]).unwrap();
+    let mut forged = fs::read(&share_files(&other_share_dir)[0]).unwrap();
+    forged[SHARE_NONCE_BYTES].copy_from_slice(&ours[SHARE_NONCE_BYTES]);
+    let forged_path = share_dir.join("zz-forged.ccms"); // after the real one with its index, so it's the duplicate
+    fs::write(&forged_path, forged).unwrap();
+
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &[]).success();
+    let printed = stderr(&assert);
+    assert!(printed.contains(&format!("Share {} may belong to a different file", forged_path.display())), "{}", printed);
+    assert!(printed.contains("1 of 4 share MAC(s) do not match"), "{}", printed);
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+    fs::remove_file(&output).unwrap();
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &["--strict"]).code(EXIT_TAMPERED);
+    assert!(!output.exists());
+}
+
+#[test]
+fn armored_shares_round_trip_and_catch_truncation() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 3, 2, &share_dir, &["--armor", "--sign"]).success();
+    assert!(share_files(&share_dir).is_empty());
+
+    let mut armored: Vec<_> = fs::read_dir(&share_dir).unwrap().map(|entry| entry.unwrap().path() ).collect();
+    armored.sort();
+    assert_eq!(armored.len(), 3);
+    assert!(armored.iter().all(|path| path.to_string_lossy().ends_with(".ccms.asc") ));
+
+    let text = fs::read_to_string(&armored[0]).unwrap();
+    assert!(text.starts_with("-----BEGIN CHACHAMIR SHARE-----\n"), "{}", text);
+    assert!(text.ends_with("-----END CHACHAMIR SHARE-----\n"), "{}", text);
+
+    chachamir().arg("info").arg(&armored[0]).assert().success();
+    chachamir().arg("verify-share").arg(&armored[0]).assert().success();
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &["--strict"]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+    fs::remove_file(&output).unwrap();
+
+    // pasted with CRLFs, and another cut short -- leaving too few good shares
+    fs::write(&armored[0], text.replace('\n', "\r\n")).unwrap();
+    chachamir().arg("verify-share").arg(&armored[0]).assert().success();
+
+    let cut = fs::read_to_string(&armored[1]).unwrap();
+    let lines: Vec<&str> = cut.lines().collect();
+    fs::write(&armored[1], lines[..lines.len() - 3].join("\n")).unwrap();
+    fs::remove_file(&armored[2]).unwrap();
+
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &[]).code(EXIT_RECOVERY_FAILED);
+    assert!(stderr(&assert).contains("cut short"), "{}", stderr(&assert));
+}
+
+#[test]
+fn split_secrets_combine_back() {
+    let dir = tempdir().unwrap();
+    let secret_file = dir.path().join("seed.txt");
+    let share_dir = dir.path().join("shares");
+    let secret = b"correct horse battery staple\n";
+    fs::write(&secret_file, secret).unwrap();
+
+    let split = |secret_file: &std::path::Path, share_dir: &std::path::Path| {
+        chachamir().arg("split").arg(secret_file).args(["5", "3", "--share-dir"]).arg(share_dir).assert()
+    };
+
+    split(&secret_file, &share_dir).success();
+    let secret_shares = share_files(&share_dir);
+    assert_eq!(secret_shares.len(), 5);
+
+    // shares of a file alongside are left alone
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    encrypt(&file, 3, 2, &share_dir, &[]).success();
+
+    let combine = || chachamir().arg("combine").arg(&share_dir).assert();
+
+    // byte for byte, trailing newline and all
+    let assert = combine().success();
+    assert_eq!(assert.get_output().stdout, secret);
+
+    // two of five is one short
+    for share in &secret_shares[..3] {
+        fs::remove_file(share).unwrap();
+    }
+
+    combine().code(EXIT_RECOVERY_FAILED);
+
+    // a second secret's shares can't be told apart from the first's by where they are
+    split(&secret_file, &share_dir).success();
+    let assert = combine().failure();
+    assert!(stderr(&assert).contains("shares of 2 different secrets"), "{}", stderr(&assert));
+
+    // and the secret's length has to fit in a byte
+    fs::write(&secret_file, [b'x'; 256]).unwrap();
+    split(&secret_file, &dir.path().join("too_long")).failure();
+}
+
+#[test]
+fn share_names_carry_a_checksum() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 3, 2, &share_dir, &[]).success();
+
+    let shares = share_files(&share_dir);
+    let share_name = shares[0].file_stem().unwrap().to_string_lossy().into_owned();
+    assert_eq!(share_name.split('-').count(), 3, "{}", share_name);
+
+    // a truncated share is caught by its name, before it's parsed
+    let contents = fs::read(&shares[0]).unwrap();
+    fs::write(&shares[0], &contents[..contents.len() - 1]).unwrap();
+
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &[]).success();
+    assert!(stderr(&assert).contains("may be corrupt or truncated"), "{}", stderr(&assert));
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+
+    // names from before checksums (or that have lost theirs) aren't checked
+    fs::write(&shares[0], &contents).unwrap();
+
+    for share in &shares {
+        let stem = share.file_stem().unwrap().to_string_lossy().into_owned();
+        fs::rename(share, share_dir.join(format!("{}.ccms", stem.rsplit_once('-').unwrap().0))).unwrap();
+    }
+
+    fs::remove_file(&output).unwrap();
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &[]).success();
+    assert!(!stderr(&assert).contains("may be corrupt"), "{}", stderr(&assert));
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn verify_subsets_checks_quorums() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    // few enough to try every one of them
+    let assert = encrypt(&file, 5, 3, &share_dir, &["--verify-subsets"]).success();
+    assert!(stderr(&assert).contains("Share subset recovery succeeded (10 subset(s) of 3 share(s))"), "{}", stderr(&assert));
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &[]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+
+    // 20 choose 2 is too many, so some are picked at random
+    let other = fixture(dir.path(), "other.bin", 16);
+    let assert = encrypt(&other, 20, 2, &dir.path().join("other-shares"), &["--verify-subsets"]).success();
+    assert!(stderr(&assert).contains("Share subset recovery succeeded (16 subset(s) of 2 share(s))"), "{}", stderr(&assert));
+
+    // and 10 of 10 only has the one
+    let assert = encrypt(&other, 10, 10, &dir.path().join("all-shares"), &["--verify-subsets", "--force"]).success();
+    assert!(stderr(&assert).contains("Share subset recovery succeeded (1 subset(s) of 10 share(s))"), "{}", stderr(&assert));
+}
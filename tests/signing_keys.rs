@@ -0,0 +1,154 @@
+// Keypairs from keygen, and signing (and checking) files with them
+
+mod common;
+
+use std::fs;
+
+use assert_cmd::Command;
+use common::{chachamir, decrypt, encrypt, encrypted_path, fixture, share_files, stderr};
+
+use tempfile::tempdir;
+
+const EXIT_NEEDS_CONFIRMATION: i32 = 2;
+const EXIT_TAMPERED: i32 = 7;
+
+#[test]
+fn keygen_keypair_signs_files() {
+    let dir = tempdir().unwrap();
+    let key_file = dir.path().join("mykey");
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let output = dir.path().join("out.bin");
+
+    let assert = chachamir().arg("keygen").arg(&key_file).assert().success();
+    let printed = String::from_utf8_lossy(&assert.get_output().stdout).into_owned();
+
+    let pub_key = fs::read_to_string(dir.path().join("mykey.pub")).unwrap().trim().to_owned();
+    assert_eq!(pub_key.len(), 64);
+    assert!(printed.contains(&pub_key), "{}", printed);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&key_file).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    // the same public key comes back out of the key file
+    let assert = chachamir().arg("keygen").arg(&key_file).arg("--print-pubkey").assert().success();
+    assert!(String::from_utf8_lossy(&assert.get_output().stdout).contains(&pub_key));
+
+    // and nothing is overwritten
+    chachamir().arg("keygen").arg(&key_file).assert().failure();
+    assert_eq!(fs::read_to_string(dir.path().join("mykey.pub")).unwrap().trim(), pub_key);
+
+    encrypt(&file, 3, 2, &share_dir, &["--sign", "--key-file", key_file.to_str().unwrap()]).success();
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &["--expect-pubkey", &pub_key]).success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn fingerprint_must_be_confirmed() {
+    let dir = tempdir().unwrap();
+    let key_file = dir.path().join("mykey");
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let unsigned = fixture(dir.path(), "unsigned.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let unsigned_share_dir = dir.path().join("unsigned_shares");
+    let output = dir.path().join("out.bin");
+
+    let assert = chachamir().arg("keygen").arg(&key_file).assert().success();
+    let printed = String::from_utf8_lossy(&assert.get_output().stdout).into_owned();
+    let fingerprint = printed.lines().find_map(|line| line.strip_prefix("[+] Fingerprint: ") ).unwrap().to_owned();
+    assert_eq!(fingerprint.len(), 19); // four groups of four hex digits
+
+    encrypt(&file, 3, 2, &share_dir, &["--sign", "--key-file", key_file.to_str().unwrap()]).success();
+    encrypt(&unsigned, 3, 2, &unsigned_share_dir, &[]).success();
+
+    // nothing to confirm on an unsigned file
+    decrypt(&encrypted_path(&unsigned), &unsigned_share_dir, &output, &["--verify-pubkey-fingerprint"]).code(EXIT_TAMPERED);
+
+    // nobody to confirm it
+    let refused = decrypt(&encrypted_path(&file), &share_dir, &output, &["--verify-pubkey-fingerprint"]).code(EXIT_NEEDS_CONFIRMATION);
+    assert!(stderr(&refused).contains(&fingerprint));
+    assert!(!output.exists());
+
+    let decrypt_answering = |answer: &str| {
+        Command::cargo_bin("chachamir").unwrap()
+            .arg("decrypt").arg(encrypted_path(&file))
+            .arg("--share-dir").arg(&share_dir)
+            .arg("--output").arg(&output)
+            .arg("--verify-pubkey-fingerprint")
+            .write_stdin(answer)
+            .assert()
+    };
+
+    decrypt_answering("n\n").code(EXIT_TAMPERED);
+    assert!(!output.exists());
+
+    decrypt_answering("y\n").success();
+    assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+}
+
+#[test]
+fn verify_all_signatures_reports_every_bad_share() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let unsigned = fixture(dir.path(), "unsigned.bin", 4096);
+    let share_dir = dir.path().join("shares");
+    let unsigned_share_dir = dir.path().join("unsigned_shares");
+    let output = dir.path().join("out.bin");
+
+    encrypt(&file, 5, 2, &share_dir, &["--sign", "--share-name-template", "{index}-{nonce}.ccms"]).success(); // signatures, not name checksums, to catch them
+    encrypt(&unsigned, 3, 2, &unsigned_share_dir, &[]).success();
+
+    // no signatures to speak of
+    decrypt(&encrypted_path(&unsigned), &unsigned_share_dir, &output, &["--verify-all-signatures"]).code(EXIT_TAMPERED);
+
+    let assert = decrypt(&encrypted_path(&file), &share_dir, &output, &["--verify-all-signatures"]).success();
+    assert!(stderr(&assert).contains("All 5 share signature(s) verified"), "{}", stderr(&assert));
+    fs::remove_file(&output).unwrap();
+
+    // two bad shares among five -- plenty left to decrypt with, but both are named
+    let shares = share_files(&share_dir);
+    for share in [&shares[1], &shares[3]] {
+        let mut contents = fs::read(share).unwrap();
+        *contents.last_mut().unwrap() ^= 0xff;
+        fs::write(share, contents).unwrap();
+    }
+
+    let refused = decrypt(&encrypted_path(&file), &share_dir, &output, &["--verify-all-signatures"]).code(EXIT_NEEDS_CONFIRMATION);
+    let printed = stderr(&refused);
+    assert!(printed.contains("2 of 5 share signature(s) failed verification"), "{}", printed);
+    assert!(printed.contains(&shares[1].display().to_string()) && printed.contains(&shares[3].display().to_string()), "{}", printed);
+    assert!(!output.exists());
+
+    decrypt(&encrypted_path(&file), &share_dir, &output, &["--verify-all-signatures", "--strict"]).code(EXIT_TAMPERED);
+    assert!(!output.exists());
+}
+
+#[test]
+fn file_or_shares_can_be_signed_alone() {
+    let dir = tempdir().unwrap();
+    let file = fixture(dir.path(), "plain.bin", 4096);
+    let output = dir.path().join("out.bin");
+
+    for (flag, file_signed, shares_signed) in [("--sign-file", true, false), ("--sign-shares", false, true)] {
+        let share_dir = dir.path().join(flag.trim_start_matches('-'));
+        encrypt(&file, 3, 2, &share_dir, &[flag, "--force"]).success();
+
+        let info = |path: &std::path::Path| {
+            let assert = chachamir().arg("info").arg(path).assert().success();
+            String::from_utf8_lossy(&assert.get_output().stdout).into_owned()
+        };
+        assert!(info(&encrypted_path(&file)).contains(&format!("Signed: {}", if file_signed { "yes" } else { "no" })));
+        assert!(info(&share_files(&share_dir)[0]).contains(&format!("Signed: {}", if shares_signed { "yes" } else { "no" })));
+
+        // the mismatch was on purpose, so there's nothing to confirm, strict or not
+        decrypt(&encrypted_path(&file), &share_dir, &output, &["--strict", "--force"]).success();
+        assert_eq!(fs::read(&output).unwrap(), fs::read(&file).unwrap());
+    }
+
+    // --detached-sig has no file signature to detach without --sign-file
+    encrypt(&file, 3, 2, &dir.path().join("detached"), &["--sign-shares", "--detached-sig", "--force"]).failure();
+}